@@ -0,0 +1,4362 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io,
+    net::Ipv4Addr,
+    rc::{Rc, Weak},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    ipv4_fmt,
+    json::Json,
+    json_object, mac_fmt,
+    network::{
+        capture::{Capture, Replay},
+        device::{
+            cable::{CableSimulator, EthernetPort, Impairments, LinkProperties, PortMap},
+            desktop::Desktop,
+            hub::Hub,
+            multilayer_switch::{MultilayerSwitch, MULTILAYER_SWITCH_PORTS, ROUTED_VLAN_BASE},
+            router::Router,
+            switch::{
+                LoadBalance, MirrorDirection, StormAction, Switch, TrafficClass, ViolationMode,
+            },
+        },
+        ethernet::ByteSerializable,
+        ipv4::{IcmpFrame, IcmpType, Ipv4Address},
+        log::LogEvent,
+        trace::{TraceId, TraceRecord, TraceStep, Tracer},
+    },
+    tick::{Clock, Event, TickTimer, Tickable, STEP},
+};
+
+use super::utils::{self, PacketKind};
+
+/// Version of the topology file format, see `DeviceRepository::save`.
+pub const TOPOLOGY_VERSION: u64 = 1;
+
+/// Entries kept in the event log of the simulation. Older entries are forgotten first.
+const LOG_LIMIT: usize = 10_000;
+
+/// Traces kept by the simulation. Older traces are forgotten first.
+const TRACE_LIMIT: usize = 1000;
+
+/// Hops kept in a trace. Bounds the trace of a frame looping around the network.
+const TRACE_HOP_LIMIT: usize = 500;
+
+/// Largest MAC seed, as MAC addresses are 48 bits long.
+const MAX_MAC_SEED: u64 = 0xFFFF_FFFF_FFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceId {
+    Router(u64),
+    Switch(u64),
+    Desktop(u64),
+    MultilayerSwitch(u64),
+    Hub(u64),
+}
+
+impl DeviceId {
+    fn as_u64(&self) -> u64 {
+        match self {
+            DeviceId::Router(i) => *i,
+            DeviceId::Switch(i) => *i,
+            DeviceId::Desktop(i) => *i,
+            DeviceId::MultilayerSwitch(i) => *i,
+            DeviceId::Hub(i) => *i,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum DeviceKind {
+    Desktop,
+    Switch,
+    Router,
+    MultilayerSwitch,
+    Hub,
+}
+
+impl DeviceKind {
+    /// Returns the number of MAC addresses seeded after a device's id.
+    fn mac_span(&self) -> u64 {
+        match self {
+            DeviceKind::Desktop => 1,
+            DeviceKind::Router | DeviceKind::Hub => 8,
+            DeviceKind::Switch => 32,
+            DeviceKind::MultilayerSwitch => 40, // Switch and routing engine addresses
+        }
+    }
+}
+
+pub enum DeviceSetQuery {
+    Pos(Position),
+    Label(String),
+    Connect(DeviceId, usize, usize), // Adj Device, Self Port, Adj Port
+    Delete,
+    Disconnect(usize),
+    TerminalInput(String),
+}
+
+/// Where a device is placed in the topology, for frontends to lay it out.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Clone)]
+pub struct DeviceAttributes {
+    pub id: DeviceId,
+    pub label: String,
+    pub pos: Position,
+    pub ports_len: usize,
+    deleted: bool,
+}
+
+/// A decision of a device, stamped with the simulated time it was made at. See `DeviceRepository::log`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub time: Duration, // Simulated time since the clock started, see `Clock::elapsed`
+    pub device: DeviceId,
+    pub label: String,
+    pub port: Option<usize>, // None if the decision concerns the whole device
+    pub event: LogEvent,
+}
+
+impl LogEntry {
+    /// The entry as a JSON object with the members `time` (in seconds), `device` (the id of the device), `label`,
+    /// `port`, `event` (the kind of event), `reason` (a sentence describing it), and the fields of the event.
+    /// Addresses are written like in `DeviceRepository::save`.
+    pub fn to_json(&self) -> Json {
+        let mut json = json_object! {
+            "time" => self.time.as_secs_f64(),
+            "device" => self.device.as_u64(),
+            "label" => self.label.as_str(),
+            "port" => self.port.map(|port| port as u64),
+            "event" => self.event.kind(),
+            "reason" => self.event.to_string(),
+        };
+
+        let fields = match &self.event {
+            LogEvent::MacLearned { mac, vlan } => json_object! {
+                "mac" => mac_fmt!(mac),
+                "vlan" => *vlan as u64,
+            },
+            LogEvent::Flooded {
+                destination,
+                vlan,
+                class,
+            } => json_object! {
+                "destination" => mac_fmt!(destination),
+                "vlan" => *vlan as u64,
+                "class" => match class {
+                    TrafficClass::Broadcast => "broadcast",
+                    TrafficClass::Multicast => "multicast",
+                    TrafficClass::UnknownUnicast => "unknown_unicast",
+                },
+            },
+            LogEvent::StpChanged {
+                instance,
+                role,
+                state,
+            } => json_object! {
+                "instance" => *instance as u64,
+                "role" => role.map(|role| format!("{:?}", role).to_lowercase()),
+                "state" => format!("{:?}", state).to_lowercase(),
+            },
+            LogEvent::RouteInstalled {
+                network,
+                mask,
+                via,
+                metric,
+            } => json_object! {
+                "network" => ipv4_fmt!(network),
+                "mask" => ipv4_fmt!(mask),
+                "via" => via.map(|via| ipv4_fmt!(via)),
+                "metric" => *metric as u64,
+            },
+            LogEvent::ArpLearned { ip, mac } => json_object! {
+                "ip" => ipv4_fmt!(ip),
+                "mac" => mac_fmt!(mac),
+            },
+            LogEvent::Dropped {
+                reason,
+                destination,
+            } => json_object! {
+                "drop_reason" => reason.as_str(),
+                "destination" => destination.map(|destination| ipv4_fmt!(destination)),
+            },
+        };
+
+        if let (Json::Object(members), Json::Object(fields)) = (&mut json, fields) {
+            members.extend(fields);
+        }
+        json
+    }
+}
+
+/// Selects the entries of the event log by device and kind of event. The default filter selects every entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LogFilter {
+    pub device: Option<DeviceId>,
+    pub kind: Option<&'static str>, // One of `LogEvent::KINDS`
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.device.is_none_or(|device| entry.device == device)
+            && self.kind.is_none_or(|kind| entry.event.kind() == kind)
+    }
+}
+
+/// A step of a traced frame at a device, stamped with the simulated time it happened at. See `DeviceRepository::trace`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceHop {
+    pub time: Duration, // Simulated time since the clock started, see `Clock::elapsed`
+    pub device: DeviceId,
+    pub label: String,
+    pub port: Option<usize>, // None for a port inside the device, such as one of a multilayer switch's routing engine
+    pub step: TraceStep,
+    pub frame: Option<&'static str>, // The protocol of the frame, see `PacketKind::name` ; None for a decision
+}
+
+/// The journey of an originated packet across the network, along with the frames sent on its behalf, such as the ARP
+/// exchanges resolving its next hops and the replies to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub id: TraceId,
+    pub hops: Vec<TraceHop>, // Oldest first
+}
+
+impl Trace {
+    /// The hop the fate of the originated packet was decided at: the first hop it was delivered or dropped at.
+    /// None while the packet is in flight, or if it ended without reaching a host, like an unanswered flood.
+    pub fn fate(&self) -> Option<&TraceHop> {
+        self.hops
+            .iter()
+            .find(|hop| matches!(hop.step, TraceStep::Delivered | TraceStep::Dropped(_)))
+    }
+
+    /// Adds a hop, unless the trace has `TRACE_HOP_LIMIT` hops.
+    fn push(&mut self, hop: TraceHop) {
+        if self.hops.len() < TRACE_HOP_LIMIT {
+            self.hops.push(hop);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Components<T> {
+    attributes: DeviceAttributes,
+    terminal: Terminal<T>,
+    device: T,
+}
+
+impl<T> Components<T> {
+    fn new(
+        id: DeviceId,
+        mut terminal: Terminal<T>,
+        label: String,
+        pos: Position,
+        device: T,
+        ports_len: usize,
+        clock: &Clock,
+    ) -> Self {
+        terminal.set_clock(clock);
+        Self {
+            attributes: DeviceAttributes {
+                id,
+                label,
+                pos,
+                ports_len,
+                deleted: false,
+            },
+            terminal,
+            device,
+        }
+    }
+
+    fn input(&mut self, input: &str) {
+        self.terminal.execute(&mut self.device, input);
+    }
+
+    /// Stamps the decisions of the device into the log of the simulation.
+    fn log(
+        &self,
+        log: &mut VecDeque<LogEntry>,
+        events: Vec<(Option<usize>, LogEvent)>,
+        time: Duration,
+    ) {
+        for (port, event) in events {
+            if log.len() >= LOG_LIMIT {
+                log.pop_front();
+            }
+            log.push_back(LogEntry {
+                time,
+                device: self.attributes.id,
+                label: self.attributes.label.clone(),
+                port,
+                event,
+            });
+        }
+    }
+}
+
+impl<T> Components<T> {
+    /// Stamps the steps of the frames traced at the device into the traces of the simulation.
+    /// * `ports` - The ports of the device, which the steps happened at.
+    fn trace(
+        &self,
+        traces: &mut BTreeMap<TraceId, Trace>,
+        records: Vec<TraceRecord>,
+        ports: &[Rc<RefCell<EthernetPort>>],
+        time: Duration,
+    ) {
+        for record in records {
+            let port = ports
+                .iter()
+                .position(|port| Weak::ptr_eq(&Rc::downgrade(port), &record.port));
+            let hop = TraceHop {
+                time,
+                device: self.attributes.id,
+                label: self.attributes.label.clone(),
+                port,
+                step: record.step,
+                frame: record
+                    .frame
+                    .map(|frame| PacketKind::from_bytes(&frame).name()),
+            };
+
+            traces
+                .entry(record.trace)
+                .or_insert_with(|| Trace {
+                    id: record.trace,
+                    hops: vec![],
+                })
+                .push(hop);
+            if traces.len() > TRACE_LIMIT {
+                traces.pop_first();
+            }
+        }
+    }
+}
+
+impl Components<Desktop> {
+    fn tick(&mut self) {
+        self.terminal.tick(&mut self.device);
+        self.device.tick();
+    }
+}
+
+/// The frames sniffed in one direction of a port with their traces, and the device at the other end of its cable.
+pub type Sniffed = (Option<DeviceId>, Vec<(TraceId, PacketKind)>);
+
+/// The simulated topology: every device, its terminal and the cables between them.
+///
+/// The repository is independent of any frontend. It is driven by `update`, one tick at a time, and by the terminal
+/// input of its devices. The GUI renders it and forwards input to it.
+#[derive(Default)]
+pub struct DeviceRepository {
+    lookup: HashMap<u64, usize>,
+
+    routers: Vec<Components<Router>>,
+    switches: Vec<Components<Switch>>,
+    desktops: Vec<Components<Desktop>>,
+    multilayer_switches: Vec<Components<MultilayerSwitch>>,
+    hubs: Vec<Components<Hub>>,
+    label_seeds: (i32, i32, i32, i32, i32), // (router, switch, desktop, multilayer switch, hub)
+
+    adj_devices: HashMap<DeviceId, Vec<(usize, DeviceId, usize)>>, // Id -> (Self Port, Adjacent Id, Adjacent Port)
+
+    pub cable_simulator: CableSimulator,
+    mac_seed: u64,
+
+    capture: Option<Rc<RefCell<Capture>>>, // Capture of every port, see `start_capture`
+    log: VecDeque<LogEntry>,               // Decisions of every device, see `log`
+    traces: BTreeMap<TraceId, Trace>,      // Journeys of the packets, see `trace`
+}
+
+impl DeviceRepository {
+    /// Adds a device, labeled and seeded after the devices of its kind, returning its id.
+    pub fn add(&mut self, kind: DeviceKind, pos: Position) -> DeviceId {
+        let label = 1 + match kind {
+            DeviceKind::Router => self.label_seeds.0,
+            DeviceKind::Switch => self.label_seeds.1,
+            DeviceKind::Desktop => self.label_seeds.2,
+            DeviceKind::MultilayerSwitch => self.label_seeds.3,
+            DeviceKind::Hub => self.label_seeds.4,
+        };
+        self._add(kind, pos, self.mac_seed + 1, label)
+    }
+
+    /// Adds a device with a given id, which seeds its MAC addresses, and label number.
+    fn _add(&mut self, kind: DeviceKind, pos: Position, mac_seed: u64, label: i32) -> DeviceId {
+        let clock = self.clock().clone();
+        let id = match kind {
+            DeviceKind::Router => {
+                self.label_seeds.0 = self.label_seeds.0.max(label);
+                let mut component = Components::new(
+                    DeviceId::Router(mac_seed),
+                    Terminal::new_router(),
+                    format!("Router {}", label),
+                    pos,
+                    Router::from_seed(mac_seed),
+                    8,
+                    &clock,
+                );
+                component.device.set_clock(&clock);
+
+                self.lookup.insert(mac_seed, self.routers.len());
+                self.cable_simulator.adds(component.device.ports());
+                self.routers.push(component);
+                DeviceId::Router(mac_seed)
+            }
+            DeviceKind::Switch => {
+                self.label_seeds.1 = self.label_seeds.1.max(label);
+                let mut component = Components::new(
+                    DeviceId::Switch(mac_seed),
+                    Terminal::new_switch(),
+                    format!("Switch {}", label),
+                    pos,
+                    Switch::from_seed(mac_seed, label as u16),
+                    32,
+                    &clock,
+                );
+                component.device.set_clock(&clock);
+
+                self.lookup.insert(mac_seed, self.switches.len());
+                self.cable_simulator.adds(component.device.ports());
+                self.switches.push(component);
+                DeviceId::Switch(mac_seed)
+            }
+            DeviceKind::Desktop => {
+                self.label_seeds.2 = self.label_seeds.2.max(label);
+                let component = Components::new(
+                    DeviceId::Desktop(mac_seed),
+                    Terminal::new_desktop(),
+                    format!("Desktop {}", label),
+                    pos,
+                    Desktop::from_seed(mac_seed),
+                    1,
+                    &clock,
+                );
+
+                self.lookup.insert(mac_seed, self.desktops.len());
+                self.cable_simulator
+                    .add(component.device.interface.ethernet.port());
+                self.desktops.push(component);
+                DeviceId::Desktop(mac_seed)
+            }
+            DeviceKind::MultilayerSwitch => {
+                self.label_seeds.3 = self.label_seeds.3.max(label);
+                let mut component = Components::new(
+                    DeviceId::MultilayerSwitch(mac_seed),
+                    Terminal::new_multilayer_switch(),
+                    format!("Multilayer Switch {}", label),
+                    pos,
+                    MultilayerSwitch::from_seed(mac_seed, label as u16),
+                    MULTILAYER_SWITCH_PORTS,
+                    &clock,
+                );
+                component.device.set_clock(&clock);
+                component.device.set_tracer(self.cable_simulator.tracer());
+
+                self.lookup.insert(mac_seed, self.multilayer_switches.len());
+                self.cable_simulator.adds(component.device.ports());
+                self.multilayer_switches.push(component);
+                DeviceId::MultilayerSwitch(mac_seed)
+            }
+            DeviceKind::Hub => {
+                self.label_seeds.4 = self.label_seeds.4.max(label);
+                let component = Components::new(
+                    DeviceId::Hub(mac_seed),
+                    Terminal::new_hub(),
+                    format!("Hub {}", label),
+                    pos,
+                    Hub::from_seed(mac_seed),
+                    8,
+                    &clock,
+                );
+
+                self.lookup.insert(mac_seed, self.hubs.len());
+                self.cable_simulator.adds(component.device.ports());
+                self.hubs.push(component);
+                DeviceId::Hub(mac_seed)
+            }
+        };
+
+        self.mac_seed = self.mac_seed.max(mac_seed + kind.mac_span());
+        id
+    }
+
+    pub fn get(&self, id: DeviceId) -> Option<DeviceAttributes> {
+        let i = self.lookup.get(&id.as_u64())?;
+        Some(match id {
+            DeviceId::Router(_) => self.routers[*i].attributes.clone(),
+            DeviceId::Switch(_) => self.switches[*i].attributes.clone(),
+            DeviceId::Desktop(_) => self.desktops[*i].attributes.clone(),
+            DeviceId::MultilayerSwitch(_) => self.multilayer_switches[*i].attributes.clone(),
+            DeviceId::Hub(_) => self.hubs[*i].attributes.clone(),
+        })
+    }
+
+    /// Returns the attributes of every device: routers, switches, multilayer switches, hubs, then desktops.
+    pub fn devices(&self) -> Vec<DeviceAttributes> {
+        let mut devices: Vec<DeviceAttributes> =
+            self.routers.iter().map(|c| c.attributes.clone()).collect();
+        devices.extend(self.switches.iter().map(|c| c.attributes.clone()));
+        devices.extend(
+            self.multilayer_switches
+                .iter()
+                .map(|c| c.attributes.clone()),
+        );
+        devices.extend(self.hubs.iter().map(|c| c.attributes.clone()));
+        devices.extend(self.desktops.iter().map(|c| c.attributes.clone()));
+        devices
+    }
+
+    /// Returns the device with a label.
+    pub fn find(&self, label: &str) -> Option<DeviceId> {
+        self.devices()
+            .into_iter()
+            .find(|attributes| attributes.label == label)
+            .map(|attributes| attributes.id)
+    }
+
+    /// Returns every cable as (Device, Port, Adjacent Device, Adjacent Port), once from each of its ends.
+    pub fn links(&self) -> Vec<(DeviceId, usize, DeviceId, usize)> {
+        self.adj_devices
+            .iter()
+            .flat_map(|(id, adjs)| {
+                adjs.iter()
+                    .map(|(port, adj, adj_port)| (*id, *port, *adj, *adj_port))
+            })
+            .collect()
+    }
+
+    pub fn is_shutdown(&self, id: DeviceId, port: usize) -> bool {
+        self.ethernet_port(id, self.lookup(id), port)
+            .borrow()
+            .is_shutdown()
+    }
+
+    /// Returns if a port is up. Ports of hubs and desktops are always up.
+    pub fn is_port_up(&self, id: DeviceId, port: usize) -> bool {
+        let i = self.lookup(id);
+        match id {
+            DeviceId::Switch(_) => self.switches[i].device.is_port_up(port),
+            DeviceId::Router(_) => self.routers[i].device.is_port_up(port),
+            DeviceId::MultilayerSwitch(_) => self.multilayer_switches[i].device.is_port_up(port),
+            _ => true,
+        }
+    }
+
+    pub fn get_terminal_output(&mut self, id: DeviceId) -> Vec<String> {
+        let i = self.lookup(id);
+        let out_buf = match id {
+            DeviceId::Router(_) => &mut self.routers[i].terminal.out_buf,
+            DeviceId::Switch(_) => &mut self.switches[i].terminal.out_buf,
+            DeviceId::Desktop(_) => &mut self.desktops[i].terminal.out_buf,
+            DeviceId::MultilayerSwitch(_) => &mut self.multilayer_switches[i].terminal.out_buf,
+            DeviceId::Hub(_) => &mut self.hubs[i].terminal.out_buf,
+        };
+        out_buf.drain(..).collect()
+    }
+
+    pub fn set(&mut self, id: DeviceId, query: DeviceSetQuery) {
+        let i = self.lookup(id);
+        if !matches!(query, DeviceSetQuery::Pos(_) | DeviceSetQuery::Label(_)) {
+            self.clock().wake("Input");
+        }
+
+        match query {
+            DeviceSetQuery::Pos(pos) => match id {
+                DeviceId::Router(_) => self.routers[i].attributes.pos = pos,
+                DeviceId::Switch(_) => self.switches[i].attributes.pos = pos,
+                DeviceId::Desktop(_) => self.desktops[i].attributes.pos = pos,
+                DeviceId::MultilayerSwitch(_) => self.multilayer_switches[i].attributes.pos = pos,
+                DeviceId::Hub(_) => self.hubs[i].attributes.pos = pos,
+            },
+            DeviceSetQuery::Label(label) => match id {
+                DeviceId::Router(_) => self.routers[i].attributes.label = label,
+                DeviceId::Switch(_) => self.switches[i].attributes.label = label,
+                DeviceId::Desktop(_) => self.desktops[i].attributes.label = label,
+                DeviceId::MultilayerSwitch(_) => {
+                    self.multilayer_switches[i].attributes.label = label
+                }
+                DeviceId::Hub(_) => self.hubs[i].attributes.label = label,
+            },
+            DeviceSetQuery::Connect(adj_id, self_port, adj_port) => {
+                self.connect(id, self_port, adj_id, adj_port);
+            }
+            DeviceSetQuery::Disconnect(port) => {
+                self.disconnect(id, port);
+            }
+            DeviceSetQuery::TerminalInput(input) => match id {
+                DeviceId::Router(_) => self.routers[i].input(&input),
+                DeviceId::Switch(_) => self.switches[i].input(&input),
+                DeviceId::Desktop(_) => self.desktops[i].input(&input),
+                DeviceId::MultilayerSwitch(_) => self.multilayer_switches[i].input(&input),
+                DeviceId::Hub(_) => self.hubs[i].input(&input),
+            },
+            DeviceSetQuery::Delete => match id {
+                DeviceId::Router(_) => self.routers[i].attributes.deleted = true,
+                DeviceId::Switch(_) => self.switches[i].attributes.deleted = true,
+                DeviceId::Desktop(_) => self.desktops[i].attributes.deleted = true,
+                DeviceId::MultilayerSwitch(_) => {
+                    self.multilayer_switches[i].attributes.deleted = true
+                }
+                DeviceId::Hub(_) => self.hubs[i].attributes.deleted = true,
+            },
+        }
+    }
+
+    /// Runs every device and cable once at the current time, taking the events due from the agenda.
+    pub fn update(&mut self) {
+        self.clock().take_due();
+        self._update();
+    }
+
+    fn _update(&mut self) {
+        let mut delete = Vec::<DeviceId>::new();
+        let time = self.clock().elapsed();
+        let tracer = self.cable_simulator.tracer().clone();
+        self._trace_ports(time); // Frames sent by terminal input since the last update
+
+        for component in &mut self.routers {
+            if component.attributes.deleted {
+                delete.push(component.attributes.id);
+            } else {
+                component.terminal.replay_tick();
+                component.device.tick();
+                let events = component.device.take_events();
+                component.log(&mut self.log, events, time);
+                let ports = component.device.ports();
+                component.trace(&mut self.traces, tracer.take(), &ports, time);
+            }
+        }
+
+        for component in &mut self.switches {
+            if component.attributes.deleted {
+                delete.push(component.attributes.id);
+            } else {
+                component.terminal.replay_tick();
+                component.device.tick();
+                let events = component.device.take_events();
+                component.log(&mut self.log, events, time);
+                let ports = component.device.ports();
+                component.trace(&mut self.traces, tracer.take(), &ports, time);
+            }
+        }
+
+        for component in &mut self.multilayer_switches {
+            if component.attributes.deleted {
+                delete.push(component.attributes.id);
+            } else {
+                component.terminal.replay_tick();
+                component.device.tick();
+                let events = component.device.take_events();
+                component.log(&mut self.log, events, time);
+                let ports = component.device.ports();
+                component.trace(&mut self.traces, tracer.take(), &ports, time);
+            }
+        }
+
+        for component in &mut self.hubs {
+            if component.attributes.deleted {
+                delete.push(component.attributes.id);
+            } else {
+                component.terminal.replay_tick();
+                component.device.tick();
+                let ports = component.device.ports();
+                component.trace(&mut self.traces, tracer.take(), &ports, time);
+            }
+        }
+
+        for component in &mut self.desktops {
+            if component.attributes.deleted {
+                delete.push(component.attributes.id);
+            } else {
+                component.terminal.replay_tick();
+                component.tick();
+                let events = component.device.take_events();
+                component.log(&mut self.log, events, time);
+                let ports = [component.device.interface.ethernet.port()];
+                component.trace(&mut self.traces, tracer.take(), &ports, time);
+            }
+        }
+
+        for id in delete {
+            let devices = self.adj_devices.get(&id).cloned();
+            if let Some(devices) = devices {
+                for (port, _, _) in devices {
+                    self.disconnect(id, port);
+                }
+            }
+
+            let i = self.lookup(id);
+            self.adj_devices.remove(&id);
+            self.lookup.remove(&id.as_u64());
+
+            // Remove from entity list and sim
+            match id {
+                DeviceId::Desktop(_) => {
+                    self.cable_simulator
+                        .remove(self.desktops[i].device.interface.ethernet.port());
+                    self.desktops.swap_remove(i);
+
+                    if i < self.desktops.len() {
+                        self.lookup
+                            .insert(self.desktops[i].attributes.id.as_u64(), i);
+                    }
+                }
+                DeviceId::Switch(_) => {
+                    self.cable_simulator
+                        .removes(self.switches[i].device.ports());
+                    self.switches.swap_remove(i);
+
+                    if i < self.switches.len() {
+                        self.lookup
+                            .insert(self.switches[i].attributes.id.as_u64(), i);
+                    }
+                }
+                DeviceId::Router(_) => {
+                    self.cable_simulator.removes(self.routers[i].device.ports());
+                    self.routers.swap_remove(i);
+
+                    if i < self.routers.len() {
+                        self.lookup
+                            .insert(self.routers[i].attributes.id.as_u64(), i);
+                    }
+                }
+                DeviceId::Hub(_) => {
+                    self.cable_simulator.removes(self.hubs[i].device.ports());
+                    self.hubs.swap_remove(i);
+
+                    if i < self.hubs.len() {
+                        self.lookup.insert(self.hubs[i].attributes.id.as_u64(), i);
+                    }
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    self.cable_simulator
+                        .removes(self.multilayer_switches[i].device.ports());
+                    self.multilayer_switches.swap_remove(i);
+
+                    if i < self.multilayer_switches.len() {
+                        self.lookup
+                            .insert(self.multilayer_switches[i].attributes.id.as_u64(), i);
+                    }
+                }
+            }
+        }
+
+        self.cable_simulator.tick();
+        self._trace_ports(time); // Frames lost on the cables
+    }
+
+    /// Stamps the steps traced outside of the tick of a device into the traces of the simulation, each at the device
+    /// of the port it happened at.
+    fn _trace_ports(&mut self, time: Duration) {
+        fn claim<T>(
+            components: &[Components<T>],
+            ports: impl Fn(&T) -> Vec<Rc<RefCell<EthernetPort>>>,
+            traces: &mut BTreeMap<TraceId, Trace>,
+            records: &mut Vec<TraceRecord>,
+            time: Duration,
+        ) {
+            for c in components {
+                if records.is_empty() {
+                    return;
+                }
+
+                let ports = ports(&c.device);
+                let (claimed, rest) = std::mem::take(records).into_iter().partition(|record| {
+                    ports
+                        .iter()
+                        .any(|port| Weak::ptr_eq(&Rc::downgrade(port), &record.port))
+                });
+                *records = rest;
+                c.trace(traces, claimed, &ports, time);
+            }
+        }
+
+        let mut records = self.cable_simulator.tracer().take();
+        let traces = &mut self.traces;
+        claim(&self.routers, Router::ports, traces, &mut records, time);
+        claim(&self.switches, Switch::ports, traces, &mut records, time);
+        claim(
+            &self.multilayer_switches,
+            MultilayerSwitch::ports,
+            traces,
+            &mut records,
+            time,
+        );
+        claim(&self.hubs, Hub::ports, traces, &mut records, time);
+        claim(
+            &self.desktops,
+            |desktop| vec![desktop.interface.ethernet.port()],
+            traces,
+            &mut records,
+            time,
+        );
+    }
+
+    /// Returns the clock of the simulation, shared by every device and cable in it.
+    pub fn clock(&self) -> &Clock {
+        self.cable_simulator.clock()
+    }
+
+    /// Moves every device and cable onto another clock, such as the clock of a simulation being replaced.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        self.cable_simulator.set_clock(clock);
+        for c in &mut self.routers {
+            c.terminal.set_clock(clock);
+            c.device.set_clock(clock);
+        }
+        for c in &mut self.switches {
+            c.terminal.set_clock(clock);
+            c.device.set_clock(clock);
+        }
+        for c in &mut self.multilayer_switches {
+            c.terminal.set_clock(clock);
+            c.device.set_clock(clock);
+        }
+        for c in &mut self.hubs {
+            c.terminal.set_clock(clock);
+        }
+        for c in &mut self.desktops {
+            c.terminal.set_clock(clock);
+        }
+    }
+
+    /// Runs the simulation for a number of ticks.
+    pub fn run(&mut self, ticks: usize) {
+        for _ in 0..ticks {
+            self.update();
+        }
+    }
+
+    /// Copies the full state of the simulation: the tables and timers of every device, the frames in its ports and on its
+    /// cables, and the clock with its agenda. The copy runs apart from the original, on a copy of the clock, and runs
+    /// the same way. Captures are shared, frames of both are recorded into the same capture.
+    pub fn snapshot(&self) -> DeviceRepository {
+        let mut ports = PortMap::default();
+        let mut copy = DeviceRepository {
+            lookup: self.lookup.clone(),
+            routers: self.routers.clone(),
+            switches: self.switches.clone(),
+            desktops: self.desktops.clone(),
+            multilayer_switches: self.multilayer_switches.clone(),
+            hubs: self.hubs.clone(),
+            label_seeds: self.label_seeds,
+            adj_devices: self.adj_devices.clone(),
+            cable_simulator: self.cable_simulator.clone(),
+            mac_seed: self.mac_seed,
+            capture: self.capture.clone(),
+            log: self.log.clone(),
+            traces: self.traces.clone(),
+        };
+
+        for c in &mut copy.routers {
+            c.terminal.relink(&mut ports);
+            c.device.relink(&mut ports);
+        }
+        for c in &mut copy.switches {
+            c.terminal.relink(&mut ports);
+            c.device.relink(&mut ports);
+        }
+        for c in &mut copy.multilayer_switches {
+            c.terminal.relink(&mut ports);
+            c.device.relink(&mut ports);
+        }
+        for c in &mut copy.hubs {
+            c.terminal.relink(&mut ports);
+            c.device.relink(&mut ports);
+        }
+        for c in &mut copy.desktops {
+            c.terminal.relink(&mut ports);
+            c.device.interface.ethernet.relink(&mut ports);
+        }
+        copy.cable_simulator.relink(&mut ports);
+        ports.relink();
+
+        copy.set_clock(&self.clock().snapshot());
+        copy.set_tracer(&self.cable_simulator.tracer().snapshot());
+        copy
+    }
+
+    /// Moves every port onto another tracer, such as the copy of the tracer of a snapshot.
+    fn set_tracer(&mut self, tracer: &Tracer) {
+        self.cable_simulator.set_tracer(tracer);
+        for c in &mut self.multilayer_switches {
+            c.device.set_tracer(tracer);
+        }
+    }
+
+    /// Returns the time of the next event on the agenda of the simulation.
+    pub fn next_event(&self) -> Option<SystemTime> {
+        self.clock().next_event()
+    }
+
+    /// Jumps the clock to the next event and runs the simulation at its time, returning the events that were due.
+    /// Events at or before the current time run one `STEP` later. None if nothing is scheduled.
+    ///
+    /// # Panics
+    /// Panics if the clock is not frozen, see `ClockMode`.
+    pub fn step(&mut self) -> Option<Vec<Event>> {
+        let clock = self.clock().clone();
+        let next = clock.next_event()?;
+        clock.advance_to(next.max(clock.now() + STEP));
+
+        let events = clock.take_due();
+        self._update();
+        Some(events)
+    }
+
+    /// Steps to the next event if it runs by the given time, see `step`.
+    pub fn step_until(&mut self, time: SystemTime) -> Option<Vec<Event>> {
+        let next = self.next_event()?.max(self.clock().now() + STEP);
+        if next > time {
+            return None;
+        }
+        self.step()
+    }
+
+    /// Steps from event to event up to the given time, then moves the clock to it. Returns the number of steps.
+    ///
+    /// # Panics
+    /// Panics if the clock is not frozen, see `ClockMode`.
+    pub fn run_until(&mut self, time: SystemTime) -> usize {
+        let mut steps = 0;
+        while self.step_until(time).is_some() {
+            steps += 1;
+        }
+
+        self.clock().advance_to(time);
+        steps
+    }
+
+    /// Runs the simulation through the simulated time that passes in `elapsed` time at the given speed, such as the
+    /// time between two frames of the GUI. Returns the number of steps.
+    ///
+    /// # Panics
+    /// Panics if the clock is not frozen, see `ClockMode`.
+    pub fn fast_forward(&mut self, elapsed: Duration, speed: f64) -> usize {
+        let until = self.clock().now() + elapsed.mul_f64(speed.max(0.0));
+        self.run_until(until)
+    }
+
+    /// Returns the entries of the event log selected by the filter, oldest first.
+    ///
+    /// Devices log their decisions as they make them: addresses learned, frames flooded and dropped, spanning tree
+    /// changes and routes installed. The log keeps the last `LOG_LIMIT` entries.
+    pub fn log(&self, filter: &LogFilter) -> Vec<&LogEntry> {
+        self.log
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .collect()
+    }
+
+    pub fn clear_log(&mut self) {
+        self.log.clear();
+    }
+
+    /// Writes the entries of the event log selected by the filter as JSON Lines, one `LogEntry::to_json` object per
+    /// line.
+    pub fn export_log(&self, filter: &LogFilter) -> String {
+        self.log(filter)
+            .into_iter()
+            .map(|entry| entry.to_json().to_line() + "\n")
+            .collect()
+    }
+
+    /// Returns the journey of a packet, if the simulation still keeps it. See `Tracer`.
+    pub fn trace(&self, id: TraceId) -> Option<&Trace> {
+        self.traces.get(&id)
+    }
+
+    /// Returns the journeys of the packets kept by the simulation, oldest first.
+    pub fn traces(&self) -> Vec<&Trace> {
+        self.traces.values().collect()
+    }
+
+    pub fn clear_traces(&mut self) {
+        self.traces.clear();
+    }
+
+    /// Returns the cables a trace crossed in the order it first crossed them, as (Device, Port, Adjacent Device,
+    /// Adjacent Port) from the end the frames were sent from. See `links`.
+    pub fn trace_links(&self, trace: &Trace) -> Vec<(DeviceId, usize, DeviceId, usize)> {
+        let mut links = Vec::new();
+        for hop in trace.hops.iter().filter(|hop| hop.step == TraceStep::Sent) {
+            let Some(port) = hop.port else {
+                continue;
+            };
+
+            let link = self.adj_devices.get(&hop.device).and_then(|adjs| {
+                adjs.iter()
+                    .find(|(p, _, _)| *p == port)
+                    .map(|(_, adj, adj_port)| (hop.device, port, *adj, *adj_port))
+            });
+            if let Some(link) = link {
+                let crossed = |(d, p, a, ap): &(DeviceId, usize, DeviceId, usize)| {
+                    (*d, *p) == (link.0, link.1) || (*a, *ap) == (link.0, link.1)
+                };
+                if !links.iter().any(crossed) {
+                    links.push(link);
+                }
+            }
+        }
+        links
+    }
+
+    /// Starts capturing the frames of every port of every device into a single capture.
+    pub fn start_capture(&mut self) {
+        let capture = Rc::new(RefCell::new(Capture::default()));
+        for (name, port) in self.named_ports() {
+            let interface = capture.borrow_mut().add_interface(&name);
+            port.borrow_mut().tap(&capture, interface);
+        }
+        self.capture = Some(capture);
+    }
+
+    /// Stops the capture of every port, returning the captured frames.
+    pub fn stop_capture(&mut self) -> Option<Capture> {
+        let capture = self.capture.take()?;
+        for (_, port) in self.named_ports() {
+            port.borrow_mut().untap(&capture);
+        }
+
+        // Snapshots share the capture, so its frames are moved out rather than unwrapped. Ports of a snapshot keep
+        // recording into it, under the same interfaces.
+        let mut capture = capture.borrow_mut();
+        let captured = std::mem::take(&mut *capture);
+        for name in captured.interfaces() {
+            capture.add_interface(name);
+        }
+        Some(captured)
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Serializes the topology as a JSON document, to be rebuilt with `load`.
+    ///
+    /// The document is an object with the members:
+    /// * `version` - The format version, `TOPOLOGY_VERSION`. Files of a newer version are rejected.
+    /// * `devices` - Every device, with its `id` (the seed of its MAC addresses), `kind` (`router`, `switch`,
+    ///   `multilayer_switch`, `hub` or `desktop`), `label` and `pos` (`[x, y]`), and by kind:
+    ///     * router: `interfaces`, the enabled ports as `{ port, ip, subnet, rip }`.
+    ///     * switch: `priority`, the STP bridge priority.
+    ///     * multilayer_switch: `priority`, and `svis` as `{ vlan, ip, subnet, rip }`. Routed ports are listed
+    ///       with `port` instead of `vlan`.
+    ///     * desktop: `ip`, `subnet` and `gateway`, which may be null.
+    /// * `links` - Every cable as `{ device, port, peer, peer_port }`, by device id.
+    ///
+    /// Addresses are written in dotted decimal notation. Other state, such as VLANs, learned addresses and
+    /// counters, is not saved.
+    pub fn save(&self) -> String {
+        fn attributes(attributes: &DeviceAttributes, kind: &str) -> Vec<(String, Json)> {
+            vec![
+                ("id".to_string(), attributes.id.as_u64().into()),
+                ("kind".to_string(), kind.into()),
+                ("label".to_string(), attributes.label.as_str().into()),
+                (
+                    "pos".to_string(),
+                    vec![attributes.pos.x as f64, attributes.pos.y as f64].into(),
+                ),
+            ]
+        }
+
+        fn interface(
+            key: &str,
+            value: u64,
+            ip: Ipv4Address,
+            subnet: Ipv4Address,
+            rip: bool,
+        ) -> Json {
+            json_object! {
+                key => value,
+                "ip" => Ipv4Addr::from(ip).to_string(),
+                "subnet" => Ipv4Addr::from(subnet).to_string(),
+                "rip" => rip,
+            }
+        }
+
+        let mut devices = vec![];
+        for c in &self.routers {
+            let mut members = attributes(&c.attributes, "router");
+            let interfaces = c
+                .device
+                .interface_config()
+                .into_iter()
+                .filter(|(_, _, _, enabled, _)| *enabled)
+                .map(|(ip, subnet, port, _, rip)| interface("port", port as u64, ip, subnet, rip))
+                .collect();
+            members.push(("interfaces".to_string(), Json::Array(interfaces)));
+            devices.push(Json::Object(members));
+        }
+
+        for c in &self.switches {
+            let mut members = attributes(&c.attributes, "switch");
+            members.push((
+                "priority".to_string(),
+                (c.device.bridge_priority() as u64).into(),
+            ));
+            devices.push(Json::Object(members));
+        }
+
+        for c in &self.multilayer_switches {
+            let mut members = attributes(&c.attributes, "multilayer_switch");
+            members.push((
+                "priority".to_string(),
+                (c.device.switch().bridge_priority() as u64).into(),
+            ));
+            let svis = c
+                .device
+                .svis()
+                .into_iter()
+                .map(|(vlan, ip, subnet, rip)| match vlan >= ROUTED_VLAN_BASE {
+                    true => interface("port", (vlan - ROUTED_VLAN_BASE) as u64, ip, subnet, rip),
+                    false => interface("vlan", vlan as u64, ip, subnet, rip),
+                })
+                .collect();
+            members.push(("svis".to_string(), Json::Array(svis)));
+            devices.push(Json::Object(members));
+        }
+
+        for c in &self.hubs {
+            devices.push(Json::Object(attributes(&c.attributes, "hub")));
+        }
+
+        for c in &self.desktops {
+            let interface = &c.device.interface;
+            let mut members = attributes(&c.attributes, "desktop");
+            members.extend([
+                (
+                    "ip".to_string(),
+                    Ipv4Addr::from(interface.ip_address).to_string().into(),
+                ),
+                (
+                    "subnet".to_string(),
+                    Ipv4Addr::from(interface.subnet_mask).to_string().into(),
+                ),
+                (
+                    "gateway".to_string(),
+                    interface
+                        .default_gateway
+                        .map(|gateway| Ipv4Addr::from(gateway).to_string())
+                        .into(),
+                ),
+            ]);
+            devices.push(Json::Object(members));
+        }
+
+        // Every cable is in the adjacency list of both its devices
+        let mut links: Vec<(u64, usize, u64, usize)> = self
+            .adj_devices
+            .iter()
+            .flat_map(|(id, adjs)| {
+                adjs.iter()
+                    .map(|(port, adj, adj_port)| (id.as_u64(), *port, adj.as_u64(), *adj_port))
+            })
+            .filter(|(id, port, adj, adj_port)| (id, port) < (adj, adj_port))
+            .collect();
+        links.sort();
+
+        let links = links
+            .into_iter()
+            .map(|(id, port, adj, adj_port)| {
+                json_object! {
+                    "device" => id,
+                    "port" => port as u64,
+                    "peer" => adj,
+                    "peer_port" => adj_port as u64,
+                }
+            })
+            .collect();
+
+        let topology = json_object! {
+            "version" => TOPOLOGY_VERSION,
+            "devices" => Json::Array(devices),
+            "links" => Json::Array(links),
+        };
+        format!("{}\n", topology)
+    }
+
+    /// Rebuilds a topology written by `save`, with its devices, addresses, RIP, STP priorities and cables.
+    pub fn load(text: &str) -> io::Result<DeviceRepository> {
+        let topology = Json::parse(text)?;
+        let version = member(&topology, "version")?
+            .as_u64()
+            .ok_or_else(|| invalid_topology("'version' is not a number"))?;
+        if version > TOPOLOGY_VERSION {
+            return Err(invalid_topology(&format!(
+                "Version {} is newer than the supported version {}",
+                version, TOPOLOGY_VERSION
+            )));
+        }
+
+        let mut dr = DeviceRepository::default();
+        let mut ids: HashMap<u64, DeviceId> = HashMap::new();
+        let mut seeds: Vec<(u64, u64)> = vec![]; // First and last MAC seed of each device
+        let mut rip: Vec<(DeviceId, u64)> = vec![]; // Enabled once cabled, so the first update is sent
+        for device in array_member(&topology, "devices")? {
+            let id = u64_member(device, "id")?;
+            if ids.contains_key(&id) {
+                return Err(invalid_topology(&format!("Duplicate device id {}", id)));
+            }
+
+            let kind = match str_member(device, "kind")? {
+                "router" => DeviceKind::Router,
+                "switch" => DeviceKind::Switch,
+                "multilayer_switch" => DeviceKind::MultilayerSwitch,
+                "hub" => DeviceKind::Hub,
+                "desktop" => DeviceKind::Desktop,
+                kind => return Err(invalid_topology(&format!("Unknown device kind '{}'", kind))),
+            };
+
+            // Devices seed their MAC addresses from their id, so their ranges may not overlap
+            let last = id
+                .checked_add(kind.mac_span())
+                .filter(|last| *last <= MAX_MAC_SEED)
+                .ok_or_else(|| invalid_topology(&format!("Device id {} is too large", id)))?;
+            if let Some(other) = seeds
+                .iter()
+                .find(|(first, other_last)| id <= *other_last && *first <= last)
+            {
+                return Err(invalid_topology(&format!(
+                    "Device id {} overlaps the addresses of device {}",
+                    id, other.0
+                )));
+            }
+            seeds.push((id, last));
+
+            let label = str_member(device, "label")?;
+            let pos = match array_member(device, "pos")? {
+                [x, y] => x.as_f64().zip(y.as_f64()),
+                _ => None,
+            }
+            .ok_or_else(|| invalid_topology("'pos' is not an [x, y] pair"))?;
+            let pos = Position {
+                x: pos.0 as f32,
+                y: pos.1 as f32,
+            };
+
+            // Keep numbering new devices after the loaded ones
+            let number = label
+                .rsplit(' ')
+                .next()
+                .and_then(|n| n.parse::<i32>().ok())
+                .unwrap_or(0);
+            let id = dr._add(kind, pos, id, number);
+            ids.insert(id.as_u64(), id);
+            let i = dr.lookup(id);
+
+            match id {
+                DeviceId::Router(_) => {
+                    let c = &mut dr.routers[i];
+                    c.attributes.label = label.to_string();
+                    for interface in array_member(device, "interfaces")? {
+                        let port = u64_member(interface, "port")? as usize;
+                        if port >= c.attributes.ports_len {
+                            return Err(invalid_topology(&format!("Port {} does not exist", port)));
+                        }
+
+                        c.device.enable_interface(
+                            port,
+                            ipv4_member(interface, "ip")?,
+                            ipv4_member(interface, "subnet")?,
+                        );
+                        if bool_member(interface, "rip")? {
+                            rip.push((id, port as u64));
+                        }
+                    }
+                }
+                DeviceId::Switch(_) => {
+                    let c = &mut dr.switches[i];
+                    c.attributes.label = label.to_string();
+                    c.device
+                        .set_bridge_priority(u16_member(device, "priority")?);
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    let c = &mut dr.multilayer_switches[i];
+                    c.attributes.label = label.to_string();
+                    c.device
+                        .switch_mut()
+                        .set_bridge_priority(u16_member(device, "priority")?);
+
+                    for svi in array_member(device, "svis")? {
+                        let (ip, subnet) = (ipv4_member(svi, "ip")?, ipv4_member(svi, "subnet")?);
+                        let vlan = match svi.get("port") {
+                            Some(_) => {
+                                let port = u64_member(svi, "port")? as usize;
+                                c.device
+                                    .set_routed_port(port, ip, subnet)
+                                    .map_err(invalid_topology)?;
+                                ROUTED_VLAN_BASE + port as u16
+                            }
+                            None => {
+                                let vlan = u16_member(svi, "vlan")?;
+                                c.device
+                                    .set_svi(vlan, ip, subnet)
+                                    .map_err(invalid_topology)?;
+                                vlan
+                            }
+                        };
+
+                        if bool_member(svi, "rip")? {
+                            rip.push((id, vlan as u64));
+                        }
+                    }
+                }
+                DeviceId::Hub(_) => dr.hubs[i].attributes.label = label.to_string(),
+                DeviceId::Desktop(_) => {
+                    let c = &mut dr.desktops[i];
+                    c.attributes.label = label.to_string();
+                    c.device.interface.ip_address = ipv4_member(device, "ip")?;
+                    c.device.interface.subnet_mask = ipv4_member(device, "subnet")?;
+                    c.device.interface.default_gateway = match member(device, "gateway")? {
+                        Json::Null => None,
+                        _ => Some(ipv4_member(device, "gateway")?),
+                    };
+                }
+            }
+        }
+
+        let mut cabled = HashSet::new();
+        for link in array_member(&topology, "links")? {
+            let mut ends = vec![];
+            for (device, port) in [("device", "port"), ("peer", "peer_port")] {
+                let id = u64_member(link, device)?;
+                let id = *ids
+                    .get(&id)
+                    .ok_or_else(|| invalid_topology(&format!("Link to unknown device {}", id)))?;
+
+                let port = u64_member(link, port)? as usize;
+                let ports_len = dr.get(id).unwrap().ports_len;
+                if port >= ports_len {
+                    return Err(invalid_topology(&format!("Port {} does not exist", port)));
+                }
+                ends.push((id, port));
+            }
+
+            for &(id, port) in &ends {
+                if !cabled.insert((id.as_u64(), port)) {
+                    return Err(invalid_topology(&format!(
+                        "Port {} of device {} is cabled twice",
+                        port,
+                        id.as_u64()
+                    )));
+                }
+            }
+
+            dr.connect(ends[0].0, ends[0].1, ends[1].0, ends[1].1);
+        }
+
+        for (id, port) in rip {
+            let i = dr.lookup(id);
+            let result = match id {
+                DeviceId::Router(_) => dr.routers[i].device.enable_rip(port as usize),
+                _ => dr.multilayer_switches[i].device.enable_rip(port as u16),
+            };
+            result.map_err(invalid_topology)?;
+        }
+
+        Ok(dr)
+    }
+
+    /// Returns every port of every device, named by its device's label.
+    fn named_ports(&self) -> Vec<(String, Rc<RefCell<EthernetPort>>)> {
+        fn named<T>(
+            components: &[Components<T>],
+            ports: impl Fn(&T) -> Vec<Rc<RefCell<EthernetPort>>>,
+        ) -> Vec<(String, Rc<RefCell<EthernetPort>>)> {
+            components
+                .iter()
+                .flat_map(|c| {
+                    ports(&c.device)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, port)| (format!("{} Port {}", c.attributes.label, i), port))
+                })
+                .collect()
+        }
+
+        let mut ports = named(&self.routers, Router::ports);
+        ports.extend(named(&self.switches, Switch::ports));
+        ports.extend(named(&self.multilayer_switches, MultilayerSwitch::ports));
+        ports.extend(named(&self.hubs, Hub::ports));
+        ports.extend(self.desktops.iter().map(|c| {
+            (
+                c.attributes.label.clone(),
+                c.device.interface.ethernet.port(),
+            )
+        }));
+        ports
+    }
+
+    /// Returns the packets sniffed on every port that sent or received any, with the device on the other end of the
+    /// port's link.
+    pub fn sniff(&self) -> Vec<(DeviceId, (Sniffed, Sniffed))> {
+        fn sniffed<T>(
+            components: &[Components<T>],
+            ports: impl Fn(&T) -> Vec<Rc<RefCell<EthernetPort>>>,
+            adj_devices: &HashMap<DeviceId, Vec<(usize, DeviceId, usize)>>,
+        ) -> Vec<(DeviceId, (Sniffed, Sniffed))> {
+            let mut values = Vec::new();
+            for component in components {
+                for (i, port) in ports(&component.device).iter().enumerate() {
+                    let (incoming, outgoing) = port.borrow().sniff_traced();
+                    if incoming.is_empty() && outgoing.is_empty() {
+                        continue;
+                    }
+
+                    let adj = adj_devices.get(&component.attributes.id).and_then(|adjs| {
+                        adjs.iter()
+                            .find(|(p, _, _)| *p == i)
+                            .map(|(_, adj, _)| *adj)
+                    });
+                    values.push((
+                        component.attributes.id,
+                        (
+                            (adj, utils::sniffed(&incoming)),
+                            (adj, utils::sniffed(&outgoing)),
+                        ),
+                    ));
+                }
+            }
+            values
+        }
+
+        let mut values = sniffed(&self.routers, Router::ports, &self.adj_devices);
+        values.extend(sniffed(&self.switches, Switch::ports, &self.adj_devices));
+        values.extend(sniffed(
+            &self.multilayer_switches,
+            MultilayerSwitch::ports,
+            &self.adj_devices,
+        ));
+        values.extend(sniffed(&self.hubs, Hub::ports, &self.adj_devices));
+        values.extend(sniffed(
+            &self.desktops,
+            |d: &Desktop| vec![d.interface.ethernet.port()],
+            &self.adj_devices,
+        ));
+        values
+    }
+
+    /// Returns the EthernetPort of a device's port.
+    fn ethernet_port(&self, id: DeviceId, i: usize, port: usize) -> Rc<RefCell<EthernetPort>> {
+        match id {
+            DeviceId::Desktop(_) => self.desktops[i].device.interface.ethernet.port(),
+            DeviceId::Switch(_) => self.switches[i].device.ports()[port].clone(),
+            DeviceId::Router(_) => self.routers[i].device.ports()[port].clone(),
+            DeviceId::MultilayerSwitch(_) => {
+                self.multilayer_switches[i].device.ports()[port].clone()
+            }
+            DeviceId::Hub(_) => self.hubs[i].device.ports()[port].clone(),
+        }
+    }
+
+    fn lookup(&self, id: DeviceId) -> usize {
+        *self.lookup.get(&id.as_u64()).expect("Bad lookup")
+    }
+
+    fn connect(&mut self, d1: DeviceId, p1: usize, d2: DeviceId, p2: usize) {
+        if d1 == d2 {
+            return;
+        }
+
+        self.disconnect(d1, p1);
+        self.disconnect(d2, p2);
+
+        let (d1_i, d2_i) = (self.lookup(d1), self.lookup(d2));
+
+        fn connect_desktop(
+            dr: &mut DeviceRepository,
+            d_i: usize,
+            other_port: usize,
+            other_id: DeviceId,
+            other_i: usize,
+        ) {
+            let component = &mut dr.desktops[d_i];
+            match other_id {
+                DeviceId::Desktop(_) => {
+                    EthernetPort::connect(
+                        &component.device.interface.ethernet.port(),
+                        &dr.desktops[other_i].device.interface.ethernet.port(),
+                    );
+                }
+                DeviceId::Switch(_) => {
+                    dr.switches[other_i]
+                        .device
+                        .connect(other_port, &mut component.device.interface.ethernet);
+                }
+                DeviceId::Router(_) => {
+                    dr.routers[other_i]
+                        .device
+                        .connect(other_port, &mut component.device.interface);
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    dr.multilayer_switches[other_i]
+                        .device
+                        .connect(other_port, &mut component.device.interface.ethernet);
+                }
+                DeviceId::Hub(_) => {
+                    dr.hubs[other_i]
+                        .device
+                        .connect(other_port, &component.device.interface.ethernet.port());
+                }
+            }
+        }
+
+        fn connect_switch(
+            dr: &mut DeviceRepository,
+            d_i: usize,
+            port: usize,
+            other_port: usize,
+            other_id: DeviceId,
+            other_i: usize,
+        ) {
+            let component = &mut dr.switches[d_i];
+            match other_id {
+                DeviceId::Desktop(_) => {
+                    component
+                        .device
+                        .connect(port, &mut dr.desktops[other_i].device.interface.ethernet);
+                }
+                DeviceId::Switch(_) => {
+                    // have to call connect on the switch device so the switch hello bpdu is sent.
+                    // compiler gymnastics ensue...
+                    let (component, other_component) = if d_i < other_i {
+                        let (left, right) = dr.switches.split_at_mut(other_i);
+                        (&mut left[d_i], &mut right[0])
+                    } else {
+                        let (left, right) = dr.switches.split_at_mut(d_i);
+                        (&mut right[0], &mut left[other_i])
+                    };
+
+                    component
+                        .device
+                        .connect_switch(port, &mut other_component.device, other_port);
+                }
+                DeviceId::Router(_) => {
+                    EthernetPort::connect(
+                        &component.device.ports()[port],
+                        &dr.routers[other_i].device.ports()[other_port],
+                    );
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    component.device.connect_switch(
+                        port,
+                        dr.multilayer_switches[other_i].device.switch_mut(),
+                        other_port,
+                    );
+                }
+                DeviceId::Hub(_) => {
+                    dr.hubs[other_i]
+                        .device
+                        .connect(other_port, &component.device.ports()[port]);
+                }
+            }
+        }
+
+        fn connect_router(
+            dr: &mut DeviceRepository,
+            d_i: usize,
+            port: usize,
+            other_port: usize,
+            other_id: DeviceId,
+            other_i: usize,
+        ) {
+            let component = &mut dr.routers[d_i];
+            match other_id {
+                DeviceId::Desktop(_) => {
+                    component
+                        .device
+                        .connect(port, &mut dr.desktops[other_i].device.interface);
+                }
+                DeviceId::Switch(_) => {
+                    EthernetPort::connect(
+                        &component.device.ports()[port],
+                        &dr.switches[other_i].device.ports()[other_port],
+                    );
+                }
+                DeviceId::Router(_) => {
+                    EthernetPort::connect(
+                        &component.device.ports()[port],
+                        &dr.routers[other_i].device.ports()[other_port],
+                    );
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    EthernetPort::connect(
+                        &component.device.ports()[port],
+                        &dr.multilayer_switches[other_i].device.ports()[other_port],
+                    );
+                }
+                DeviceId::Hub(_) => {
+                    dr.hubs[other_i]
+                        .device
+                        .connect(other_port, &component.device.ports()[port]);
+                }
+            }
+        }
+
+        fn connect_multilayer_switch(
+            dr: &mut DeviceRepository,
+            d_i: usize,
+            port: usize,
+            other_port: usize,
+            other_id: DeviceId,
+            other_i: usize,
+        ) {
+            let component = &mut dr.multilayer_switches[d_i];
+            match other_id {
+                DeviceId::Desktop(_) => {
+                    component
+                        .device
+                        .connect(port, &mut dr.desktops[other_i].device.interface.ethernet);
+                }
+                DeviceId::Switch(_) => {
+                    component.device.switch_mut().connect_switch(
+                        port,
+                        &mut dr.switches[other_i].device,
+                        other_port,
+                    );
+                }
+                DeviceId::Router(_) => {
+                    EthernetPort::connect(
+                        &component.device.ports()[port],
+                        &dr.routers[other_i].device.ports()[other_port],
+                    );
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    // Same gymnastics as connecting two switches
+                    let (component, other_component) = if d_i < other_i {
+                        let (left, right) = dr.multilayer_switches.split_at_mut(other_i);
+                        (&mut left[d_i], &mut right[0])
+                    } else {
+                        let (left, right) = dr.multilayer_switches.split_at_mut(d_i);
+                        (&mut right[0], &mut left[other_i])
+                    };
+
+                    component.device.switch_mut().connect_switch(
+                        port,
+                        other_component.device.switch_mut(),
+                        other_port,
+                    );
+                }
+                DeviceId::Hub(_) => {
+                    dr.hubs[other_i]
+                        .device
+                        .connect(other_port, &component.device.ports()[port]);
+                }
+            }
+        }
+
+        fn connect_hub(
+            dr: &mut DeviceRepository,
+            d_i: usize,
+            port: usize,
+            other_port: usize,
+            other_id: DeviceId,
+            other_i: usize,
+        ) {
+            let other = dr.ethernet_port(other_id, other_i, other_port);
+            dr.hubs[d_i].device.connect(port, &other);
+        }
+
+        match d1 {
+            DeviceId::Desktop(_) => {
+                connect_desktop(self, d1_i, p2, d2, d2_i);
+            }
+            DeviceId::Switch(_) => {
+                connect_switch(self, d1_i, p1, p2, d2, d2_i);
+            }
+            DeviceId::Router(_) => {
+                connect_router(self, d1_i, p1, p2, d2, d2_i);
+            }
+            DeviceId::MultilayerSwitch(_) => {
+                connect_multilayer_switch(self, d1_i, p1, p2, d2, d2_i);
+            }
+            DeviceId::Hub(_) => {
+                connect_hub(self, d1_i, p1, p2, d2, d2_i);
+            }
+        }
+
+        self.adj_devices.entry(d1).or_default().push((p1, d2, p2));
+        self.adj_devices.entry(d2).or_default().push((p2, d1, p1));
+    }
+
+    fn disconnect(&mut self, id: DeviceId, port: usize) {
+        fn _dc(dr: &mut DeviceRepository, id: DeviceId, i: usize, port: usize) {
+            match id {
+                DeviceId::Desktop(_) => {
+                    dr.desktops[i].device.interface.disconnect();
+                }
+                DeviceId::Switch(_) => {
+                    dr.switches[i].device.disconnect(port);
+                }
+                DeviceId::Router(_) => {
+                    dr.routers[i].device.disconnect(port);
+                }
+                DeviceId::MultilayerSwitch(_) => {
+                    dr.multilayer_switches[i].device.disconnect(port);
+                }
+                DeviceId::Hub(_) => {
+                    dr.hubs[i].device.disconnect(port);
+                }
+            }
+        }
+
+        let d1_id = id;
+        let d1_adjacency = {
+            let adj_list = self.adj_devices.get(&d1_id);
+            adj_list.and_then(|adj| adj.iter().find(|(p, _, _)| *p == port).cloned())
+        };
+
+        if let Some((d1_port, d2_id, d2_port)) = d1_adjacency {
+            if let Some(adj) = self.adj_devices.get_mut(&d1_id) {
+                adj.retain(|(p, _, _)| *p != d1_port);
+            }
+            if let Some(adj) = self.adj_devices.get_mut(&d2_id) {
+                adj.retain(|(p, _, _)| *p != d2_port);
+            }
+
+            let (d1_i, d2_i) = (self.lookup(d1_id), self.lookup(d2_id));
+
+            _dc(self, d1_id, d1_i, d1_port);
+            _dc(self, d2_id, d2_i, d2_port);
+        }
+    }
+}
+
+fn invalid_topology(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid topology: {}", message),
+    )
+}
+
+/// Returns a member of a topology object, or an error naming the missing member.
+fn member<'a>(value: &'a Json, key: &str) -> io::Result<&'a Json> {
+    value
+        .get(key)
+        .ok_or_else(|| invalid_topology(&format!("Missing '{}'", key)))
+}
+
+fn array_member<'a>(value: &'a Json, key: &str) -> io::Result<&'a [Json]> {
+    member(value, key)?
+        .as_array()
+        .ok_or_else(|| invalid_topology(&format!("'{}' is not an array", key)))
+}
+
+fn str_member<'a>(value: &'a Json, key: &str) -> io::Result<&'a str> {
+    member(value, key)?
+        .as_str()
+        .ok_or_else(|| invalid_topology(&format!("'{}' is not a string", key)))
+}
+
+fn bool_member(value: &Json, key: &str) -> io::Result<bool> {
+    member(value, key)?
+        .as_bool()
+        .ok_or_else(|| invalid_topology(&format!("'{}' is not a boolean", key)))
+}
+
+fn u64_member(value: &Json, key: &str) -> io::Result<u64> {
+    member(value, key)?
+        .as_u64()
+        .ok_or_else(|| invalid_topology(&format!("'{}' is not a positive integer", key)))
+}
+
+fn u16_member(value: &Json, key: &str) -> io::Result<u16> {
+    u16::try_from(u64_member(value, key)?)
+        .map_err(|_| invalid_topology(&format!("'{}' is out of range", key)))
+}
+
+fn ipv4_member(value: &Json, key: &str) -> io::Result<Ipv4Address> {
+    str_member(value, key)?
+        .parse::<Ipv4Addr>()
+        .map(|ip| ip.octets())
+        .map_err(|_| invalid_topology(&format!("'{}' is not an IPv4 address", key)))
+}
+
+type CommandFunction<T> = fn(&mut Terminal<T>, &mut T, &[&str]) -> ();
+#[derive(Clone)]
+struct Terminal<T> {
+    out_buf: VecDeque<String>,
+    dict: HashMap<String, (CommandFunction<T>, String)>,
+    awaiting_command: Option<String>,
+    awaiting_since: Option<SystemTime>, // When the awaited command was issued
+    timer: TickTimer<String>,
+    clock: Clock,
+    capture: Option<Rc<RefCell<Capture>>>, // Capture started by the `capture` command
+    replay: Option<(Replay, Rc<RefCell<EthernetPort>>)>, // Replay started by the `replay` command, into the port
+}
+
+impl<T> Terminal<T> {
+    fn new() -> Self {
+        let mut dict = HashMap::new();
+
+        // Insert the help command into the dictionary
+        dict.insert(
+            "help".to_string(),
+            (
+                Self::help as CommandFunction<T>,
+                "Prints this help message".to_string(),
+            ),
+        );
+
+        dict.insert(
+            "clear".to_string(),
+            (
+                Self::clear as CommandFunction<T>,
+                "Clear the terminal screen".to_string(),
+            ),
+        );
+
+        Self {
+            out_buf: VecDeque::new(),
+            dict,
+            awaiting_command: None,
+            awaiting_since: None,
+            timer: TickTimer::default(),
+            clock: Clock::default(),
+            capture: None,
+            replay: None,
+        }
+    }
+
+    /// Sets the clock timing awaited commands and replays.
+    fn set_clock(&mut self, clock: &Clock) {
+        self.timer.set_clock(clock);
+        self.clock = clock.clone();
+        if self.replay.is_some() {
+            self.clock.wake("Replay");
+        }
+    }
+
+    /// Moves the running replay onto the copy of its port, see `PortMap`.
+    fn relink(&mut self, ports: &mut PortMap) {
+        if let Some((_, port)) = &mut self.replay {
+            *port = ports.copy(port);
+        }
+    }
+
+    fn help(term: &mut Terminal<T>, _device: &mut T, _args: &[&str]) {
+        for (cmd, (_, manual)) in term.dict.iter() {
+            term.out_buf.push_back(format!("{}: {}", cmd, manual));
+        }
+    }
+
+    fn clear(term: &mut Terminal<T>, _device: &mut T, _args: &[&str]) {
+        term.clear_screen();
+    }
+
+    fn clear_screen(&mut self) {
+        // append a bunch of newlines to clear the terminal
+        for _ in 0..100 {
+            self.out_buf.push_back("".to_string());
+        }
+    }
+
+    /// Clears the terminal screen, or with `counters` the traffic counters of every port.
+    fn reset_counters(&mut self, ports: &[Rc<RefCell<EthernetPort>>], args: &[&str]) {
+        match args {
+            [] => self.clear_screen(),
+            ["counters"] => {
+                for port in ports {
+                    port.borrow_mut().clear_counters();
+                }
+                self.out_buf.push_back("Counters cleared".to_string());
+            }
+            _ => self
+                .out_buf
+                .push_back("Usage: clear [counters]".to_string()),
+        }
+    }
+
+    /// Prints the status and traffic counters of a port, or of every connected port without `<port>`.
+    fn show_interfaces(&mut self, ports: &[Rc<RefCell<EthernetPort>>], args: &[&str]) {
+        const USAGE: &str = "Usage: show interfaces [<port>]";
+        if args.first() != Some(&"interfaces") || args.len() > 2 {
+            self.out_buf.push_back(USAGE.to_string());
+            return;
+        }
+
+        if args.len() == 2 {
+            if let Some(port) = self.port_arg(&args[1..], ports.len(), USAGE) {
+                self.print_interface(&format!("Port {}", port), &ports[port].borrow());
+            }
+            return;
+        }
+
+        for (i, port) in ports.iter().enumerate() {
+            let port = port.borrow();
+            if port.is_connected() {
+                self.print_interface(&format!("Port {}", i), &port);
+            }
+        }
+    }
+
+    fn print_interface(&mut self, name: &str, port: &EthernetPort) {
+        let status = if port.is_shutdown() {
+            "administratively down"
+        } else if port.has_carrier() {
+            "up"
+        } else {
+            "down"
+        };
+        let c = port.counters();
+
+        self.out_buf.push_back(format!("{} is {}", name, status));
+        self.out_buf.push_back(format!(
+            "  {} frames input, {} bytes, {} broadcasts, {} multicasts",
+            c.frames_in, c.bytes_in, c.broadcasts, c.multicasts
+        ));
+        self.out_buf.push_back(format!(
+            "  {} runts, {} giants, {} FCS errors",
+            c.runts, c.giants, c.fcs_errors
+        ));
+        self.out_buf.push_back(format!(
+            "  {} frames output, {} bytes, {} drops",
+            c.frames_out, c.bytes_out, c.drops
+        ));
+    }
+
+    /// Prints the properties of the cable on a port, or sets them from `<latency ms> <bandwidth kbps|off> <queue|off>`.
+    fn configure_link(&mut self, port: &Rc<RefCell<EthernetPort>>, args: &[&str], usage: &str) {
+        if args.is_empty() {
+            let port = port.borrow();
+            let link = port.link();
+            self.out_buf.push_back(format!(
+                "Latency: {}ms, Bandwidth: {}, Queue: {}, Queue drops: {}",
+                link.latency.as_millis(),
+                link.bandwidth
+                    .map_or("unlimited".to_string(), |bps| format!("{}kbps", bps / 1000)),
+                link.queue_limit
+                    .map_or("unbounded".to_string(), |limit| limit.to_string()),
+                port.queue_drops()
+            ));
+            return;
+        }
+
+        if args.len() != 3 {
+            self.out_buf.push_back(usage.to_string());
+            return;
+        }
+
+        let latency = match args[0].parse::<u64>() {
+            Ok(ms) => Duration::from_millis(ms),
+            Err(_) => {
+                self.out_buf
+                    .push_back(format!("Error: '{}' is not a valid latency", args[0]));
+                return;
+            }
+        };
+
+        let bandwidth = match args[1] {
+            "off" => None,
+            arg => match arg.parse::<u64>() {
+                Ok(kbps) if kbps > 0 && kbps.checked_mul(1000).is_some() => Some(kbps * 1000),
+                _ => {
+                    self.out_buf
+                        .push_back(format!("Error: '{}' is not a valid bandwidth", arg));
+                    return;
+                }
+            },
+        };
+
+        let queue_limit = match args[2] {
+            "off" => None,
+            arg => match arg.parse::<usize>() {
+                Ok(limit) => Some(limit),
+                Err(_) => {
+                    self.out_buf
+                        .push_back(format!("Error: '{}' is not a valid queue limit", arg));
+                    return;
+                }
+            },
+        };
+
+        EthernetPort::set_link(
+            port,
+            LinkProperties {
+                latency,
+                bandwidth,
+                queue_limit,
+            },
+        );
+        self.out_buf.push_back("Link properties set".to_string());
+    }
+
+    /// Prints the faults of the cable on a port, or sets them from `<loss %> <corruption %> <duplication %> <reordering %>`.
+    fn configure_impairments(
+        &mut self,
+        port: &Rc<RefCell<EthernetPort>>,
+        args: &[&str],
+        usage: &str,
+    ) {
+        if args.is_empty() {
+            let impairments = port.borrow().impairments();
+            self.out_buf.push_back(format!(
+                "Loss: {}%, Corruption: {}%, Duplication: {}%, Reordering: {}%",
+                impairments.loss,
+                impairments.corruption,
+                impairments.duplication,
+                impairments.reordering
+            ));
+            return;
+        }
+
+        if args.len() != 4 {
+            self.out_buf.push_back(usage.to_string());
+            return;
+        }
+
+        let mut percents = [0.0; 4];
+        for (percent, arg) in percents.iter_mut().zip(args) {
+            match arg.parse::<f64>() {
+                Ok(p) if (0.0..=100.0).contains(&p) => *percent = p,
+                _ => {
+                    self.out_buf
+                        .push_back(format!("Error: '{}' is not a valid percentage", arg));
+                    return;
+                }
+            }
+        }
+
+        let [loss, corruption, duplication, reordering] = percents;
+        EthernetPort::set_impairments(
+            port,
+            Impairments {
+                loss,
+                corruption,
+                duplication,
+                reordering,
+            },
+        );
+        self.out_buf.push_back("Impairments set".to_string());
+    }
+
+    /// Starts capturing the frames of the ports, or of one port with `start <port>`, or stops the capture and writes
+    /// it to a pcapng file with `stop [<file>]`.
+    fn configure_capture(
+        &mut self,
+        ports: &[Rc<RefCell<EthernetPort>>],
+        args: &[&str],
+        usage: &str,
+    ) {
+        match args {
+            ["start"] | ["start", _] if self.capture.is_some() => {
+                self.out_buf
+                    .push_back("Error: A capture is already running".to_string());
+            }
+            ["start"] => {
+                let capture = Rc::new(RefCell::new(Capture::default()));
+                for (i, port) in ports.iter().enumerate() {
+                    let interface = capture.borrow_mut().add_interface(&format!("Port {}", i));
+                    port.borrow_mut().tap(&capture, interface);
+                }
+                self.capture = Some(capture);
+                self.out_buf.push_back("Capture started".to_string());
+            }
+            ["start", _] => {
+                if let Some(port) = self.port_arg(&args[1..], ports.len(), usage) {
+                    let capture = Rc::new(RefCell::new(Capture::default()));
+                    let interface = capture
+                        .borrow_mut()
+                        .add_interface(&format!("Port {}", port));
+                    ports[port].borrow_mut().tap(&capture, interface);
+                    self.capture = Some(capture);
+                    self.out_buf
+                        .push_back(format!("Capture started on port {}", port));
+                }
+            }
+            ["stop"] | ["stop", _] => {
+                let Some(capture) = self.capture.take() else {
+                    self.out_buf
+                        .push_back("Error: No capture is running".to_string());
+                    return;
+                };
+
+                for port in ports {
+                    port.borrow_mut().untap(&capture);
+                }
+
+                let path = args.get(1).copied().unwrap_or("capture.pcapng");
+                let capture = capture.borrow();
+                match capture.write(path) {
+                    Ok(()) => self.out_buf.push_back(format!(
+                        "{} frames written to {}",
+                        capture.frames().len(),
+                        path
+                    )),
+                    Err(e) => self
+                        .out_buf
+                        .push_back(format!("Error: Could not write {}: {}", path, e)),
+                }
+            }
+            _ => self.out_buf.push_back(usage.to_string()),
+        }
+    }
+
+    /// Starts replaying a pcap or pcapng file into a port, or stops the replay with `stop`.
+    /// * `ports` - The ports of the device. A device with a single port takes no `<port>` argument.
+    /// * `args` - `[<port>] <file> [<scale>]` or `stop`, the scale multiplying the recorded time between frames.
+    fn configure_replay(
+        &mut self,
+        ports: &[Rc<RefCell<EthernetPort>>],
+        args: &[&str],
+        usage: &str,
+    ) {
+        if args == ["stop"] {
+            match self.replay.take() {
+                Some((replay, _)) => self.out_buf.push_back(format!(
+                    "Replay stopped, {} frames were not injected",
+                    replay.remaining()
+                )),
+                None => self
+                    .out_buf
+                    .push_back("Error: No replay is running".to_string()),
+            }
+            return;
+        }
+
+        let (port, args) = match ports.len() {
+            1 => (0, args),
+            _ => match self.port_arg(args, ports.len(), usage) {
+                Some(port) => (port, &args[1..]),
+                None => return,
+            },
+        };
+
+        let (path, scale) = match args {
+            [path] => (*path, 1.0),
+            [path, scale] => match scale.parse::<f64>() {
+                Ok(scale) if scale >= 0.0 && scale.is_finite() => (*path, scale),
+                _ => {
+                    self.out_buf
+                        .push_back("Error: Invalid time scale".to_string());
+                    return;
+                }
+            },
+            _ => {
+                self.out_buf.push_back(usage.to_string());
+                return;
+            }
+        };
+
+        if self.replay.is_some() {
+            self.out_buf
+                .push_back("Error: A replay is already running".to_string());
+            return;
+        }
+
+        match Capture::read(path) {
+            Ok(capture) => {
+                self.out_buf.push_back(format!(
+                    "Replaying {} frames from {}",
+                    capture.frames().len(),
+                    path
+                ));
+                self.replay = Some((Replay::new(&capture, scale), ports[port].clone()));
+            }
+            Err(e) => self
+                .out_buf
+                .push_back(format!("Error: Could not read {}: {}", path, e)),
+        }
+    }
+
+    /// Injects the frames of the running replay that are due, before the device is ticked.
+    fn replay_tick(&mut self) {
+        let Some((replay, port)) = &mut self.replay else {
+            return;
+        };
+
+        let now = self.clock.now();
+        replay.inject(&mut port.borrow_mut(), now);
+        if replay.is_finished() {
+            self.replay = None;
+            self.out_buf.push_back("Replay finished".to_string());
+        } else if let Some(due) = replay.next_due() {
+            self.clock.schedule(due, "Replay");
+        }
+    }
+
+    /// Parses the `shutdown <port>` arguments of the `no` command, printing an error on failure.
+    fn no_shutdown_arg(&mut self, args: &[&str], ports_len: usize) -> Option<usize> {
+        const USAGE: &str = "Usage: no shutdown <port>";
+        if args.first() != Some(&"shutdown") {
+            self.out_buf.push_back(USAGE.to_string());
+            return None;
+        }
+
+        self.port_arg(&args[1..], ports_len, USAGE)
+    }
+
+    /// Parses the leading `<port>` argument of a command, printing an error on failure.
+    fn port_arg(&mut self, args: &[&str], ports_len: usize, usage: &str) -> Option<usize> {
+        let Some(arg) = args.first() else {
+            self.out_buf.push_back(usage.to_string());
+            return None;
+        };
+
+        match arg.parse::<usize>() {
+            Ok(port) if port < ports_len => Some(port),
+            _ => {
+                self.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", arg));
+                None
+            }
+        }
+    }
+
+    fn execute(&mut self, device: &mut T, input: &str) {
+        if input.is_empty() {
+            return;
+        }
+
+        let mut args = input.split_whitespace();
+        let cmd = args.next().unwrap_or_default();
+
+        if let Some((func, _)) = self.dict.get(cmd) {
+            func(self, device, &args.collect::<Vec<&str>>());
+        } else {
+            self.out_buf
+                .push_back(format!("Error: '{}' is not a valid command", cmd));
+        }
+    }
+}
+
+impl Terminal<Router> {
+    fn new_router() -> Self {
+        let mut term = Self::new();
+        term.dict.insert(
+            "enable".to_string(),
+            (
+                Self::enable as CommandFunction<Router>,
+                "Enable a port. Usage: enable <port> <ip> <subnet>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "rip".to_string(),
+            (
+                Self::rip as CommandFunction<Router>,
+                "Enable or disable RIP. Usage: rip <port>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "igmp".to_string(),
+            (
+                Self::igmp as CommandFunction<Router>,
+                "Enable IGMP on a port, or print the multicast groups of each port. Usage: igmp [<port>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "routes".to_string(),
+            (
+                Self::routes as CommandFunction<Router>,
+                "Print the routing table".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "ipconfig".to_string(),
+            (
+                Self::ifconfig as CommandFunction<Router>,
+                "Print the current IP configuration of the router".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "link".to_string(),
+            (
+                Self::link as CommandFunction<Router>,
+                "Show or set the latency, bandwidth and queue limit of a port's cable. Usage: link <port> [<latency ms> <bandwidth kbps|off> <queue|off>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "impair".to_string(),
+            (
+                Self::impair as CommandFunction<Router>,
+                "Show or set the loss, corruption, duplication and reordering percentages of a port's cable. Usage: impair <port> [<loss %> <corruption %> <duplication %> <reordering %>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "shutdown".to_string(),
+            (
+                Self::shutdown as CommandFunction<Router>,
+                "Administratively shut down a port, keeping its cable. Usage: shutdown <port>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "no".to_string(),
+            (
+                Self::no as CommandFunction<Router>,
+                "Bring a shut down port back up. Usage: no shutdown <port>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "show".to_string(),
+            (
+                Self::show as CommandFunction<Router>,
+                "Print the status and traffic counters of the connected ports, or of one port. Usage: show interfaces [<port>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "clear".to_string(),
+            (
+                Self::clear_counters as CommandFunction<Router>,
+                "Clear the terminal screen, or the traffic counters of every port. Usage: clear [counters]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "capture".to_string(),
+            (
+                Self::capture as CommandFunction<Router>,
+                "Capture the frames of every port, or of one port, and write them to a pcapng file. Usage: capture start [<port>] | capture stop [<file>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "replay".to_string(),
+            (
+                Self::replay as CommandFunction<Router>,
+                "Replay the frames of a pcap or pcapng file into a port, as if received from its cable. The scale multiplies the time between frames. Usage: replay <port> <file> [<scale>] | replay stop"
+                    .to_string(),
+            ),
+        );
+
+        term
+    }
+
+    fn link(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        const USAGE: &str = "Usage: link <port> [<latency ms> <bandwidth kbps|off> <queue|off>]";
+        if let Some(port) = term.port_arg(args, 8, USAGE) {
+            term.configure_link(&router.ports()[port], &args[1..], USAGE);
+        }
+    }
+
+    fn impair(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        const USAGE: &str =
+            "Usage: impair <port> [<loss %> <corruption %> <duplication %> <reordering %>]";
+        if let Some(port) = term.port_arg(args, 8, USAGE) {
+            term.configure_impairments(&router.ports()[port], &args[1..], USAGE);
+        }
+    }
+
+    fn shutdown(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        if let Some(port) = term.port_arg(args, 8, "Usage: shutdown <port>") {
+            router.set_shutdown(port, true);
+            term.out_buf
+                .push_back(format!("Port {} is administratively down", port));
+        }
+    }
+
+    fn no(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        if let Some(port) = term.no_shutdown_arg(args, 8) {
+            router.set_shutdown(port, false);
+            term.out_buf.push_back(format!("Port {} is up", port));
+        }
+    }
+
+    fn show(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        term.show_interfaces(&router.ports(), args);
+    }
+
+    fn clear_counters(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        term.reset_counters(&router.ports(), args);
+    }
+
+    fn capture(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        term.configure_capture(
+            &router.ports(),
+            args,
+            "Usage: capture start [<port>] | capture stop [<file>]",
+        );
+    }
+
+    fn replay(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        term.configure_replay(
+            &router.ports(),
+            args,
+            "Usage: replay <port> <file> [<scale>] | replay stop",
+        );
+    }
+
+    fn enable(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        if args.len() != 3 {
+            term.out_buf
+                .push_back("Usage: enable <port> <ip> <subnet>".to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) => port,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        let ip = match args[1].parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[1]));
+                return;
+            }
+        };
+
+        let subnet = match args[2].parse::<std::net::Ipv4Addr>() {
+            Ok(subnet) => subnet,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid subnet mask", args[2]));
+                return;
+            }
+        };
+
+        router.enable_interface(port, ip.octets(), subnet.octets());
+        term.out_buf.push_back(format!(
+            "Port {} enabled with IP {} and subnet mask {}",
+            port, ip, subnet
+        ));
+    }
+
+    fn rip(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        if args.len() != 1 {
+            term.out_buf.push_back("Usage: rip <port>".to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) => port,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        match router.enable_rip(port) {
+            Ok(_) => {
+                term.out_buf
+                    .push_back(format!("RIP enabled on port {}", port));
+            }
+            Err(e) => {
+                term.out_buf.push_back(format!("Error: {}", e));
+            }
+        }
+    }
+
+    fn igmp(term: &mut Terminal<Router>, router: &mut Router, args: &[&str]) {
+        if args.is_empty() {
+            term.out_buf.push_back("Multicast Groups:".to_string());
+            for (_, _, port, enabled, _) in router.interface_config() {
+                let groups = router.igmp_groups(port);
+                if !enabled || groups.is_empty() {
+                    continue;
+                }
+
+                let groups = groups
+                    .iter()
+                    .map(|g| ipv4_fmt!(g))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                term.out_buf.push_back(format!("Port {}: {}", port, groups));
+            }
+            return;
+        }
+
+        if args.len() != 1 {
+            term.out_buf.push_back("Usage: igmp [<port>]".to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) if port < 8 => port,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        match router.enable_igmp(port) {
+            Ok(_) => term
+                .out_buf
+                .push_back(format!("IGMP enabled on port {}", port)),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn routes(term: &mut Terminal<Router>, router: &mut Router, _args: &[&str]) {
+        term.out_buf.push_back("Routing Table:".to_string());
+
+        for (key, route) in router.routing_table().iter() {
+            term.out_buf.push_back(format!(
+                "{} -> {} via port {}",
+                ipv4_fmt!(key),
+                ipv4_fmt!(route.ip_address),
+                route.port
+            ));
+        }
+    }
+
+    fn ifconfig(term: &mut Terminal<Router>, router: &mut Router, _args: &[&str]) {
+        term.out_buf.push_back("IP Configuration:".to_string());
+
+        for (ip, subnet, port, enabled, rip_enabled) in router.interface_config().iter() {
+            term.out_buf.push_back(format!(
+                "Port {}: IP: {}, Subnet: {}, Enabled: {}, RIP: {}",
+                port,
+                ipv4_fmt!(ip),
+                ipv4_fmt!(subnet),
+                enabled,
+                rip_enabled
+            ));
+        }
+    }
+}
+
+impl Terminal<Switch> {
+    fn new_switch() -> Self {
+        let mut term = Self::new();
+        term.dict.insert(
+            "stp".to_string(),
+            (
+                Self::stp as CommandFunction<Switch>,
+                "Enable Spanning Tree Protocol, or enable or disable it on a port. Usage: stp <priority> | stp <port> <on|off>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "table".to_string(),
+            (
+                Self::table as CommandFunction<Switch>,
+                "Print the MAC address table".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "portfast".to_string(),
+            (
+                Self::portfast as CommandFunction<Switch>,
+                "Skip STP discarding on an edge port. Usage: portfast <port> <on|off>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "bpduguard".to_string(),
+            (
+                Self::bpduguard as CommandFunction<Switch>,
+                "Err-disable an edge port that receives a BPDU. Usage: bpduguard <port> <on|off>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "rootguard".to_string(),
+            (
+                Self::rootguard as CommandFunction<Switch>,
+                "Block a port advertising a superior root. Usage: rootguard <port> <on|off>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "errdisable".to_string(),
+            (
+                Self::errdisable as CommandFunction<Switch>,
+                "Recover err-disabled ports after a delay. Usage: errdisable recovery <seconds|off>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "portsec".to_string(),
+            (
+                Self::portsec as CommandFunction<Switch>,
+                "Configure port security. Usage: portsec <port> [on | off | maximum <n> | violation <protect|restrict|shutdown> | sticky <on|off> | mac <address>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "vlan".to_string(),
+            (
+                Self::vlan as CommandFunction<Switch>,
+                "Set the access VLAN of a port. Usage: vlan <port> <vlan>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "trunk".to_string(),
+            (
+                Self::trunk as CommandFunction<Switch>,
+                "Carry all VLANs on a port with 802.1Q tags. Usage: trunk <port> <on|off>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "mst".to_string(),
+            (
+                Self::mst as CommandFunction<Switch>,
+                "Configure Multiple Spanning Tree. Usage: mst [name <name> | revision <number> | instance <id> <vlan,...> | priority <id> <priority>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "channel".to_string(),
+            (
+                Self::channel as CommandFunction<Switch>,
+                "Add a port to an LACP port-channel, or show the port-channels. Usage: channel [<port> <group|off>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "loadbalance".to_string(),
+            (
+                Self::loadbalance as CommandFunction<Switch>,
+                "Set how frames are spread across port-channel members. Usage: loadbalance <mac|ip>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "igmp".to_string(),
+            (
+                Self::igmp as CommandFunction<Switch>,
+                "Enable or disable IGMP snooping, or print the snooped groups. Usage: igmp [on|off]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "storm".to_string(),
+            (
+                Self::storm as CommandFunction<Switch>,
+                "Limit flooded frames per second on a port, or show its counters. Usage: storm <port> [<broadcast|multicast|unicast> <limit|off> | action <drop|shutdown>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "monitor".to_string(),
+            (
+                Self::monitor as CommandFunction<Switch>,
+                "Mirror the traffic of ports to a destination port (SPAN). Usage: monitor [<port,...> <rx|tx|both> <destination> | off]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "link".to_string(),
+            (
+                Self::link as CommandFunction<Switch>,
+                "Show or set the latency, bandwidth and queue limit of a port's cable. Usage: link <port> [<latency ms> <bandwidth kbps|off> <queue|off>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "impair".to_string(),
+            (
+                Self::impair as CommandFunction<Switch>,
+                "Show or set the loss, corruption, duplication and reordering percentages of a port's cable. Usage: impair <port> [<loss %> <corruption %> <duplication %> <reordering %>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "shutdown".to_string(),
+            (
+                Self::shutdown as CommandFunction<Switch>,
+                "Administratively shut down a port, keeping its cable. Usage: shutdown <port>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "no".to_string(),
+            (
+                Self::no as CommandFunction<Switch>,
+                "Bring a shut down port back up. Usage: no shutdown <port>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "show".to_string(),
+            (
+                Self::show as CommandFunction<Switch>,
+                "Print the status and traffic counters of the connected ports, or of one port. Usage: show interfaces [<port>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "clear".to_string(),
+            (
+                Self::clear_counters as CommandFunction<Switch>,
+                "Clear the terminal screen, or the traffic counters of every port. Usage: clear [counters]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "capture".to_string(),
+            (
+                Self::capture as CommandFunction<Switch>,
+                "Capture the frames of every port, or of one port, and write them to a pcapng file. Usage: capture start [<port>] | capture stop [<file>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "replay".to_string(),
+            (
+                Self::replay as CommandFunction<Switch>,
+                "Replay the frames of a pcap or pcapng file into a port, as if received from its cable. The scale multiplies the time between frames. Usage: replay <port> <file> [<scale>] | replay stop"
+                    .to_string(),
+            ),
+        );
+
+        term
+    }
+
+    fn link(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        const USAGE: &str = "Usage: link <port> [<latency ms> <bandwidth kbps|off> <queue|off>]";
+        if let Some(port) = term.port_arg(args, 32, USAGE) {
+            term.configure_link(&switch.ports()[port], &args[1..], USAGE);
+        }
+    }
+
+    fn impair(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        const USAGE: &str =
+            "Usage: impair <port> [<loss %> <corruption %> <duplication %> <reordering %>]";
+        if let Some(port) = term.port_arg(args, 32, USAGE) {
+            term.configure_impairments(&switch.ports()[port], &args[1..], USAGE);
+        }
+    }
+
+    fn shutdown(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if let Some(port) = term.port_arg(args, 32, "Usage: shutdown <port>") {
+            switch.set_shutdown(port, true);
+            term.out_buf
+                .push_back(format!("Port {} is administratively down", port));
+        }
+    }
+
+    fn no(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if let Some(port) = term.no_shutdown_arg(args, 32) {
+            switch.set_shutdown(port, false);
+            term.out_buf.push_back(format!("Port {} is up", port));
+        }
+    }
+
+    fn show(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        term.show_interfaces(&switch.ports(), args);
+    }
+
+    fn clear_counters(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        term.reset_counters(&switch.ports(), args);
+    }
+
+    fn capture(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        term.configure_capture(
+            &switch.ports(),
+            args,
+            "Usage: capture start [<port>] | capture stop [<file>]",
+        );
+    }
+
+    fn replay(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        term.configure_replay(
+            &switch.ports(),
+            args,
+            "Usage: replay <port> <file> [<scale>] | replay stop",
+        );
+    }
+
+    /// Parses `<port> <on|off>` arguments, printing the usage on failure.
+    fn port_toggle_args(&mut self, args: &[&str], usage: &str) -> Option<(usize, bool)> {
+        if args.len() != 2 {
+            self.out_buf.push_back(usage.to_string());
+            return None;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) if port < 32 => port,
+            _ => {
+                self.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return None;
+            }
+        };
+
+        match args[1] {
+            "on" => Some((port, true)),
+            "off" => Some((port, false)),
+            _ => {
+                self.out_buf.push_back(usage.to_string());
+                None
+            }
+        }
+    }
+
+    fn portfast(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if let Some((port, enabled)) =
+            term.port_toggle_args(args, "Usage: portfast <port> <on|off>")
+        {
+            switch.set_portfast(port, enabled);
+            term.out_buf
+                .push_back(format!("PortFast set to {} on port {}", args[1], port));
+        }
+    }
+
+    fn bpduguard(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if let Some((port, enabled)) =
+            term.port_toggle_args(args, "Usage: bpduguard <port> <on|off>")
+        {
+            switch.set_bpdu_guard(port, enabled);
+            term.out_buf
+                .push_back(format!("BPDU Guard set to {} on port {}", args[1], port));
+        }
+    }
+
+    fn rootguard(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if let Some((port, enabled)) =
+            term.port_toggle_args(args, "Usage: rootguard <port> <on|off>")
+        {
+            switch.set_root_guard(port, enabled);
+            term.out_buf
+                .push_back(format!("Root Guard set to {} on port {}", args[1], port));
+        }
+    }
+
+    fn errdisable(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if args.len() != 2 || args[0] != "recovery" {
+            term.out_buf
+                .push_back("Usage: errdisable recovery <seconds|off>".to_string());
+            return;
+        }
+
+        if args[1] == "off" {
+            switch.set_errdisable_recovery(None);
+            term.out_buf
+                .push_back("Errdisable recovery disabled".to_string());
+            return;
+        }
+
+        let interval = match args[1].parse::<u64>() {
+            Ok(interval) if interval > 0 => interval,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid interval", args[1]));
+                return;
+            }
+        };
+
+        switch.set_errdisable_recovery(Some(interval));
+        term.out_buf.push_back(format!(
+            "Err-disabled ports will recover after {} seconds",
+            interval
+        ));
+    }
+
+    fn portsec(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        const USAGE: &str = "Usage: portsec <port> [on | off | maximum <n> | violation <protect|restrict|shutdown> | sticky <on|off> | mac <address>]";
+        if args.is_empty() || args.len() > 3 {
+            term.out_buf.push_back(USAGE.to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) if port < 32 => port,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        let result = match args[1..] {
+            [] => {
+                match switch.port_security(port) {
+                    Some(security) => {
+                        term.out_buf.push_back(format!(
+                            "Port {}: maximum {}, violation {:?}, sticky {}, violations {}{}",
+                            port,
+                            security.maximum,
+                            security.violation,
+                            security.sticky,
+                            switch.security_violations(port),
+                            if switch.is_err_disabled(port) {
+                                ", err-disabled"
+                            } else {
+                                ""
+                            }
+                        ));
+                        for mac in security.secure_macs.iter() {
+                            term.out_buf.push_back(format!("  {}", mac_fmt!(mac)));
+                        }
+                    }
+                    None => term
+                        .out_buf
+                        .push_back(format!("Port security is disabled on port {}", port)),
+                }
+                return;
+            }
+            ["on"] => {
+                switch.set_port_security(port, true);
+                Ok(())
+            }
+            ["off"] => {
+                switch.set_port_security(port, false);
+                Ok(())
+            }
+            ["maximum", maximum] => match maximum.parse::<usize>() {
+                Ok(maximum) => switch.set_port_security_maximum(port, maximum),
+                Err(_) => Err("Maximum is not a number."),
+            },
+            ["violation", mode] => match mode {
+                "protect" => switch.set_port_security_violation(port, ViolationMode::Protect),
+                "restrict" => switch.set_port_security_violation(port, ViolationMode::Restrict),
+                "shutdown" => switch.set_port_security_violation(port, ViolationMode::Shutdown),
+                _ => Err("Violation mode must be protect, restrict or shutdown."),
+            },
+            ["sticky", "on"] => switch.set_port_security_sticky(port, true),
+            ["sticky", "off"] => switch.set_port_security_sticky(port, false),
+            ["mac", mac] => match utils::parse_mac(mac) {
+                Some(mac) => switch.add_secure_mac(port, mac),
+                None => Err("Address must be in the form XX:XX:XX:XX:XX:XX."),
+            },
+            _ => {
+                term.out_buf.push_back(USAGE.to_string());
+                return;
+            }
+        };
+
+        match result {
+            Ok(_) => term
+                .out_buf
+                .push_back(format!("Port security updated on port {}", port)),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn vlan(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if args.len() != 2 {
+            term.out_buf
+                .push_back("Usage: vlan <port> <vlan>".to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) if port < 32 => port,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        let vlan = match args[1].parse::<u16>() {
+            Ok(vlan) if (1..4095).contains(&vlan) => vlan,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid VLAN", args[1]));
+                return;
+            }
+        };
+
+        switch.set_access_vlan(port, vlan);
+        term.out_buf
+            .push_back(format!("Port {} set to access VLAN {}", port, vlan));
+    }
+
+    fn trunk(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if let Some((port, enabled)) = term.port_toggle_args(args, "Usage: trunk <port> <on|off>") {
+            switch.set_trunk(port, enabled);
+            term.out_buf
+                .push_back(format!("Trunking set to {} on port {}", args[1], port));
+        }
+    }
+
+    fn mst(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if args.is_empty() {
+            let config = match switch.mst_config() {
+                Some(config) => config.clone(),
+                None => {
+                    term.out_buf
+                        .push_back("MST region is not configured".to_string());
+                    return;
+                }
+            };
+
+            term.out_buf.push_back(format!(
+                "Region '{}' revision {}",
+                config.name, config.revision
+            ));
+            for (instance, bid, root_bid, root_port) in switch.instances() {
+                let root = match root_port {
+                    Some(port) => format!("root port {}", port),
+                    None if bid == root_bid => "root bridge".to_string(),
+                    None => "no root port".to_string(),
+                };
+                term.out_buf.push_back(format!(
+                    "MST{} VLANs {:?}, priority {}, {}",
+                    instance,
+                    config.vlans(instance),
+                    bid & 0xFFFF,
+                    root
+                ));
+            }
+            return;
+        }
+
+        let (name, revision) = switch
+            .mst_config()
+            .map_or((String::new(), 0), |c| (c.name.clone(), c.revision));
+
+        match (args[0], args.len()) {
+            ("name", 2) => {
+                switch.set_mst_region(args[1], revision);
+                term.out_buf
+                    .push_back(format!("MST region name set to '{}'", args[1]));
+            }
+            ("revision", 2) => match args[1].parse::<u16>() {
+                Ok(revision) => {
+                    switch.set_mst_region(&name, revision);
+                    term.out_buf
+                        .push_back(format!("MST revision set to {}", revision));
+                }
+                Err(_) => term
+                    .out_buf
+                    .push_back(format!("Error: '{}' is not a valid revision", args[1])),
+            },
+            ("instance", 3) => {
+                let instance = match args[1].parse::<u8>() {
+                    Ok(instance) => instance,
+                    Err(_) => {
+                        term.out_buf
+                            .push_back(format!("Error: '{}' is not a valid instance", args[1]));
+                        return;
+                    }
+                };
+
+                let vlans = match args[2]
+                    .split(',')
+                    .map(|v| v.parse::<u16>())
+                    .collect::<Result<Vec<u16>, _>>()
+                {
+                    Ok(vlans) => vlans,
+                    Err(_) => {
+                        term.out_buf
+                            .push_back(format!("Error: '{}' is not a valid VLAN list", args[2]));
+                        return;
+                    }
+                };
+
+                match switch.map_mst_instance(instance, &vlans) {
+                    Ok(_) => term
+                        .out_buf
+                        .push_back(format!("VLANs {:?} mapped to MST{}", vlans, instance)),
+                    Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+                }
+            }
+            ("priority", 3) => {
+                let (instance, priority) =
+                    match (args[1].parse::<u8>(), args[2].parse::<u16>()) {
+                        (Ok(instance), Ok(priority)) => (instance, priority),
+                        _ => {
+                            term.out_buf.push_back(
+                                "Usage: mst priority <instance> <priority>".to_string(),
+                            );
+                            return;
+                        }
+                    };
+
+                match switch.set_instance_priority(instance, priority) {
+                    Ok(_) => term.out_buf.push_back(format!(
+                        "MST{} priority set to {}",
+                        instance, priority
+                    )),
+                    Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+                }
+            }
+            _ => term.out_buf.push_back(
+                "Usage: mst [name <name> | revision <number> | instance <id> <vlan,...> | priority <id> <priority>]"
+                    .to_string(),
+            ),
+        }
+    }
+
+    fn channel(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if args.is_empty() {
+            let mut groups = (0..32)
+                .filter_map(|i| switch.port_channel(i))
+                .collect::<Vec<_>>();
+            groups.sort();
+            groups.dedup();
+
+            if groups.is_empty() {
+                term.out_buf.push_back("No port-channels".to_string());
+            }
+            for group in groups {
+                let ports = (0..32)
+                    .filter(|i| switch.port_channel(*i) == Some(group))
+                    .map(|i| {
+                        if switch.is_bundled(i) {
+                            format!("{}(P)", i)
+                        } else {
+                            format!("{}(I)", i) // Individual; not bundled
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                term.out_buf
+                    .push_back(format!("Po{}: {}", group, ports.join(" ")));
+            }
+            return;
+        }
+
+        if args.len() != 2 {
+            term.out_buf
+                .push_back("Usage: channel [<port> <group|off>]".to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) if port < 32 => port,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        let group = match args[1] {
+            "off" => None,
+            g => match g.parse::<u8>() {
+                Ok(group) => Some(group),
+                Err(_) => {
+                    term.out_buf
+                        .push_back(format!("Error: '{}' is not a valid port-channel", g));
+                    return;
+                }
+            },
+        };
+
+        switch.set_channel_group(port, group);
+        match group {
+            Some(group) => term
+                .out_buf
+                .push_back(format!("Port {} added to port-channel {}", port, group)),
+            None => term
+                .out_buf
+                .push_back(format!("Port {} removed from its port-channel", port)),
+        }
+    }
+
+    fn loadbalance(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        let load_balance = match args {
+            ["mac"] => LoadBalance::SrcDstMac,
+            ["ip"] => LoadBalance::SrcDstIp,
+            _ => {
+                term.out_buf.push_back(format!(
+                    "Load balance: {:?}. Usage: loadbalance <mac|ip>",
+                    switch.load_balance()
+                ));
+                return;
+            }
+        };
+
+        switch.set_load_balance(load_balance);
+        term.out_buf
+            .push_back(format!("Load balance set to {:?}", load_balance));
+    }
+
+    fn igmp(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        match args {
+            [] => {
+                term.out_buf.push_back(format!(
+                    "IGMP snooping: {}, Router ports: {:?}",
+                    if switch.is_igmp_snooping() {
+                        "on"
+                    } else {
+                        "off"
+                    },
+                    switch.mrouter_ports()
+                ));
+
+                let mut groups = switch.igmp_groups().into_iter().collect::<Vec<_>>();
+                groups.sort();
+                for ((group, vlan), ports) in groups {
+                    term.out_buf.push_back(format!(
+                        "Group {} VLAN {} -> Ports {:?}",
+                        ipv4_fmt!(group),
+                        vlan,
+                        ports
+                    ));
+                }
+            }
+            ["on"] | ["off"] => {
+                switch.set_igmp_snooping(args[0] == "on");
+                term.out_buf
+                    .push_back(format!("IGMP snooping set to {}", args[0]));
+            }
+            _ => term.out_buf.push_back("Usage: igmp [on|off]".to_string()),
+        }
+    }
+
+    fn storm(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        const USAGE: &str =
+            "Usage: storm <port> [<broadcast|multicast|unicast> <limit|off> | action <drop|shutdown>]";
+
+        let port = match args.first().map(|p| p.parse::<usize>()) {
+            Some(Ok(port)) if port < 32 => port,
+            Some(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+            None => {
+                term.out_buf.push_back(USAGE.to_string());
+                return;
+            }
+        };
+
+        match args[1..] {
+            [] => {
+                let config = switch.storm_control(port);
+                for (name, class) in [
+                    ("Broadcast", TrafficClass::Broadcast),
+                    ("Multicast", TrafficClass::Multicast),
+                    ("Unknown unicast", TrafficClass::UnknownUnicast),
+                ] {
+                    let limit = match config.threshold(class) {
+                        Some(limit) => format!("{} fps", limit),
+                        None => "none".to_string(),
+                    };
+                    term.out_buf.push_back(format!(
+                        "{}: {} fps, limit {}",
+                        name,
+                        switch.storm_count(port, class),
+                        limit
+                    ));
+                }
+                term.out_buf.push_back(format!(
+                    "Action: {:?}, dropped: {}",
+                    config.action,
+                    switch.storm_drops(port)
+                ));
+            }
+            ["action", action] => {
+                let action = match action {
+                    "drop" => StormAction::Drop,
+                    "shutdown" => StormAction::Shutdown,
+                    _ => {
+                        term.out_buf.push_back(USAGE.to_string());
+                        return;
+                    }
+                };
+                switch.set_storm_control_action(port, action);
+                term.out_buf.push_back(format!(
+                    "Storm control action set to {:?} on port {}",
+                    action, port
+                ));
+            }
+            [class, limit] => {
+                let class = match class {
+                    "broadcast" => TrafficClass::Broadcast,
+                    "multicast" => TrafficClass::Multicast,
+                    "unicast" => TrafficClass::UnknownUnicast,
+                    _ => {
+                        term.out_buf.push_back(USAGE.to_string());
+                        return;
+                    }
+                };
+
+                let limit = match limit {
+                    "off" => None,
+                    l => match l.parse::<u32>() {
+                        Ok(limit) => Some(limit),
+                        Err(_) => {
+                            term.out_buf
+                                .push_back(format!("Error: '{}' is not a valid limit", l));
+                            return;
+                        }
+                    },
+                };
+
+                switch.set_storm_control(port, class, limit);
+                term.out_buf.push_back(format!(
+                    "{:?} storm control set to {} on port {}",
+                    class, args[2], port
+                ));
+            }
+            _ => term.out_buf.push_back(USAGE.to_string()),
+        }
+    }
+
+    fn monitor(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        const USAGE: &str = "Usage: monitor [<port,...> <rx|tx|both> <destination> | off]";
+
+        match args {
+            [] => match switch.monitor_session() {
+                Some(session) => term.out_buf.push_back(format!(
+                    "Monitoring ports {:?} ({:?}) to port {}",
+                    session.sources, session.direction, session.destination
+                )),
+                None => term.out_buf.push_back("No monitor session".to_string()),
+            },
+            ["off"] => {
+                switch.clear_monitor_session();
+                term.out_buf
+                    .push_back("Monitor session removed".to_string());
+            }
+            [sources, direction, destination] => {
+                let sources = match sources
+                    .split(',')
+                    .map(|p| p.parse::<usize>())
+                    .collect::<Result<Vec<usize>, _>>()
+                {
+                    Ok(sources) => sources,
+                    Err(_) => {
+                        term.out_buf
+                            .push_back(format!("Error: '{}' is not a valid port list", sources));
+                        return;
+                    }
+                };
+
+                let direction = match *direction {
+                    "rx" => MirrorDirection::Rx,
+                    "tx" => MirrorDirection::Tx,
+                    "both" => MirrorDirection::Both,
+                    _ => {
+                        term.out_buf.push_back(USAGE.to_string());
+                        return;
+                    }
+                };
+
+                let destination = match destination.parse::<usize>() {
+                    Ok(destination) => destination,
+                    Err(_) => {
+                        term.out_buf
+                            .push_back(format!("Error: '{}' is not a valid port", destination));
+                        return;
+                    }
+                };
+
+                match switch.set_monitor_session(&sources, direction, destination) {
+                    Ok(_) => term.out_buf.push_back(format!(
+                        "Monitoring ports {:?} ({:?}) to port {}",
+                        sources, direction, destination
+                    )),
+                    Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+                }
+            }
+            _ => term.out_buf.push_back(USAGE.to_string()),
+        }
+    }
+
+    fn stp(term: &mut Terminal<Switch>, switch: &mut Switch, args: &[&str]) {
+        if args.len() == 2 {
+            if let Some((port, enabled)) = term.port_toggle_args(args, "Usage: stp <port> <on|off>")
+            {
+                switch.set_port_stp(port, enabled);
+                term.out_buf
+                    .push_back(format!("STP set to {} on port {}", args[1], port));
+            }
+            return;
+        }
+
+        if args.len() != 1 {
+            term.out_buf
+                .push_back("Usage: stp <priority> | stp <port> <on|off>".to_string());
+            return;
+        }
+
+        let priority = match args[0].parse::<u16>() {
+            Ok(priority) => priority,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid priority", args[0]));
+                return;
+            }
+        };
+
+        switch.set_bridge_priority(priority);
+        switch.init_stp();
+        term.out_buf.push_back(format!(
+            "Spanning Tree Protocol priority set to {}",
+            priority
+        ));
+    }
+
+    fn table(term: &mut Terminal<Switch>, switch: &mut Switch, _args: &[&str]) {
+        term.out_buf.push_back("MAC Address Table:".to_string());
+        for ((mac, vlan), port) in switch.mac_table().iter() {
+            term.out_buf
+                .push_back(format!("{} VLAN {} -> Port {}", mac_fmt!(mac), vlan, port));
+        }
+    }
+}
+
+/// Generates commands that run the switch command of the same name against the layer 2 half of a multilayer switch.
+macro_rules! switch_commands {
+    ($($name:ident),*) => {
+        $(
+            fn $name(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+                term.switch_command(Terminal::<Switch>::$name, mls, args);
+            }
+        )*
+    };
+}
+
+impl Terminal<MultilayerSwitch> {
+    fn new_multilayer_switch() -> Self {
+        let mut term = Self::new();
+        term.dict.insert(
+            "svi".to_string(),
+            (
+                Self::svi as CommandFunction<MultilayerSwitch>,
+                "Create or remove the switched virtual interface of a VLAN. Usage: svi <vlan> <ip> <subnet> | svi <vlan> off"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "routed".to_string(),
+            (
+                Self::routed as CommandFunction<MultilayerSwitch>,
+                "Make a port a routed port (no switchport), or switch it again. Usage: routed <port> <ip> <subnet> | routed <port> off"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "rip".to_string(),
+            (
+                Self::rip as CommandFunction<MultilayerSwitch>,
+                "Enable RIP on the SVI of a VLAN. Usage: rip <vlan>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "routes".to_string(),
+            (
+                Self::routes as CommandFunction<MultilayerSwitch>,
+                "Print the routing table".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "ipconfig".to_string(),
+            (
+                Self::ipconfig as CommandFunction<MultilayerSwitch>,
+                "Print the IP configuration of the SVIs and routed ports".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "shutdown".to_string(),
+            (
+                Self::shutdown as CommandFunction<MultilayerSwitch>,
+                "Administratively shut down a port, keeping its cable. Usage: shutdown <port>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "no".to_string(),
+            (
+                Self::no as CommandFunction<MultilayerSwitch>,
+                "Bring a shut down port back up. Usage: no shutdown <port>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "show".to_string(),
+            (
+                Self::show as CommandFunction<MultilayerSwitch>,
+                "Print the status and traffic counters of the connected ports, or of one port. Usage: show interfaces [<port>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "clear".to_string(),
+            (
+                Self::clear_counters as CommandFunction<MultilayerSwitch>,
+                "Clear the terminal screen, or the traffic counters of every port. Usage: clear [counters]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "capture".to_string(),
+            (
+                Self::capture as CommandFunction<MultilayerSwitch>,
+                "Capture the frames of every port, or of one port, and write them to a pcapng file. Usage: capture start [<port>] | capture stop [<file>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "replay".to_string(),
+            (
+                Self::replay as CommandFunction<MultilayerSwitch>,
+                "Replay the frames of a pcap or pcapng file into a port, as if received from its cable. The scale multiplies the time between frames. Usage: replay <port> <file> [<scale>] | replay stop"
+                    .to_string(),
+            ),
+        );
+
+        // Switching commands share the manual of the switch terminal
+        let switch_term = Terminal::<Switch>::new_switch();
+        let switching: [(&str, CommandFunction<MultilayerSwitch>); 11] = [
+            ("stp", Self::stp),
+            ("table", Self::table),
+            ("vlan", Self::vlan),
+            ("trunk", Self::trunk),
+            ("portfast", Self::portfast),
+            ("bpduguard", Self::bpduguard),
+            ("rootguard", Self::rootguard),
+            ("channel", Self::channel),
+            ("storm", Self::storm),
+            ("link", Self::link),
+            ("impair", Self::impair),
+        ];
+        for (cmd, func) in switching {
+            let manual = switch_term.dict[cmd].1.clone();
+            term.dict.insert(cmd.to_string(), (func, manual));
+        }
+
+        term
+    }
+
+    switch_commands!(
+        stp, table, vlan, trunk, portfast, bpduguard, rootguard, channel, storm, link, impair
+    );
+
+    /// Runs a switch command, keeping the internal ports to the routing engine out of reach.
+    fn switch_command(
+        &mut self,
+        command: CommandFunction<Switch>,
+        mls: &mut MultilayerSwitch,
+        args: &[&str],
+    ) {
+        if args.len() >= 2 {
+            if let Ok(port) = args[0].parse::<usize>() {
+                if port >= MULTILAYER_SWITCH_PORTS {
+                    self.out_buf
+                        .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                    return;
+                }
+
+                if mls.is_routed_port(port) {
+                    self.out_buf
+                        .push_back(format!("Error: Port {} is a routed port", port));
+                    return;
+                }
+            }
+        }
+
+        let mut switch_term = Terminal::<Switch>::new();
+        command(&mut switch_term, mls.switch_mut(), args);
+        self.out_buf.extend(switch_term.out_buf);
+    }
+
+    fn shutdown(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        if let Some(port) = term.port_arg(args, MULTILAYER_SWITCH_PORTS, "Usage: shutdown <port>") {
+            mls.set_shutdown(port, true);
+            term.out_buf
+                .push_back(format!("Port {} is administratively down", port));
+        }
+    }
+
+    fn no(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        if let Some(port) = term.no_shutdown_arg(args, MULTILAYER_SWITCH_PORTS) {
+            mls.set_shutdown(port, false);
+            term.out_buf.push_back(format!("Port {} is up", port));
+        }
+    }
+
+    fn show(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        term.show_interfaces(&mls.ports(), args);
+    }
+
+    fn clear_counters(
+        term: &mut Terminal<MultilayerSwitch>,
+        mls: &mut MultilayerSwitch,
+        args: &[&str],
+    ) {
+        term.reset_counters(&mls.ports(), args);
+    }
+
+    fn capture(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        term.configure_capture(
+            &mls.ports(),
+            args,
+            "Usage: capture start [<port>] | capture stop [<file>]",
+        );
+    }
+
+    fn replay(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        term.configure_replay(
+            &mls.ports(),
+            args,
+            "Usage: replay <port> <file> [<scale>] | replay stop",
+        );
+    }
+
+    /// Parses `<ip> <subnet>` arguments, printing an error on failure.
+    fn ip_args(&mut self, args: &[&str]) -> Option<([u8; 4], [u8; 4])> {
+        let ip = match args[0].parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                self.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[0]));
+                return None;
+            }
+        };
+
+        let subnet = match args[1].parse::<std::net::Ipv4Addr>() {
+            Ok(subnet) => subnet,
+            Err(_) => {
+                self.out_buf
+                    .push_back(format!("Error: '{}' is not a valid subnet mask", args[1]));
+                return None;
+            }
+        };
+
+        Some((ip.octets(), subnet.octets()))
+    }
+
+    fn svi(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        const USAGE: &str = "Usage: svi <vlan> <ip> <subnet> | svi <vlan> off";
+        if args.len() != 2 && args.len() != 3 {
+            term.out_buf.push_back(USAGE.to_string());
+            return;
+        }
+
+        let vlan = match args[0].parse::<u16>() {
+            Ok(vlan) if (1..ROUTED_VLAN_BASE).contains(&vlan) => vlan,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid VLAN", args[0]));
+                return;
+            }
+        };
+
+        let result = match args[1..] {
+            ["off"] => mls
+                .remove_svi(vlan)
+                .map(|_| format!("SVI of VLAN {} removed", vlan)),
+            [ip, subnet] => match term.ip_args(&[ip, subnet]) {
+                Some((ip, subnet)) => mls.set_svi(vlan, ip, subnet).map(|_| {
+                    format!(
+                        "SVI of VLAN {} set to IP {} and subnet mask {}",
+                        vlan,
+                        ipv4_fmt!(ip),
+                        ipv4_fmt!(subnet)
+                    )
+                }),
+                None => return,
+            },
+            _ => {
+                term.out_buf.push_back(USAGE.to_string());
+                return;
+            }
+        };
+
+        match result {
+            Ok(message) => term.out_buf.push_back(message),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn routed(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        const USAGE: &str = "Usage: routed <port> <ip> <subnet> | routed <port> off";
+        if args.len() != 2 && args.len() != 3 {
+            term.out_buf.push_back(USAGE.to_string());
+            return;
+        }
+
+        let port = match args[0].parse::<usize>() {
+            Ok(port) if port < MULTILAYER_SWITCH_PORTS => port,
+            _ => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid port", args[0]));
+                return;
+            }
+        };
+
+        let result = match args[1..] {
+            ["off"] => mls
+                .set_switchport(port)
+                .map(|_| format!("Port {} is a switchport", port)),
+            [ip, subnet] => match term.ip_args(&[ip, subnet]) {
+                Some((ip, subnet)) => mls.set_routed_port(port, ip, subnet).map(|_| {
+                    format!(
+                        "Port {} routed with IP {} and subnet mask {}",
+                        port,
+                        ipv4_fmt!(ip),
+                        ipv4_fmt!(subnet)
+                    )
+                }),
+                None => return,
+            },
+            _ => {
+                term.out_buf.push_back(USAGE.to_string());
+                return;
+            }
+        };
+
+        match result {
+            Ok(message) => term.out_buf.push_back(message),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn rip(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, args: &[&str]) {
+        if args.len() != 1 {
+            term.out_buf.push_back("Usage: rip <vlan>".to_string());
+            return;
+        }
+
+        let vlan = match args[0].parse::<u16>() {
+            Ok(vlan) => vlan,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid VLAN", args[0]));
+                return;
+            }
+        };
+
+        match mls.enable_rip(vlan) {
+            Ok(_) => term
+                .out_buf
+                .push_back(format!("RIP enabled on VLAN {}", vlan)),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn routes(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, _args: &[&str]) {
+        term.out_buf.push_back("Routing Table:".to_string());
+
+        for (key, route, vlan) in mls.routing_table().iter() {
+            term.out_buf.push_back(format!(
+                "{} -> {} via {}",
+                ipv4_fmt!(key),
+                ipv4_fmt!(route.ip_address),
+                MultilayerSwitch::interface_name(*vlan)
+            ));
+        }
+    }
+
+    fn ipconfig(term: &mut Terminal<MultilayerSwitch>, mls: &mut MultilayerSwitch, _args: &[&str]) {
+        term.out_buf.push_back("IP Configuration:".to_string());
+
+        for (vlan, ip, subnet, rip_enabled) in mls.svis().iter() {
+            term.out_buf.push_back(format!(
+                "{}: IP: {}, Subnet: {}, RIP: {}",
+                MultilayerSwitch::interface_name(*vlan),
+                ipv4_fmt!(ip),
+                ipv4_fmt!(subnet),
+                rip_enabled
+            ));
+        }
+    }
+}
+
+impl Terminal<Hub> {
+    fn new_hub() -> Self {
+        let mut term = Self::new();
+        term.dict.insert(
+            "collisions".to_string(),
+            (
+                Self::collisions as CommandFunction<Hub>,
+                "Print the number of collisions in the collision domain".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "link".to_string(),
+            (
+                Self::link as CommandFunction<Hub>,
+                "Show or set the latency, bandwidth and queue limit of a port's cable. Usage: link <port> [<latency ms> <bandwidth kbps|off> <queue|off>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "impair".to_string(),
+            (
+                Self::impair as CommandFunction<Hub>,
+                "Show or set the loss, corruption, duplication and reordering percentages of a port's cable. Usage: impair <port> [<loss %> <corruption %> <duplication %> <reordering %>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "shutdown".to_string(),
+            (
+                Self::shutdown as CommandFunction<Hub>,
+                "Administratively shut down a port, keeping its cable. Usage: shutdown <port>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "no".to_string(),
+            (
+                Self::no as CommandFunction<Hub>,
+                "Bring a shut down port back up. Usage: no shutdown <port>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "show".to_string(),
+            (
+                Self::show as CommandFunction<Hub>,
+                "Print the status and traffic counters of the connected ports, or of one port. Usage: show interfaces [<port>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "clear".to_string(),
+            (
+                Self::clear_counters as CommandFunction<Hub>,
+                "Clear the terminal screen, or the traffic counters of every port. Usage: clear [counters]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "capture".to_string(),
+            (
+                Self::capture as CommandFunction<Hub>,
+                "Capture the frames of every port, or of one port, and write them to a pcapng file. Usage: capture start [<port>] | capture stop [<file>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "replay".to_string(),
+            (
+                Self::replay as CommandFunction<Hub>,
+                "Replay the frames of a pcap or pcapng file into a port, as if received from its cable. The scale multiplies the time between frames. Usage: replay <port> <file> [<scale>] | replay stop"
+                    .to_string(),
+            ),
+        );
+
+        term
+    }
+
+    fn link(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        const USAGE: &str = "Usage: link <port> [<latency ms> <bandwidth kbps|off> <queue|off>]";
+        if let Some(port) = term.port_arg(args, 8, USAGE) {
+            term.configure_link(&hub.ports()[port], &args[1..], USAGE);
+        }
+    }
+
+    fn impair(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        const USAGE: &str =
+            "Usage: impair <port> [<loss %> <corruption %> <duplication %> <reordering %>]";
+        if let Some(port) = term.port_arg(args, 8, USAGE) {
+            term.configure_impairments(&hub.ports()[port], &args[1..], USAGE);
+        }
+    }
+
+    fn shutdown(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        if let Some(port) = term.port_arg(args, 8, "Usage: shutdown <port>") {
+            hub.set_shutdown(port, true);
+            term.out_buf
+                .push_back(format!("Port {} is administratively down", port));
+        }
+    }
+
+    fn no(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        if let Some(port) = term.no_shutdown_arg(args, 8) {
+            hub.set_shutdown(port, false);
+            term.out_buf.push_back(format!("Port {} is up", port));
+        }
+    }
+
+    fn show(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        term.show_interfaces(&hub.ports(), args);
+    }
+
+    fn clear_counters(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        term.reset_counters(&hub.ports(), args);
+    }
+
+    fn capture(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        term.configure_capture(
+            &hub.ports(),
+            args,
+            "Usage: capture start [<port>] | capture stop [<file>]",
+        );
+    }
+
+    fn replay(term: &mut Terminal<Hub>, hub: &mut Hub, args: &[&str]) {
+        term.configure_replay(
+            &hub.ports(),
+            args,
+            "Usage: replay <port> <file> [<scale>] | replay stop",
+        );
+    }
+
+    fn collisions(term: &mut Terminal<Hub>, hub: &mut Hub, _args: &[&str]) {
+        term.out_buf
+            .push_back(format!("Collisions: {}", hub.collisions()));
+    }
+}
+
+impl Terminal<Desktop> {
+    fn new_desktop() -> Self {
+        let mut term = Self::new();
+        term.dict.insert(
+            "ipset".to_string(),
+            (
+                Self::ipset as CommandFunction<Desktop>,
+                "Set the IP address of the desktop. Usage: ipset <ipv4 addr> <subnet addr>"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "dgateway".to_string(),
+            (
+                Self::dgateway as CommandFunction<Desktop>,
+                "Set the default gateway of the desktop. Usage: dgateway <ipv4 addr>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "ipconfig".to_string(),
+            (
+                Self::ipconfig as CommandFunction<Desktop>,
+                "Print the current IP configuration of the desktop".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "arptab".to_string(),
+            (
+                Self::arptab as CommandFunction<Desktop>,
+                "Print the ARP table".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "ping".to_string(),
+            (
+                Self::ping as CommandFunction<Desktop>,
+                "Ping an IP address. Usage: ping <ipv4 addr>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "join".to_string(),
+            (
+                Self::join as CommandFunction<Desktop>,
+                "Join a multicast group, or print the joined groups. Usage: join [<group addr>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "leave".to_string(),
+            (
+                Self::leave as CommandFunction<Desktop>,
+                "Leave a multicast group. Usage: leave <group addr>".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "link".to_string(),
+            (
+                Self::link as CommandFunction<Desktop>,
+                "Show or set the latency, bandwidth and queue limit of the cable. Usage: link [<latency ms> <bandwidth kbps|off> <queue|off>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "impair".to_string(),
+            (
+                Self::impair as CommandFunction<Desktop>,
+                "Show or set the loss, corruption, duplication and reordering percentages of the cable. Usage: impair [<loss %> <corruption %> <duplication %> <reordering %>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "shutdown".to_string(),
+            (
+                Self::shutdown as CommandFunction<Desktop>,
+                "Administratively shut down the ethernet port, keeping its cable".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "no".to_string(),
+            (
+                Self::no as CommandFunction<Desktop>,
+                "Bring the shut down ethernet port back up. Usage: no shutdown".to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "show".to_string(),
+            (
+                Self::show as CommandFunction<Desktop>,
+                "Print the status and traffic counters of the ethernet port. Usage: show interfaces"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "clear".to_string(),
+            (
+                Self::clear_counters as CommandFunction<Desktop>,
+                "Clear the terminal screen, or the traffic counters of the ethernet port. Usage: clear [counters]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "capture".to_string(),
+            (
+                Self::capture as CommandFunction<Desktop>,
+                "Capture the frames of the ethernet port and write them to a pcapng file. Usage: capture start | capture stop [<file>]"
+                    .to_string(),
+            ),
+        );
+
+        term.dict.insert(
+            "replay".to_string(),
+            (
+                Self::replay as CommandFunction<Desktop>,
+                "Replay the frames of a pcap or pcapng file into the ethernet port, as if received from its cable. The scale multiplies the time between frames. Usage: replay <file> [<scale>] | replay stop"
+                    .to_string(),
+            ),
+        );
+
+        term
+    }
+
+    fn link(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        term.configure_link(
+            &desktop.interface.ethernet.port(),
+            args,
+            "Usage: link [<latency ms> <bandwidth kbps|off> <queue|off>]",
+        );
+    }
+
+    fn impair(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        term.configure_impairments(
+            &desktop.interface.ethernet.port(),
+            args,
+            "Usage: impair [<loss %> <corruption %> <duplication %> <reordering %>]",
+        );
+    }
+
+    fn shutdown(term: &mut Terminal<Desktop>, desktop: &mut Desktop, _args: &[&str]) {
+        desktop
+            .interface
+            .ethernet
+            .port()
+            .borrow_mut()
+            .set_shutdown(true);
+        term.out_buf
+            .push_back("Ethernet port is administratively down".to_string());
+    }
+
+    fn no(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args != ["shutdown"] {
+            term.out_buf.push_back("Usage: no shutdown".to_string());
+            return;
+        }
+
+        desktop
+            .interface
+            .ethernet
+            .port()
+            .borrow_mut()
+            .set_shutdown(false);
+        term.out_buf.push_back("Ethernet port is up".to_string());
+    }
+
+    fn show(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args != ["interfaces"] {
+            term.out_buf.push_back("Usage: show interfaces".to_string());
+            return;
+        }
+
+        term.print_interface("Ethernet", &desktop.interface.ethernet.port().borrow());
+    }
+
+    fn clear_counters(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        term.reset_counters(&[desktop.interface.ethernet.port()], args);
+    }
+
+    fn capture(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        term.configure_capture(
+            &[desktop.interface.ethernet.port()],
+            args,
+            "Usage: capture start | capture stop [<file>]",
+        );
+    }
+
+    fn replay(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        term.configure_replay(
+            &[desktop.interface.ethernet.port()],
+            args,
+            "Usage: replay <file> [<scale>] | replay stop",
+        );
+    }
+
+    fn ipset(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args.len() != 2 {
+            term.out_buf
+                .push_back("Usage: ipset <ipv4 addr> <subnet mask>".to_string());
+            return;
+        }
+
+        let ip = match args[0].parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[0]));
+                return;
+            }
+        };
+
+        let subnet = match args[1].parse::<std::net::Ipv4Addr>() {
+            Ok(subnet) => subnet,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid subnet mask", args[1]));
+                return;
+            }
+        };
+
+        desktop.interface.ip_address = ip.octets();
+        desktop.interface.subnet_mask = subnet.octets();
+
+        term.out_buf.push_back(format!(
+            "IP address set to {} with subnet mask {}",
+            ip, subnet
+        ));
+    }
+
+    fn dgateway(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args.len() != 1 {
+            term.out_buf
+                .push_back("Usage: dgateway <ipv4 addr>".to_string());
+            return;
+        }
+
+        let ip = match args[0].parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[0]));
+                return;
+            }
+        };
+
+        desktop.interface.default_gateway = Some(ip.octets());
+
+        term.out_buf
+            .push_back(format!("Default gateway set to {}", ip));
+    }
+
+    fn ipconfig(term: &mut Terminal<Desktop>, desktop: &mut Desktop, _args: &[&str]) {
+        term.out_buf.push_back(format!(
+            "IP Address: {}",
+            ipv4_fmt!(desktop.interface.ip_address)
+        ));
+
+        term.out_buf.push_back(format!(
+            "Subnet Mask: {}",
+            ipv4_fmt!(desktop.interface.subnet_mask)
+        ));
+        if let Some(gateway) = desktop.interface.default_gateway {
+            term.out_buf
+                .push_back(format!("Default Gateway: {}", ipv4_fmt!(gateway)));
+        } else {
+            term.out_buf.push_back("Default Gateway: None".to_string());
+        }
+
+        let mac = desktop.interface.ethernet.mac_address;
+        term.out_buf
+            .push_back(format!("MAC Address: {}", mac_fmt!(mac)));
+    }
+
+    fn arptab(term: &mut Terminal<Desktop>, desktop: &mut Desktop, _args: &[&str]) {
+        term.out_buf.push_back("ARP Table:".to_string());
+        for (ip, mac) in desktop.interface.arp_table().iter() {
+            term.out_buf
+                .push_back(format!("{} -> {}", ipv4_fmt!(ip), mac_fmt!(mac)));
+        }
+    }
+
+    fn join(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args.is_empty() {
+            term.out_buf.push_back("Multicast Groups:".to_string());
+            for group in desktop.interface.groups() {
+                term.out_buf.push_back(ipv4_fmt!(group));
+            }
+            return;
+        }
+
+        if args.len() != 1 {
+            term.out_buf
+                .push_back("Usage: join [<group addr>]".to_string());
+            return;
+        }
+
+        let group = match args[0].parse::<std::net::Ipv4Addr>() {
+            Ok(group) => group,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[0]));
+                return;
+            }
+        };
+
+        match desktop.interface.join_group(group.octets()) {
+            Ok(_) => term.out_buf.push_back(format!("Joined group {}", group)),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn leave(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args.len() != 1 {
+            term.out_buf
+                .push_back("Usage: leave <group addr>".to_string());
+            return;
+        }
+
+        let group = match args[0].parse::<std::net::Ipv4Addr>() {
+            Ok(group) => group,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[0]));
+                return;
+            }
+        };
+
+        match desktop.interface.leave_group(group.octets()) {
+            Ok(_) => term.out_buf.push_back(format!("Left group {}", group)),
+            Err(e) => term.out_buf.push_back(format!("Error: {}", e)),
+        }
+    }
+
+    fn ping(term: &mut Terminal<Desktop>, desktop: &mut Desktop, args: &[&str]) {
+        if args.len() != 1 {
+            term.out_buf
+                .push_back("Usage: ping <ipv4 addr>".to_string());
+            return;
+        }
+
+        let ip = match args[0].parse::<std::net::Ipv4Addr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                term.out_buf
+                    .push_back(format!("Error: '{}' is not a valid IPv4 address", args[0]));
+                return;
+            }
+        };
+
+        match desktop
+            .interface
+            .send_icmp(ip.octets(), IcmpType::EchoRequest)
+        {
+            Ok(_) => {
+                term.out_buf.push_back(format!("Pinging {}...", ip));
+                term.awaiting_command = Some("ping".to_string());
+                term.awaiting_since = Some(term.clock.now());
+                term.timer.schedule("ping".to_string(), 3, false);
+            }
+            Err(e) => {
+                term.out_buf.push_back(format!("Error: {}", e));
+            }
+        };
+    }
+
+    fn tick(&mut self, desktop: &mut Desktop) {
+        if self.awaiting_command.is_none() {
+            self.timer.tick();
+            return;
+        }
+
+        for event in self.timer.ready() {
+            self.out_buf.push_back(format!("'{}' timed out.", event));
+            self.awaiting_command = None;
+        }
+
+        self.timer.tick();
+
+        if let Some("ping") = self.awaiting_command.as_deref() {
+            // Manually tick a desktop device. Find an ICMP reply frame to close the channel.
+            for frame in desktop.interface.receive() {
+                if frame.destination != desktop.interface.ip_address {
+                    continue;
+                }
+
+                if frame.protocol == 1 {
+                    let icmp = match IcmpFrame::from_bytes(frame.data) {
+                        Ok(icmp) => icmp,
+                        Err(_) => {
+                            continue;
+                        }
+                    };
+
+                    if icmp.icmp_type == IcmpType::EchoReply as u8 {
+                        let rtt = self
+                            .awaiting_since
+                            .take()
+                            .and_then(|since| self.clock.now().duration_since(since).ok())
+                            .unwrap_or_default();
+                        self.out_buf
+                            .push_back(format!("Pong! time={}ms", rtt.as_millis()));
+                        self.awaiting_command = None;
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
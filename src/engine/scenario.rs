@@ -0,0 +1,395 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::tick::{Clock, ClockMode};
+
+use super::device::{DeviceId, DeviceKind, DeviceRepository, DeviceSetQuery, LogFilter, Position};
+
+/// A scripted scenario, run headless with `virtual-packet-tracer run <file>`.
+///
+/// Scenarios are read line by line, blank lines and lines starting with `#` are ignored. Setup lines build the
+/// topology before the simulation starts:
+/// * `topology <file>` - Starts over from a topology saved with Ctrl+S, relative to the scenario file.
+/// * `add <kind> <label>` - Adds a `router`, `switch`, `multilayer_switch`, `hub` or `desktop`.
+/// * `link <device>:<port> <device>:<port>` - Cables two ports.
+///
+/// Timed lines run at a simulated time since the start, such as `500ms`, `2s` or `1.5m`:
+/// * `at <time> <device>: <command>` - Enters a command in the terminal of a device.
+/// * `at <time> expect <device>: <text>` - Expects a line containing the text to have been printed by the device
+///   since its last command.
+///
+/// Devices are named by their label. The simulation ends after the last timed line.
+///
+/// A run prints the commands, the terminal output of every device, the decisions of every device from the event log
+/// and ports going up or down, each stamped with the simulated time.
+pub struct Scenario {
+    setup: Vec<(usize, Setup)>,            // (Line, Step)
+    timed: Vec<(usize, Duration, Action)>, // (Line, Time, Action), ordered by time
+}
+
+enum Setup {
+    Topology(PathBuf),
+    Add(DeviceKind, String),
+    Link(String, usize, String, usize), // Device, Port, Peer, Peer Port
+}
+
+enum Action {
+    Command(String, String), // Device, Command
+    Expect(String, String),  // Device, Text
+}
+
+/// Outcome of a scenario run.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub expectations: usize,
+    pub failures: usize,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.failures == 0
+    }
+}
+
+impl Scenario {
+    /// Reads a scenario file.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Scenario> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        Scenario::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parses a scenario, with topology files relative to `dir`.
+    pub fn parse(text: &str, dir: &Path) -> io::Result<Scenario> {
+        let mut scenario = Scenario {
+            setup: vec![],
+            timed: vec![],
+        };
+
+        for (i, line) in text.lines().enumerate() {
+            let n = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            match keyword {
+                "topology" if !rest.is_empty() => {
+                    scenario.setup.push((n, Setup::Topology(dir.join(rest))));
+                }
+                "add" => {
+                    let (kind, label) = rest
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| invalid_line(n, "Usage: add <kind> <label>"))?;
+                    let kind = match kind {
+                        "router" => DeviceKind::Router,
+                        "switch" => DeviceKind::Switch,
+                        "multilayer_switch" => DeviceKind::MultilayerSwitch,
+                        "hub" => DeviceKind::Hub,
+                        "desktop" => DeviceKind::Desktop,
+                        _ => {
+                            return Err(invalid_line(n, &format!("Unknown device kind '{}'", kind)))
+                        }
+                    };
+                    scenario
+                        .setup
+                        .push((n, Setup::Add(kind, label.trim().to_string())));
+                }
+                "link" => {
+                    // Labels may contain spaces, the port follows the colon up to the next space
+                    let usage = || invalid_line(n, "Usage: link <device>:<port> <device>:<port>");
+                    let [device, middle, peer_port] = rest.splitn(3, ':').collect::<Vec<_>>()[..]
+                    else {
+                        return Err(usage());
+                    };
+                    let (port, peer) = middle.split_once(' ').ok_or_else(usage)?;
+                    let (port, peer_port) = match (port.parse(), peer_port.trim().parse()) {
+                        (Ok(port), Ok(peer_port)) => (port, peer_port),
+                        _ => return Err(usage()),
+                    };
+                    scenario.setup.push((
+                        n,
+                        Setup::Link(
+                            device.trim().to_string(),
+                            port,
+                            peer.trim().to_string(),
+                            peer_port,
+                        ),
+                    ));
+                }
+                "at" => {
+                    let usage = || {
+                        invalid_line(
+                            n,
+                            "Usage: at <time> <device>: <command> | at <time> expect <device>: <text>",
+                        )
+                    };
+                    let (time, rest) = rest.split_once(char::is_whitespace).ok_or_else(usage)?;
+                    let time = parse_time(time).ok_or_else(|| {
+                        invalid_line(n, &format!("'{}' is not a valid time", time))
+                    })?;
+
+                    let (expect, rest) = match rest.trim_start().strip_prefix("expect ") {
+                        Some(rest) => (true, rest),
+                        None => (false, rest),
+                    };
+                    let (device, text) = rest.split_once(':').ok_or_else(usage)?;
+                    let (device, text) = (device.trim().to_string(), text.trim().to_string());
+                    if device.is_empty() || text.is_empty() {
+                        return Err(usage());
+                    }
+
+                    let action = match expect {
+                        true => Action::Expect(device, text),
+                        false => Action::Command(device, text),
+                    };
+                    scenario.timed.push((n, time, action));
+                }
+                _ => {
+                    return Err(invalid_line(
+                        n,
+                        &format!("'{}' is not a valid scenario line", line),
+                    ))
+                }
+            }
+        }
+
+        // Stable, lines at the same time run in the order they were written
+        scenario.timed.sort_by_key(|(_, time, _)| *time);
+        Ok(scenario)
+    }
+
+    /// Builds the topology and runs the timed lines on simulated time, printing terminal output, port state changes
+    /// and expectation results to `out`.
+    ///
+    /// The simulation runs on a virtual clock, stepping from event to event, so runs are deterministic.
+    pub fn run(&self, out: &mut impl Write) -> io::Result<Report> {
+        let mut dr = self.build()?;
+
+        // Resolve every device up front, so a typo fails before the simulation starts
+        let mut timed = vec![];
+        for (n, time, action) in &self.timed {
+            let device = match action {
+                Action::Command(device, _) | Action::Expect(device, _) => device,
+            };
+            let id = find(&dr, *n, device)?;
+            timed.push((*time, id, action));
+        }
+
+        dr.set_clock(&Clock::new(ClockMode::Virtual));
+        Scenario::simulate(&mut dr, timed, out)
+    }
+
+    fn build(&self) -> io::Result<DeviceRepository> {
+        let mut dr = DeviceRepository::default();
+        for (n, setup) in &self.setup {
+            match setup {
+                Setup::Topology(path) => {
+                    let text = fs::read_to_string(path).map_err(|e| {
+                        invalid_line(*n, &format!("Cannot read '{}': {}", path.display(), e))
+                    })?;
+                    dr = DeviceRepository::load(&text)
+                        .map_err(|e| invalid_line(*n, &e.to_string()))?;
+                }
+                Setup::Add(kind, label) => {
+                    if dr.find(label).is_some() {
+                        return Err(invalid_line(
+                            *n,
+                            &format!("Device '{}' already exists", label),
+                        ));
+                    }
+
+                    let id = dr.add(*kind, Position::default());
+                    dr.set(id, DeviceSetQuery::Label(label.clone()));
+                }
+                Setup::Link(device, port, peer, peer_port) => {
+                    let (id, peer_id) = (find(&dr, *n, device)?, find(&dr, *n, peer)?);
+                    for (id, port) in [(id, *port), (peer_id, *peer_port)] {
+                        let ports_len = dr.get(id).unwrap().ports_len;
+                        if port >= ports_len {
+                            return Err(invalid_line(*n, &format!("Port {} does not exist", port)));
+                        }
+                    }
+                    dr.set(id, DeviceSetQuery::Connect(peer_id, *port, *peer_port));
+                }
+            }
+        }
+        Ok(dr)
+    }
+
+    fn simulate(
+        dr: &mut DeviceRepository,
+        timed: Vec<(Duration, DeviceId, &Action)>,
+        out: &mut impl Write,
+    ) -> io::Result<Report> {
+        let mut report = Report {
+            expectations: 0,
+            failures: 0,
+        };
+        let mut printed: HashMap<DeviceId, Vec<String>> = HashMap::new(); // Output since the last command
+        let mut ports = port_states(dr);
+        let start = dr.clock().now();
+        let mut timed = timed.into_iter().peekable();
+
+        loop {
+            let elapsed = dr.clock().now().duration_since(start).unwrap();
+            while let Some((_, id, action)) = timed.next_if(|(time, _, _)| *time <= elapsed) {
+                let label = dr.get(id).unwrap().label;
+                match action {
+                    Action::Command(_, command) => {
+                        writeln!(out, "{} {}> {}", timestamp(elapsed), label, command)?;
+                        printed.remove(&id);
+                        dr.set(id, DeviceSetQuery::TerminalInput(command.clone()));
+                        print_output(dr, &mut printed, elapsed, out)?;
+                        print_log(dr, elapsed, out)?;
+                    }
+                    Action::Expect(_, text) => {
+                        let found = printed.get(&id).is_some_and(|lines| {
+                            lines.iter().any(|line| line.contains(text.as_str()))
+                        });
+                        report.expectations += 1;
+                        if !found {
+                            report.failures += 1;
+                        }
+                        writeln!(
+                            out,
+                            "{} expect {}: \"{}\" {}",
+                            timestamp(elapsed),
+                            label,
+                            text,
+                            if found { "ok" } else { "FAILED" }
+                        )?;
+                    }
+                }
+            }
+
+            let Some((time, _, _)) = timed.peek() else {
+                break;
+            };
+
+            let until = start + *time;
+            while dr.step_until(until).is_some() {
+                let elapsed = dr.clock().now().duration_since(start).unwrap();
+                print_output(dr, &mut printed, elapsed, out)?;
+                print_log(dr, elapsed, out)?;
+                let states = port_states(dr);
+                for (label, port, up) in &states {
+                    if !ports.contains(&(label.clone(), *port, *up)) {
+                        let state = if *up { "up" } else { "down" };
+                        writeln!(
+                            out,
+                            "{} {} port {} is {}",
+                            timestamp(elapsed),
+                            label,
+                            port,
+                            state
+                        )?;
+                    }
+                }
+                ports = states;
+            }
+            dr.clock().advance_to(until);
+        }
+
+        writeln!(
+            out,
+            "{} of {} expectations passed",
+            report.expectations - report.failures,
+            report.expectations
+        )?;
+        Ok(report)
+    }
+}
+
+/// Prints and keeps the terminal output of every device.
+fn print_output(
+    dr: &mut DeviceRepository,
+    printed: &mut HashMap<DeviceId, Vec<String>>,
+    elapsed: Duration,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for attributes in dr.devices() {
+        for line in dr.get_terminal_output(attributes.id) {
+            writeln!(out, "{} {}: {}", timestamp(elapsed), attributes.label, line)?;
+            printed.entry(attributes.id).or_default().push(line);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the decisions devices made since the last call, then clears the event log so each is printed once.
+fn print_log(dr: &mut DeviceRepository, elapsed: Duration, out: &mut impl Write) -> io::Result<()> {
+    for entry in dr.log(&LogFilter::default()) {
+        match entry.port {
+            Some(port) => writeln!(
+                out,
+                "{} {} port {} log: {}",
+                timestamp(elapsed),
+                entry.label,
+                port,
+                entry.event
+            )?,
+            None => writeln!(
+                out,
+                "{} {} log: {}",
+                timestamp(elapsed),
+                entry.label,
+                entry.event
+            )?,
+        }
+    }
+    dr.clear_log();
+    Ok(())
+}
+
+/// Returns if each cabled port is up, as (Label, Port, Up) ordered by label and port.
+fn port_states(dr: &DeviceRepository) -> Vec<(String, usize, bool)> {
+    let mut states: Vec<(String, usize, bool)> = dr
+        .links()
+        .into_iter()
+        .map(|(id, port, _, _)| (dr.get(id).unwrap().label, port, dr.is_port_up(id, port)))
+        .collect();
+    states.sort();
+    states
+}
+
+fn find(dr: &DeviceRepository, line: usize, label: &str) -> io::Result<DeviceId> {
+    dr.find(label)
+        .ok_or_else(|| invalid_line(line, &format!("Device '{}' does not exist", label)))
+}
+
+fn timestamp(elapsed: Duration) -> String {
+    format!("[{:>8.3}s]", elapsed.as_secs_f64())
+}
+
+/// Parses a time such as `500ms`, `2s` or `1.5m`.
+fn parse_time(time: &str) -> Option<Duration> {
+    let (value, unit) = if let Some(ms) = time.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = time.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = time.strip_suffix('m') {
+        (m, 60.0)
+    } else {
+        return None;
+    };
+
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * unit).ok())
+}
+
+fn invalid_line(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Line {}: {}", line, message),
+    )
+}
@@ -0,0 +1,129 @@
+use crate::{
+    is_mac_multicast_or_broadcast,
+    network::{
+        device::{router::RipFrame, switch::BpduFrame},
+        ethernet::{
+            ByteSerializable, EtherType, Ethernet2Frame, Ethernet802_3Frame, EthernetFrame,
+            MacAddress,
+        },
+        ipv4::{ArpFrame, IcmpFrame, IgmpFrame, Ipv4Frame},
+        trace::{TraceId, TracedFrame},
+    },
+};
+
+#[macro_export]
+macro_rules! ipv4_fmt {
+    ($ip:expr) => {
+        format!("{}.{}.{}.{}", $ip[0], $ip[1], $ip[2], $ip[3])
+    };
+}
+
+#[macro_export]
+macro_rules! mac_fmt {
+    ($mac:expr) => {
+        format!(
+            "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+            $mac[0], $mac[1], $mac[2], $mac[3], $mac[4], $mac[5]
+        )
+    };
+}
+
+/// Parses a MAC address in the form `XX:XX:XX:XX:XX:XX`
+pub fn parse_mac(s: &str) -> Option<MacAddress> {
+    let bytes = s
+        .split(':')
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .ok()?;
+
+    bytes.try_into().ok()
+}
+
+#[derive(Clone)]
+pub enum PacketKind {
+    Arp(Ethernet2Frame),
+    Bpdu(Ethernet802_3Frame),
+    Rip(Ethernet2Frame),
+    Icmp(Ethernet2Frame),
+    Igmp(Ethernet2Frame),
+    Other(EthernetFrame), // A valid frame of a protocol without a detailed view
+    Corrupt(Vec<u8>), // Bytes a receiver rejects, such as a frame failing its Frame Check Sequence
+}
+
+impl PacketKind {
+    pub fn loopback(&self) -> bool {
+        let (source, dest) = match self {
+            PacketKind::Arp(frame) => (frame.source_address, frame.destination_address),
+            PacketKind::Bpdu(frame) => (frame.source_address, frame.destination_address),
+            PacketKind::Rip(frame) => (frame.source_address, frame.destination_address),
+            PacketKind::Icmp(frame) => (frame.source_address, frame.destination_address),
+            PacketKind::Igmp(frame) => (frame.source_address, frame.destination_address),
+            PacketKind::Other(frame) => (frame.source_address(), frame.destination_address()),
+            PacketKind::Corrupt(_) => return false,
+        };
+
+        !is_mac_multicast_or_broadcast!(source) && source == dest
+    }
+
+    /// The protocol of the packet, as displayed.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PacketKind::Arp(_) => "ARP",
+            PacketKind::Bpdu(_) => "BPDU",
+            PacketKind::Rip(_) => "RIP",
+            PacketKind::Icmp(_) => "ICMP",
+            PacketKind::Igmp(_) => "IGMP",
+            PacketKind::Other(_) => "Ethernet",
+            PacketKind::Corrupt(_) => "FCS Error",
+        }
+    }
+
+    /// Classifies the bytes of a frame. VLAN tagged frames are classified (and displayed) by their untagged frame.
+    pub fn from_bytes(packet: &[u8]) -> PacketKind {
+        let eth_frame = match EthernetFrame::from_bytes(packet.to_owned()) {
+            Ok(EthernetFrame::Ethernet2(frame)) => frame,
+            Ok(EthernetFrame::Ethernet802_3(frame)) => {
+                return match BpduFrame::from_frame(&frame) {
+                    Ok(_) => PacketKind::Bpdu(frame),
+                    Err(_) => PacketKind::Other(EthernetFrame::Ethernet802_3(frame)),
+                };
+            }
+            Err(_) => return PacketKind::Corrupt(packet.to_owned()),
+        };
+
+        let (_, untagged) = eth_frame.clone().untag();
+        match untagged.ether_type {
+            EtherType::Arp if ArpFrame::from_bytes(untagged.data.clone()).is_ok() => {
+                return PacketKind::Arp(untagged);
+            }
+            EtherType::Ipv4 => {
+                if let Ok(ipv4_frame) = Ipv4Frame::from_bytes(untagged.data.clone()) {
+                    let data = || ipv4_frame.data.clone();
+                    match ipv4_frame.protocol {
+                        1 if IcmpFrame::from_bytes(data()).is_ok() => {
+                            return PacketKind::Icmp(untagged)
+                        }
+                        2 if IgmpFrame::from_bytes(data()).is_ok() => {
+                            return PacketKind::Igmp(untagged)
+                        }
+                        17 if RipFrame::from_bytes(data()).is_ok() => {
+                            return PacketKind::Rip(untagged)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        PacketKind::Other(EthernetFrame::Ethernet2(eth_frame))
+    }
+}
+
+/// Classifies the frames of a port's buffer, keeping the trace of each. See `EthernetPort::sniff_traced`.
+pub fn sniffed(frames: &[TracedFrame]) -> Vec<(TraceId, PacketKind)> {
+    frames
+        .iter()
+        .map(|(trace, frame)| (*trace, PacketKind::from_bytes(frame)))
+        .collect()
+}
@@ -0,0 +1,409 @@
+use std::{
+    fmt::{self, Write},
+    io,
+    iter::Peekable,
+    str::CharIndices,
+};
+
+/// A JSON value, as defined by RFC 8259.
+///
+/// Objects keep their members in insertion order so written files are stable and diffable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parses a JSON document. Trailing content after the value is an error.
+    pub fn parse(text: &str) -> io::Result<Json> {
+        let mut parser = Parser {
+            chars: text.char_indices().peekable(),
+            len: text.len(),
+        };
+
+        let value = parser.value()?;
+        parser.whitespace();
+        match parser.chars.next() {
+            Some((i, _)) => Err(invalid(i, "Unexpected content after the JSON value")),
+            None => Ok(value),
+        }
+    }
+
+    /// Returns the member of an object, or None if this is not an object or has no such member.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the number if it is a non-negative integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+
+    /// Writes the value on a single line, as a line of a JSON Lines file.
+    pub fn to_line(&self) -> String {
+        let mut line = String::new();
+        self.write_line(&mut line)
+            .expect("Writing to a string can't fail");
+        line
+    }
+
+    fn write_line(&self, f: &mut String) -> fmt::Result {
+        match self {
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    value.write_line(f)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":")?;
+                    value.write_line(f)?;
+                }
+                write!(f, "}}")
+            }
+            value => write!(f, "{}", value),
+        }
+    }
+
+    /// Writes the value indented by `depth` levels. Arrays without objects or arrays are kept on one line.
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        const INDENT: &str = "  ";
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => write!(f, "null"), // JSON has no infinities or NaN
+            Json::String(s) => write_string(f, s),
+            Json::Array(values) if values.is_empty() => write!(f, "[]"),
+            Json::Array(values)
+                if values
+                    .iter()
+                    .all(|v| !matches!(v, Json::Array(_) | Json::Object(_))) =>
+            {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write(f, depth)?;
+                }
+                write!(f, "]")
+            }
+            Json::Array(values) => {
+                writeln!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(f, "{}", INDENT.repeat(depth + 1))?;
+                    value.write(f, depth + 1)?;
+                    writeln!(f, "{}", if i + 1 < values.len() { "," } else { "" })?;
+                }
+                write!(f, "{}]", INDENT.repeat(depth))
+            }
+            Json::Object(members) if members.is_empty() => write!(f, "{{}}"),
+            Json::Object(members) => {
+                writeln!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    write!(f, "{}", INDENT.repeat(depth + 1))?;
+                    write_string(f, key)?;
+                    write!(f, ": ")?;
+                    value.write(f, depth + 1)?;
+                    writeln!(f, "{}", if i + 1 < members.len() { "," } else { "" })?;
+                }
+                write!(f, "{}}}", INDENT.repeat(depth))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    /// Pretty prints the value with two space indentation.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Creates a `Json::Object` from `key => value` pairs, keeping their order.
+#[macro_export]
+macro_rules! json_object {
+    ($($key:expr => $value:expr),* $(,)?) => {
+        $crate::json::Json::Object(vec![$(($key.to_string(), $crate::json::Json::from($value))),*])
+    };
+}
+
+fn write_string(f: &mut impl Write, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+fn invalid(at: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} at byte {}", message, at),
+    )
+}
+
+/// A recursive descent parser over the characters of a document.
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, ' ' | '\t' | '\n' | '\r'))
+            .is_some()
+        {}
+    }
+
+    fn expect(&mut self, expected: char) -> io::Result<()> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, _)) => Err(invalid(i, &format!("Expected '{}'", expected))),
+            None => Err(invalid(self.len, "Unexpected end of document")),
+        }
+    }
+
+    fn value(&mut self) -> io::Result<Json> {
+        self.whitespace();
+        let Some(&(i, c)) = self.chars.peek() else {
+            return Err(invalid(self.len, "Unexpected end of document"));
+        };
+
+        match c {
+            'n' => self.literal("null", Json::Null),
+            't' => self.literal("true", Json::Bool(true)),
+            'f' => self.literal("false", Json::Bool(false)),
+            '"' => self.string().map(Json::String),
+            '[' => self.array(),
+            '{' => self.object(),
+            '-' | '0'..='9' => self.number(),
+            _ => Err(invalid(i, "Expected a value")),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> io::Result<Json> {
+        for expected in literal.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> io::Result<Json> {
+        let mut number = String::new();
+        let start = self.chars.peek().map_or(0, |(i, _)| *i);
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+        {
+            number.push(c);
+        }
+
+        number
+            .parse::<f64>()
+            .map(Json::Number)
+            .map_err(|_| invalid(start, "Invalid number"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((i, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => s.push('"'),
+                    Some((_, '\\')) => s.push('\\'),
+                    Some((_, '/')) => s.push('/'),
+                    Some((_, 'b')) => s.push('\u{8}'),
+                    Some((_, 'f')) => s.push('\u{c}'),
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 'r')) => s.push('\r'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, 'u')) => {
+                        let mut code = self.hex4(i)?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            // High surrogate, followed by the escaped low surrogate
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4(i)?;
+                            code = 0x10000
+                                + ((code - 0xD800) << 10)
+                                + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        s.push(char::from_u32(code).ok_or_else(|| invalid(i, "Invalid escape"))?);
+                    }
+                    _ => return Err(invalid(i, "Invalid escape")),
+                },
+                Some((_, c)) => s.push(c),
+                None => return Err(invalid(self.len, "Unterminated string")),
+            }
+        }
+    }
+
+    fn hex4(&mut self, at: usize) -> io::Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .chars
+                .next()
+                .and_then(|(_, c)| c.to_digit(16))
+                .ok_or_else(|| invalid(at, "Invalid escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> io::Result<Json> {
+        self.expect('[')?;
+        let mut values = vec![];
+        self.whitespace();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Json::Array(values)),
+                Some((i, _)) => return Err(invalid(i, "Expected ',' or ']'")),
+                None => return Err(invalid(self.len, "Unterminated array")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> io::Result<Json> {
+        self.expect('{')?;
+        let mut members = vec![];
+        self.whitespace();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Json::Object(members)),
+                Some((i, _)) => return Err(invalid(i, "Expected ',' or '}'")),
+                None => return Err(invalid(self.len, "Unterminated object")),
+            }
+        }
+    }
+}
@@ -1,35 +1,88 @@
 #![allow(dead_code)]
 
 mod network {
+    pub mod capture;
     pub mod ethernet;
     pub mod ipv4;
+    pub mod log;
+    pub mod trace;
 
     pub mod device {
         pub mod cable;
         pub mod desktop;
+        pub mod hub;
+        pub mod multilayer_switch;
         pub mod router;
         pub mod switch;
     }
 }
 
+mod engine {
+    pub mod device;
+    pub mod scenario;
+    pub mod utils;
+}
+
+mod json;
+#[cfg(feature = "gui")]
 mod simulation;
 mod tick;
 
 #[cfg(test)]
 mod tests {
+    mod engine {
+        pub mod device_tests;
+        pub mod scenario_tests;
+    }
+
     mod network {
         pub mod cable_tests;
+        pub mod capture_tests;
         pub mod ethernet_tests;
+        pub mod fixtures;
+        pub mod hub_tests;
         pub mod ipv4_interface_tests;
+        pub mod multilayer_switch_tests;
         pub mod router_tests;
         pub mod switch_tests;
     }
 
     mod simulation {
+        pub mod json_tests;
         pub mod tick_tests;
     }
 }
 
 fn main() {
-    simulation::run();
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        None => {
+            #[cfg(feature = "gui")]
+            simulation::run();
+
+            #[cfg(not(feature = "gui"))]
+            eprintln!("Built without the gui feature, there is no window to run.");
+        }
+        Some("run") if args.len() == 3 => std::process::exit(run_scenario(&args[2])),
+        _ => {
+            eprintln!("Usage: {} [run <scenario.vpt>]", args[0]);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Runs a scenario headless, returning the exit code: 0 if every expectation passed, 1 if one failed and 2 if the
+/// scenario could not be run.
+fn run_scenario(path: &str) -> i32 {
+    let result = engine::scenario::Scenario::read(path)
+        .and_then(|scenario| scenario.run(&mut std::io::stdout().lock()));
+
+    match result {
+        Ok(report) if report.passed() => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            2
+        }
+    }
 }
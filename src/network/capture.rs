@@ -0,0 +1,535 @@
+use std::{
+    collections::VecDeque,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{device::cable::EthernetPort, ethernet::crc32};
+
+/// pcapng block types
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x00000001;
+const SIMPLE_PACKET_BLOCK: u32 = 0x00000003;
+const ENHANCED_PACKET_BLOCK: u32 = 0x00000006;
+
+/// pcap magic numbers, with timestamps in microseconds or nanoseconds
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B23C4D;
+
+/// pcapng option codes
+const OPT_ENDOFOPT: u16 = 0;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_TSRESOL: u16 = 9;
+const IF_FCSLEN: u16 = 13;
+const EPB_FLAGS: u16 = 2;
+
+/// IEEE 802.3 Ethernet, starting at the destination address.
+const LINKTYPE_ETHERNET: u16 = 1;
+
+/// The preamble and start frame delimiter, which are not captured.
+const PREAMBLE: [u8; 8] = [0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0xD5];
+
+/// Direction of a captured frame, relative to the port it was captured on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound = 1,
+    Outbound = 2,
+}
+
+/// A frame captured on a port.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    pub interface: u32,
+    pub time: SystemTime,
+    pub direction: Direction,
+    pub data: Vec<u8>, // Destination address through the Frame Check Sequence, in the byte order of the wire
+}
+
+/// Records the frames crossing a set of ports, to be written as a pcapng file that can be opened in Wireshark.
+///
+/// Each port is an interface of the capture. Frames are stored as LINKTYPE_ETHERNET, without their preamble and start
+/// frame delimiter but with their Frame Check Sequence, and are timestamped with the simulated time. The simulated frames
+/// carry their FCS most significant byte first, so it is reversed to the least significant byte first order of the wire.
+#[derive(Debug, Default)]
+pub struct Capture {
+    interfaces: Vec<String>,
+    frames: Vec<CapturedFrame>,
+}
+
+impl Capture {
+    /// Adds an interface to the capture, returning its id for `record`.
+    pub fn add_interface(&mut self, name: &str) -> u32 {
+        self.interfaces.push(name.to_string());
+        (self.interfaces.len() - 1) as u32
+    }
+
+    /// Records the serialized bytes of a frame crossing an interface.
+    pub fn record(&mut self, interface: u32, direction: Direction, time: SystemTime, bytes: &[u8]) {
+        let mut data = bytes.get(PREAMBLE.len()..).unwrap_or_default().to_vec();
+        reverse_fcs(&mut data);
+
+        self.frames.push(CapturedFrame {
+            interface,
+            time,
+            direction,
+            data,
+        });
+    }
+
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    pub fn frames(&self) -> &[CapturedFrame] {
+        &self.frames
+    }
+
+    /// Serializes the capture as a pcapng section: a section header, a description of every interface and a packet
+    /// block for every frame, in the order they were recorded.
+    pub fn to_pcapng(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&0x1A2B3C4Du32.to_le_bytes()); // Byte-order magic
+        shb.extend_from_slice(&1u16.to_le_bytes()); // Major version
+        shb.extend_from_slice(&0u16.to_le_bytes()); // Minor version
+        shb.extend_from_slice(&(-1i64).to_le_bytes()); // Section length, unspecified
+        shb.extend(option(SHB_USERAPPL, b"Virtual Packet Tracer"));
+        shb.extend(option(OPT_ENDOFOPT, &[]));
+        bytes.extend(block(SECTION_HEADER_BLOCK, shb));
+
+        for name in &self.interfaces {
+            let mut idb = Vec::new();
+            idb.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+            idb.extend_from_slice(&0u16.to_le_bytes()); // Reserved
+            idb.extend_from_slice(&0u32.to_le_bytes()); // Snap length, unlimited
+            idb.extend(option(IF_NAME, name.as_bytes()));
+            idb.extend(option(IF_FCSLEN, &[4]));
+            idb.extend(option(OPT_ENDOFOPT, &[]));
+            bytes.extend(block(INTERFACE_DESCRIPTION_BLOCK, idb));
+        }
+
+        for frame in &self.frames {
+            // Microseconds since the epoch, the default timestamp resolution
+            let micros = frame
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_micros() as u64;
+
+            let mut epb = Vec::new();
+            epb.extend_from_slice(&frame.interface.to_le_bytes());
+            epb.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+            epb.extend_from_slice(&(micros as u32).to_le_bytes());
+            epb.extend_from_slice(&(frame.data.len() as u32).to_le_bytes()); // Captured length
+            epb.extend_from_slice(&(frame.data.len() as u32).to_le_bytes()); // Original length
+            epb.extend_from_slice(&frame.data);
+            pad(&mut epb);
+            epb.extend(option(EPB_FLAGS, &(frame.direction as u32).to_le_bytes()));
+            epb.extend(option(OPT_ENDOFOPT, &[]));
+            bytes.extend(block(ENHANCED_PACKET_BLOCK, epb));
+        }
+
+        bytes
+    }
+
+    /// Writes the capture to a pcapng file.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_pcapng())
+    }
+
+    /// Reads a pcap or pcapng file, see `from_bytes`.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Capture> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Parses the Ethernet frames of a pcap or pcapng capture. Frames captured without a Frame Check Sequence are
+    /// given one, so every frame is accepted by the simulated devices.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Capture> {
+        let magic = bytes
+            .get(0..4)
+            .ok_or_else(|| invalid("Capture file is empty"))?;
+        let mut capture = match u32::from_le_bytes(magic.try_into().unwrap()) {
+            SECTION_HEADER_BLOCK => Self::_from_pcapng(bytes)?,
+            _ => Self::_from_pcap(bytes)?,
+        };
+
+        for frame in capture.frames.iter_mut() {
+            with_fcs(&mut frame.data);
+        }
+        Ok(capture)
+    }
+
+    fn _from_pcap(bytes: &[u8]) -> io::Result<Capture> {
+        let mut reader = Reader::new(bytes);
+        let (nanos, big_endian) = match reader.u32()? {
+            PCAP_MAGIC_MICROS => (false, false),
+            PCAP_MAGIC_NANOS => (true, false),
+            m if m.swap_bytes() == PCAP_MAGIC_MICROS => (false, true),
+            m if m.swap_bytes() == PCAP_MAGIC_NANOS => (true, true),
+            _ => return Err(invalid("Not a pcap or pcapng file")),
+        };
+        reader.big_endian = big_endian;
+
+        reader.skip(16)?; // Version, time zone, accuracy and snap length
+        if reader.u32()? & 0xFFFF != LINKTYPE_ETHERNET as u32 {
+            return Err(invalid("Only Ethernet captures can be read"));
+        }
+
+        let mut capture = Capture::default();
+        let interface = capture.add_interface("pcap");
+        while !reader.is_empty() {
+            let secs = reader.u32()? as u64;
+            let fraction = reader.u32()? as u64;
+            let captured_len = reader.u32()? as usize;
+            reader.skip(4)?; // Original length
+            let data = reader.bytes(captured_len)?.to_vec();
+
+            let since_epoch = Duration::from_secs(secs)
+                + match nanos {
+                    true => Duration::from_nanos(fraction),
+                    false => Duration::from_micros(fraction),
+                };
+            capture.frames.push(CapturedFrame {
+                interface,
+                time: UNIX_EPOCH + since_epoch,
+                direction: Direction::Inbound,
+                data,
+            });
+        }
+
+        Ok(capture)
+    }
+
+    fn _from_pcapng(bytes: &[u8]) -> io::Result<Capture> {
+        let mut capture = Capture::default();
+        let mut reader = Reader::new(bytes);
+        let mut interfaces: Vec<(Option<u32>, u8)> = vec![]; // Capture interface of an Ethernet interface, resolution
+        let mut last_time = UNIX_EPOCH;
+
+        while !reader.is_empty() {
+            let kind = reader.u32()?;
+            if kind == SECTION_HEADER_BLOCK {
+                let mut header = Reader::new(reader.peek(8)?);
+                header.skip(4)?;
+                reader.big_endian = match header.u32()? {
+                    0x1A2B3C4D => false,
+                    0x4D3C2B1A => true,
+                    _ => return Err(invalid("Invalid pcapng byte-order magic")),
+                };
+                interfaces.clear(); // Interface ids are per section
+            }
+
+            let total_len = reader.u32()? as usize;
+            if total_len < 12 || !total_len.is_multiple_of(4) {
+                return Err(invalid("Invalid pcapng block length"));
+            }
+            let mut body = Reader {
+                big_endian: reader.big_endian,
+                ..Reader::new(reader.bytes(total_len - 12)?)
+            };
+            reader.skip(4)?; // Trailing block length
+
+            match kind {
+                INTERFACE_DESCRIPTION_BLOCK => {
+                    let link_type = body.u16()?;
+                    body.skip(6)?; // Reserved and snap length
+
+                    let mut name = format!("Interface {}", interfaces.len());
+                    let mut resolution = 6;
+                    for (code, value) in body.options()? {
+                        match code {
+                            IF_NAME => name = String::from_utf8_lossy(value).into_owned(),
+                            IF_TSRESOL if !value.is_empty() => resolution = value[0],
+                            _ => {}
+                        }
+                    }
+
+                    let interface =
+                        (link_type == LINKTYPE_ETHERNET).then(|| capture.add_interface(&name));
+                    interfaces.push((interface, resolution));
+                }
+                ENHANCED_PACKET_BLOCK => {
+                    let id = body.u32()? as usize;
+                    let units = (body.u32()? as u64) << 32 | body.u32()? as u64;
+                    let captured_len = body.u32()? as usize;
+                    body.skip(4)?; // Original length
+                    let data = body.bytes(captured_len)?.to_vec();
+                    body.skip((4 - captured_len % 4) % 4)?;
+
+                    let mut direction = Direction::Inbound;
+                    for (code, value) in body.options()? {
+                        if code == EPB_FLAGS && value.len() == 4 {
+                            let flags = Reader {
+                                big_endian: body.big_endian,
+                                ..Reader::new(value)
+                            }
+                            .u32()?;
+                            if flags & 0b11 == Direction::Outbound as u32 {
+                                direction = Direction::Outbound;
+                            }
+                        }
+                    }
+
+                    let &(interface, resolution) = interfaces
+                        .get(id)
+                        .ok_or_else(|| invalid("Packet of an undescribed interface"))?;
+                    last_time = timestamp(units, resolution)?;
+                    if let Some(interface) = interface {
+                        capture.frames.push(CapturedFrame {
+                            interface,
+                            time: last_time,
+                            direction,
+                            data,
+                        });
+                    }
+                }
+                SIMPLE_PACKET_BLOCK => {
+                    let original_len = body.u32()? as usize;
+                    let data = body.bytes(original_len.min(body.remaining()))?.to_vec();
+
+                    // Simple packets are of the first interface and have no timestamp
+                    if let Some(&(Some(interface), _)) = interfaces.first() {
+                        capture.frames.push(CapturedFrame {
+                            interface,
+                            time: last_time,
+                            direction: Direction::Inbound,
+                            data,
+                        });
+                    }
+                }
+                _ => {} // Name resolution, statistics and other blocks are not needed
+            }
+        }
+
+        if capture.interfaces.is_empty() && !interfaces.is_empty() {
+            return Err(invalid("Only Ethernet captures can be read"));
+        }
+
+        Ok(capture)
+    }
+}
+
+/// Injects the frames of a capture into a port as if they were received from its cable, keeping the time between
+/// them as recorded.
+#[derive(Clone)]
+pub struct Replay {
+    frames: VecDeque<(Duration, Vec<u8>)>, // Offset from the first frame, serialized frame
+    start: Option<SystemTime>,
+}
+
+impl Replay {
+    /// Creates a replay of every frame of a capture.
+    /// * `capture` - The frames to replay.
+    /// * `scale` - Multiplies the recorded time between frames. 0.5 replays twice as fast, 0 all at once.
+    pub fn new(capture: &Capture, scale: f64) -> Self {
+        let first = capture.frames.first().map(|f| f.time).unwrap_or(UNIX_EPOCH);
+        let frames = capture
+            .frames
+            .iter()
+            .map(|frame| {
+                let offset = frame.time.duration_since(first).unwrap_or_default();
+                let mut bytes = PREAMBLE.to_vec();
+                bytes.extend_from_slice(&frame.data);
+                reverse_fcs(&mut bytes);
+                let offset = offset.as_secs_f64() * scale.max(0.0);
+                (
+                    Duration::try_from_secs_f64(offset).unwrap_or(Duration::MAX),
+                    bytes,
+                )
+            })
+            .collect();
+
+        Self {
+            frames,
+            start: None,
+        }
+    }
+
+    /// Injects the frames that are due into the incoming buffer of the port, returning how many were injected.
+    /// The replay starts the first time it is called.
+    pub fn inject(&mut self, port: &mut EthernetPort, now: SystemTime) -> usize {
+        let start = *self.start.get_or_insert(now);
+        let elapsed = now.duration_since(start).unwrap_or_default();
+
+        let mut injected = 0;
+        while self
+            .frames
+            .front()
+            .is_some_and(|(offset, _)| *offset <= elapsed)
+        {
+            let (_, frame) = self.frames.pop_front().unwrap();
+            port.send_to_self(frame);
+            injected += 1;
+        }
+        injected
+    }
+
+    /// Returns the time the next frame is due, once the replay has started. None if it is too far away to be
+    /// represented.
+    pub fn next_due(&self) -> Option<SystemTime> {
+        let start = self.start?;
+        self.frames
+            .front()
+            .and_then(|(offset, _)| start.checked_add(*offset))
+    }
+
+    /// Returns the number of frames left to inject.
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Reads the fields of pcap and pcapng files in the byte order of the file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            big_endian: false,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn peek(&self, len: usize) -> io::Result<&'a [u8]> {
+        self.bytes
+            .get(..len)
+            .ok_or_else(|| invalid("Capture file is truncated"))
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.peek(len)?;
+        self.bytes = &self.bytes[len..];
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> io::Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    /// Reads the options at the end of a block, as (Code, Value) pairs.
+    fn options(&mut self) -> io::Result<Vec<(u16, &'a [u8])>> {
+        let mut options = vec![];
+        while self.remaining() >= 4 {
+            let code = self.u16()?;
+            let len = self.u16()? as usize;
+            if code == OPT_ENDOFOPT {
+                break;
+            }
+
+            options.push((code, self.bytes(len)?));
+            self.skip(((4 - len % 4) % 4).min(self.remaining()))?;
+        }
+        Ok(options)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Converts a pcapng timestamp to a time. The resolution is a negative power of 10, or of 2 if the most significant
+/// bit is set.
+fn timestamp(units: u64, resolution: u8) -> io::Result<SystemTime> {
+    let exponent = (resolution & 0x7F) as u32;
+    let since_epoch = match (resolution & 0x80 != 0, exponent) {
+        (false, 0..=9) => 10u64
+            .checked_pow(9 - exponent)
+            .and_then(|nanos| units.checked_mul(nanos))
+            .map(Duration::from_nanos),
+        (true, _) => Duration::try_from_secs_f64(units as f64 / 2f64.powi(exponent as i32)).ok(),
+        (false, _) => Duration::try_from_secs_f64(units as f64 / 10f64.powi(exponent as i32)).ok(),
+    };
+
+    since_epoch
+        .and_then(|since_epoch| UNIX_EPOCH.checked_add(since_epoch))
+        .ok_or_else(|| invalid("Packet timestamp is out of range"))
+}
+
+/// Appends a Frame Check Sequence to a captured frame that was recorded without one. A trailing FCS in the byte order
+/// of the simulated frames is rewritten in the wire's byte order.
+fn with_fcs(data: &mut Vec<u8>) {
+    if data.len() >= 4 {
+        let (frame, trailer) = data.split_at(data.len() - 4);
+        let fcs = crc32(frame);
+        if trailer == fcs.to_le_bytes() {
+            return;
+        }
+
+        if trailer == fcs.to_be_bytes() {
+            reverse_fcs(data);
+            return;
+        }
+    }
+
+    let fcs = crc32(data);
+    data.extend_from_slice(&fcs.to_le_bytes());
+}
+
+/// Reverses the byte order of the Frame Check Sequence at the end of a frame, converting between the order of the
+/// simulated frames and of the wire. A corrupted FCS stays corrupted.
+fn reverse_fcs(data: &mut [u8]) {
+    if data.len() >= 4 {
+        let len = data.len();
+        data[len - 4..].reverse();
+    }
+}
+
+/// Pads the bytes to a 32-bit boundary.
+fn pad(bytes: &mut Vec<u8>) {
+    bytes.resize(bytes.len().next_multiple_of(4), 0);
+}
+
+/// Wraps a block body with its type and total length.
+fn block(kind: u32, body: Vec<u8>) -> Vec<u8> {
+    let total = (body.len() + 12) as u32;
+    let mut bytes = Vec::with_capacity(total as usize);
+    bytes.extend_from_slice(&kind.to_le_bytes());
+    bytes.extend_from_slice(&total.to_le_bytes());
+    bytes.extend(body);
+    bytes.extend_from_slice(&total.to_le_bytes());
+    bytes
+}
+
+/// Serializes an option as its code, length and value padded to a 32-bit boundary.
+fn option(code: u16, value: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&code.to_le_bytes());
+    bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(value);
+    pad(&mut bytes);
+    bytes
+}
@@ -1,17 +1,150 @@
-use crate::tick::Tickable;
-use std::{cell::RefCell, rc::Rc};
+use crate::{
+    network::{
+        capture::{Capture, Direction},
+        ethernet::{check_frame, FrameError},
+        log::DropReason,
+        trace::{TraceId, TraceRecord, TraceStep, TracedFrame, Tracer},
+    },
+    tick::{Clock, Tickable},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::{Rc, Weak},
+    time::{Duration, SystemTime},
+};
+
+/// Collisions after which a frame is discarded rather than retransmitted.
+const MAX_ATTEMPTS: u32 = 16;
+
+/// Collisions after which the backoff window stops growing.
+const BACKOFF_LIMIT: u32 = 10;
+
+/// Physical properties of a cable. The default is an ideal cable, delivering every frame on the next transmit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LinkProperties {
+    /// Propagation delay from one end of the cable to the other.
+    pub latency: Duration,
+
+    /// Bits per second a port serializes onto the cable. None is unlimited.
+    pub bandwidth: Option<u64>,
+
+    /// Frames a port's transmit queue holds before dropping new ones. None is unbounded.
+    pub queue_limit: Option<usize>,
+}
+
+/// Faults a cable introduces into the frames crossing it, as percentages of frames in [0, 100].
+/// The default is a cable without faults.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Impairments {
+    /// Frames lost on the cable.
+    pub loss: f64,
+
+    /// Frames arriving with a flipped bit, rejected by the receiver's Frame Check Sequence.
+    pub corruption: f64,
+
+    /// Frames arriving twice.
+    pub duplication: f64,
+
+    /// Frames held back to arrive after the frames of the next transmit.
+    pub reordering: f64,
+}
+
+/// Traffic statistics of a port since it was created or its counters were last cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PortCounters {
+    /// Frames received, including invalid ones.
+    pub frames_in: u64,
+    pub bytes_in: u64,
+
+    /// Frames transmitted onto the cable.
+    pub frames_out: u64,
+    pub bytes_out: u64,
+
+    /// Valid frames received for the broadcast address.
+    pub broadcasts: u64,
+
+    /// Valid frames received for a multicast address.
+    pub multicasts: u64,
+
+    /// Frames discarded before transmission: a full transmit queue, no carrier or excessive collisions.
+    pub drops: u64,
+
+    /// Frames received below the minimum frame size.
+    pub runts: u64,
+
+    /// Frames received above the maximum frame size.
+    pub giants: u64,
+
+    /// Frames received with a Frame Check Sequence mismatch.
+    pub fcs_errors: u64,
+}
 
 /// Simulates the movement of data.
 ///
 /// Holds a collection of EthernetPorts and moves data between on each `tick`.
-#[derive(Default)]
+#[derive(Clone)]
 pub struct CableSimulator {
     ports: Vec<Rc<RefCell<EthernetPort>>>,
+    rng: StdRng, // Random source of cable impairments
+    clock: Clock,
+    tracer: Tracer,
+}
+
+impl Default for CableSimulator {
+    fn default() -> Self {
+        Self {
+            ports: vec![],
+            rng: StdRng::seed_from_u64(0),
+            clock: Clock::default(),
+            tracer: Tracer::default(),
+        }
+    }
 }
 
 impl CableSimulator {
-    /// Adds a port to the simulator.
+    /// Reseeds the random source of cable impairments, making faults reproducible.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the clock timing the cables, and capturing the frames, of every port in the simulator.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        self.clock = clock.clone();
+        for port in &self.ports {
+            port.borrow_mut().set_clock(clock);
+        }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Sets the tracer recording the frames of every port in the simulator.
+    pub fn set_tracer(&mut self, tracer: &Tracer) {
+        self.tracer = tracer.clone();
+        for port in &self.ports {
+            port.borrow_mut().tracer = tracer.clone();
+        }
+    }
+
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    /// Moves the simulator onto the copies of its ports, see `PortMap`.
+    pub fn relink(&mut self, ports: &mut PortMap) {
+        for port in self.ports.iter_mut() {
+            *port = ports.copy(port);
+        }
+    }
+
+    /// Adds a port to the simulator, on the simulator's clock and tracer.
     pub fn add(&mut self, ethernet_port: Rc<RefCell<EthernetPort>>) {
+        ethernet_port.borrow_mut().set_clock(&self.clock);
+        ethernet_port.borrow_mut().tracer = self.tracer.clone();
+        ethernet_port.borrow_mut().this = Rc::downgrade(&ethernet_port);
         self.ports.push(ethernet_port.clone());
     }
 
@@ -42,9 +175,57 @@ impl CableSimulator {
             let mut port = port.borrow_mut();
 
             if let Some(ref mut connection) = port.connection.clone() {
-                port.consume_outgoing(&mut connection.borrow_mut());
+                port.consume_outgoing(&mut connection.borrow_mut(), &mut self.rng);
             } else {
-                port.consume_outgoing(&mut EthernetPort::default());
+                port.consume_outgoing(&mut EthernetPort::default(), &mut self.rng);
+            }
+        }
+    }
+}
+
+/// Copies of the ports of a simulation, for a snapshot of it.
+///
+/// A cloned device still holds the ports of the original. Each device moves onto copies of its ports with `copy`, which
+/// hands out a single copy of every port, then `relink` connects the copies with each other in place of the originals.
+#[derive(Default)]
+pub struct PortMap {
+    copies: HashMap<*const RefCell<EthernetPort>, Rc<RefCell<EthernetPort>>>, // Original => Copy
+}
+
+impl PortMap {
+    /// Returns the copy of a port, copying it the first time.
+    pub fn copy(&mut self, port: &Rc<RefCell<EthernetPort>>) -> Rc<RefCell<EthernetPort>> {
+        self.copies
+            .entry(Rc::as_ptr(port))
+            .or_insert_with(|| {
+                let copy = Rc::new(RefCell::new(port.borrow().clone()));
+                copy.borrow_mut().this = Rc::downgrade(&copy);
+                copy
+            })
+            .clone()
+    }
+
+    /// Connects every copy to the copy of the port its original is connected to.
+    pub fn relink(&mut self) {
+        let mut relinked = HashSet::new();
+        loop {
+            // Relinking copies the peers of ports no device holds, such as a cable to a deleted device
+            let pending: Vec<_> = self
+                .copies
+                .iter()
+                .filter(|(original, _)| !relinked.contains(*original))
+                .map(|(original, copy)| (*original, copy.clone()))
+                .collect();
+            if pending.is_empty() {
+                return;
+            }
+
+            for (original, copy) in pending {
+                relinked.insert(original);
+                let connection = copy.borrow().connection.clone();
+                if let Some(connection) = connection {
+                    copy.borrow_mut().connection = Some(self.copy(&connection));
+                }
             }
         }
     }
@@ -62,20 +243,69 @@ impl Tickable for CableSimulator {
 /// (represented by the Vec<Vec<u8>>, each Vec<u8> is a frame, able to be individually received because of the IPG).
 #[derive(Debug, Clone, Default)]
 pub struct EthernetPort {
-    /// Incoming bytes from the physical connection
-    incoming_buffer: Vec<Vec<u8>>,
+    /// Incoming bytes from the physical connection, each frame with its trace
+    incoming_buffer: Vec<TracedFrame>,
 
     /// Outgoing bytes to the physical connection.
     /// Note that the EthernetPort is only responsible for putting bytes into this buffer.
     /// The simulator will take care of moving the bytes to the other port.
-    outgoing_buffer: Vec<Vec<u8>>,
+    outgoing_buffer: Vec<TracedFrame>,
 
     /// None if a physical connection is not established
     connection: Option<Rc<RefCell<EthernetPort>>>,
+
+    /// Frames that collided on a shared medium, waiting to be retransmitted (CSMA/CD).
+    collided_buffer: Vec<TracedFrame>,
+
+    /// Consecutive collisions of the collided frames.
+    attempts: u32,
+
+    /// Slots (steps) left to wait before transmitting again.
+    backoff: u32,
+
+    /// Physical properties of the connected cable.
+    link: LinkProperties,
+
+    /// Frames serialized onto the cable, with the time they arrive at the other port.
+    in_flight: VecDeque<(SystemTime, TraceId, Vec<u8>)>,
+
+    /// Time the port finishes serializing its last frame.
+    busy_until: Option<SystemTime>,
+
+    /// Time of the last transmit. Frames queued since then may start serializing from it.
+    last_transmit: Option<SystemTime>,
+
+    /// Frames dropped because the transmit queue was full.
+    queue_drops: u32,
+
+    /// Faults of the connected cable.
+    impairments: Impairments,
+
+    /// Frames held back by reordering, delivered on the next transmit.
+    reordered: Vec<TracedFrame>,
+
+    /// The port is administratively down, keeping its cable but neither transmitting nor receiving.
+    shutdown: bool,
+
+    /// Traffic statistics of the port.
+    counters: PortCounters,
+
+    /// Captures recording the frames of the port, with the port's interface id in each.
+    taps: Vec<(Rc<RefCell<Capture>>, u32)>,
+
+    /// Times the cable and the captured frames. Set by the simulator the port is added to.
+    clock: Clock,
+
+    /// Records the steps of the frames of the port. Set by the simulator the port is added to.
+    tracer: Tracer,
+
+    /// The port itself, identifying it in the steps it records. Set by the simulator the port is added to.
+    this: Weak<RefCell<EthernetPort>>,
 }
 
 impl EthernetPort {
     /// Connects two ethernet ports together. This is a bi-directional connection.
+    /// The new cable is ideal, see `set_link`.
     /// * `port1` - The first port to connect.
     /// * `port2` - The second port to connect.
     ///
@@ -87,6 +317,130 @@ impl EthernetPort {
         }
         port1.borrow_mut().connection = Some(port2.clone());
         port2.borrow_mut().connection = Some(port1.clone());
+        port1.borrow_mut().link = LinkProperties::default();
+        port2.borrow_mut().link = LinkProperties::default();
+        port1.borrow_mut().impairments = Impairments::default();
+        port2.borrow_mut().impairments = Impairments::default();
+    }
+
+    /// Sets the physical properties of the cable connected to the port, on both of its ends.
+    pub fn set_link(port: &Rc<RefCell<EthernetPort>>, link: LinkProperties) {
+        let mut port = port.borrow_mut();
+        port.link = link;
+        if let Some(connection) = &port.connection {
+            connection.borrow_mut().link = link;
+        }
+    }
+
+    pub fn link(&self) -> LinkProperties {
+        self.link
+    }
+
+    /// Sets the faults of the cable connected to the port, on both of its ends.
+    pub fn set_impairments(port: &Rc<RefCell<EthernetPort>>, impairments: Impairments) {
+        let mut port = port.borrow_mut();
+        port.impairments = impairments;
+        if let Some(connection) = &port.connection {
+            connection.borrow_mut().impairments = impairments;
+        }
+    }
+
+    pub fn impairments(&self) -> Impairments {
+        self.impairments
+    }
+
+    /// Returns the number of frames dropped because the transmit queue was full.
+    pub fn queue_drops(&self) -> u32 {
+        self.queue_drops
+    }
+
+    /// Administratively shuts down the port, or brings it back up. Frames queued or on the cable are lost.
+    pub fn set_shutdown(&mut self, shutdown: bool) {
+        self.shutdown = shutdown;
+        if shutdown {
+            self.counters.drops += (self.outgoing_buffer.len() + self.collided_buffer.len()) as u64;
+            let queued: Vec<_> = self
+                .outgoing_buffer
+                .drain(..)
+                .chain(self.collided_buffer.drain(..))
+                .collect();
+            self._drop(queued, DropReason::NoCarrier);
+            self._lose_cable(DropReason::NoCarrier);
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+
+    pub fn counters(&self) -> PortCounters {
+        self.counters
+    }
+
+    /// Resets the traffic statistics of the port to zero.
+    pub fn clear_counters(&mut self) {
+        self.counters = PortCounters::default();
+    }
+
+    /// Moves the port onto another clock. Frames on the cable keep the time they had left to arrive.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        let (old, new) = (self.clock.now(), clock.now());
+        let rebase = |time: SystemTime| match time.duration_since(old) {
+            Ok(ahead) => new + ahead,
+            Err(behind) => new - behind.duration(),
+        };
+        for (arrival, _, _) in self.in_flight.iter_mut() {
+            *arrival = rebase(*arrival);
+            clock.schedule(*arrival, "Arrival");
+        }
+        self.busy_until = self.busy_until.map(rebase);
+        self.last_transmit = self.last_transmit.map(rebase);
+        self.clock = clock.clone();
+        if !self.outgoing_buffer.is_empty()
+            || !self.incoming_buffer.is_empty()
+            || !self.collided_buffer.is_empty()
+        {
+            self.clock.wake("Frame");
+        }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+
+    /// Records a decision about the frame the device is handling, in the trace entered. See `Tracer::enter`.
+    pub fn trace(&self, step: TraceStep) {
+        if let Some(trace) = self.tracer.current() {
+            self._trace(trace, step, None);
+        }
+    }
+
+    /// Records every frame the port receives or transmits into the capture, under the given interface id.
+    pub fn tap(&mut self, capture: &Rc<RefCell<Capture>>, interface: u32) {
+        self.untap(capture);
+        self.taps.push((capture.clone(), interface));
+    }
+
+    /// Stops recording the port's frames into the capture.
+    pub fn untap(&mut self, capture: &Rc<RefCell<Capture>>) {
+        self.taps.retain(|(tap, _)| !Rc::ptr_eq(tap, capture));
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Returns true if the port is connected and neither end of the cable is shut down.
+    pub fn has_carrier(&self) -> bool {
+        !self.shutdown
+            && self
+                .connection
+                .as_ref()
+                .is_some_and(|connection| !connection.borrow().shutdown)
     }
 
     /// Mutually disconnects the connection between two ethernet ports.
@@ -95,34 +449,283 @@ impl EthernetPort {
     // Instead of handling the situation of dangling connections, just disconnect both ports.
     pub fn disconnect(&mut self) {
         if let Some(connection) = self.connection.clone() {
-            connection.borrow_mut().connection = None;
+            let mut connection = connection.borrow_mut();
+            connection.connection = None;
+            connection._lose_cable(DropReason::CableLoss);
         }
         self.connection = None;
+        self._lose_cable(DropReason::CableLoss); // Frames on a cut cable are lost
     }
 
-    /// Appends the data to the outgoing buffer.
+    /// Appends the data to the outgoing buffer, or drops it if the transmit queue is full (tail drop).
+    /// The frame joins the trace entered, see `Tracer`.
     pub fn send(&mut self, data: Vec<u8>) {
-        self.outgoing_buffer.push(data);
+        let trace = self.tracer.join();
+        if self.shutdown {
+            self.counters.drops += 1;
+            self._drop(vec![(trace, data)], DropReason::NoCarrier);
+            return;
+        }
+
+        if self
+            .link
+            .queue_limit
+            .is_some_and(|limit| self.outgoing_buffer.len() >= limit)
+        {
+            self.queue_drops += 1;
+            self.counters.drops += 1;
+            self._drop(vec![(trace, data)], DropReason::QueueFull);
+            return;
+        }
+
+        self._trace(trace, TraceStep::Sent, Some(data.clone()));
+        self.outgoing_buffer.push((trace, data));
+        self.clock.wake("Transmit");
     }
 
     /// Appends the data the incoming buffer.
     pub fn send_to_self(&mut self, data: Vec<u8>) {
-        self.incoming_buffer.push(data);
+        if self.shutdown {
+            self.counters.drops += 1;
+            return;
+        }
+
+        let trace = self.tracer.join();
+        self._receive(trace, data);
+    }
+
+    /// Puts a frame into the incoming buffer, counting it.
+    fn _receive(&mut self, trace: TraceId, frame: Vec<u8>) {
+        self._capture(Direction::Inbound, &frame);
+        self.counters.frames_in += 1;
+        self.counters.bytes_in += frame.len() as u64;
+
+        match check_frame(&frame) {
+            Ok(()) if frame[8..14] == crate::mac_broadcast_addr!() => self.counters.broadcasts += 1,
+            Ok(()) if frame[8] & 0x01 == 0x01 => self.counters.multicasts += 1,
+            Ok(()) => {}
+            Err(FrameError::Runt) => self.counters.runts += 1,
+            Err(FrameError::Giant) => self.counters.giants += 1,
+            Err(FrameError::FrameCheckSequence) => self.counters.fcs_errors += 1,
+        }
+
+        self.incoming_buffer.push((trace, frame));
+        self.clock.wake("Receive");
     }
 
     /// Clears the outgoing buffer and appends it to the other's incoming buffer.
-    fn consume_outgoing(&mut self, consumable: &mut EthernetPort) {
-        consumable.incoming_buffer.append(&mut self.outgoing_buffer);
+    /// While backing off from a collision, nothing is transmitted and the outgoing buffer is held.
+    /// Without a carrier, the frames are lost.
+    fn consume_outgoing(&mut self, consumable: &mut EthernetPort, rng: &mut impl Rng) {
+        if self.shutdown || consumable.shutdown || self.connection.is_none() {
+            self.counters.drops += self.outgoing_buffer.len() as u64;
+            let queued = std::mem::take(&mut self.outgoing_buffer);
+            self._drop(queued, DropReason::NoCarrier);
+            self._lose_cable(DropReason::NoCarrier);
+            return;
+        }
+
+        if self.backoff > 0 {
+            self.backoff -= 1;
+            self.clock.wake("Backoff");
+            return;
+        }
+
+        if self.collided_buffer.is_empty() {
+            self.attempts = 0; // The last transmission went through
+        } else {
+            self.collided_buffer.append(&mut self.outgoing_buffer);
+            self.outgoing_buffer.append(&mut self.collided_buffer);
+        }
+
+        let reordered = std::mem::take(&mut self.reordered);
+
+        if self.link == LinkProperties::default() {
+            // Frames put onto the cable before it became ideal arrive now, ahead of the queue
+            for (_, trace, frame) in std::mem::take(&mut self.in_flight) {
+                self._deliver(trace, frame, consumable, rng);
+            }
+            self.busy_until = None;
+            self.last_transmit = None;
+
+            for (trace, frame) in std::mem::take(&mut self.outgoing_buffer) {
+                self._transmitted(&frame);
+                self._deliver(trace, frame, consumable, rng);
+            }
+        } else {
+            let now = self.clock.now();
+            self._serialize(now);
+
+            while self
+                .in_flight
+                .front()
+                .is_some_and(|(arrival, _, _)| *arrival <= now)
+            {
+                let (_, trace, frame) = self.in_flight.pop_front().unwrap();
+                self._deliver(trace, frame, consumable, rng);
+            }
+        }
+
+        for (trace, frame) in reordered {
+            consumable._receive(trace, frame);
+        }
+
+        if !self.reordered.is_empty() {
+            self.clock.wake("Reorder");
+        }
+    }
+
+    /// Puts a frame into the other port's incoming buffer, applying the cable's impairments.
+    fn _deliver(
+        &mut self,
+        trace: TraceId,
+        mut frame: Vec<u8>,
+        consumable: &mut EthernetPort,
+        rng: &mut impl Rng,
+    ) {
+        if self.impairments == Impairments::default() {
+            consumable._receive(trace, frame);
+            return;
+        }
+
+        let impairments = self.impairments;
+        let mut chance = |percent: f64| rng.gen_bool((percent / 100.0).clamp(0.0, 1.0));
+        let (lost, corrupted, duplicated, reordered) = (
+            chance(impairments.loss),
+            chance(impairments.corruption),
+            chance(impairments.duplication),
+            chance(impairments.reordering),
+        );
+
+        if lost {
+            self._drop(vec![(trace, frame)], DropReason::CableLoss);
+            return;
+        }
+
+        // Flip a bit after the preamble and start frame delimiter, where the Frame Check Sequence catches it
+        if corrupted && frame.len() > 8 {
+            let bit = rng.gen_range(64..frame.len() * 8);
+            frame[bit / 8] ^= 1 << (bit % 8);
+        }
+
+        if duplicated {
+            consumable._receive(trace, frame.clone());
+        }
+
+        if reordered {
+            self.reordered.push((trace, frame));
+            return;
+        }
+
+        consumable._receive(trace, frame);
+    }
+
+    /// Counts and captures a frame put onto the cable.
+    fn _transmitted(&mut self, frame: &[u8]) {
+        self._capture(Direction::Outbound, frame);
+        self.counters.frames_out += 1;
+        self.counters.bytes_out += frame.len() as u64;
+    }
+
+    fn _capture(&self, direction: Direction, frame: &[u8]) {
+        if self.taps.is_empty() {
+            return;
+        }
+
+        let now = self.clock.now();
+        for (capture, interface) in &self.taps {
+            capture
+                .borrow_mut()
+                .record(*interface, direction, now, frame);
+        }
+    }
+
+    /// Moves frames from the transmit queue onto the cable, for as long as the port was free to serialize them since
+    /// the last transmit. A frame arrives at the other port after its serialization delay and the cable's latency.
+    fn _serialize(&mut self, now: SystemTime) {
+        let mut start = self.busy_until.max(self.last_transmit).unwrap_or(now);
+        self.last_transmit = Some(now);
+
+        while start <= now && !self.outgoing_buffer.is_empty() {
+            let (trace, frame) = self.outgoing_buffer.remove(0);
+            self._transmitted(&frame);
+            let serialization = match self.link.bandwidth {
+                Some(bps) => Duration::from_secs_f64(frame.len() as f64 * 8.0 / bps as f64),
+                None => Duration::ZERO,
+            };
+
+            start += serialization;
+            self.in_flight
+                .push_back((start + self.link.latency, trace, frame));
+            self.clock.schedule(start + self.link.latency, "Arrival");
+            self.busy_until = Some(start);
+        }
+
+        // The rest of the queue waits for the port to finish serializing
+        if !self.outgoing_buffer.is_empty() {
+            self.clock.schedule(start, "Serialize");
+        }
+    }
+
+    /// Sends a jam signal down the cable. The port on the other end detects the collision of its frames and backs off
+    /// before retransmitting them.
+    /// * `frames` - The frames the other port transmitted into the collision.
+    /// * `rng` - The random source of the collision domain, so simulations are reproducible.
+    pub fn jam(&self, frames: Vec<TracedFrame>, rng: &mut impl Rng) {
+        if let Some(connection) = &self.connection {
+            connection.borrow_mut().collide(frames, rng);
+        }
+    }
+
+    /// Binary exponential backoff: after the nth collision, waits a random number of slots in [0, 2^n).
+    /// The frames are discarded after 16 collisions.
+    fn collide(&mut self, mut frames: Vec<TracedFrame>, rng: &mut impl Rng) {
+        self.attempts += 1;
+        if self.attempts >= MAX_ATTEMPTS {
+            self.counters.drops += (frames.len() + self.collided_buffer.len()) as u64;
+            self.attempts = 0;
+            self.backoff = 0;
+            frames.append(&mut self.collided_buffer);
+            self._drop(frames, DropReason::Collisions);
+            return;
+        }
+
+        frames.append(&mut self.collided_buffer);
+        self.collided_buffer = frames;
+        self.backoff = rng.gen_range(0..1 << self.attempts.min(BACKOFF_LIMIT));
+        self.clock.wake("Backoff");
+    }
+
+    /// Returns true if the port is waiting out a collision backoff.
+    pub fn is_backing_off(&self) -> bool {
+        !self.collided_buffer.is_empty()
     }
 
     /// Clears the incoming buffer and returns it.
     pub fn consume_incoming(&mut self) -> Vec<Vec<u8>> {
-        let mut incoming = vec![];
-        incoming.append(&mut self.incoming_buffer);
+        self.consume_incoming_traced()
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect()
+    }
+
+    /// Clears the incoming buffer and returns it, each frame with its trace.
+    pub fn consume_incoming_traced(&mut self) -> Vec<TracedFrame> {
+        let incoming = std::mem::take(&mut self.incoming_buffer);
+        for (trace, frame) in &incoming {
+            self._trace(*trace, TraceStep::Received, Some(frame.clone()));
+        }
         incoming
     }
 
     pub fn sniff(&self) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        let (incoming, outgoing) = self.sniff_traced();
+        let frames = |buffer: Vec<TracedFrame>| buffer.into_iter().map(|(_, f)| f).collect();
+        (frames(incoming), frames(outgoing))
+    }
+
+    /// Returns the incoming and outgoing buffers, each frame with its trace.
+    pub fn sniff_traced(&self) -> (Vec<TracedFrame>, Vec<TracedFrame>) {
         (self.incoming_buffer.clone(), self.outgoing_buffer.clone())
     }
 
@@ -133,4 +736,31 @@ impl EthernetPort {
     pub fn has_incoming(&self) -> bool {
         !self.incoming_buffer.is_empty()
     }
+
+    fn _trace(&self, trace: TraceId, step: TraceStep, frame: Option<Vec<u8>>) {
+        self.tracer.record(TraceRecord {
+            trace,
+            port: self.this.clone(),
+            step,
+            frame,
+        });
+    }
+
+    /// Records the frames as dropped by the port.
+    fn _drop(&self, frames: Vec<TracedFrame>, reason: DropReason) {
+        for (trace, frame) in frames {
+            self._trace(trace, TraceStep::Dropped(reason), Some(frame));
+        }
+    }
+
+    /// Loses the frames on the cable, and those held back by reordering.
+    fn _lose_cable(&mut self, reason: DropReason) {
+        let mut lost: Vec<_> = self
+            .in_flight
+            .drain(..)
+            .map(|(_, trace, frame)| (trace, frame))
+            .collect();
+        lost.append(&mut self.reordered);
+        self._drop(lost, reason);
+    }
 }
@@ -1,5 +1,10 @@
-use crate::{mac_addr, network::ipv4::interface::Ipv4Interface, tick::Tickable};
+use crate::{
+    mac_addr,
+    network::{ipv4::interface::Ipv4Interface, log::LogEvent},
+    tick::Tickable,
+};
 
+#[derive(Clone)]
 pub struct Desktop {
     pub interface: Ipv4Interface,
 }
@@ -15,6 +20,15 @@ impl Desktop {
             interface: Ipv4Interface::new(mac_addr, ip_addr, subnet_mask, default_gateway),
         }
     }
+
+    /// Removes and returns the decisions of the desktop's interface, on port 0.
+    pub fn take_events(&mut self) -> Vec<(Option<usize>, LogEvent)> {
+        self.interface
+            .take_events()
+            .into_iter()
+            .map(|(_, event)| (Some(0), event))
+            .collect()
+    }
 }
 
 impl Tickable for Desktop {
@@ -0,0 +1,108 @@
+use std::{cell::RefCell, rc::Rc};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{network::trace::TracedFrame, tick::Tickable};
+
+use super::cable::{EthernetPort, PortMap};
+
+/// A layer 1 hub that repeats every frame out of all its other ports, without learning addresses.
+///
+/// All ports share one collision domain. When more than one port receives in the same tick, nothing is repeated and
+/// the senders are jammed, backing off with CSMA/CD binary exponential backoff before retransmitting.
+#[derive(Clone)]
+pub struct Hub {
+    ports: [Rc<RefCell<EthernetPort>>; 8], // 8 physical ports
+    collisions: u32,
+    rng: StdRng,
+}
+
+impl Hub {
+    /// Creates a new hub with 8 disconnected ports.
+    /// * `seed` - The seed of the random backoff times, making collisions reproducible.
+    pub fn from_seed(seed: u64) -> Self {
+        Self {
+            ports: Default::default(),
+            collisions: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Repeats the frames received on a port out of all other ports. Frames received on multiple ports collide.
+    /// A repeated frame stays in its trace.
+    pub fn repeat(&mut self) {
+        let incoming: Vec<(usize, Vec<TracedFrame>)> = self
+            .ports
+            .iter()
+            .enumerate()
+            .filter_map(|(i, port)| {
+                let frames = port.borrow_mut().consume_incoming_traced();
+                (!frames.is_empty()).then_some((i, frames))
+            })
+            .collect();
+
+        if incoming.len() > 1 {
+            self.collisions += 1;
+            for (i, frames) in incoming {
+                self.ports[i].borrow().jam(frames, &mut self.rng);
+            }
+            return;
+        }
+
+        for (i, frames) in incoming {
+            for (j, port) in self.ports.iter().enumerate() {
+                if i == j || !port.borrow().has_carrier() {
+                    continue; // Nothing is repeated out of a port without a link
+                }
+
+                let mut port = port.borrow_mut();
+                let tracer = port.tracer().clone();
+                for (trace, frame) in frames.iter() {
+                    tracer.enter(*trace);
+                    port.send(frame.clone());
+                }
+                tracer.leave();
+            }
+        }
+    }
+
+    pub fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
+        self.ports.to_vec()
+    }
+
+    /// Moves the hub onto the copies of its ports, see `PortMap`.
+    pub fn relink(&mut self, ports: &mut PortMap) {
+        for port in self.ports.iter_mut() {
+            *port = ports.copy(port);
+        }
+    }
+
+    /// Connects a port of the hub to another port.
+    pub fn connect(&mut self, port: usize, other: &Rc<RefCell<EthernetPort>>) {
+        EthernetPort::connect(&self.ports[port], other);
+    }
+
+    pub fn disconnect(&mut self, port: usize) {
+        self.ports[port].borrow_mut().disconnect();
+    }
+
+    /// Administratively shuts down a port, or brings it back up, without removing its cable.
+    pub fn set_shutdown(&mut self, port: usize, shutdown: bool) {
+        self.ports[port].borrow_mut().set_shutdown(shutdown);
+    }
+
+    pub fn is_shutdown(&self, port: usize) -> bool {
+        self.ports[port].borrow().is_shutdown()
+    }
+
+    /// Returns the number of collisions detected in the collision domain.
+    pub fn collisions(&self) -> u32 {
+        self.collisions
+    }
+}
+
+impl Tickable for Hub {
+    fn tick(&mut self) {
+        self.repeat();
+    }
+}
@@ -0,0 +1,300 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    network::{
+        ethernet::{interface::EthernetInterface, MacAddress},
+        ipv4::Ipv4Address,
+        log::LogEvent,
+        trace::Tracer,
+    },
+    network_address,
+    tick::{Clock, Tickable},
+};
+
+use super::{
+    cable::{CableSimulator, EthernetPort, PortMap},
+    router::{Route, Router},
+    switch::{Switch, NATIVE_VLAN},
+};
+
+/// Number of physical ports that can be cabled to other devices.
+pub const MULTILAYER_SWITCH_PORTS: usize = 24;
+
+/// Number of switched virtual interfaces, one per port of the routing engine.
+const SVI_COUNT: usize = 8;
+
+/// Routed port `p` is placed alone in the reserved VLAN `ROUTED_VLAN_BASE + p`.
+pub const ROUTED_VLAN_BASE: u16 = 4064;
+
+/// A layer 3 switch that forwards frames within a VLAN like a `Switch`, and routes between VLANs like a `Router`.
+///
+/// The switch has 32 ports, of which the first 24 are physical. The remaining 8 are cabled internally to the ports
+/// of a routing engine, each acting as the switched virtual interface (SVI) of the VLAN it is an access port of.
+///
+/// A routed port (no switchport) is a physical port placed alone in a reserved VLAN with its own SVI, so its frames
+/// are only ever routed.
+#[derive(Clone)]
+pub struct MultilayerSwitch {
+    switch: Switch,
+    router: Router,
+    svis: [Option<u16>; SVI_COUNT], // Routing engine port => VLAN of the SVI
+    internal: CableSimulator, // Links between the internal switch ports and the routing engine
+}
+
+impl MultilayerSwitch {
+    /// Creates a new multilayer switch with no SVIs.
+    /// * `mac_seed` - The seed for the MAC addresses. Will take the range [mac_seed, mac_seed + 40].
+    /// * `bridge_priority` - The priority of the switch in the spanning tree protocol.
+    pub fn from_seed(mac_seed: u64, bridge_priority: u16) -> Self {
+        let mut switch = Switch::from_seed(mac_seed, bridge_priority);
+        let router = Router::from_seed(mac_seed + 33);
+
+        let mut internal = CableSimulator::default();
+        for i in 0..SVI_COUNT {
+            let port = MULTILAYER_SWITCH_PORTS + i;
+            switch.set_port_stp(port, false);
+            internal.add(switch.ports()[port].clone());
+            internal.add(router.ports()[i].clone());
+        }
+
+        Self {
+            switch,
+            router,
+            svis: [None; SVI_COUNT],
+            internal,
+        }
+    }
+
+    /// Sets the clock of the switch, the routing engine and the links between them.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        self.switch.set_clock(clock);
+        self.router.set_clock(clock);
+        self.internal.set_clock(clock);
+    }
+
+    /// Sets the tracer of the links between the switch and the routing engine.
+    pub fn set_tracer(&mut self, tracer: &Tracer) {
+        self.internal.set_tracer(tracer);
+    }
+
+    /// Moves the switch, its routing engine and the links between them onto the copies of their ports, see `PortMap`.
+    pub fn relink(&mut self, ports: &mut PortMap) {
+        self.switch.relink(ports);
+        self.router.relink(ports);
+        self.internal.relink(ports);
+    }
+
+    /// Returns the physical EthernetPorts of the switch.
+    pub fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
+        self.switch.ports()[..MULTILAYER_SWITCH_PORTS].to_vec()
+    }
+
+    pub fn switch(&self) -> &Switch {
+        &self.switch
+    }
+
+    /// Returns the layer 2 half of the switch, for configuring VLANs, STP and other switching features.
+    pub fn switch_mut(&mut self) -> &mut Switch {
+        &mut self.switch
+    }
+
+    /// Connects an interface to a physical port of the switch.
+    pub fn connect(&mut self, port_id: usize, interface: &mut EthernetInterface) {
+        self.switch.connect(port_id, interface);
+    }
+
+    pub fn disconnect(&mut self, port_id: usize) {
+        self.switch.disconnect(port_id);
+    }
+
+    pub fn is_port_up(&self, port_id: usize) -> bool {
+        self.switch.is_port_up(port_id)
+    }
+
+    /// Administratively shuts down a physical port, or brings it back up, without removing its cable.
+    pub fn set_shutdown(&mut self, port_id: usize, shutdown: bool) {
+        self.switch.set_shutdown(port_id, shutdown);
+    }
+
+    pub fn is_shutdown(&self, port_id: usize) -> bool {
+        self.switch.is_shutdown(port_id)
+    }
+
+    pub fn mac_addr(&self, port_id: usize) -> MacAddress {
+        self.switch.mac_addr(port_id)
+    }
+
+    /// Creates or reconfigures the switched virtual interface of a VLAN.
+    /// * `vlan` - The VLAN to route for.
+    /// * `ipv4_address` - The IP address of the SVI, the default gateway of the VLAN's hosts.
+    /// * `subnet_mask` - The subnet mask of the VLAN's network.
+    pub fn set_svi(
+        &mut self,
+        vlan: u16,
+        ipv4_address: Ipv4Address,
+        subnet_mask: Ipv4Address,
+    ) -> Result<(), &'static str> {
+        if !(1..ROUTED_VLAN_BASE).contains(&vlan) {
+            return Err("VLAN is not valid for an SVI.");
+        }
+
+        self._set_svi(vlan, ipv4_address, subnet_mask)
+    }
+
+    /// Removes the switched virtual interface of a VLAN, along with its routes.
+    pub fn remove_svi(&mut self, vlan: u16) -> Result<(), &'static str> {
+        let i = self._svi(vlan).ok_or("VLAN has no SVI.")?;
+
+        self.router.disconnect(i);
+        self.switch
+            .set_access_vlan(MULTILAYER_SWITCH_PORTS + i, NATIVE_VLAN);
+        self.svis[i] = None;
+        Ok(())
+    }
+
+    /// Returns (IP, Subnet) of the VLAN's SVI.
+    pub fn svi(&self, vlan: u16) -> Option<(Ipv4Address, Ipv4Address)> {
+        let i = self._svi(vlan)?;
+        let (ip, subnet, _, _, _) = self.router.interface_config()[i];
+        Some((ip, subnet))
+    }
+
+    /// Returns (VLAN, IP, Subnet, RIP Enabled) of every SVI, ordered by VLAN.
+    pub fn svis(&self) -> Vec<(u16, Ipv4Address, Ipv4Address, bool)> {
+        let config = self.router.interface_config();
+        let mut svis: Vec<_> = self
+            .svis
+            .iter()
+            .enumerate()
+            .filter_map(|(i, vlan)| {
+                let (ip, subnet, _, _, rip_enabled) = config[i];
+                vlan.map(|vlan| (vlan, ip, subnet, rip_enabled))
+            })
+            .collect();
+        svis.sort_by_key(|(vlan, _, _, _)| *vlan);
+        svis
+    }
+
+    /// Turns a physical port into a routed port with its own IP address. The port leaves its VLAN and STP.
+    pub fn set_routed_port(
+        &mut self,
+        port_id: usize,
+        ipv4_address: Ipv4Address,
+        subnet_mask: Ipv4Address,
+    ) -> Result<(), &'static str> {
+        if port_id >= MULTILAYER_SWITCH_PORTS {
+            return Err("Port is not a physical port.");
+        }
+
+        let vlan = ROUTED_VLAN_BASE + port_id as u16;
+        self._set_svi(vlan, ipv4_address, subnet_mask)?;
+
+        self.switch.set_trunk(port_id, false);
+        self.switch.set_access_vlan(port_id, vlan);
+        self.switch.set_port_stp(port_id, false);
+        Ok(())
+    }
+
+    /// Returns a routed port to switching in the native VLAN.
+    pub fn set_switchport(&mut self, port_id: usize) -> Result<(), &'static str> {
+        if !self.is_routed_port(port_id) {
+            return Err("Port is not a routed port.");
+        }
+
+        self.remove_svi(ROUTED_VLAN_BASE + port_id as u16)?;
+        self.switch.set_access_vlan(port_id, NATIVE_VLAN);
+        self.switch.set_port_stp(port_id, true);
+        Ok(())
+    }
+
+    pub fn is_routed_port(&self, port_id: usize) -> bool {
+        port_id < MULTILAYER_SWITCH_PORTS && self._svi(ROUTED_VLAN_BASE + port_id as u16).is_some()
+    }
+
+    /// Enables RIP on the SVI of a VLAN.
+    pub fn enable_rip(&mut self, vlan: u16) -> Result<(), &'static str> {
+        let i = self._svi(vlan).ok_or("VLAN has no SVI.")?;
+        self.router.enable_rip(i)
+    }
+
+    /// Returns (Network Address, Route, VLAN of the outgoing SVI) of every route.
+    pub fn routing_table(&self) -> Vec<(Ipv4Address, Route, u16)> {
+        self.router
+            .routing_table()
+            .into_iter()
+            .filter_map(|(network, route)| self.svis[route.port].map(|vlan| (network, route, vlan)))
+            .collect()
+    }
+
+    /// Removes and returns the decisions of the switch and of the routing engine, see `Switch::take_events` and
+    /// `Router::take_events`. The decisions of the routing engine concern the routed port or the internal port of its SVI.
+    pub fn take_events(&mut self) -> Vec<(Option<usize>, LogEvent)> {
+        let mut events = self.switch.take_events();
+        for (port, event) in self.router.take_events() {
+            let port = port.map(|i| match self.svis[i] {
+                Some(vlan) if vlan >= ROUTED_VLAN_BASE => (vlan - ROUTED_VLAN_BASE) as usize,
+                _ => MULTILAYER_SWITCH_PORTS + i,
+            });
+            events.push((port, event));
+        }
+        events
+    }
+
+    /// Returns the name of the interface routing for the VLAN, either an SVI or a routed port.
+    pub fn interface_name(vlan: u16) -> String {
+        if vlan >= ROUTED_VLAN_BASE {
+            format!("Port {}", vlan - ROUTED_VLAN_BASE)
+        } else {
+            format!("Vlan{}", vlan)
+        }
+    }
+
+    /// Returns the routing engine port of the VLAN's SVI.
+    fn _svi(&self, vlan: u16) -> Option<usize> {
+        self.svis.iter().position(|v| *v == Some(vlan))
+    }
+
+    fn _set_svi(
+        &mut self,
+        vlan: u16,
+        ipv4_address: Ipv4Address,
+        subnet_mask: Ipv4Address,
+    ) -> Result<(), &'static str> {
+        let network = network_address!(ipv4_address, subnet_mask);
+        let overlaps = self
+            .svis()
+            .iter()
+            .any(|(v, ip, subnet, _)| *v != vlan && network_address!(*ip, *subnet) == network);
+        if overlaps {
+            return Err("Network overlaps with another SVI.");
+        }
+
+        let i = match self._svi(vlan) {
+            Some(i) => {
+                self.router.disconnect(i); // Clears the routes of the previous address
+                i
+            }
+            None => self
+                .svis
+                .iter()
+                .position(|v| v.is_none())
+                .ok_or("No SVIs left.")?,
+        };
+
+        let port = MULTILAYER_SWITCH_PORTS + i;
+        EthernetPort::connect(&self.switch.ports()[port], &self.router.ports()[i]);
+        self.switch.set_access_vlan(port, vlan);
+        self.router.enable_interface(i, ipv4_address, subnet_mask);
+        self.svis[i] = Some(vlan);
+        Ok(())
+    }
+}
+
+impl Tickable for MultilayerSwitch {
+    fn tick(&mut self) {
+        self.switch.tick();
+        self.internal.transmit();
+        self.router.tick();
+        self.internal.transmit();
+    }
+}
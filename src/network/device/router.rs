@@ -1,16 +1,24 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
     is_ipv4_multicast_or_broadcast, mac_addr,
     network::{
         ethernet::{ByteSerializable, MacAddress},
-        ipv4::{interface::Ipv4Interface, IcmpType, Ipv4Address, Ipv4Protocol},
+        ipv4::{
+            interface::Ipv4Interface, IcmpType, IgmpFrame, IgmpType, Ipv4Address, Ipv4Protocol,
+        },
+        log::{DropReason, EventLog, LogEvent},
+        trace::TraceStep,
     },
     network_address,
-    tick::{TickTimer, Tickable},
+    tick::{Clock, TickTimer, Tickable},
 };
 
-use super::cable::EthernetPort;
+use super::cable::{EthernetPort, PortMap};
 
 /// A route in the router's routing table.
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
@@ -32,25 +40,33 @@ impl Route {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct RouterPort {
     interface: RefCell<Ipv4Interface>,
     enabled: bool,
     rip_enabled: bool,
+    igmp_enabled: bool,           // The port is the IGMP querier of its network
+    groups: HashSet<Ipv4Address>, // Multicast groups with members on the port's network
+    carrier: bool, // The link was up when the router last checked ; see `EthernetPort::has_carrier`
 }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
 enum RouterDelayedAction {
     RipMulticast,
+    IgmpQuery,
 }
 
 /// A layer 3 router that routes IPv4 frames between interfaces, and broadcasts RIP frames on all RIP-enabled interfaces.
+///
+/// IGMP-enabled interfaces query their network for multicast group members.
+#[derive(Clone)]
 pub struct Router {
     ports: [RefCell<RouterPort>; 8],    // 8 physical ports
     table: HashMap<Ipv4Address, Route>, // network address => route
     mac_address: MacAddress,
     rip_enabled: bool,
     timer: TickTimer<RouterDelayedAction>,
+    log: EventLog,
 }
 
 impl Router {
@@ -74,6 +90,9 @@ impl Router {
                     )),
                     enabled: false,
                     rip_enabled: false,
+                    igmp_enabled: false,
+                    groups: HashSet::new(),
+                    carrier: false,
                 })
             })
             .collect::<Vec<_>>()
@@ -86,12 +105,33 @@ impl Router {
             mac_address: mac_addr!(mac_seed),
             rip_enabled: false,
             timer: TickTimer::default(),
+            log: EventLog::default(),
+        }
+    }
+
+    /// Sets the clock timing the router's RIP updates and IGMP queries.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        self.timer.set_clock(clock);
+    }
+
+    /// Moves the router onto the copies of its ports, see `PortMap`.
+    pub fn relink(&mut self, ports: &mut PortMap) {
+        for port in &self.ports {
+            port.borrow()
+                .interface
+                .borrow_mut()
+                .ethernet
+                .relink(ports);
         }
     }
 
     /// Routes frames between interfaces on the router.
     /// Routes broadcast and multicast frames to their broadcast domain.
     pub fn route(&mut self) {
+        for i in 0..self.ports.len() {
+            self._detect_carrier(i);
+        }
+
         for i in 0..self.ports.len() {
             let rp = &mut *self.ports[i].borrow_mut();
             if !rp.enabled {
@@ -99,8 +139,17 @@ impl Router {
                 continue;
             }
 
-            let frames = rp.interface.borrow_mut().receive();
-            for frame in frames {
+            let frames = rp.interface.borrow_mut().receive_traced();
+            let tracer = rp.interface.borrow().ethernet.tracer();
+            for (trace, frame) in frames {
+                tracer.enter(trace);
+                if frame.protocol == Ipv4Protocol::Igmp as u8 {
+                    if let Ok(igmp) = IgmpFrame::from_bytes(frame.data) {
+                        Self::_receive_igmp(rp, igmp);
+                    }
+                    continue;
+                }
+
                 if is_ipv4_multicast_or_broadcast!(frame.destination) {
                     let rip_frame = RipFrame::from_bytes(frame.data);
                     if rip_frame.is_err() {
@@ -116,15 +165,24 @@ impl Router {
                             port: i,
                         };
 
-                        match self.table.get(&rip_route.ip_address) {
-                            Some(current_route) if current_route.metric > new_route.metric => {
-                                self.table.insert(rip_route.ip_address, new_route);
-                            }
-                            None => {
-                                self.table.insert(rip_route.ip_address, new_route);
-                            }
-                            _ => {}
+                        if self
+                            .table
+                            .get(&rip_route.ip_address)
+                            .is_some_and(|current_route| current_route.metric <= new_route.metric)
+                        {
+                            continue;
                         }
+
+                        self.log.record(
+                            Some(i),
+                            LogEvent::RouteInstalled {
+                                network: rip_route.ip_address,
+                                mask: new_route.subnet_mask,
+                                via: Some(frame.source),
+                                metric: new_route.metric,
+                            },
+                        );
+                        self.table.insert(rip_route.ip_address, new_route);
                     }
 
                     continue;
@@ -137,6 +195,25 @@ impl Router {
                     .find(|(k, v)| network_address!(frame.destination, v.subnet_mask) == **k)
                     .map(|(_, v)| v);
 
+                if route.is_some() && frame.ttl <= 1 {
+                    rp.interface
+                        .borrow()
+                        .ethernet
+                        .trace(TraceStep::Dropped(DropReason::TtlExpired));
+                    self.log.record(
+                        Some(i),
+                        LogEvent::Dropped {
+                            reason: DropReason::TtlExpired,
+                            destination: Some(frame.destination),
+                        },
+                    );
+                    rp.interface
+                        .borrow_mut()
+                        .send_icmp(frame.source, IcmpType::TimeExceeded)
+                        .expect("Failed to send ICMP frame.");
+                    continue;
+                }
+
                 if let Some(route) = route {
                     let d_rp = &mut *self.ports[route.port].borrow_mut();
 
@@ -153,11 +230,37 @@ impl Router {
                     continue;
                 }
 
+                rp.interface
+                    .borrow()
+                    .ethernet
+                    .trace(TraceStep::Dropped(DropReason::NoRoute));
+                self.log.record(
+                    Some(i),
+                    LogEvent::Dropped {
+                        reason: DropReason::NoRoute,
+                        destination: Some(frame.destination),
+                    },
+                );
                 rp.interface
                     .borrow_mut()
                     .send_icmp(frame.source, IcmpType::Unreachable)
                     .expect("Failed to send ICMP frame.");
             }
+            tracer.leave();
+        }
+    }
+
+    /// Records the group memberships reported on the port's network.
+    fn _receive_igmp(rp: &mut RouterPort, igmp: IgmpFrame) {
+        if !rp.igmp_enabled {
+            return;
+        }
+
+        if igmp.igmp_type == IgmpType::MembershipReport as u8 {
+            rp.groups.insert(igmp.group);
+        } else if igmp.igmp_type == IgmpType::LeaveGroup as u8 && rp.groups.remove(&igmp.group) {
+            // Other members of the group will answer with a report
+            rp.interface.borrow_mut().send_igmp_query(Some(igmp.group));
         }
     }
 
@@ -211,18 +314,32 @@ impl Router {
         ipv4_address: Ipv4Address,
         subnet_mask: Ipv4Address,
     ) {
-        let rp = &mut *self.ports[port].borrow_mut();
-        rp.enabled = true;
-
-        // Set the IP address and subnet mask
-        let rp_ipv4 = &mut *rp.interface.borrow_mut();
-        rp_ipv4.ip_address = ipv4_address;
-        rp_ipv4.subnet_mask = subnet_mask;
+        {
+            let rp = &mut *self.ports[port].borrow_mut();
+            rp.enabled = true;
+
+            // Set the IP address and subnet mask
+            let rp_ipv4 = &mut *rp.interface.borrow_mut();
+            rp_ipv4.ip_address = ipv4_address;
+            rp_ipv4.subnet_mask = subnet_mask;
+        }
 
         // Add the route to the table
-        self.table.insert(
-            network_address!(ipv4_address, subnet_mask),
-            Route::new(ipv4_address, subnet_mask, port),
+        self._install_connected_route(port, ipv4_address, subnet_mask);
+    }
+
+    /// Adds the network of a port to the table as a connected route.
+    fn _install_connected_route(&mut self, port: usize, ip: Ipv4Address, subnet: Ipv4Address) {
+        let network = network_address!(ip, subnet);
+        self.table.insert(network, Route::new(ip, subnet, port));
+        self.log.record(
+            Some(port),
+            LogEvent::RouteInstalled {
+                network,
+                mask: subnet,
+                via: None,
+                metric: 0,
+            },
         );
     }
 
@@ -247,10 +364,39 @@ impl Router {
         Ok(())
     }
 
+    /// Enables IGMP on a port on the router.
+    /// Sends a general query, and repeats it every 125 seconds.
+    pub fn enable_igmp(&mut self, port: usize) -> Result<(), &'static str> {
+        let mut rp = self.ports[port].borrow_mut();
+        if !rp.enabled {
+            return Err("Port is not enabled.");
+        }
+
+        rp.igmp_enabled = true;
+        rp.interface.borrow_mut().send_igmp_query(None);
+
+        self.timer
+            .schedule(RouterDelayedAction::IgmpQuery, 125, true);
+
+        Ok(())
+    }
+
+    /// Returns the multicast groups with members on the port's network.
+    pub fn igmp_groups(&self, port: usize) -> Vec<Ipv4Address> {
+        let mut groups: Vec<Ipv4Address> =
+            self.ports[port].borrow().groups.iter().copied().collect();
+        groups.sort();
+        groups
+    }
+
     /// Connects an interface to the router with the given port number.
     pub fn connect(&mut self, port: usize, interface: &mut Ipv4Interface) {
-        let rp = &mut *self.ports[port].borrow_mut();
-        rp.interface.borrow_mut().connect(interface);
+        self.ports[port]
+            .borrow()
+            .interface
+            .borrow_mut()
+            .connect(interface);
+        self._detect_carrier(port);
     }
 
     #[cfg(test)]
@@ -288,6 +434,98 @@ impl Router {
         rp.interface.borrow_mut().disconnect();
         rp.enabled = false;
         rp.rip_enabled = false;
+        rp.igmp_enabled = false;
+        rp.groups.clear();
+    }
+
+    /// Administratively shuts down a port, or brings it back up, keeping its cable and IP configuration.
+    ///
+    /// The routes through a shut down port are removed, and the device on the other end of the cable loses its
+    /// carrier too.
+    pub fn set_shutdown(&mut self, port: usize, shutdown: bool) {
+        self.ports[port]
+            .borrow()
+            .interface
+            .borrow()
+            .ethernet
+            .port()
+            .borrow_mut()
+            .set_shutdown(shutdown);
+        self._detect_carrier(port);
+    }
+
+    pub fn is_shutdown(&self, port: usize) -> bool {
+        self.ports[port]
+            .borrow()
+            .interface
+            .borrow()
+            .ethernet
+            .port()
+            .borrow()
+            .is_shutdown()
+    }
+
+    /// Reacts to the carrier of a port coming or going, without waiting for routing updates.
+    ///
+    /// On carrier loss, the routes through the port are removed. When the carrier returns, the port's network is a
+    /// connected route again and RIP advertises the table on the port.
+    fn _detect_carrier(&mut self, port: usize) {
+        let carrier = {
+            let rp = self.ports[port].borrow();
+            let carrier = rp.interface.borrow().ethernet.port().borrow().has_carrier();
+            if carrier == rp.carrier {
+                return;
+            }
+            carrier
+        };
+
+        self.ports[port].borrow_mut().carrier = carrier;
+        if !carrier {
+            self.table.retain(|_, v| v.port != port);
+            self.ports[port].borrow_mut().groups.clear();
+            return;
+        }
+
+        let (enabled, rip_enabled, ip, subnet) = {
+            let rp = self.ports[port].borrow();
+            let interface = rp.interface.borrow();
+            (
+                rp.enabled,
+                rp.rip_enabled,
+                interface.ip_address,
+                interface.subnet_mask,
+            )
+        };
+
+        if !enabled {
+            return;
+        }
+
+        self._install_connected_route(port, ip, subnet);
+
+        if rip_enabled {
+            let frame = self._create_rip_frame();
+            self.ports[port]
+                .borrow()
+                .interface
+                .borrow_mut()
+                .multicast(frame.to_bytes(), Ipv4Protocol::Rip);
+        }
+    }
+
+    /// Removes and returns the routes installed, addresses learned and frames dropped by the router, with the port
+    /// each concerns.
+    pub fn take_events(&mut self) -> Vec<(Option<usize>, LogEvent)> {
+        let mut events = self.log.take();
+        for (i, port) in self.ports.iter().enumerate() {
+            let interface_events = port.borrow().interface.borrow_mut().take_events();
+            events.extend(
+                interface_events
+                    .into_iter()
+                    .map(|(_, event)| (Some(i), event)),
+            );
+        }
+        events
     }
 
     pub fn mac_addr(&self, port: usize) -> MacAddress {
@@ -300,7 +538,7 @@ impl Router {
     }
 
     pub fn is_port_up(&self, port: usize) -> bool {
-        self.ports[port].borrow().enabled
+        self.ports[port].borrow().enabled && !self.is_shutdown(port)
     }
 
     pub fn routing_table(&self) -> HashMap<Ipv4Address, Route> {
@@ -331,6 +569,14 @@ impl Tickable for Router {
                 RouterDelayedAction::RipMulticast => {
                     self.send_rip_frames();
                 }
+                RouterDelayedAction::IgmpQuery => {
+                    for rp in self.ports.iter() {
+                        let rp = rp.borrow();
+                        if rp.enabled && rp.igmp_enabled {
+                            rp.interface.borrow_mut().send_igmp_query(None);
+                        }
+                    }
+                }
             }
         }
 
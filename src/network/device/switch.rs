@@ -1,32 +1,59 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    rc::Rc,
+};
 
 use crate::{
-    is_mac_multicast_or_broadcast, mac_addr,
-    network::ethernet::{interface::*, *},
-    tick::{TickTimer, Tickable},
+    is_ipv4_link_local_multicast, is_mac_multicast_or_broadcast, mac_addr,
+    network::{
+        ethernet::{interface::*, *},
+        ipv4::{IgmpFrame, IgmpType, Ipv4Address, Ipv4Frame, Ipv4Protocol},
+        log::{DropReason, EventLog, LogEvent},
+        trace::TraceStep,
+    },
+    tick::{Clock, TickTimer, Tickable},
 };
 
 use super::cable::*;
 
+/// The VLAN every port belongs to by default. Frames on the native VLAN are sent untagged on trunk ports.
+pub const NATIVE_VLAN: u16 = 1;
+
+/// The Common and Internal Spanning Tree (CIST), instance 0 of MSTP. The only tree when MSTP is not configured.
+pub const CIST: u8 = 0;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum StpRole {
+pub enum StpRole {
     Root,       // The port that leads to the root bridge
     Designated, // The lowest cost path to the root bridge for a network segment
     Alternate,  // The lowest cost path to the root bridge (that isn't the root port)
     Backup,     // A higher cost path to the root bridge for a network segment
 }
 
+impl StpRole {
+    /// The port role as encoded in the BPDU flags. 0 = Root, 1 = Designated, 2 = Alternate, 3 = Backup, 4 = Disabled
+    fn flag(role: Option<StpRole>) -> u8 {
+        match role {
+            Some(StpRole::Root) => 0,
+            Some(StpRole::Designated) => 1,
+            Some(StpRole::Alternate) => 2,
+            Some(StpRole::Backup) => 3,
+            None => 4,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum StpState {
+pub enum StpState {
     Discarding, // No forwarded frames, receives and transmits bpdus, no learning mac addresses
     Learning,   // No forwarded frames, receives and transmits BPDUs, learning mac addresses
     Forwarding, // Forwarded frames, receives and transmits BPDUs learning mac addresses
 }
 
-/// An ethernet interface that participates in the Spanning Tree Protocol (STP).
-#[derive(Debug)]
-struct SwitchPort {
-    interface: EthernetInterface,
+/// The state of a port within a single spanning tree.
+#[derive(Debug, Clone)]
+struct StpPort {
     stp_state: StpState,
     stp_role: Option<StpRole>, // None if the port hasn't initialized its role yet
     id: usize,
@@ -36,52 +63,199 @@ struct SwitchPort {
     connected_role: Option<StpRole>, // The inferred role from the last BPDU received
 }
 
-#[derive(Hash, Eq, PartialEq, Clone)]
+/// An ethernet interface on the switch and its configuration.
+#[derive(Debug, Clone)]
+struct SwitchPort {
+    interface: EthernetInterface,
+
+    vlan: u16,   // The access VLAN of the port
+    trunk: bool, // Carries all VLANs, tagging frames that aren't on the native VLAN
+
+    stp_enabled: bool, // The port participates in STP ; a disabled port always forwards and drops BPDUs
+    edge: bool,        // PortFast; the port skips the Discarding state when STP is initialized
+    bpdu_guard: bool,  // Err-disables an edge port that receives a BPDU
+    root_guard: bool,  // Blocks the port while it receives a superior root BID
+    root_inconsistent: bool, // Root Guard is blocking the port
+    err_disabled: bool, // The port has been shut down by a protection feature
+    security: Option<PortSecurity>, // None if port security is disabled
+    storm_control: StormControl,
+    boundary: bool, // The last BPDU came from outside the MST region ; MSTIs follow the CIST on this port
+    channel: Option<u8>, // The port-channel the port negotiates LACP for ; None if the port isn't in a channel
+    partner: Option<(MacAddress, u16, u16)>, // The LACP partner (System, Key, Port) from the last LACPDU
+    bundled: bool,                           // The port is an active member of its port-channel
+    carrier: bool, // The link was up when the switch last checked ; see `EthernetPort::has_carrier`
+}
+
+impl SwitchPort {
+    /// Returns true if a protection feature is blocking the port regardless of its STP state.
+    fn is_blocked(&self) -> bool {
+        self.err_disabled || self.root_inconsistent
+    }
+
+    /// Returns true if the port carries frames of the given VLAN.
+    fn is_member(&self, vlan: u16) -> bool {
+        self.trunk || self.vlan == vlan
+    }
+
+    /// Returns true if frames of the given VLAN leave the port untagged.
+    fn is_untagged_member(&self, vlan: u16) -> bool {
+        if self.trunk {
+            vlan == NATIVE_VLAN
+        } else {
+            self.vlan == vlan
+        }
+    }
+}
+
+/// The action taken when a port with port security receives a frame from a MAC address it doesn't allow.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ViolationMode {
+    Protect,  // Drop the frame
+    Restrict, // Drop the frame and count the violation
+    Shutdown, // Err-disable the port and count the violation
+}
+
+/// Port security settings; limits the source MAC addresses a port will learn.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PortSecurity {
+    pub maximum: usize, // Maximum number of secure MAC addresses, static, sticky and dynamic
+    pub sticky: bool,   // Learned addresses become part of the configuration
+    pub violation: ViolationMode,
+    pub secure_macs: Vec<MacAddress>, // Static and sticky addresses
+}
+
+impl Default for PortSecurity {
+    fn default() -> Self {
+        Self {
+            maximum: 1,
+            sticky: false,
+            violation: ViolationMode::Shutdown,
+            secure_macs: Vec::new(),
+        }
+    }
+}
+
+/// How frames are spread across the members of a port-channel. Frames of the same flow always leave the same member.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LoadBalance {
+    SrcDstMac, // Hash of the source and destination MAC addresses
+    SrcDstIp, // Hash of the source and destination IPv4 addresses ; MAC addresses for frames that aren't IPv4
+}
+
+/// A class of flooded traffic limited by storm control.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TrafficClass {
+    Broadcast,
+    Multicast,
+    UnknownUnicast, // Unicast frames to a destination that isn't in the MAC address table
+}
+
+/// The action taken when a port receives more frames of a traffic class than its storm control threshold.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StormAction {
+    Drop,     // Drop frames of the class until the next second
+    Shutdown, // Err-disable the port
+}
+
+/// Storm control settings; limits the flooded traffic a port accepts, in frames per second.
+#[derive(Debug, PartialEq, Clone)]
+pub struct StormControl {
+    pub broadcast: Option<u32>, // None for no limit
+    pub multicast: Option<u32>,
+    pub unknown_unicast: Option<u32>,
+    pub action: StormAction,
+}
+
+impl Default for StormControl {
+    fn default() -> Self {
+        Self {
+            broadcast: None,
+            multicast: None,
+            unknown_unicast: None,
+            action: StormAction::Drop,
+        }
+    }
+}
+
+impl StormControl {
+    pub fn threshold(&self, class: TrafficClass) -> Option<u32> {
+        match class {
+            TrafficClass::Broadcast => self.broadcast,
+            TrafficClass::Multicast => self.multicast,
+            TrafficClass::UnknownUnicast => self.unknown_unicast,
+        }
+    }
+
+    fn threshold_mut(&mut self, class: TrafficClass) -> &mut Option<u32> {
+        match class {
+            TrafficClass::Broadcast => &mut self.broadcast,
+            TrafficClass::Multicast => &mut self.multicast,
+            TrafficClass::UnknownUnicast => &mut self.unknown_unicast,
+        }
+    }
+}
+
+/// The traffic of a source port that a monitor session copies.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MirrorDirection {
+    Rx,   // Frames received on the port
+    Tx,   // Frames sent out of the port
+    Both, // Frames received on and sent out of the port
+}
+
+/// A Switched Port Analyzer (SPAN) session; copies the traffic of the source ports to the destination port.
+///
+/// The destination port is dedicated to the session. It drops received frames, is never flooded to and takes no part
+/// in STP, so mirrored frames can't start a loop.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MonitorSession {
+    pub sources: Vec<usize>,
+    pub direction: MirrorDirection,
+    pub destination: usize,
+}
+
+impl MonitorSession {
+    /// Returns true if the session copies frames received on (`rx`) or sent out of (`!rx`) the port.
+    fn mirrors(&self, port_id: usize, rx: bool) -> bool {
+        self.sources.contains(&port_id)
+            && match self.direction {
+                MirrorDirection::Rx => rx,
+                MirrorDirection::Tx => !rx,
+                MirrorDirection::Both => true,
+            }
+    }
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Clone)]
 enum SwitchDelayedAction {
     BpduMulticast,
     RstpInit,
+    ErrDisableRecovery(usize), // Port to bring back up
+    StormControlReset,
+    LacpPeriodic,
 }
 
-/// A layer two switch; forwards Ethernet frames to the correct interface.
+/// A single spanning tree instance; the CIST or an MSTI.
 ///
-/// Implements IEEE 802.1W Rapid Spanning Tree Protocol (RSTP) to prevent loops.
-pub struct Switch {
-    ports: [RefCell<SwitchPort>; 32],  // 32 physical ports
-    table: HashMap<MacAddress, usize>, // maps an address to the interface it's connected to.
-
-    pub mac_address: MacAddress,
-    bridge_priority: u16, // The priority of the switch in the spanning tree protocol. Lowest priority is the root bridge.
-
-    rstp: bool,               // if rstp is enabled
-    root_bid: u64,            // Root Bridge ID = Root MAC Address + Root Priority
-    root_cost: u32,           // The cost of the path to the root bridge ; 0 for the root bridge
+/// Holds the root election and the role and state of every port within the tree.
+#[derive(Debug, Clone)]
+struct SpanningTree {
+    ports: [RefCell<StpPort>; 32],
+    bid: u64, // Bridge ID of this switch within the tree = Bridge MAC Address + Bridge Priority
+    root_bid: u64, // Root Bridge ID = Root MAC Address + Root Priority
+    root_cost: u32, // The cost of the path to the root bridge ; 0 for the root bridge
     root_port: Option<usize>, // The port that leads to the root bridge ; None if the switch is the root bridge
-
-    timer: TickTimer<SwitchDelayedAction>,
-    missed_hellos: [u8; 32], // Number of missed hellos for each port, 3 missed hellos will trigger a topology change
-    received_bpdu: [bool; 32], // Whether a BPDU has been received on the port in the last 2 seconds
 }
 
-impl Switch {
-    /// Creates a new switch with 32 interfaces, each with a unique MAC address based on the given seed. All ports assume they
-    /// are designated ports. The switch is assumed to be the root bridge.
-    /// * `mac_seed` - The seed for the MAC addresses of the interfaces. Will take the range [mac_seed, mac_seed + 32].
-    /// * `bridge_priority` - The priority of the switch in the spanning tree protocol.
-    ///
-    /// # Example
-    /// ```
-    /// let switch = Switch::from_seed(1, 1);
-    /// ```
-    /// This will create a switch with the switch's MAC address as `mac_addr!(1)` and the interfaces MAC addresses as `mac_addr!(2)` through `mac_addr!(33)`.
-    /// The switch will have a bridge priority of 1.
-    pub fn from_seed(mac_seed: u64, bridge_priority: u16) -> Self {
-        let ports: [RefCell<SwitchPort>; 32] = (0..32)
+impl SpanningTree {
+    /// Creates a tree where the switch assumes it is the root bridge and all ports are forwarding.
+    fn new(bid: u64) -> Self {
+        let ports: [RefCell<StpPort>; 32] = (0..32)
             .map(|i| {
-                RefCell::new(SwitchPort {
-                    interface: EthernetInterface::new(mac_addr!(mac_seed + i + 1)),
+                RefCell::new(StpPort {
                     stp_state: StpState::Forwarding,
                     stp_role: None,
-                    id: i as usize,
+                    id: i,
                     root_cost: 0,
                     connected_root_bid: None,
                     connected_bid: None,
@@ -94,183 +268,1521 @@ impl Switch {
 
         Self {
             ports,
-            table: HashMap::new(),
-            bridge_priority,
-            mac_address: mac_addr!(mac_seed),
-            rstp: false,
-            root_bid: crate::bridge_id!(mac_addr!(mac_seed), bridge_priority), // Assume the switch is the root bridge
+            bid,
+            root_bid: bid, // Assume the switch is the root bridge
             root_cost: 0,
             root_port: None,
-            timer: TickTimer::default(),
-            missed_hellos: [0; 32],
-            received_bpdu: [false; 32],
         }
     }
 
-    /// Connects two ports together via EthernetPorts (bi-directional).
-    pub fn connect(&mut self, port_id: usize, interface: &mut EthernetInterface) {
-        self.ports[port_id]
-            .borrow_mut()
-            .interface
-            .connect(interface);
+    fn is_root_bridge(&self) -> bool {
+        self.root_bid == self.bid
+    }
 
-        if self.rstp {
-            self.ports[port_id].borrow_mut().interface.send8023(
-                crate::mac_bpdu_addr!(),
-                BpduFrame::hello(
-                    self.mac_address,
-                    self.root_bid,
-                    self.root_cost,
-                    self.bid(),
-                    port_id,
-                )
-                .to_bytes(),
-            );
+    /// Changes the bridge ID of the switch within the tree, assuming the switch is the root bridge.
+    fn set_bid(&mut self, bid: u64) {
+        self.bid = bid;
+        self.root_bid = bid;
+    }
+
+    /// Puts all ports into the Discarding state, except edge ports which go straight to Forwarding.
+    fn init(&mut self, edges: &[bool; 32]) {
+        for stp_port in self.ports.iter() {
+            let mut sp = stp_port.borrow_mut();
+            if edges[sp.id] {
+                // PortFast, assume an end device is connected
+                sp.stp_state = StpState::Forwarding;
+                sp.stp_role = Some(StpRole::Designated);
+                continue;
+            }
+            sp.stp_state = StpState::Discarding;
+            sp.stp_role = Some(StpRole::Root);
         }
     }
 
-    /// Shorthand for connecting two switches ports together via EthernetPorts (bi-directional).
-    pub fn connect_switch(
+    /// Opens all ports that haven't acted in the STP process to the Forwarding state.
+    fn finish_init(&mut self) {
+        for stp_port in self.ports.iter() {
+            if stp_port.borrow().connected_bid.is_none() {
+                stp_port.borrow_mut().stp_role = Some(StpRole::Designated);
+                stp_port.borrow_mut().stp_state = StpState::Forwarding;
+            }
+        }
+    }
+
+    fn link_down(&mut self, port_id: usize) {
+        let mut port = self.ports[port_id].borrow_mut();
+        port.connected_bid = None;
+        port.connected_role = None;
+        port.connected_root_bid = None;
+        port.stp_role = Some(StpRole::Designated);
+        port.stp_state = StpState::Forwarding;
+        port.root_cost = 0;
+    }
+
+    /// Updates the port with the neighbor's view of the tree.
+    ///
+    /// ## Returns
+    /// * `None` if this switch has the better root, the neighbor should be told with a hello BPDU.
+    /// * `Some((role_changed, root_changed))` after recalculating the port roles.
+    fn receive(
         &mut self,
         port_id: usize,
-        other_switch: &mut Switch,
-        other_port_id: usize,
-    ) {
-        self.connect(
-            port_id,
-            &mut other_switch.ports[other_port_id].borrow_mut().interface,
-        );
+        root_bid: u64,
+        bid: u64,
+        role: Option<StpRole>,
+        root_cost: u32,
+    ) -> Option<(bool, bool)> {
+        {
+            let mut sp = self.ports[port_id].borrow_mut();
+            sp.connected_root_bid = Some(root_bid);
+            sp.connected_bid = Some(bid);
+            sp.connected_role = role;
+            sp.root_cost = root_cost + 1;
+
+            match Switch::compare_bids(self.root_bid, root_bid) {
+                Some(true) => {
+                    if self.is_root_bridge() {
+                        // Only done during STP initialization
+                        sp.stp_role = Some(StpRole::Designated);
+                        sp.stp_state = StpState::Forwarding;
+                    }
+
+                    return None; // Role recalculations aren't necessary
+                }
+                Some(false) => {
+                    self.root_bid = root_bid; // Root is better
+                }
+                _ => {}
+            }
+        }
+
+        Some(self.calculate_port_roles())
     }
 
-    /// Forwards incoming frames to the correct interface based on the destination MAC address.
-    /// If the destination MAC address is not in the table, the frame is flooded to all interfaces.
-    ///
-    /// On a BPDU frame, it will update its port roles and states, and flood it's own BPDU if necessary.
-    pub fn forward(&mut self) {
-        for i in 0..32 {
-            let (state, frames) = {
-                let mut p = self.ports[i].borrow_mut();
-                let state = p.stp_state;
-                let frames = p.interface.receive();
-                (state, frames)
-            };
+    /// Returns (role_changed, root_changed)
+    fn calculate_port_roles(&mut self) -> (bool, bool) {
+        fn enter_election(tree: &mut SpanningTree) {
+            for stp_port in tree.ports.iter() {
+                let mut sp = stp_port.borrow_mut();
+                sp.stp_role = Some(StpRole::Designated);
+                sp.stp_state = StpState::Forwarding;
+                sp.root_cost = 0;
+                sp.connected_bid = None;
+                sp.connected_role = None;
+            }
 
-            for frame in frames {
-                if is_mac_multicast_or_broadcast!(frame.source_address()) {
-                    continue; // Invalid address; A source address cannot be a multicast or broadcast address
+            tree.root_bid = tree.bid;
+            tree.root_cost = 0;
+            tree.root_port = None;
+        }
+
+        if self.is_root_bridge() {
+            return (false, false);
+        }
+
+        let new_root_port = {
+            let mut rp: Option<(u32, u64, usize)> = None; // (root_cost, bid, port_id)
+
+            for stp_port in self.ports.iter() {
+                let sp = stp_port.borrow();
+                if sp.connected_root_bid != Some(self.root_bid) || sp.connected_bid.is_none() {
+                    continue;
                 }
 
-                match frame {
-                    EthernetFrame::Ethernet2(f) => {
-                        if state != StpState::Discarding {
-                            self._receive_ethernet2(f, i);
-                        }
+                if let Some((root_cost, bid, _)) = rp {
+                    if sp.root_cost > root_cost {
+                        continue;
                     }
-                    EthernetFrame::Ethernet802_3(f) => {
-                        if !self.rstp {
-                            continue; // TODO: Switch should just forward the frame if RSTP is disabled
-                        }
-                        if let Ok(bpdu) = BpduFrame::from_bytes(f.data) {
-                            self._receive_bpdu(bpdu, i);
-                        }
+
+                    let compare = Switch::compare_bids(sp.connected_bid.unwrap(), bid)
+                        .expect("Bids are equivalent");
+                    if sp.root_cost < root_cost || compare {
+                        rp = Some((sp.root_cost, sp.connected_bid.unwrap(), sp.id));
                     }
+                    continue;
+                }
+
+                if let Some(connected_bid) = sp.connected_bid {
+                    rp = Some((sp.root_cost, connected_bid, sp.id));
                 }
             }
-        }
-    }
 
-    fn _receive_ethernet2(&mut self, f: Ethernet2Frame, port: usize) {
-        // If the sender MAC address is not in the table, add it.
-        self.table.entry(f.source_address).or_insert(port);
+            rp
+        };
 
-        // If the destination MAC address is in the table, forward the mapped interface
-        if let Some(destination_index) = self.table.get(&f.destination_address) {
-            self.ports[*destination_index].borrow_mut().interface.sendv(
-                f.source_address,
-                f.destination_address,
-                f.ether_type,
-                f.data,
-            );
-            return;
+        let (new_root_cost, new_root_port_id, root_changed) = match new_root_port {
+            Some((root_cost, _, root_port_id)) => {
+                if self.root_port.is_some_and(|rp| rp != root_port_id)
+                    && self.ports[root_port_id].borrow().stp_role != Some(StpRole::Alternate)
+                {
+                    enter_election(self); // A new root port has been found, but it isn't an alternate port.
+                    return (true, true);
+                }
+                (
+                    root_cost,
+                    root_port_id,
+                    self.root_port != Some(root_port_id),
+                )
+            }
+            None => {
+                enter_election(self); // There is no port leading to the root bridge.
+                return (true, true);
+            }
+        };
+
+        self.root_port = Some(new_root_port_id);
+        self.root_cost = new_root_cost;
+        self.ports[new_root_port_id].borrow_mut().stp_role = Some(StpRole::Root);
+        self.ports[new_root_port_id].borrow_mut().stp_state = StpState::Forwarding;
+        let mut role_changed = root_changed;
+
+        let segment_to_port = {
+            let mut segment_to_port: HashMap<u64, usize> = HashMap::new();
+            for stp_port in self.ports.iter() {
+                let mut sp = stp_port.borrow_mut();
+                if sp.connected_bid.is_none() || sp.id == new_root_port_id {
+                    continue;
+                }
+
+                sp.root_cost = std::cmp::max(sp.root_cost, new_root_cost); // Cost cannot be less than the root cost
+
+                if let Some(min_port_id) = segment_to_port.get(&sp.connected_bid.unwrap()) {
+                    let bid = sp.connected_bid.unwrap();
+                    let min_bid = self.ports[*min_port_id].borrow().connected_bid.unwrap();
+                    let min_cost = self.ports[*min_port_id].borrow().root_cost;
+
+                    let is_min = {
+                        if min_cost != sp.root_cost {
+                            sp.root_cost < self.ports[*min_port_id].borrow().root_cost
+                        }
+                        // Tiebreaker: Compare by port number if the bids are equivalent
+                        else if bid == min_bid {
+                            sp.id > *min_port_id
+                        }
+                        // Tiebreaker: Compare by bid if the costs are equal
+                        else {
+                            Switch::compare_bids(bid, min_bid).expect("Bids are equivalent")
+                        }
+                    };
+
+                    if is_min {
+                        segment_to_port.insert(bid, sp.id);
+                    }
+
+                    continue;
+                }
+                segment_to_port.insert(sp.connected_bid.unwrap(), sp.id);
+            }
+            segment_to_port
+        };
+
+        for stp_port in self.ports.iter() {
+            let mut sp = stp_port.borrow_mut();
+            let prev_role = sp.stp_role;
+
+            if sp.connected_bid.is_none()
+                || sp.connected_role.is_none()
+                || sp.id == self.root_port.unwrap()
+            {
+                continue; // Port doesnt participate in STP, or is the root port (which is already determined `find_root_port`)
+            }
+
+            let bid = sp.connected_bid.unwrap();
+            if bid == self.root_bid {
+                sp.stp_role = Some(StpRole::Alternate);
+                sp.stp_state = StpState::Discarding;
+            } else if !segment_to_port.contains_key(&bid) {
+                sp.stp_role = Some(StpRole::Backup);
+                sp.stp_state = StpState::Discarding;
+            } else {
+                sp.stp_role = Some(StpRole::Designated);
+                sp.stp_state = StpState::Forwarding;
+            }
+
+            let conn_role = sp.connected_role.unwrap();
+            // Never block another switches root port
+            if conn_role == StpRole::Root {
+                sp.stp_role = Some(StpRole::Designated);
+                sp.stp_state = StpState::Forwarding;
+            }
+            // Designated to designated is a redundancy
+            else if conn_role == StpRole::Designated && sp.stp_role == Some(StpRole::Designated) {
+                // Only one of the switches has to block.
+                let compare = Switch::compare_bids(self.bid, bid);
+                match compare {
+                    Some(true) => {
+                        continue; // This switch wins, stay designated
+                    }
+                    Some(false) => {
+                        sp.stp_role = Some(StpRole::Backup);
+                        sp.stp_state = StpState::Discarding;
+                    }
+                    None => {
+                        todo!("Further tiebreakers. Will not occur in simulation.");
+                    }
+                }
+            }
+
+            if prev_role != sp.stp_role {
+                role_changed = true;
+            }
+        }
+
+        (role_changed, root_changed)
+    }
+}
+
+/// Multiple Spanning Tree (IEEE 802.1s) region configuration.
+///
+/// Neighbors with the same name, revision and VLAN to instance mapping belong to the same region and share their MSTIs.
+/// VLANs that aren't mapped to an instance belong to the IST (instance 0).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MstConfig {
+    pub name: String,
+    pub revision: u16,
+    instances: BTreeMap<u16, u8>, // VLAN => MSTI
+    digest: [u8; 16],             // Digest of `instances`, updated whenever they change
+}
+
+impl MstConfig {
+    pub fn new(name: &str, revision: u16) -> Self {
+        Self {
+            name: name.to_string(),
+            revision,
+            instances: BTreeMap::new(),
+            digest: Self::digest(&BTreeMap::new()),
+        }
+    }
+
+    /// Maps the VLANs to the instance. Instance 0 returns the VLANs to the IST.
+    fn map(&mut self, instance: u8, vlans: &[u16]) {
+        for vlan in vlans {
+            if instance == CIST {
+                self.instances.remove(vlan);
+            } else {
+                self.instances.insert(*vlan, instance);
+            }
+        }
+        self.digest = Self::digest(&self.instances);
+    }
+
+    /// Returns the instance the VLAN is mapped to.
+    pub fn instance(&self, vlan: u16) -> u8 {
+        self.instances.get(&vlan).copied().unwrap_or(CIST)
+    }
+
+    /// Returns the VLANs mapped to the instance.
+    pub fn vlans(&self, instance: u8) -> Vec<u16> {
+        self.instances
+            .iter()
+            .filter(|(_, i)| **i == instance)
+            .map(|(v, _)| *v)
+            .collect()
+    }
+
+    /// Digest of the VLAN to instance table.
+    //
+    // The standard uses HMAC-MD5 over the 4096 entry table. Switches in the simulation only compare digests with each other,
+    // so a FNV-1a hash of the table is used instead.
+    fn digest(instances: &BTreeMap<u16, u8>) -> [u8; 16] {
+        let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+        for vlan in 0..4096u16 {
+            let instance = instances.get(&vlan).copied().unwrap_or(CIST) as u16;
+            for byte in instance.to_be_bytes() {
+                hash ^= byte as u128;
+                hash = hash.wrapping_mul(0x0000000001000000000000000000013B);
+            }
+        }
+        hash.to_be_bytes()
+    }
+
+    /// The MST Configuration Identifier carried in MSTP BPDUs.
+    fn identifier(&self) -> MstConfigId {
+        let mut name = [0u8; 32];
+        for (i, byte) in self.name.bytes().take(32).enumerate() {
+            name[i] = byte;
+        }
+
+        MstConfigId {
+            format_selector: 0,
+            name,
+            revision: self.revision,
+            digest: self.digest,
+        }
+    }
+}
+
+/// A layer two switch; forwards Ethernet frames to the correct interface.
+///
+/// Implements IEEE 802.1W Rapid Spanning Tree Protocol (RSTP) to prevent loops, and IEEE 802.1s Multiple Spanning Tree
+/// Protocol (MSTP) to run a separate tree per group of VLANs.
+///
+/// IGMP snooping limits IPv4 multicast to the ports with members of the group.
+///
+/// Ports can be bundled into port-channels with IEEE 802.3ad Link Aggregation Control Protocol (LACP). The lowest active
+/// member of a channel is its logical port; STP and the MAC address table only see the logical port.
+#[derive(Clone)]
+pub struct Switch {
+    ports: [RefCell<SwitchPort>; 32],         // 32 physical ports
+    table: HashMap<(MacAddress, u16), usize>, // maps an address and VLAN to the interface it's connected to.
+
+    pub mac_address: MacAddress,
+    bridge_priority: u16, // The priority of the switch in the spanning tree protocol. Lowest priority is the root bridge.
+
+    rstp: bool,                        // if rstp is enabled
+    cist: SpanningTree, // The spanning tree shared by all VLANs that aren't mapped to an MSTI
+    mst: Option<MstConfig>, // The MST region ; None if the switch runs RSTP
+    mstis: BTreeMap<u8, SpanningTree>, // Multiple Spanning Tree Instances
+
+    timer: TickTimer<SwitchDelayedAction>,
+    missed_hellos: [u8; 32], // Number of missed hellos for each port, 3 missed hellos will trigger a topology change
+    received_bpdu: [bool; 32], // Whether a BPDU has been received on the port in the last 2 seconds
+    errdisable_recovery: Option<u64>, // Seconds until an err-disabled port is brought back up ; None to stay down
+    security_violations: [u32; 32],   // Number of port security violations on each port
+    storm_counts: [[u32; 3]; 32], // Frames of each traffic class received on each port in the current second
+    storm_drops: [u32; 32],       // Number of frames dropped by storm control on each port
+    load_balance: LoadBalance,    // How frames are spread across port-channel members
+    lacp_received: [bool; 32], // Whether a LACPDU has been received on the port in the last second
+    lacp_missed: [u8; 32], // Number of missed LACPDUs for each port, 3 missed LACPDUs remove the port from its channel
+    monitor: Option<MonitorSession>, // The SPAN session ; None if no traffic is mirrored
+    igmp_snooping: bool,
+    igmp_groups: HashMap<(Ipv4Address, u16), BTreeSet<usize>>, // maps a multicast group and VLAN to its member ports
+    mrouter_ports: BTreeSet<usize>, // Ports that lead to a multicast router (IGMP querier)
+    log: EventLog,
+    stp_logged: HashMap<(u8, usize), (Option<StpRole>, StpState)>, // (Instance, Port) => The last logged role and state
+}
+
+impl Switch {
+    /// Creates a new switch with 32 interfaces, each with a unique MAC address based on the given seed. All ports assume they
+    /// are designated ports. The switch is assumed to be the root bridge.
+    /// * `mac_seed` - The seed for the MAC addresses of the interfaces. Will take the range [mac_seed, mac_seed + 32].
+    /// * `bridge_priority` - The priority of the switch in the spanning tree protocol.
+    ///
+    /// # Example
+    /// ```
+    /// let switch = Switch::from_seed(1, 1);
+    /// ```
+    /// This will create a switch with the switch's MAC address as `mac_addr!(1)` and the interfaces MAC addresses as `mac_addr!(2)` through `mac_addr!(33)`.
+    /// The switch will have a bridge priority of 1.
+    pub fn from_seed(mac_seed: u64, bridge_priority: u16) -> Self {
+        let ports: [RefCell<SwitchPort>; 32] = (0..32)
+            .map(|i| {
+                RefCell::new(SwitchPort {
+                    interface: EthernetInterface::new(mac_addr!(mac_seed + i + 1)),
+                    vlan: NATIVE_VLAN,
+                    trunk: false,
+                    stp_enabled: true,
+                    edge: false,
+                    bpdu_guard: false,
+                    root_guard: false,
+                    root_inconsistent: false,
+                    err_disabled: false,
+                    security: None,
+                    storm_control: StormControl::default(),
+                    boundary: false,
+                    channel: None,
+                    partner: None,
+                    bundled: false,
+                    carrier: false,
+                })
+            })
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+
+        let mut switch = Self {
+            ports,
+            table: HashMap::new(),
+            bridge_priority,
+            mac_address: mac_addr!(mac_seed),
+            rstp: false,
+            cist: SpanningTree::new(crate::bridge_id!(mac_addr!(mac_seed), bridge_priority)),
+            mst: None,
+            mstis: BTreeMap::new(),
+            timer: TickTimer::default(),
+            missed_hellos: [0; 32],
+            received_bpdu: [false; 32],
+            errdisable_recovery: None,
+            security_violations: [0; 32],
+            storm_counts: [[0; 3]; 32],
+            storm_drops: [0; 32],
+            load_balance: LoadBalance::SrcDstMac,
+            lacp_received: [false; 32],
+            lacp_missed: [0; 32],
+            monitor: None,
+            igmp_snooping: true,
+            igmp_groups: HashMap::new(),
+            mrouter_ports: BTreeSet::new(),
+            log: EventLog::default(),
+            stp_logged: HashMap::new(),
+        };
+        switch._log_stp_changes(); // The initial roles and states aren't changes
+        switch
+    }
+
+    /// Sets the clock timing the switch's spanning tree, LACP, storm control and errdisable recovery.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        self.timer.set_clock(clock);
+    }
+
+    /// Moves the switch onto the copies of its ports, see `PortMap`.
+    pub fn relink(&mut self, ports: &mut PortMap) {
+        for port in &self.ports {
+            port.borrow_mut().interface.relink(ports);
+        }
+    }
+
+    /// Connects two ports together via EthernetPorts (bi-directional).
+    pub fn connect(&mut self, port_id: usize, interface: &mut EthernetInterface) {
+        self.ports[port_id]
+            .borrow_mut()
+            .interface
+            .connect(interface);
+
+        self._detect_carrier(port_id);
+    }
+
+    /// Shorthand for connecting two switches ports together via EthernetPorts (bi-directional).
+    pub fn connect_switch(
+        &mut self,
+        port_id: usize,
+        other_switch: &mut Switch,
+        other_port_id: usize,
+    ) {
+        self.connect(
+            port_id,
+            &mut other_switch.ports[other_port_id].borrow_mut().interface,
+        );
+    }
+
+    /// Forwards incoming frames to the correct interface based on the destination MAC address.
+    /// If the destination MAC address is not in the table, the frame is flooded to all interfaces in the same VLAN.
+    ///
+    /// On a BPDU frame, it will update its port roles and states, and flood it's own BPDU if necessary.
+    pub fn forward(&mut self) {
+        for i in 0..32 {
+            self._detect_carrier(i);
+        }
+
+        for i in 0..32 {
+            let frames = {
+                let mut p = self.ports[i].borrow_mut();
+                let frames = p.interface.receive_traced();
+                if p.err_disabled || self._is_monitor_destination(i) {
+                    continue; // Drop everything on an err-disabled port or a SPAN destination
+                }
+                frames
+            };
+
+            // The frames sent on behalf of a received frame join its trace, except for control frames
+            let tracer = self.ports[i].borrow().interface.tracer();
+            for (trace, frame) in frames {
+                tracer.enter(trace);
+                if self.ports[i].borrow().err_disabled {
+                    break; // Shut down while receiving
+                }
+
+                if is_mac_multicast_or_broadcast!(frame.source_address()) {
+                    continue; // Invalid address; A source address cannot be a multicast or broadcast address
+                }
+
+                // Frames received on a member of a port-channel belong to the channel's logical port
+                let port = self._logical(i);
+
+                match frame {
+                    EthernetFrame::Ethernet2(f) => {
+                        if f.ether_type == EtherType::SlowProtocols {
+                            // LACPDUs are consumed by the switch, slow protocols never leave the link
+                            self.ports[i].borrow().interface.trace(TraceStep::Delivered);
+                            tracer.leave();
+                            if let Ok(lacpdu) = Lacpdu::from_bytes(f.data) {
+                                self._receive_lacpdu(lacpdu, i);
+                            }
+                            continue;
+                        }
+
+                        let (tag, f) = f.untag();
+                        let vlan = match self._ingress_vlan(i, tag) {
+                            Some(vlan) => vlan,
+                            None => continue, // The port doesn't carry the VLAN
+                        };
+
+                        if self.is_port_forwarding(port, vlan) {
+                            if self._is_mirrored(i, true) {
+                                match tag {
+                                    Some(tag) => self._mirror_ethernet2(&f.clone().tag(tag)),
+                                    None => self._mirror_ethernet2(&f),
+                                }
+                            }
+                            self._receive_ethernet2(f, port, vlan);
+                        }
+                    }
+                    EthernetFrame::Ethernet802_3(f) => {
+                        if self.rstp && f.destination_address == crate::mac_bpdu_addr!() {
+                            // BPDUs are consumed by the switch, and dropped on ports that don't participate in STP
+                            if !self.ports[port].borrow().stp_enabled {
+                                continue;
+                            }
+                            self.ports[i].borrow().interface.trace(TraceStep::Delivered);
+                            tracer.leave();
+                            if let Ok(bpdu) = BpduFrame::from_frame(&f) {
+                                self._receive_bpdu(bpdu, port);
+                            }
+                            continue;
+                        }
+
+                        // Any other LLC frame is ordinary traffic on the port's untagged VLAN
+                        let vlan = self._ingress_vlan(i, None).unwrap();
+                        if self.is_port_forwarding(port, vlan) {
+                            if self._is_mirrored(i, true) {
+                                self._mirror_ethernet802_3(&f);
+                            }
+                            self._receive_ethernet802_3(f, port, vlan);
+                        }
+                    }
+                }
+            }
+            tracer.leave();
+        }
+    }
+
+    fn _receive_ethernet2(&mut self, f: Ethernet2Frame, port: usize, vlan: u16) {
+        let hash = self._flow_hash(&f);
+        let mut egress =
+            self._egress_ports(f.source_address, f.destination_address, port, vlan, hash);
+
+        if let Some(allowed) = self._snoop(&f, port, vlan) {
+            egress.retain(|i| allowed.contains(&self._logical(*i)));
+        }
+
+        for i in egress {
+            self._send_ethernet2(i, f.clone(), vlan);
+        }
+    }
+
+    fn _receive_ethernet802_3(&mut self, f: Ethernet802_3Frame, port: usize, vlan: u16) {
+        let hash = Self::_mac_hash(f.source_address, f.destination_address);
+        for i in self._egress_ports(f.source_address, f.destination_address, port, vlan, hash) {
+            // 802.3 frames can't carry a tag, they only leave ports where the VLAN is untagged
+            if self.ports[i].borrow().is_untagged_member(vlan) {
+                self.ports[i].borrow_mut().interface.send8023_frame(&f);
+                if self._is_mirrored(i, false) {
+                    self._mirror_ethernet802_3(&f);
+                }
+            }
+        }
+    }
+
+    /// Learns the source address and returns the ports a frame should be sent out of.
+    /// * `source` - The source MAC address of the frame.
+    /// * `destination` - The destination MAC address of the frame.
+    /// * `port` - The (logical) port the frame was received on.
+    /// * `vlan` - The VLAN of the frame.
+    /// * `hash` - The flow hash of the frame ; picks the port-channel member the frame leaves through.
+    fn _egress_ports(
+        &mut self,
+        source: MacAddress,
+        destination: MacAddress,
+        port: usize,
+        vlan: u16,
+        hash: usize,
+    ) -> Vec<usize> {
+        if !self._secure_source(source, port, vlan) {
+            self._log_drop(port, DropReason::PortSecurity);
+            return vec![];
+        }
+
+        // If the sender MAC address is not in the table, add it.
+        if let Entry::Vacant(entry) = self.table.entry((source, vlan)) {
+            entry.insert(port);
+            self.log
+                .record(Some(port), LogEvent::MacLearned { mac: source, vlan });
+        }
+
+        let class = if destination == crate::mac_broadcast_addr!() {
+            Some(TrafficClass::Broadcast)
+        } else if is_mac_multicast_or_broadcast!(destination) {
+            Some(TrafficClass::Multicast)
+        } else if !self.table.contains_key(&(destination, vlan)) {
+            Some(TrafficClass::UnknownUnicast)
+        } else {
+            None
+        };
+        if class.is_some_and(|class| !self._storm_control(port, class)) {
+            self._log_drop(port, DropReason::StormControl);
+            return vec![];
+        }
+
+        // If the destination MAC address is in the table, forward the mapped interface
+        if let Some(destination_index) = self.table.get(&(destination, vlan)).copied() {
+            if self.is_port_forwarding(destination_index, vlan) {
+                return vec![self._member(destination_index, hash)];
+            }
+            return vec![];
+        }
+
+        // Destination isn't in table, flood to all interfaces in the VLAN (except the one it came from, disabled ports and
+        // ports without a link). A port-channel is flooded to once, through one of its members
+        if let Some(class) = class {
+            self.log.record(
+                Some(port),
+                LogEvent::Flooded {
+                    destination,
+                    vlan,
+                    class,
+                },
+            );
+        }
+        (0..32)
+            .filter(|i| {
+                *i != port
+                    && !self._is_monitor_destination(*i)
+                    && self._logical(*i) == *i
+                    && self.ports[*i].borrow().is_member(vlan)
+                    && self.ports[*i].borrow().carrier
+                    && self.is_port_forwarding(*i, vlan)
+            })
+            .map(|i| self._member(i, hash))
+            .collect()
+    }
+
+    fn _log_drop(&mut self, port: usize, reason: DropReason) {
+        self.ports[port]
+            .borrow()
+            .interface
+            .trace(TraceStep::Dropped(reason));
+        self.log.record(
+            Some(port),
+            LogEvent::Dropped {
+                reason,
+                destination: None,
+            },
+        );
+    }
+
+    /// Records the ports whose role or state changed in any spanning tree since the switch last looked at them. Ports
+    /// without a link change silently.
+    fn _log_stp_changes(&mut self) {
+        let trees = std::iter::once((CIST, &self.cist))
+            .chain(self.mstis.iter().map(|(instance, tree)| (*instance, tree)));
+        for (instance, tree) in trees {
+            for (i, port) in tree.ports.iter().enumerate() {
+                let port = port.borrow();
+                let current = (port.stp_role, port.stp_state);
+                if self
+                    .stp_logged
+                    .insert((instance, i), current)
+                    .is_some_and(|logged| logged != current)
+                    && self.ports[i]
+                        .borrow()
+                        .interface
+                        .port()
+                        .borrow()
+                        .has_carrier()
+                {
+                    self.log.record(
+                        Some(i),
+                        LogEvent::StpChanged {
+                            instance,
+                            role: port.stp_role,
+                            state: port.stp_state,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the addresses learned, frames flooded and dropped, and spanning tree changes of the switch,
+    /// with the port each concerns.
+    pub fn take_events(&mut self) -> Vec<(Option<usize>, LogEvent)> {
+        self.log.take()
+    }
+
+    /// Returns the VLAN of a frame received on the port, None if the port doesn't carry the frame's VLAN.
+    /// * `tag` - The VLAN ID of the frame's 802.1Q tag, None if the frame is untagged.
+    fn _ingress_vlan(&self, port_id: usize, tag: Option<u16>) -> Option<u16> {
+        let sp = self.ports[port_id].borrow();
+        match tag {
+            None | Some(0) if sp.trunk => Some(NATIVE_VLAN),
+            None | Some(0) => Some(sp.vlan),
+            Some(vlan) if sp.trunk || vlan == sp.vlan => Some(vlan),
+            Some(_) => None,
+        }
+    }
+
+    /// Sends the frame out of the port, tagging it if the port is a trunk and the VLAN isn't native.
+    fn _send_ethernet2(&self, port_id: usize, f: Ethernet2Frame, vlan: u16) {
+        let mut sp = self.ports[port_id].borrow_mut();
+        let f = if sp.trunk && vlan != NATIVE_VLAN {
+            f.tag(vlan)
+        } else {
+            f
+        };
+
+        if self._is_mirrored(port_id, false) {
+            self._mirror_ethernet2(&f);
+        }
+
+        sp.interface.sendv(
+            f.source_address,
+            f.destination_address,
+            f.ether_type,
+            f.data,
+        );
+    }
+
+    /// Returns a list of all the physical EthernetPorts on the switch.
+    pub fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
+        self.ports
+            .iter()
+            .map(|i| i.borrow().interface.port().clone())
+            .collect()
+    }
+
+    /// Returns the STP state of the port. A shut down port is never up.
+    pub fn is_port_up(&self, port_id: usize) -> bool {
+        if self.is_shutdown(port_id) {
+            return false;
+        }
+
+        let port_id = self._logical(port_id);
+        let sp = self.ports[port_id].borrow();
+        !sp.is_blocked()
+            && (!sp.stp_enabled
+                || self.cist.ports[port_id].borrow().stp_state != StpState::Discarding)
+    }
+
+    /// Returns true if frames of the VLAN are forwarded on the port, considering the spanning tree the VLAN belongs to.
+    pub fn is_port_forwarding(&self, port_id: usize, vlan: u16) -> bool {
+        let port_id = self._logical(port_id);
+        let sp = self.ports[port_id].borrow();
+        if sp.is_blocked() {
+            return false;
+        }
+
+        if !sp.stp_enabled {
+            return true;
+        }
+
+        let tree = if sp.boundary {
+            &self.cist
+        } else {
+            self._tree(self.instance(vlan))
+        };
+        tree.ports[port_id].borrow().stp_state != StpState::Discarding
+    }
+
+    /// Returns true if the port has been shut down by BPDU Guard, port security or storm control.
+    pub fn is_err_disabled(&self, port_id: usize) -> bool {
+        self.ports[port_id].borrow().err_disabled
+    }
+
+    /// Returns true if Root Guard is blocking the port.
+    pub fn is_root_inconsistent(&self, port_id: usize) -> bool {
+        self.ports[port_id].borrow().root_inconsistent
+    }
+
+    pub fn bridge_priority(&self) -> u16 {
+        self.bridge_priority
+    }
+
+    pub fn root_bid(&self) -> u64 {
+        self.cist.root_bid
+    }
+
+    pub fn root_cost(&self) -> u32 {
+        self.cist.root_cost
+    }
+
+    pub fn root_port(&self) -> Option<usize> {
+        self.cist.root_port
+    }
+
+    pub fn mac_addr(&self, port_id: usize) -> MacAddress {
+        self.ports[port_id].borrow().interface.mac_address
+    }
+
+    pub fn set_bridge_priority(&mut self, priority: u16) {
+        self.bridge_priority = priority;
+        self.cist
+            .set_bid(crate::bridge_id!(self.mac_address, priority));
+    }
+
+    /// Returns the MAC address table, (MAC Address, VLAN) => Port
+    pub fn mac_table(&self) -> HashMap<(MacAddress, u16), usize> {
+        self.table.clone()
+    }
+
+    /// Returns all ports in the designated role.
+    #[cfg(test)]
+    pub(crate) fn designated_ports(&self) -> Vec<usize> {
+        self.instance_designated_ports(CIST)
+    }
+
+    /// Returns all ports in the discarding state.
+    #[cfg(test)]
+    pub(crate) fn discarding_ports(&self) -> Vec<usize> {
+        (0..32).filter(|i| !self.is_port_up(*i)).collect()
+    }
+
+    /// Returns all ports in the designated role of a spanning tree instance.
+    #[cfg(test)]
+    pub(crate) fn instance_designated_ports(&self, instance: u8) -> Vec<usize> {
+        self._tree(instance)
+            .ports
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.borrow().stp_role == Some(StpRole::Designated))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Returns all ports in the discarding state of a spanning tree instance.
+    #[cfg(test)]
+    pub(crate) fn instance_discarding_ports(&self, instance: u8) -> Vec<usize> {
+        self._tree(instance)
+            .ports
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| {
+                self.ports[*i].borrow().is_blocked() || p.borrow().stp_state == StpState::Discarding
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+// Virtual LAN (VLAN) methods
+impl Switch {
+    /// Sets the access VLAN of a port. Untagged frames received on the port belong to the VLAN.
+    pub fn set_access_vlan(&mut self, port_id: usize, vlan: u16) {
+        self.ports[port_id].borrow_mut().vlan = vlan;
+        self.table.retain(|_, p| *p != port_id);
+    }
+
+    /// Sets the port as a trunk, carrying every VLAN with IEEE 802.1Q tags. Frames on the native VLAN are untagged.
+    pub fn set_trunk(&mut self, port_id: usize, trunk: bool) {
+        self.ports[port_id].borrow_mut().trunk = trunk;
+        self.table.retain(|_, p| *p != port_id);
+    }
+
+    /// Returns (access VLAN, trunk) of the port.
+    pub fn port_vlan(&self, port_id: usize) -> (u16, bool) {
+        let sp = self.ports[port_id].borrow();
+        (sp.vlan, sp.trunk)
+    }
+}
+
+// Port security methods
+impl Switch {
+    /// Enables or disables port security on a port. Enabling uses the defaults of a single secure address and the
+    /// shutdown violation mode, and flushes the addresses learned on the port.
+    pub fn set_port_security(&mut self, port_id: usize, enabled: bool) {
+        self.ports[port_id].borrow_mut().security = if enabled {
+            Some(PortSecurity::default())
+        } else {
+            None
+        };
+        self.table.retain(|_, p| *p != port_id);
+    }
+
+    /// Sets the maximum number of secure MAC addresses on a port.
+    pub fn set_port_security_maximum(
+        &mut self,
+        port_id: usize,
+        maximum: usize,
+    ) -> Result<(), &'static str> {
+        if maximum == 0 {
+            return Err("Maximum must be at least 1.");
+        }
+
+        let mut sp = self.ports[port_id].borrow_mut();
+        let security = sp
+            .security
+            .as_mut()
+            .ok_or("Port security is not enabled.")?;
+        if security.secure_macs.len() > maximum {
+            return Err("Port has more secure addresses than the maximum.");
+        }
+
+        security.maximum = maximum;
+        Ok(())
+    }
+
+    /// Sets the action taken on a port security violation.
+    pub fn set_port_security_violation(
+        &mut self,
+        port_id: usize,
+        mode: ViolationMode,
+    ) -> Result<(), &'static str> {
+        let mut sp = self.ports[port_id].borrow_mut();
+        let security = sp
+            .security
+            .as_mut()
+            .ok_or("Port security is not enabled.")?;
+        security.violation = mode;
+        Ok(())
+    }
+
+    /// Enables or disables sticky learning on a port. Enabling converts the addresses already learned on the port into
+    /// sticky secure addresses.
+    pub fn set_port_security_sticky(
+        &mut self,
+        port_id: usize,
+        sticky: bool,
+    ) -> Result<(), &'static str> {
+        let learned: Vec<MacAddress> = self
+            .table
+            .iter()
+            .filter(|(_, p)| **p == port_id)
+            .map(|((mac, _), _)| *mac)
+            .collect();
+
+        let mut sp = self.ports[port_id].borrow_mut();
+        let security = sp
+            .security
+            .as_mut()
+            .ok_or("Port security is not enabled.")?;
+        security.sticky = sticky;
+
+        if sticky {
+            for mac in learned {
+                if !security.secure_macs.contains(&mac) {
+                    security.secure_macs.push(mac);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a static secure MAC address to a port.
+    pub fn add_secure_mac(&mut self, port_id: usize, mac: MacAddress) -> Result<(), &'static str> {
+        if is_mac_multicast_or_broadcast!(mac) {
+            return Err("Secure address cannot be multicast or broadcast.");
+        }
+
+        let mut sp = self.ports[port_id].borrow_mut();
+        let security = sp
+            .security
+            .as_mut()
+            .ok_or("Port security is not enabled.")?;
+        if security.secure_macs.contains(&mac) {
+            return Ok(());
+        }
+
+        if security.secure_macs.len() >= security.maximum {
+            return Err("Port already has the maximum number of secure addresses.");
+        }
+
+        security.secure_macs.push(mac);
+        Ok(())
+    }
+
+    /// Returns the port security settings of a port, None if port security is disabled.
+    pub fn port_security(&self, port_id: usize) -> Option<PortSecurity> {
+        self.ports[port_id].borrow().security.clone()
+    }
+
+    /// Returns the number of port security violations on a port.
+    pub fn security_violations(&self, port_id: usize) -> u32 {
+        self.security_violations[port_id]
+    }
+
+    /// Checks the source address of a frame against port security, handling the violation if it isn't allowed.
+    /// Sticky ports add newly learned addresses to their secure addresses.
+    ///
+    /// ## Returns
+    /// True if the frame may be learned and forwarded.
+    fn _secure_source(&mut self, source: MacAddress, port_id: usize, vlan: u16) -> bool {
+        // A secure address of another port on the same VLAN is a spoofed address
+        let spoofed = self.ports.iter().enumerate().any(|(i, p)| {
+            let p = p.borrow();
+            i != port_id
+                && p.is_member(vlan)
+                && p.security
+                    .as_ref()
+                    .is_some_and(|s| s.secure_macs.contains(&source))
+        });
+
+        let mode = {
+            let mut sp = self.ports[port_id].borrow_mut();
+            let security = match sp.security.as_mut() {
+                None => return !spoofed, // Dropped, but only a secure port counts violations
+                Some(security) => security,
+            };
+
+            let allowed = if spoofed {
+                false
+            } else if security.secure_macs.contains(&source) {
+                true
+            } else {
+                let mut addresses: Vec<MacAddress> = security.secure_macs.clone();
+                for ((mac, _), p) in self.table.iter() {
+                    if *p == port_id && !addresses.contains(mac) {
+                        addresses.push(*mac);
+                    }
+                }
+
+                let learned = addresses.contains(&source);
+                if !learned && addresses.len() >= security.maximum {
+                    false
+                } else {
+                    if security.sticky && !security.secure_macs.contains(&source) {
+                        security.secure_macs.push(source);
+                    }
+                    true
+                }
+            };
+
+            if allowed {
+                return true;
+            }
+            security.violation
+        };
+
+        match mode {
+            ViolationMode::Protect => {}
+            ViolationMode::Restrict => self.security_violations[port_id] += 1,
+            ViolationMode::Shutdown => {
+                self.security_violations[port_id] += 1;
+                self._err_disable(port_id);
+            }
+        }
+        false
+    }
+}
+
+// IGMP snooping methods
+impl Switch {
+    /// Enables or disables IGMP snooping. Without snooping, IPv4 multicast is flooded like any other multicast.
+    pub fn set_igmp_snooping(&mut self, enabled: bool) {
+        self.igmp_snooping = enabled;
+        if !enabled {
+            self.igmp_groups.clear();
+            self.mrouter_ports.clear();
+        }
+    }
+
+    pub fn is_igmp_snooping(&self) -> bool {
+        self.igmp_snooping
+    }
+
+    /// Returns the multicast groups learned by IGMP snooping, (Group, VLAN) => Member ports
+    pub fn igmp_groups(&self) -> HashMap<(Ipv4Address, u16), BTreeSet<usize>> {
+        self.igmp_groups.clone()
+    }
+
+    /// Returns the ports that IGMP queries were received on.
+    pub fn mrouter_ports(&self) -> Vec<usize> {
+        self.mrouter_ports.iter().copied().collect()
+    }
+
+    /// Learns group memberships from IGMP messages, and returns the ports an IPv4 multicast frame may be sent out of.
+    ///
+    /// ## Returns
+    /// * `None` if the frame isn't constrained by snooping and should be flooded.
+    /// * `Some(ports)` with the (logical) ports that lead to members of the group or to a multicast router.
+    fn _snoop(&mut self, f: &Ethernet2Frame, port: usize, vlan: u16) -> Option<BTreeSet<usize>> {
+        if !self.igmp_snooping
+            || f.ether_type != EtherType::Ipv4
+            || f.destination_address[..3] != [0x01, 0x00, 0x5E]
+        {
+            return None;
+        }
+
+        let packet = Ipv4Frame::from_bytes(f.data.clone()).ok()?;
+        if packet.protocol == Ipv4Protocol::Igmp as u8 {
+            let igmp = IgmpFrame::from_bytes(packet.data).ok()?;
+            match igmp.igmp_type {
+                t if t == IgmpType::MembershipQuery as u8 => {
+                    self.mrouter_ports.insert(port);
+                    return None; // Queries reach every host
+                }
+                t if t == IgmpType::MembershipReport as u8 => {
+                    self.igmp_groups
+                        .entry((igmp.group, vlan))
+                        .or_default()
+                        .insert(port);
+                }
+                t if t == IgmpType::LeaveGroup as u8 => {
+                    if let Some(members) = self.igmp_groups.get_mut(&(igmp.group, vlan)) {
+                        members.remove(&port);
+                        if members.is_empty() {
+                            self.igmp_groups.remove(&(igmp.group, vlan));
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            // Reports and leaves only go to the routers, so other members don't suppress their own reports
+            return Some(self.mrouter_ports.clone());
+        }
+
+        if is_ipv4_link_local_multicast!(packet.destination) {
+            return None;
+        }
+
+        let mut allowed = self.mrouter_ports.clone();
+        if let Some(members) = self.igmp_groups.get(&(packet.destination, vlan)) {
+            allowed.extend(members);
+        }
+        Some(allowed)
+    }
+}
+
+// Storm control methods
+impl Switch {
+    /// Limits the frames of a traffic class the port accepts per second.
+    /// * `threshold` - Frames per second, None to remove the limit.
+    pub fn set_storm_control(
+        &mut self,
+        port_id: usize,
+        class: TrafficClass,
+        threshold: Option<u32>,
+    ) {
+        *self.ports[port_id]
+            .borrow_mut()
+            .storm_control
+            .threshold_mut(class) = threshold;
+
+        if threshold.is_some() {
+            self.timer
+                .schedule(SwitchDelayedAction::StormControlReset, 1, true);
+        }
+    }
+
+    /// Sets the action taken when a port exceeds a storm control threshold.
+    pub fn set_storm_control_action(&mut self, port_id: usize, action: StormAction) {
+        self.ports[port_id].borrow_mut().storm_control.action = action;
+    }
+
+    pub fn storm_control(&self, port_id: usize) -> StormControl {
+        self.ports[port_id].borrow().storm_control.clone()
+    }
+
+    /// Returns the number of frames of the traffic class received on the port in the current second. Only classes
+    /// with a threshold are counted.
+    pub fn storm_count(&self, port_id: usize, class: TrafficClass) -> u32 {
+        self.storm_counts[port_id][class as usize]
+    }
+
+    /// Returns the number of frames dropped by storm control on the port.
+    pub fn storm_drops(&self, port_id: usize) -> u32 {
+        self.storm_drops[port_id]
+    }
+
+    /// Counts a frame of the traffic class received on the port. Returns false if the frame exceeds the port's
+    /// threshold and should be dropped.
+    fn _storm_control(&mut self, port_id: usize, class: TrafficClass) -> bool {
+        let (threshold, action) = {
+            let sp = self.ports[port_id].borrow();
+            (sp.storm_control.threshold(class), sp.storm_control.action)
+        };
+
+        // Counts are only reset once a second while a threshold is set
+        let Some(threshold) = threshold else {
+            return true;
+        };
+
+        let count = &mut self.storm_counts[port_id][class as usize];
+        *count = count.saturating_add(1);
+        if *count <= threshold {
+            return true;
+        }
+
+        self.storm_drops[port_id] += 1;
+        if action == StormAction::Shutdown {
+            self._err_disable(port_id);
+        }
+        false
+    }
+}
+
+// Switched Port Analyzer (SPAN) methods
+impl Switch {
+    /// Starts a monitor session, replacing the current one. Frames of the source ports are copied to the destination
+    /// port, which stops forwarding traffic of its own.
+    /// * `sources` - The ports to mirror.
+    /// * `direction` - Whether received frames, sent frames or both are mirrored.
+    /// * `destination` - The port the copies are sent out of, such as a port with a packet capturing desktop.
+    pub fn set_monitor_session(
+        &mut self,
+        sources: &[usize],
+        direction: MirrorDirection,
+        destination: usize,
+    ) -> Result<(), &'static str> {
+        if sources.is_empty() {
+            return Err("A monitor session needs at least one source port");
+        }
+        if destination >= 32 || sources.iter().any(|p| *p >= 32) {
+            return Err("Invalid port");
+        }
+        if sources.contains(&destination) {
+            return Err("The destination port cannot be a source port");
+        }
+
+        self.clear_monitor_session();
+        self.monitor = Some(MonitorSession {
+            sources: sources.to_vec(),
+            direction,
+            destination,
+        });
+
+        // The destination leaves STP and the MAC address table
+        self.table.retain(|_, p| *p != destination);
+        self._link_down(destination);
+        self._calculate_port_roles();
+        Ok(())
+    }
+
+    /// Stops the monitor session. The destination port goes back to forwarding traffic.
+    pub fn clear_monitor_session(&mut self) {
+        if let Some(session) = self.monitor.take() {
+            if self.rstp && self.ports[session.destination].borrow().stp_enabled {
+                self._send_hello(session.destination);
+            }
+        }
+    }
+
+    pub fn monitor_session(&self) -> Option<MonitorSession> {
+        self.monitor.clone()
+    }
+
+    fn _is_monitor_destination(&self, port_id: usize) -> bool {
+        self.monitor
+            .as_ref()
+            .is_some_and(|m| m.destination == port_id)
+    }
+
+    /// Returns true if frames received on (`rx`) or sent out of (`!rx`) the port are mirrored.
+    fn _is_mirrored(&self, port_id: usize, rx: bool) -> bool {
+        self.monitor
+            .as_ref()
+            .is_some_and(|m| m.mirrors(port_id, rx))
+    }
+
+    /// Copies the frame out of the monitor session's destination port, unchanged.
+    fn _mirror_ethernet2(&self, f: &Ethernet2Frame) {
+        if let Some(session) = &self.monitor {
+            self.ports[session.destination]
+                .borrow_mut()
+                .interface
+                .sendv(
+                    f.source_address,
+                    f.destination_address,
+                    f.ether_type,
+                    f.data.clone(),
+                );
+        }
+    }
+
+    /// Copies the frame out of the monitor session's destination port, unchanged.
+    fn _mirror_ethernet802_3(&self, f: &Ethernet802_3Frame) {
+        if let Some(session) = &self.monitor {
+            self.ports[session.destination]
+                .borrow_mut()
+                .interface
+                .send8023_frame(f);
+        }
+    }
+}
+
+// Link Aggregation Control Protocol (LACP) methods
+impl Switch {
+    /// Adds the port to a port-channel, or removes it from its channel. The port joins the bundle once LACPDUs have
+    /// been exchanged with the partner, and leaves it after 3 seconds without a LACPDU.
+    /// * `group` - The port-channel to negotiate for ; None to remove the port from its channel.
+    pub fn set_channel_group(&mut self, port_id: usize, group: Option<u8>) {
+        self._unbundle(port_id);
+        {
+            let mut sp = self.ports[port_id].borrow_mut();
+            sp.channel = group;
+            sp.partner = None;
+        }
+        self.lacp_missed[port_id] = 0;
+
+        if group.is_some() {
+            self._send_lacpdu(port_id);
+            self.timer
+                .schedule(SwitchDelayedAction::LacpPeriodic, 1, true);
         }
+    }
 
-        // Destination isn't in table, flood to all interfaces (except the one it came from, and disabled ports)
-        for (i, other_interface) in self.ports.iter().enumerate() {
-            if i == port || other_interface.borrow().stp_state == StpState::Discarding {
-                continue;
-            }
+    /// Returns the port-channel the port negotiates for, None if the port isn't in a channel.
+    pub fn port_channel(&self, port_id: usize) -> Option<u8> {
+        self.ports[port_id].borrow().channel
+    }
 
-            other_interface.borrow_mut().interface.sendv(
-                f.source_address,
-                f.destination_address,
-                f.ether_type,
-                f.data.clone(),
-            );
-        }
+    /// Returns true if the port is an active member of its port-channel.
+    pub fn is_bundled(&self, port_id: usize) -> bool {
+        self.ports[port_id].borrow().bundled
     }
 
-    /// Returns a list of all the physical EthernetPorts on the switch.
-    pub fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
-        self.ports
-            .iter()
-            .map(|i| i.borrow().interface.port().clone())
+    /// Returns the active members of a port-channel. The first member is the channel's logical port.
+    pub fn channel_members(&self, group: u8) -> Vec<usize> {
+        (0..32)
+            .filter(|i| {
+                let sp = self.ports[*i].borrow();
+                sp.bundled && sp.channel == Some(group)
+            })
             .collect()
     }
 
-    /// Returns the STP state of the port.
-    pub fn is_port_up(&self, port_id: usize) -> bool {
-        self.ports[port_id].borrow().stp_state != StpState::Discarding
+    pub fn set_load_balance(&mut self, load_balance: LoadBalance) {
+        self.load_balance = load_balance;
     }
 
-    pub fn bridge_priority(&self) -> u16 {
-        self.bridge_priority
+    pub fn load_balance(&self) -> LoadBalance {
+        self.load_balance
     }
 
-    pub fn root_bid(&self) -> u64 {
-        self.root_bid
+    /// Returns the logical port of the port's channel, or the port itself if it isn't bundled.
+    fn _logical(&self, port_id: usize) -> usize {
+        let sp = self.ports[port_id].borrow();
+        match sp.channel {
+            Some(group) if sp.bundled => self.channel_members(group)[0],
+            _ => port_id,
+        }
     }
 
-    pub fn root_cost(&self) -> u32 {
-        self.root_cost
+    /// Returns the member of the port's channel that a frame with the flow hash leaves through.
+    fn _member(&self, port_id: usize, hash: usize) -> usize {
+        let group = {
+            let sp = self.ports[port_id].borrow();
+            match sp.channel {
+                Some(group) if sp.bundled => group,
+                _ => return port_id,
+            }
+        };
+
+        let members = self.channel_members(group);
+        members[hash % members.len()]
     }
 
-    pub fn root_port(&self) -> Option<usize> {
-        self.root_port
+    fn _mac_hash(source: MacAddress, destination: MacAddress) -> usize {
+        source
+            .iter()
+            .chain(destination.iter())
+            .fold(0, |hash, byte| hash ^ *byte as usize)
     }
 
-    pub fn mac_addr(&self, port_id: usize) -> MacAddress {
-        self.ports[port_id].borrow().interface.mac_address
+    fn _flow_hash(&self, f: &Ethernet2Frame) -> usize {
+        if self.load_balance == LoadBalance::SrcDstIp
+            && f.ether_type == EtherType::Ipv4
+            && f.data.len() >= 20
+        {
+            // Source and destination addresses of the IPv4 header
+            return f.data[12..20]
+                .iter()
+                .fold(0, |hash, byte| hash ^ *byte as usize);
+        }
+
+        Self::_mac_hash(f.source_address, f.destination_address)
     }
 
-    pub fn set_bridge_priority(&mut self, priority: u16) {
-        self.bridge_priority = priority;
-        self.root_bid = crate::bridge_id!(self.mac_address, priority);
+    fn _send_lacpdu(&self, port_id: usize) {
+        let lacpdu = {
+            let sp = self.ports[port_id].borrow();
+            let group = match sp.channel {
+                Some(group) => group,
+                None => return,
+            };
+
+            let (partner_system, partner_key, partner_port) = sp.partner.unwrap_or(([0; 6], 0, 0));
+            Lacpdu {
+                actor_system: self.mac_address,
+                actor_key: group as u16,
+                actor_port: port_id as u16,
+                actor_state: Lacpdu::state(sp.bundled),
+                partner_system,
+                partner_key,
+                partner_port,
+            }
+        };
+
+        self.ports[port_id].borrow_mut().interface.send(
+            crate::mac_slow_protocols_addr!(),
+            EtherType::SlowProtocols,
+            lacpdu.to_bytes(),
+        );
     }
 
-    pub fn mac_table(&self) -> HashMap<MacAddress, usize> {
-        self.table.clone()
+    /// Records the partner of the port, and adds or removes the port from its channel.
+    ///
+    /// A port is bundled once the partner echoes this port back (the link works both ways), and every other member of
+    /// the channel leads to the same partner system and key.
+    fn _receive_lacpdu(&mut self, lacpdu: Lacpdu, port_id: usize) {
+        let group = match self.ports[port_id].borrow().channel {
+            Some(group) => group,
+            None => return, // LACP isn't running on the port
+        };
+        self.lacp_received[port_id] = true;
+
+        let partner = (lacpdu.actor_system, lacpdu.actor_key, lacpdu.actor_port);
+        let synchronized = lacpdu.partner_system == self.mac_address
+            && lacpdu.partner_key == group as u16
+            && lacpdu.partner_port == port_id as u16;
+        let compatible = self.channel_members(group).iter().all(|m| {
+            *m == port_id
+                || self.ports[*m]
+                    .borrow()
+                    .partner
+                    .is_some_and(|(system, key, _)| system == partner.0 && key == partner.1)
+        });
+
+        let (was_bundled, partner_changed) = {
+            let mut sp = self.ports[port_id].borrow_mut();
+            let partner_changed = sp.partner != Some(partner);
+            sp.partner = Some(partner);
+            (sp.bundled, partner_changed)
+        };
+
+        let bundled = synchronized && compatible;
+        if bundled && !was_bundled {
+            self.ports[port_id].borrow_mut().bundled = true;
+            self._channel_changed(group);
+        } else if !bundled {
+            self._unbundle(port_id);
+        }
+
+        // Reply if the partner's view of this port is out of date
+        if !synchronized || partner_changed || bundled != was_bundled {
+            self._send_lacpdu(port_id);
+        }
     }
 
-    /// Returns all ports in the designated role.
-    #[cfg(test)]
-    pub(crate) fn designated_ports(&self) -> Vec<usize> {
-        self.ports
-            .iter()
-            .enumerate()
-            .filter(|(_, p)| p.borrow().stp_role == Some(StpRole::Designated))
-            .map(|(i, _)| i)
-            .collect()
+    /// Removes the port from its channel. The port continues as an individual link.
+    fn _unbundle(&mut self, port_id: usize) {
+        let group = {
+            let mut sp = self.ports[port_id].borrow_mut();
+            if !sp.bundled {
+                return;
+            }
+            sp.bundled = false;
+            sp.channel.unwrap()
+        };
+
+        self.table.retain(|_, p| *p != port_id);
+        self._link_down(port_id);
+        self._channel_changed(group);
+
+        if self.rstp && self.ports[port_id].borrow().stp_enabled {
+            self._send_hello(port_id);
+        }
     }
 
-    /// Returns all ports in the discarding state.
-    #[cfg(test)]
-    pub(crate) fn discarding_ports(&self) -> Vec<usize> {
-        self.ports
-            .iter()
-            .enumerate()
-            .filter(|(_, p)| p.borrow().stp_state == StpState::Discarding)
-            .map(|(i, _)| i)
-            .collect()
+    /// Flushes the addresses learned on the channel and removes the members that aren't the logical port from STP.
+    fn _channel_changed(&mut self, group: u8) {
+        let members = self.channel_members(group);
+        self.table.retain(|_, p| !members.contains(p));
+
+        for member in members.iter().skip(1) {
+            self._link_down(*member);
+        }
+        self._calculate_port_roles();
     }
 }
 
@@ -283,7 +1795,7 @@ impl Switch {
 
     /// Returns true if the switch is the root bridge of the network.
     pub fn is_root_bridge(&self) -> bool {
-        self.root_bid == self.bid()
+        self.cist.is_root_bridge()
     }
 
     /// Compares two BIDs and returns true if bid1 is better than bid2.
@@ -305,41 +1817,103 @@ impl Switch {
         Some(priority1 < priority2 || (priority1 == priority2 && bid1 < bid2))
     }
 
+    /// Returns the spanning tree of the instance, the CIST if the instance doesn't exist.
+    fn _tree(&self, instance: u8) -> &SpanningTree {
+        self.mstis.get(&instance).unwrap_or(&self.cist)
+    }
+
+    /// Returns every spanning tree on the switch, the CIST first.
+    fn _trees_mut(&mut self) -> impl Iterator<Item = &mut SpanningTree> {
+        std::iter::once(&mut self.cist).chain(self.mstis.values_mut())
+    }
+
+    /// Creates a BPDU for the port. Carries the MST configuration and a record for every MSTI if MSTP is configured.
+    /// * `flags` - The CIST flags of the BPDU.
+    fn _bpdu(&self, port_id: usize, flags: u8) -> BpduFrame {
+        let mut bpdu = BpduFrame::new(
+            false,
+            flags,
+            self.cist.root_bid,
+            self.cist.root_cost,
+            self.bid(),
+            port_id as u16,
+        );
+
+        if let Some(mst) = &self.mst {
+            bpdu.version = 3;
+            bpdu.mst_config = Some(mst.identifier());
+            bpdu.msti = self
+                .mstis
+                .iter()
+                .map(|(instance, tree)| {
+                    let sp = tree.ports[port_id].borrow();
+                    let role = StpRole::flag(sp.stp_role);
+                    MstiRecord {
+                        instance: *instance,
+                        flags: BpduFrame::flags(
+                            false,
+                            false,
+                            role,
+                            sp.stp_state == StpState::Learning,
+                            role == 0 || role == 1,
+                            false,
+                        ),
+                        regional_root_bid: tree.root_bid,
+                        internal_root_cost: tree.root_cost,
+                        bid: tree.bid,
+                        port: port_id as u16,
+                        remaining_hops: 20,
+                    }
+                })
+                .collect();
+        }
+
+        bpdu
+    }
+
+    /// Sends a Hello BPDU out of a single port as a designated port.
+    fn _send_hello(&self, port_id: usize) {
+        let bpdu = self._bpdu(
+            port_id,
+            BpduFrame::flags(false, false, 1, false, true, false),
+        );
+        self.ports[port_id]
+            .borrow_mut()
+            .interface
+            .send8023(crate::mac_bpdu_addr!(), bpdu.to_bytes());
+    }
+
     /// Sends a Hello BPDU to all interfaces.
     /// * `tcn` - Topology Change Notification. Set to true if the BPDU is a TCN BPDU, ie a BPDU that indicates a topology change.
     /// * `proposal` - Set to true if the BPDU is a proposal BPDU.
     /// * `flood_to_all` - Set to true if the BPDU should be flooded to all interfaces.
     fn _send_bpdus(&self, tcn: bool, proposal: bool, flood_to_all: bool) {
-        let mut bpdu = BpduFrame::hello(
-            self.mac_address,
-            self.root_bid,
-            self.root_cost,
-            self.bid(),
-            0,
-        );
-
-        for stp_port in self.ports.iter() {
+        for (i, stp_port) in self.cist.ports.iter().enumerate() {
             if !flood_to_all && stp_port.borrow().connected_bid.is_none() {
                 continue;
             }
 
-            let port_role = match stp_port.borrow().stp_role {
-                Some(StpRole::Root) => 0,
-                Some(StpRole::Designated) => 1,
-                Some(StpRole::Alternate) => 2,
-                Some(StpRole::Backup) => 3,
-                None => 4,
-            };
-            bpdu.port = stp_port.borrow().id as u16;
-            bpdu.flags = BpduFrame::flags(
-                tcn,
-                proposal,
-                port_role,
-                stp_port.borrow().stp_state == StpState::Learning,
-                port_role == 0 || port_role == 1,
-                false,
+            if self.ports[i].borrow().err_disabled
+                || !self.ports[i].borrow().stp_enabled
+                || self._logical(i) != i
+                || self._is_monitor_destination(i)
+            {
+                continue; // Only the logical port of a port-channel takes part in STP, a SPAN destination never does
+            }
+
+            let port_role = StpRole::flag(stp_port.borrow().stp_role);
+            let bpdu = self._bpdu(
+                i,
+                BpduFrame::flags(
+                    tcn,
+                    proposal,
+                    port_role,
+                    stp_port.borrow().stp_state == StpState::Learning,
+                    port_role == 0 || port_role == 1,
+                    false,
+                ),
             );
-            stp_port
+            self.ports[i]
                 .borrow_mut()
                 .interface
                 .send8023(crate::mac_bpdu_addr!(), bpdu.to_bytes());
@@ -351,9 +1925,14 @@ impl Switch {
     /// The switch will wait 15 seconds before transitioning to `finish_init_stp`.
     pub fn init_stp(&mut self) {
         self.rstp = true;
-        for stp_port in self.ports.iter() {
-            stp_port.borrow_mut().stp_state = StpState::Discarding;
-            stp_port.borrow_mut().stp_role = Some(StpRole::Root);
+
+        let mut edges = [false; 32];
+        for (i, p) in self.ports.iter().enumerate() {
+            edges[i] = p.borrow().edge;
+        }
+
+        for tree in self._trees_mut() {
+            tree.init(&edges);
         }
         self._send_bpdus(true, true, true);
 
@@ -365,11 +1944,8 @@ impl Switch {
     ///
     /// Begins the BPDU multicast timer.
     pub fn finish_init_stp(&mut self) {
-        for stp_port in self.ports.iter() {
-            if stp_port.borrow().connected_bid.is_none() {
-                stp_port.borrow_mut().stp_role = Some(StpRole::Designated);
-                stp_port.borrow_mut().stp_state = StpState::Forwarding;
-            }
+        for tree in self._trees_mut() {
+            tree.finish_init();
         }
 
         self.timer
@@ -377,18 +1953,34 @@ impl Switch {
     }
 
     fn _link_down(&mut self, port_id: usize) {
-        let mut port = self.ports[port_id].borrow_mut();
-        port.connected_bid = None;
-        port.connected_role = None;
-        port.connected_root_bid = None;
-        port.stp_role = Some(StpRole::Designated);
-        port.stp_state = StpState::Forwarding;
-        port.root_cost = 0;
+        for tree in self._trees_mut() {
+            tree.link_down(port_id);
+        }
+
+        {
+            let mut port = self.ports[port_id].borrow_mut();
+            port.root_inconsistent = false;
+            port.boundary = false;
+        }
 
         self.missed_hellos[port_id] = 0;
     }
 
-    /// Disconnects a port from the switch as well as from STP.
+    /// Recalculates the port roles of every tree. Floods BPDUs if any role or root has changed.
+    fn _calculate_port_roles(&mut self) {
+        let (mut role_changed, mut root_changed) = (false, false);
+        for tree in self._trees_mut() {
+            let (r, rt) = tree.calculate_port_roles();
+            role_changed |= r;
+            root_changed |= rt;
+        }
+
+        if role_changed || root_changed {
+            self._send_bpdus(true, true, root_changed); // Flood to all IFF the root has changed
+        }
+    }
+
+    /// Disconnects a port from the switch as well as from STP and its port-channel.
     pub fn disconnect(&mut self, port_id: usize) {
         self.ports[port_id]
             .borrow_mut()
@@ -396,232 +1988,356 @@ impl Switch {
             .port()
             .borrow_mut()
             .disconnect();
-        self._link_down(port_id);
-
-        let (role_changed, root_changed) = self._calculate_port_roles();
-        if role_changed || root_changed {
-            self._send_bpdus(true, true, root_changed); // Flood to all IFF the root has changed
-        }
+        self.ports[port_id].borrow_mut().carrier = false;
+        self._carrier_lost(port_id);
     }
 
-    fn _receive_bpdu(&mut self, bpdu: BpduFrame, port_id: usize) {
-        self.received_bpdu[port_id] = true;
-        {
-            let mut sp = self.ports[port_id].borrow_mut();
-            sp.connected_root_bid = Some(bpdu.root_bid);
-            sp.connected_bid = Some(bpdu.bid);
-            sp.connected_role = bpdu.stp_role();
-            sp.root_cost = bpdu.root_cost + 1;
+    /// Administratively shuts down a port, or brings it back up, without removing its cable.
+    ///
+    /// The device on the other end of the cable loses its carrier too, reacting as if the cable was unplugged.
+    pub fn set_shutdown(&mut self, port_id: usize, shutdown: bool) {
+        self.ports[port_id]
+            .borrow()
+            .interface
+            .port()
+            .borrow_mut()
+            .set_shutdown(shutdown);
+        self._detect_carrier(port_id);
+    }
 
-            match Self::compare_bids(self.root_bid, bpdu.root_bid) {
-                Some(true) => {
-                    let bpdu = BpduFrame::hello(
-                        self.mac_address,
-                        self.root_bid,
-                        self.root_cost,
-                        self.bid(),
-                        port_id,
-                    );
+    pub fn is_shutdown(&self, port_id: usize) -> bool {
+        self.ports[port_id]
+            .borrow()
+            .interface
+            .port()
+            .borrow()
+            .is_shutdown()
+    }
 
-                    // Broadcast how much cooler we are
-                    sp.interface
-                        .send8023(crate::mac_bpdu_addr!(), bpdu.to_bytes());
+    /// Reacts to the carrier of a port coming or going, without waiting for missed hellos.
+    fn _detect_carrier(&mut self, port_id: usize) {
+        let carrier = self.ports[port_id]
+            .borrow()
+            .interface
+            .port()
+            .borrow()
+            .has_carrier();
+        if carrier == self.ports[port_id].borrow().carrier {
+            return;
+        }
 
-                    if self.is_root_bridge() {
-                        // Only done during STP initialization
-                        sp.stp_role = Some(StpRole::Designated);
-                        sp.stp_state = StpState::Forwarding;
-                    }
+        self.ports[port_id].borrow_mut().carrier = carrier;
+        if !carrier {
+            self._carrier_lost(port_id);
+            return;
+        }
 
-                    return; // Role recalculations aren't necessary
-                }
-                Some(false) => {
-                    self.root_bid = bpdu.root_bid; // Root is better
-                }
-                _ => {}
-            }
+        if self.rstp
+            && self.ports[port_id].borrow().stp_enabled
+            && !self._is_monitor_destination(port_id)
+        {
+            self._send_hello(port_id);
         }
+    }
 
-        let (role_changed, root_changed) = self._calculate_port_roles();
-        if role_changed || root_changed {
-            self._send_bpdus(true, true, root_changed); // Flood to all IFF the root has changed
+    /// Removes a port that went down from STP, its port-channel, and the addresses and groups learned on it.
+    fn _carrier_lost(&mut self, port_id: usize) {
+        self.ports[port_id].borrow_mut().partner = None;
+        self._unbundle(port_id);
+        self._link_down(port_id);
+        self._calculate_port_roles();
+        self.table.retain(|_, p| *p != port_id);
+
+        for members in self.igmp_groups.values_mut() {
+            members.remove(&port_id);
         }
+        self.igmp_groups.retain(|_, members| !members.is_empty());
+        self.mrouter_ports.remove(&port_id);
     }
 
-    /// Returns (role_changed, root_changed)
-    fn _calculate_port_roles(&mut self) -> (bool, bool) {
-        fn enter_election(switch: &mut Switch) {
-            for stp_port in switch.ports.iter() {
-                let mut sp = stp_port.borrow_mut();
-                sp.stp_role = Some(StpRole::Designated);
-                sp.stp_state = StpState::Forwarding;
-                sp.root_cost = 0;
-                sp.connected_bid = None;
-                sp.connected_role = None;
-            }
+    /// Enables or disables STP on a port. A port without STP always forwards, sends no BPDUs and drops received ones.
+    pub fn set_port_stp(&mut self, port_id: usize, enabled: bool) {
+        self.ports[port_id].borrow_mut().stp_enabled = enabled;
 
-            switch.root_bid = switch.bid();
-            switch.root_cost = 0;
-            switch.root_port = None;
+        if !enabled {
+            self._link_down(port_id);
+            self._calculate_port_roles();
+            return;
         }
 
-        if self.is_root_bridge() {
-            return (false, false);
+        if self.rstp {
+            self._send_hello(port_id);
         }
+    }
 
-        let new_root_port = {
-            let mut rp: Option<(u32, u64, usize)> = None; // (root_cost, bid, port_id)
+    /// Returns true if the port participates in STP.
+    pub fn is_port_stp_enabled(&self, port_id: usize) -> bool {
+        self.ports[port_id].borrow().stp_enabled
+    }
 
-            for stp_port in self.ports.iter() {
-                let sp = stp_port.borrow();
-                if !sp
-                    .connected_root_bid
-                    .is_some_and(|bid| bid == self.root_bid)
-                    || sp.connected_bid.is_none()
-                {
-                    continue;
-                }
+    /// Enables or disables PortFast on a port. An edge port goes straight to Forwarding when STP is initialized.
+    pub fn set_portfast(&mut self, port_id: usize, enabled: bool) {
+        self.ports[port_id].borrow_mut().edge = enabled;
+        if !enabled {
+            return;
+        }
 
-                if let Some((root_cost, bid, _)) = rp {
-                    if sp.root_cost > root_cost {
-                        continue;
-                    }
+        for tree in self._trees_mut() {
+            let mut sp = tree.ports[port_id].borrow_mut();
+            if sp.connected_bid.is_none() {
+                sp.stp_role = Some(StpRole::Designated);
+                sp.stp_state = StpState::Forwarding;
+            }
+        }
+    }
 
-                    let compare = Switch::compare_bids(sp.connected_bid.unwrap(), bid)
-                        .expect("Bids are equivalent");
-                    if sp.root_cost < root_cost || compare {
-                        rp = Some((sp.root_cost, sp.connected_bid.unwrap(), sp.id));
-                    }
-                    continue;
-                }
+    /// Enables or disables BPDU Guard on a port. An edge port with BPDU Guard is err-disabled when it receives a BPDU.
+    pub fn set_bpdu_guard(&mut self, port_id: usize, enabled: bool) {
+        self.ports[port_id].borrow_mut().bpdu_guard = enabled;
+    }
 
-                if let Some(connected_bid) = sp.connected_bid {
-                    rp = Some((sp.root_cost, connected_bid, sp.id));
-                }
-            }
+    /// Enables or disables Root Guard on a port. A port with Root Guard is blocked while it receives a superior root BID.
+    pub fn set_root_guard(&mut self, port_id: usize, enabled: bool) {
+        let mut sp = self.ports[port_id].borrow_mut();
+        sp.root_guard = enabled;
+        if !enabled {
+            sp.root_inconsistent = false;
+        }
+    }
 
-            rp
-        };
+    /// Sets the interval to bring err-disabled ports back up. Recoveries already pending restart with the new interval.
+    /// * `interval_in_seconds` - Seconds to wait before recovering a port. None disables recovery.
+    pub fn set_errdisable_recovery(&mut self, interval_in_seconds: Option<u64>) {
+        self.errdisable_recovery = interval_in_seconds;
 
-        let (new_root_cost, new_root_port_id, root_changed) = match new_root_port {
-            Some((root_cost, _, root_port_id)) => {
-                if self.root_port.is_some_and(|rp| rp != root_port_id)
-                    && self.ports[root_port_id].borrow().stp_role != Some(StpRole::Alternate)
-                {
-                    enter_election(self); // A new root port has been found, but it isn't an alternate port.
-                    return (true, true);
+        for i in 0..self.ports.len() {
+            self.timer
+                .cancel(&SwitchDelayedAction::ErrDisableRecovery(i));
+        }
+
+        if let Some(interval) = interval_in_seconds {
+            for (i, p) in self.ports.iter().enumerate() {
+                if p.borrow().err_disabled {
+                    self.timer.schedule(
+                        SwitchDelayedAction::ErrDisableRecovery(i),
+                        interval,
+                        false,
+                    );
                 }
-                (
-                    root_cost,
-                    root_port_id,
-                    self.root_port != Some(root_port_id),
-                )
-            }
-            None => {
-                enter_election(self); // There is no port leading to the root bridge.
-                return (true, true);
             }
-        };
+        }
+    }
 
-        self.root_port = Some(new_root_port_id);
-        self.root_cost = new_root_cost;
-        self.ports[new_root_port_id].borrow_mut().stp_role = Some(StpRole::Root);
-        self.ports[new_root_port_id].borrow_mut().stp_state = StpState::Forwarding;
-        let mut role_changed = root_changed;
+    pub fn errdisable_recovery(&self) -> Option<u64> {
+        self.errdisable_recovery
+    }
 
-        let segment_to_port = {
-            let mut segment_to_port: HashMap<u64, usize> = HashMap::new();
-            for stp_port in self.ports.iter() {
-                let mut sp = stp_port.borrow_mut();
-                if sp.connected_bid.is_none() || sp.id == new_root_port_id {
-                    continue;
-                }
+    /// Shuts down a port, flushing the MAC addresses learned on it.
+    /// Schedules the port to come back up if errdisable recovery is enabled.
+    fn _err_disable(&mut self, port_id: usize) {
+        self.ports[port_id].borrow_mut().err_disabled = true;
+        self.table.retain(|_, p| *p != port_id);
+        self._unbundle(port_id);
+
+        if let Some(interval) = self.errdisable_recovery {
+            self.timer.schedule(
+                SwitchDelayedAction::ErrDisableRecovery(port_id),
+                interval,
+                false,
+            );
+        }
+    }
 
-                sp.root_cost = std::cmp::max(sp.root_cost, new_root_cost); // Cost cannot be less than the root cost
+    fn _receive_bpdu(&mut self, bpdu: BpduFrame, port_id: usize) {
+        self.received_bpdu[port_id] = true;
 
-                if let Some(min_port_id) = segment_to_port.get(&sp.connected_bid.unwrap()) {
-                    let bid = sp.connected_bid.unwrap();
-                    let min_bid = self.ports[*min_port_id].borrow().connected_bid.unwrap();
-                    let min_cost = self.ports[*min_port_id].borrow().root_cost;
+        let (bpdu_guard, root_guard) = {
+            let sp = self.ports[port_id].borrow();
+            (sp.edge && sp.bpdu_guard, sp.root_guard)
+        };
 
-                    let is_min = {
-                        if min_cost != sp.root_cost {
-                            sp.root_cost < self.ports[*min_port_id].borrow().root_cost
-                        }
-                        // Tiebreaker: Compare by port number if the bids are equivalent
-                        else if bid == min_bid {
-                            sp.id > *min_port_id
-                        }
-                        // Tiebreaker: Compare by bid if the costs are equal
-                        else {
-                            Switch::compare_bids(bid, min_bid).expect("Bids are equivalent")
-                        }
-                    };
+        if bpdu_guard {
+            self._err_disable(port_id);
+            return;
+        }
 
-                    if is_min {
-                        segment_to_port.insert(bid, sp.id);
-                    }
+        if root_guard {
+            let superior = Self::compare_bids(self.cist.root_bid, bpdu.root_bid) == Some(false);
+            self.ports[port_id].borrow_mut().root_inconsistent = superior;
+            if superior {
+                return; // Ignore the superior root, the port stays blocked until they stop
+            }
+        }
 
-                    continue;
+        if bpdu.bid == self.bid() {
+            // Our own BPDU looped back through a segment without STP, the higher port blocks as a backup port
+            if (bpdu.port as usize) < port_id {
+                for tree in self._trees_mut() {
+                    let mut sp = tree.ports[port_id].borrow_mut();
+                    sp.connected_bid = Some(bpdu.bid); // Keeps the port blocked until the loop stops sending BPDUs
+                    sp.stp_role = Some(StpRole::Backup);
+                    sp.stp_state = StpState::Discarding;
+
+                    // The designated port keeps sending hellos, which feed the backup port for as long as the loop lasts
+                    let mut dp = tree.ports[bpdu.port as usize].borrow_mut();
+                    dp.connected_bid = Some(bpdu.bid);
+                    dp.stp_role = Some(StpRole::Designated);
+                    dp.stp_state = StpState::Forwarding;
                 }
-                segment_to_port.insert(sp.connected_bid.unwrap(), sp.id);
             }
-            segment_to_port
+            return;
+        }
+
+        // A neighbor with the same MST configuration is within the region and shares the MSTIs
+        let internal = match (&self.mst, &bpdu.mst_config) {
+            (Some(mst), Some(config)) => mst.identifier() == *config,
+            _ => false,
         };
+        self.ports[port_id].borrow_mut().boundary = self.mst.is_some() && !internal;
 
-        for stp_port in self.ports.iter() {
-            let mut sp = stp_port.borrow_mut();
-            let prev_role = sp.stp_role;
+        let mut reply = false;
+        let (mut role_changed, mut root_changed) = (false, false);
 
-            if sp.connected_bid.is_none()
-                || sp.connected_role.is_none()
-                || sp.id == self.root_port.unwrap()
-            {
-                continue; // Port doesnt participate in STP, or is the root port (which is already determined `find_root_port`)
+        match self.cist.receive(
+            port_id,
+            bpdu.root_bid,
+            bpdu.bid,
+            bpdu.stp_role(),
+            bpdu.root_cost,
+        ) {
+            Some((r, rt)) => {
+                role_changed |= r;
+                root_changed |= rt;
             }
+            None => reply = true,
+        }
 
-            let bid = sp.connected_bid.unwrap();
-            if bid == self.root_bid {
-                sp.stp_role = Some(StpRole::Alternate);
-                sp.stp_state = StpState::Discarding;
-            } else if !segment_to_port.contains_key(&bid) {
-                sp.stp_role = Some(StpRole::Backup);
-                sp.stp_state = StpState::Discarding;
-            } else {
-                sp.stp_role = Some(StpRole::Designated);
-                sp.stp_state = StpState::Forwarding;
-            }
+        if internal {
+            for record in bpdu.msti.iter() {
+                let tree = match self.mstis.get_mut(&record.instance) {
+                    Some(tree) => tree,
+                    None => continue,
+                };
 
-            let conn_role = sp.connected_role.unwrap();
-            // Never block another switches root port
-            if conn_role == StpRole::Root {
-                sp.stp_role = Some(StpRole::Designated);
-                sp.stp_state = StpState::Forwarding;
-            }
-            // Designated to designated is a redundancy
-            else if conn_role == StpRole::Designated && sp.stp_role == Some(StpRole::Designated) {
-                // Only one of the switches has to block.
-                let compare = Self::compare_bids(self.bid(), bid);
-                match compare {
-                    Some(true) => {
-                        continue; // This switch wins, stay designated
-                    }
-                    Some(false) => {
-                        sp.stp_role = Some(StpRole::Backup);
-                        sp.stp_state = StpState::Discarding;
-                    }
-                    None => {
-                        todo!("Further tiebreakers. Will not occur in simulation.");
+                match tree.receive(
+                    port_id,
+                    record.regional_root_bid,
+                    record.bid,
+                    record.stp_role(),
+                    record.internal_root_cost,
+                ) {
+                    Some((r, rt)) => {
+                        role_changed |= r;
+                        root_changed |= rt;
                     }
+                    None => reply = true,
                 }
             }
+        }
 
-            if prev_role != sp.stp_role {
-                role_changed = true;
+        if reply {
+            // Broadcast how much cooler we are
+            self._send_hello(port_id);
+        }
+
+        if role_changed || root_changed {
+            self._send_bpdus(true, true, root_changed); // Flood to all IFF the root has changed
+        }
+    }
+}
+
+// Multiple Spanning Tree Protocol (MSTP) methods
+impl Switch {
+    /// Configures the MST region, switching the switch from RSTP to MSTP.
+    /// Restarts the spanning tree if it is running.
+    pub fn set_mst_region(&mut self, name: &str, revision: u16) {
+        let mut mst = MstConfig::new(name, revision);
+        if let Some(old) = self.mst.take() {
+            mst.instances = old.instances;
+            mst.digest = old.digest;
+        }
+
+        self.mst = Some(mst);
+        self._restart_stp();
+    }
+
+    /// Maps VLANs to a Multiple Spanning Tree Instance, creating the instance if it doesn't exist.
+    /// Restarts the spanning tree if it is running.
+    /// * `instance` - The MSTI to map the VLANs to. Instance 0 returns the VLANs to the IST.
+    /// * `vlans` - The VLANs to map.
+    pub fn map_mst_instance(&mut self, instance: u8, vlans: &[u16]) -> Result<(), &'static str> {
+        let mst = match self.mst.as_mut() {
+            Some(mst) => mst,
+            None => return Err("MST region is not configured."),
+        };
+
+        mst.map(instance, vlans);
+
+        if instance != CIST && !self.mstis.contains_key(&instance) {
+            self.mstis.insert(
+                instance,
+                SpanningTree::new(crate::bridge_id!(self.mac_address, self.bridge_priority)),
+            );
+        }
+
+        // Remove instances that no longer have VLANs
+        let mapped: Vec<u8> = mst.instances.values().copied().collect();
+        self.mstis.retain(|i, _| mapped.contains(i));
+
+        self._restart_stp();
+        Ok(())
+    }
+
+    /// Sets the bridge priority of the switch within an instance.
+    pub fn set_instance_priority(
+        &mut self,
+        instance: u8,
+        priority: u16,
+    ) -> Result<(), &'static str> {
+        if instance == CIST {
+            self.set_bridge_priority(priority);
+            return Ok(());
+        }
+
+        match self.mstis.get_mut(&instance) {
+            Some(tree) => {
+                tree.set_bid(crate::bridge_id!(self.mac_address, priority));
+                Ok(())
             }
+            None => Err("MST instance does not exist."),
         }
+    }
 
-        (role_changed, root_changed)
+    /// Returns the MST region configuration, None if the switch runs RSTP.
+    pub fn mst_config(&self) -> Option<&MstConfig> {
+        self.mst.as_ref()
+    }
+
+    /// Returns the instance the VLAN belongs to.
+    pub fn instance(&self, vlan: u16) -> u8 {
+        self.mst.as_ref().map_or(CIST, |mst| mst.instance(vlan))
+    }
+
+    /// Returns (Instance, Bridge ID, Root Bridge ID, Root Port) of every spanning tree, the CIST first.
+    pub fn instances(&self) -> Vec<(u8, u64, u64, Option<usize>)> {
+        std::iter::once((CIST, &self.cist))
+            .chain(self.mstis.iter().map(|(i, t)| (*i, t)))
+            .map(|(i, t)| (i, t.bid, t.root_bid, t.root_port))
+            .collect()
+    }
+
+    pub fn instance_root_bid(&self, instance: u8) -> u64 {
+        self._tree(instance).root_bid
+    }
+
+    pub fn instance_root_port(&self, instance: u8) -> Option<usize> {
+        self._tree(instance).root_port
+    }
+
+    fn _restart_stp(&mut self) {
+        if self.rstp {
+            self.init_stp();
+        }
     }
 }
 
@@ -636,7 +2352,13 @@ impl Tickable for Switch {
 
                     // Find links that are down, (max age = 6 seconds)
                     for i in 0..32 {
-                        if !self.received_bpdu[i] && self.ports[i].borrow().connected_bid.is_some()
+                        // Root Guard recovers once superior BPDUs stop arriving
+                        if !self.received_bpdu[i] {
+                            self.ports[i].borrow_mut().root_inconsistent = false;
+                        }
+
+                        if !self.received_bpdu[i]
+                            && self.cist.ports[i].borrow().connected_bid.is_some()
                         {
                             self.missed_hellos[i] += 1;
                             if self.missed_hellos[i] >= 3 {
@@ -650,24 +2372,67 @@ impl Tickable for Switch {
                         self.received_bpdu[i] = false;
                     }
 
-                    let (role_changed, root_changed) = if should_recalc {
-                        self._calculate_port_roles()
-                    } else {
-                        (false, false)
-                    };
+                    let (mut role_changed, mut root_changed) = (false, false);
+                    if should_recalc {
+                        for tree in self._trees_mut() {
+                            let (r, rt) = tree.calculate_port_roles();
+                            role_changed |= r;
+                            root_changed |= rt;
+                        }
+                    }
 
                     self._send_bpdus(role_changed || root_changed, false, root_changed);
                 }
                 SwitchDelayedAction::RstpInit => {
                     self.finish_init_stp();
                 }
+                SwitchDelayedAction::ErrDisableRecovery(port_id) => {
+                    // Recovery may have been turned off since the port was shut down
+                    if self.errdisable_recovery.is_some() {
+                        self.ports[port_id].borrow_mut().err_disabled = false;
+                    }
+                }
+                SwitchDelayedAction::StormControlReset => {
+                    self.storm_counts = [[0; 3]; 32];
+                }
+                SwitchDelayedAction::LacpPeriodic => {
+                    for i in 0..32 {
+                        if self.ports[i].borrow().channel.is_none() {
+                            continue;
+                        }
+
+                        // Find partners that are gone, (timeout = 3 seconds)
+                        if self.lacp_received[i] {
+                            self.lacp_missed[i] = 0;
+                        } else if self.ports[i].borrow().partner.is_some() {
+                            self.lacp_missed[i] += 1;
+                            if self.lacp_missed[i] >= 3 {
+                                self.ports[i].borrow_mut().partner = None;
+                                self._unbundle(i);
+                                self.lacp_missed[i] = 0;
+                            }
+                        }
+
+                        self.lacp_received[i] = false;
+                        self._send_lacpdu(i);
+                    }
+                }
             }
         }
 
         self.timer.tick();
+        self._log_stp_changes();
     }
 }
 
+/// Slow Protocols MAC address for Link Aggregation Control Protocol
+#[macro_export]
+macro_rules! mac_slow_protocols_addr {
+    () => {
+        [0x01, 0x80, 0xC2, 0x00, 0x00, 0x02]
+    };
+}
+
 /// BPDU MAC address for Spanning Tree Protocol
 #[macro_export]
 macro_rules! mac_bpdu_addr {
@@ -688,12 +2453,11 @@ macro_rules! bridge_id {
     }};
 }
 
+/// Bridge Protocol Data Unit, the LLC payload of a spanning tree frame. The addresses are those of the Ethernet frame.
 #[derive(Debug, PartialEq)]
 pub struct BpduFrame {
-    pub destination_address: MacAddress,
-    pub source_address: MacAddress,
     pub protocol_id: u16, // 0x0000 for STP, 0x0000 for RSTP
-    pub version: u8,      // 0x00 for STP, 0x02 for RSTP. Always 0x02 in this implementation.
+    pub version: u8,      // 0x00 for STP, 0x02 for RSTP, 0x03 for MSTP
     pub bpdu_type: u8,    // 0x00 for Configuration BPDU, 0x02 for TCN BPDU
     pub flags: u8,
     pub root_bid: u64,  // Bridge ID = Root MAC Address + Root Priority
@@ -704,9 +2468,22 @@ pub struct BpduFrame {
     pub max_age: u16,
     pub hello_time: u16,
     pub forward_delay: u16,
+
+    pub mst_config: Option<MstConfigId>, // MSTP only
+    pub msti: Vec<MstiRecord>,           // MSTP only
 }
 
 impl BpduFrame {
+    /// Length of a Configuration BPDU, which STP BPDUs end at.
+    const STP_LEN: usize = 35;
+
+    /// Length of an RSTP BPDU, a Configuration BPDU followed by the Version 1 Length.
+    const RSTP_LEN: usize = 36;
+
+    /// Length of the MSTP fields after the MST Configuration Identifier; the CIST internal root path cost, CIST bridge
+    /// ID and CIST remaining hops.
+    const CIST_LEN: usize = 13;
+
     /// * `tcn` - Topology Change Notification. Set to true if the BPDU is a TCN BPDU, ie a BPDU that indicates a topology change.
     /// * `proposal` - Set to true if the BPDU is a proposal BPDU.
     /// * `port_role` - The role of the port sending the BPDU. 0 = Root, 1 = Designated, 2 = Alternate, 3 = Backup, 4 = Disabled
@@ -752,8 +2529,6 @@ impl BpduFrame {
     }
 
     pub fn new(
-        destination_address: MacAddress,
-        source_address: MacAddress,
         config_type: bool,
         flags: u8,
         root_bid: u64,
@@ -764,8 +2539,6 @@ impl BpduFrame {
         let bpdu_type = if config_type { 0x02 } else { 0x00 };
 
         Self {
-            destination_address,
-            source_address,
             protocol_id: 0x0000, // RSTP/STP
             version: 2,          // RSTP
             bpdu_type,           // Configuration or TCN BPDU
@@ -780,19 +2553,14 @@ impl BpduFrame {
             max_age: 0,    // Treated as 6 seconds
             hello_time: 0, // Treated as 2 seconds
             forward_delay: 0,
+
+            mst_config: None,
+            msti: Vec::new(),
         }
     }
 
-    pub fn hello(
-        source_address: MacAddress,
-        root_bid: u64,
-        root_cost: u32,
-        bid: u64,
-        port: usize,
-    ) -> Self {
+    pub fn hello(root_bid: u64, root_cost: u32, bid: u64, port: usize) -> Self {
         Self::new(
-            crate::mac_bpdu_addr!(),
-            source_address,
             false,
             Self::flags(false, false, 1, false, true, false),
             root_bid,
@@ -802,6 +2570,18 @@ impl BpduFrame {
         )
     }
 
+    /// Parses the BPDU carried by an Ethernet802_3 frame, checking its LLC header is that of the Spanning Tree Protocol.
+    pub fn from_frame(frame: &Ethernet802_3Frame) -> Result<Self, std::io::Error> {
+        if frame.dsap != 0x42 || frame.ssap != 0x42 || frame.control != 0x03 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Not a Spanning Tree Protocol LLC frame.",
+            ));
+        }
+
+        Self::from_bytes(frame.data.clone())
+    }
+
     fn stp_role(&self) -> Option<StpRole> {
         match (self.flags & 0b0000_1100) >> 2 {
             0 => Some(StpRole::Root),
@@ -815,31 +2595,67 @@ impl BpduFrame {
 
 impl ByteSerializable for BpduFrame {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
-        if bytes.len() < 35 {
-            return Err(std::io::Error::new(
+        // The bytes follow the LLC header. Frames on the wire are padded to the minimum Ethernet frame size, so bytes
+        // past the end of the BPDU are ignored.
+        let runt = || {
+            std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Insufficient bytes for BPDU frame; Runt frame.",
-            ));
+            )
+        };
+
+        if bytes.len() < Self::STP_LEN {
+            return Err(runt());
+        }
+
+        let protocol_id = u16::from_be_bytes(bytes[0..2].try_into().unwrap());
+        let version = bytes[2];
+        let bpdu_type = bytes[3];
+        let flags = bytes[4];
+        let root_id = u64::from_be_bytes(bytes[5..13].try_into().unwrap());
+        let root_path_cost = u32::from_be_bytes(bytes[13..17].try_into().unwrap());
+        let bridge_id = u64::from_be_bytes(bytes[17..25].try_into().unwrap());
+        let port_id = u16::from_be_bytes(bytes[25..27].try_into().unwrap());
+        let message_age = u16::from_be_bytes(bytes[27..29].try_into().unwrap());
+        let max_age = u16::from_be_bytes(bytes[29..31].try_into().unwrap());
+        let hello_time = u16::from_be_bytes(bytes[31..33].try_into().unwrap());
+        let forward_delay = u16::from_be_bytes(bytes[33..35].try_into().unwrap());
+
+        // RSTP and MSTP BPDUs carry a Version 1 Length, always 0
+        if version >= 2 && bytes.len() < Self::RSTP_LEN {
+            return Err(runt());
         }
 
-        let destination_address = bytes[0..6].try_into().unwrap();
-        let source_address = bytes[6..12].try_into().unwrap();
-        let protocol_id = u16::from_be_bytes(bytes[12..14].try_into().unwrap());
-        let version = bytes[14];
-        let bpdu_type = bytes[15];
-        let flags = bytes[16];
-        let root_id = u64::from_be_bytes(bytes[17..25].try_into().unwrap());
-        let root_path_cost = u32::from_be_bytes(bytes[25..29].try_into().unwrap());
-        let bridge_id = u64::from_be_bytes(bytes[29..37].try_into().unwrap());
-        let port_id = u16::from_be_bytes(bytes[37..39].try_into().unwrap());
-        let message_age = u16::from_be_bytes(bytes[39..41].try_into().unwrap());
-        let max_age = u16::from_be_bytes(bytes[41..43].try_into().unwrap());
-        let hello_time = u16::from_be_bytes(bytes[43..45].try_into().unwrap());
-        let forward_delay = u16::from_be_bytes(bytes[45..47].try_into().unwrap());
+        let mut mst_config = None;
+        let mut msti = Vec::new();
+
+        // Version 3 Length, MST Configuration Identifier, the CIST internal fields, then the MSTI configuration messages
+        if version == 3 {
+            let start = Self::RSTP_LEN + 2;
+            let length = match bytes.get(Self::RSTP_LEN..start) {
+                Some(length) => u16::from_be_bytes(length.try_into().unwrap()) as usize,
+                None => return Err(runt()),
+            };
+
+            let end = start + length;
+            if length < MstConfigId::LEN + Self::CIST_LEN || bytes.len() < end {
+                return Err(runt());
+            }
+
+            mst_config = Some(MstConfigId::from_bytes(
+                bytes[start..start + MstConfigId::LEN].to_vec(),
+            )?);
+
+            let mut i = start + MstConfigId::LEN + Self::CIST_LEN;
+            while i + MstiRecord::LEN <= end {
+                msti.push(MstiRecord::from_bytes(
+                    bytes[i..i + MstiRecord::LEN].to_vec(),
+                )?);
+                i += MstiRecord::LEN;
+            }
+        }
 
         Ok(Self {
-            destination_address,
-            source_address,
             protocol_id,
             version,
             bpdu_type,
@@ -852,14 +2668,14 @@ impl ByteSerializable for BpduFrame {
             max_age,
             hello_time,
             forward_delay,
+            mst_config,
+            msti,
         })
     }
 
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        bytes.extend_from_slice(&self.destination_address);
-        bytes.extend_from_slice(&self.source_address);
         bytes.extend_from_slice(&self.protocol_id.to_be_bytes());
         bytes.push(self.version);
         bytes.push(self.bpdu_type);
@@ -873,6 +2689,192 @@ impl ByteSerializable for BpduFrame {
         bytes.extend_from_slice(&self.hello_time.to_be_bytes());
         bytes.extend_from_slice(&self.forward_delay.to_be_bytes());
 
+        if self.version >= 2 {
+            bytes.push(0); // Version 1 Length
+        }
+
+        if let Some(mst_config) = &self.mst_config {
+            let length = MstConfigId::LEN + Self::CIST_LEN + self.msti.len() * MstiRecord::LEN;
+            bytes.extend_from_slice(&(length as u16).to_be_bytes());
+            bytes.extend_from_slice(&mst_config.to_bytes());
+
+            // The simulation doesn't run a separate IST within the region, so the CIST internal fields are those of
+            // the CIST.
+            bytes.extend_from_slice(&self.root_cost.to_be_bytes());
+            bytes.extend_from_slice(&self.bid.to_be_bytes());
+            bytes.push(20); // Remaining hops
+
+            for record in self.msti.iter() {
+                bytes.extend_from_slice(&record.to_bytes());
+            }
+        }
+
+        bytes
+    }
+}
+
+/// MST Configuration Identifier; two switches are in the same MST region if their identifiers match.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MstConfigId {
+    pub format_selector: u8, // Always 0
+    pub name: [u8; 32],      // Null padded region name
+    pub revision: u16,
+    pub digest: [u8; 16], // Digest of the VLAN to instance table
+}
+
+impl MstConfigId {
+    const LEN: usize = 51;
+}
+
+impl ByteSerializable for MstConfigId {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
+        if bytes.len() != Self::LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "MST Configuration Identifier must be 51 bytes.",
+            ));
+        }
+
+        Ok(Self {
+            format_selector: bytes[0],
+            name: bytes[1..33].try_into().unwrap(),
+            revision: u16::from_be_bytes([bytes[33], bytes[34]]),
+            digest: bytes[35..51].try_into().unwrap(),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.push(self.format_selector);
+        bytes.extend_from_slice(&self.name);
+        bytes.extend_from_slice(&self.revision.to_be_bytes());
+        bytes.extend_from_slice(&self.digest);
+        bytes
+    }
+}
+
+/// MSTI Configuration Message, the view of a single MSTI carried in an MSTP BPDU.
+//
+// The standard record is 16 bytes and abbreviates the bridge and port priorities to 4 bits. The simulation allows any
+// u16 priority, so the record carries the full bridge ID and port ID of the sender as well as the instance number.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MstiRecord {
+    pub instance: u8,
+    pub flags: u8,
+    pub regional_root_bid: u64, // Root of the MSTI within the region
+    pub internal_root_cost: u32,
+    pub bid: u64, // Bridge ID of the sender within the MSTI
+    pub port: u16,
+    pub remaining_hops: u8,
+}
+
+impl MstiRecord {
+    const LEN: usize = 25;
+
+    fn stp_role(&self) -> Option<StpRole> {
+        match (self.flags & 0b0000_1100) >> 2 {
+            0 => Some(StpRole::Root),
+            1 => Some(StpRole::Designated),
+            2 => Some(StpRole::Alternate),
+            3 => Some(StpRole::Backup),
+            _ => None,
+        }
+    }
+}
+
+impl ByteSerializable for MstiRecord {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
+        if bytes.len() != Self::LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "MSTI record must be 25 bytes.",
+            ));
+        }
+
+        Ok(Self {
+            instance: bytes[0],
+            flags: bytes[1],
+            regional_root_bid: u64::from_be_bytes(bytes[2..10].try_into().unwrap()),
+            internal_root_cost: u32::from_be_bytes(bytes[10..14].try_into().unwrap()),
+            bid: u64::from_be_bytes(bytes[14..22].try_into().unwrap()),
+            port: u16::from_be_bytes([bytes[22], bytes[23]]),
+            remaining_hops: bytes[24],
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.push(self.instance);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.regional_root_bid.to_be_bytes());
+        bytes.extend_from_slice(&self.internal_root_cost.to_be_bytes());
+        bytes.extend_from_slice(&self.bid.to_be_bytes());
+        bytes.extend_from_slice(&self.port.to_be_bytes());
+        bytes.push(self.remaining_hops);
+        bytes
+    }
+}
+
+/// Link Aggregation Control Protocol Data Unit (LACPDU)
+//
+// The standard LACPDU is a 110 byte sequence of TLVs with system and port priorities. The simulation only negotiates
+// which ports bundle, so it carries the actor and partner information without priorities or the collector TLV.
+#[derive(Debug, PartialEq)]
+pub struct Lacpdu {
+    pub actor_system: MacAddress,
+    pub actor_key: u16, // The port-channel of the sender
+    pub actor_port: u16,
+    pub actor_state: u8,
+    pub partner_system: MacAddress, // All zeros until the sender has heard from its partner
+    pub partner_key: u16,
+    pub partner_port: u16,
+}
+
+impl Lacpdu {
+    const SUBTYPE: u8 = 0x01; // LACP
+    const VERSION: u8 = 0x01;
+    const LEN: usize = 23;
+
+    /// Returns the actor state flags. Ports are always active and aggregatable ; a bundled port is also in sync,
+    /// collecting and distributing.
+    fn state(bundled: bool) -> u8 {
+        let mut state = 0x01 | 0x04; // Activity, Aggregation
+        if bundled {
+            state |= 0x08 | 0x10 | 0x20; // Synchronization, Collecting, Distributing
+        }
+        state
+    }
+}
+
+impl ByteSerializable for Lacpdu {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
+        if bytes.len() < Self::LEN || bytes[0] != Self::SUBTYPE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid LACPDU.",
+            ));
+        }
+
+        Ok(Self {
+            actor_system: bytes[2..8].try_into().unwrap(),
+            actor_key: u16::from_be_bytes([bytes[8], bytes[9]]),
+            actor_port: u16::from_be_bytes([bytes[10], bytes[11]]),
+            actor_state: bytes[12],
+            partner_system: bytes[13..19].try_into().unwrap(),
+            partner_key: u16::from_be_bytes([bytes[19], bytes[20]]),
+            partner_port: u16::from_be_bytes([bytes[21], bytes[22]]),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![Self::SUBTYPE, Self::VERSION];
+        bytes.extend_from_slice(&self.actor_system);
+        bytes.extend_from_slice(&self.actor_key.to_be_bytes());
+        bytes.extend_from_slice(&self.actor_port.to_be_bytes());
+        bytes.push(self.actor_state);
+        bytes.extend_from_slice(&self.partner_system);
+        bytes.extend_from_slice(&self.partner_key.to_be_bytes());
+        bytes.extend_from_slice(&self.partner_port.to_be_bytes());
         bytes
     }
 }
@@ -3,8 +3,10 @@ use std::{cell::RefCell, rc::Rc};
 use crate::{
     mac_addr, mac_broadcast_addr,
     network::{
-        device::cable::EthernetPort,
+        device::cable::{EthernetPort, PortCounters, PortMap},
         ipv4::{ArpFrame, ArpOperation, Ipv4Address},
+        log::DropReason,
+        trace::{TraceId, TraceStep, Tracer},
     },
 };
 
@@ -29,6 +31,26 @@ impl EthernetInterface {
         self.port.clone()
     }
 
+    /// Moves the interface onto the copy of its port, see `PortMap`.
+    pub fn relink(&mut self, ports: &mut PortMap) {
+        self.port = ports.copy(&self.port);
+    }
+
+    /// Returns the tracer of the interface's port, see `Tracer`.
+    pub fn tracer(&self) -> Tracer {
+        self.port.borrow().tracer().clone()
+    }
+
+    /// Records a decision about the frame being handled, see `EthernetPort::trace`.
+    pub fn trace(&self, step: TraceStep) {
+        self.port.borrow().trace(step);
+    }
+
+    /// Returns the traffic statistics of the interface's port.
+    pub fn counters(&self) -> PortCounters {
+        self.port.borrow().counters()
+    }
+
     /// Connects two EthernetInterfaces together via EthernetPorts
     /// * `other` - The interface to establish a bidirectional connection with. (self -> other and other -> self)
     pub fn connect(&self, other: &EthernetInterface) {
@@ -78,6 +100,12 @@ impl EthernetInterface {
         self.port.borrow_mut().send(frame.to_bytes());
     }
 
+    /// Sends an existing Ethernet802_3 frame from this interface unchanged, preserving its source address and LLC header.
+    /// * `frame` - The frame to send.
+    pub fn send8023_frame(&mut self, frame: &Ethernet802_3Frame) {
+        self.port.borrow_mut().send(frame.to_bytes());
+    }
+
     /// Broadcasts an ARP request to find the MAC address of the target IP address over EthernetII.
     /// The source MAC address is assumed to be this interface's MAC address.
     /// * `sender_ip` - The IP address of the sender.
@@ -124,16 +152,31 @@ impl EthernetInterface {
     ///
     /// Frames with a source MAC address that is broadcast or multicast are also filtered out.
     pub fn receive(&mut self) -> Vec<EthernetFrame> {
-        let bytes = self.port.borrow_mut().consume_incoming();
+        self.receive_traced()
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect()
+    }
+
+    /// Returns a list of Ethernet frames that were received since the last call, each with its trace.
+    /// Invalid frames are dropped as corrupted.
+    pub fn receive_traced(&mut self) -> Vec<(TraceId, EthernetFrame)> {
+        let bytes = self.port.borrow_mut().consume_incoming_traced();
         if bytes.is_empty() {
             return vec![];
         }
 
+        let tracer = self.tracer();
         bytes
             .into_iter()
-            .filter_map(|b| match EthernetFrame::from_bytes(b) {
-                Ok(frame) => Some(frame),
-                Err(_) => None,
+            .filter_map(|(trace, b)| match EthernetFrame::from_bytes(b) {
+                Ok(frame) => Some((trace, frame)),
+                Err(_) => {
+                    tracer.enter(trace);
+                    self.trace(TraceStep::Dropped(DropReason::Corrupted));
+                    tracer.leave();
+                    None
+                }
             })
             .collect()
     }
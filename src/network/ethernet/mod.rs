@@ -36,7 +36,7 @@ macro_rules! is_mac_multicast_or_broadcast {
 }
 
 /// An Ethernet frame that can be EthernetII or Ethernet802_3.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum EthernetFrame {
     Ethernet2(Ethernet2Frame),
     Ethernet802_3(Ethernet802_3Frame),
@@ -67,6 +67,13 @@ impl EthernetFrame {
 
 impl ByteSerializable for EthernetFrame {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
+        if bytes.len() < 22 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid Ethernet frame",
+            ));
+        }
+
         let ether_type_or_length = u16::from_be_bytes(bytes[20..22].try_into().unwrap());
 
         let frame = if ether_type_or_length >= 0x0600 {
@@ -114,11 +121,90 @@ macro_rules! eth802_3_data {
     }};
 }
 
+/// Computes the CRC-32 (IEEE 802.3) of the bytes, as used by the Frame Check Sequence.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Returns the Frame Check Sequence of serialized frame bytes, covering everything after the start frame delimiter.
+fn frame_check_sequence(bytes: &[u8]) -> u32 {
+    crc32(&bytes[8..bytes.len() - 4])
+}
+
+/// Verifies the Frame Check Sequence at the end of serialized frame bytes.
+fn verify_frame_check_sequence(bytes: &[u8]) -> Result<u32, std::io::Error> {
+    let fcs = u32::from_be_bytes([
+        bytes[bytes.len() - 4],
+        bytes[bytes.len() - 3],
+        bytes[bytes.len() - 2],
+        bytes[bytes.len() - 1],
+    ]);
+
+    if fcs != frame_check_sequence(bytes) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Frame check sequence mismatch; FCS error.",
+        ));
+    }
+
+    Ok(fcs)
+}
+
+/// Bounds of the serialized bytes of an EthernetII frame, below which it is a runt and above which it is a giant.
+const ETHERNET2_LEN: (usize, usize) = (46, 1500);
+
+/// Bounds of the serialized bytes of an Ethernet802_3 frame, below which it is a runt and above which it is a giant.
+const ETHERNET802_3_LEN: (usize, usize) = (64, 1518);
+
+/// A reason a received frame is invalid and discarded.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FrameError {
+    Runt,
+    Giant,
+    FrameCheckSequence,
+}
+
+/// Checks the size and Frame Check Sequence of serialized frame bytes, the same way `from_bytes` does.
+pub fn check_frame(bytes: &[u8]) -> Result<(), FrameError> {
+    if bytes.len() < 22 {
+        return Err(FrameError::Runt);
+    }
+
+    let ether_type_or_length = u16::from_be_bytes([bytes[20], bytes[21]]);
+    let (min, max) = if ether_type_or_length >= 0x0600 {
+        ETHERNET2_LEN
+    } else {
+        ETHERNET802_3_LEN
+    };
+
+    if bytes.len() < min {
+        return Err(FrameError::Runt);
+    }
+
+    if bytes.len() > max {
+        return Err(FrameError::Giant);
+    }
+
+    verify_frame_check_sequence(bytes)
+        .map(|_| ())
+        .map_err(|_| FrameError::FrameCheckSequence)
+}
+
 /// Ethernet II EtherType field
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum EtherType {
     Ipv4 = 0x0800,
     Arp = 0x0806,
+    Vlan = 0x8100,          // IEEE 802.1Q tag
+    SlowProtocols = 0x8809, // LACP
     Debug = 0xFFFF,
 }
 
@@ -127,6 +213,8 @@ impl From<u16> for EtherType {
         match item {
             0x0800 => Self::Ipv4,
             0x0806 => Self::Arp,
+            0x8100 => Self::Vlan,
+            0x8809 => Self::SlowProtocols,
             _ => Self::Debug,
         }
     }
@@ -158,21 +246,75 @@ impl Ethernet2Frame {
             source_address,
             ether_type,
             data,
-            frame_check_sequence: 0, // TODO: Calculate FCS
+            frame_check_sequence: 0,
+        }
+        .with_fcs()
+    }
+
+    /// Recalculates the Frame Check Sequence over the frame's current contents. Required after modifying a field.
+    pub fn update_fcs(&mut self) {
+        self.frame_check_sequence = frame_check_sequence(&self.to_bytes());
+    }
+
+    fn with_fcs(mut self) -> Self {
+        self.update_fcs();
+        self
+    }
+
+    /// Inserts an IEEE 802.1Q tag for the given VLAN.
+    ///
+    /// The tag is carried as the first four bytes of the data, the Tag Control Information (TCI) followed by the
+    /// original EtherType. The frame's EtherType becomes `EtherType::Vlan`.
+    pub fn tag(self, vlan: u16) -> Ethernet2Frame {
+        let mut data = Vec::with_capacity(self.data.len() + 4);
+        data.extend_from_slice(&(vlan & 0x0FFF).to_be_bytes()); // Priority and DEI are always 0
+        data.extend_from_slice(&(self.ether_type as u16).to_be_bytes());
+        data.extend_from_slice(&self.data);
+
+        Ethernet2Frame {
+            ether_type: EtherType::Vlan,
+            data,
+            ..self
+        }
+        .with_fcs()
+    }
+
+    /// Removes the IEEE 802.1Q tag from the frame.
+    ///
+    /// ## Returns
+    /// * The VLAN ID of the tag, None if the frame was untagged.
+    /// * The untagged frame.
+    pub fn untag(self) -> (Option<u16>, Ethernet2Frame) {
+        if self.ether_type != EtherType::Vlan || self.data.len() < 4 {
+            return (None, self);
         }
+
+        let vlan = u16::from_be_bytes([self.data[0], self.data[1]]) & 0x0FFF;
+        let ether_type = u16::from_be_bytes([self.data[2], self.data[3]]).into();
+        let data = self.data[4..].to_vec();
+
+        (
+            Some(vlan),
+            Ethernet2Frame {
+                ether_type,
+                data,
+                ..self
+            }
+            .with_fcs(),
+        )
     }
 }
 
 impl ByteSerializable for Ethernet2Frame {
     fn from_bytes(bytes: Vec<u8>) -> Result<Ethernet2Frame, std::io::Error> {
-        if bytes.len() < 46 {
+        if bytes.len() < ETHERNET2_LEN.0 {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Insufficient bytes for Ethernet frame; Runt frame.",
             ));
         }
 
-        if bytes.len() > 1500 {
+        if bytes.len() > ETHERNET2_LEN.1 {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Oversized Ethernet frame; Giant frame.",
@@ -194,12 +336,7 @@ impl ByteSerializable for Ethernet2Frame {
 
         let data = bytes[22..bytes.len() - 4].to_vec();
 
-        let frame_check_sequence = u32::from_be_bytes([
-            bytes[bytes.len() - 4],
-            bytes[bytes.len() - 3],
-            bytes[bytes.len() - 2],
-            bytes[bytes.len() - 1],
-        ]);
+        let frame_check_sequence = verify_frame_check_sequence(&bytes)?;
 
         Ok(Self {
             preamble,
@@ -255,21 +392,32 @@ impl Ethernet802_3Frame {
             ssap: 0x42, // Spanning Tree Protocol
             control: 0x03,
             data,
-            frame_check_sequence: 0, // TODO: Calculate FCS
+            frame_check_sequence: 0,
         }
+        .with_fcs()
+    }
+
+    /// Recalculates the Frame Check Sequence over the frame's current contents. Required after modifying a field.
+    pub fn update_fcs(&mut self) {
+        self.frame_check_sequence = frame_check_sequence(&self.to_bytes());
+    }
+
+    fn with_fcs(mut self) -> Self {
+        self.update_fcs();
+        self
     }
 }
 
 impl ByteSerializable for Ethernet802_3Frame {
     fn from_bytes(bytes: Vec<u8>) -> Result<Ethernet802_3Frame, std::io::Error> {
-        if bytes.len() < 64 {
+        if bytes.len() < ETHERNET802_3_LEN.0 {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Insufficient bytes for Ethernet frame; Runt frame.",
             ));
         }
 
-        if bytes.len() > 1518 {
+        if bytes.len() > ETHERNET802_3_LEN.1 {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Oversized Ethernet frame; Giant frame.",
@@ -295,12 +443,7 @@ impl ByteSerializable for Ethernet802_3Frame {
 
         let data = bytes[25..bytes.len() - 4].to_vec();
 
-        let frame_check_sequence = u32::from_be_bytes([
-            bytes[bytes.len() - 4],
-            bytes[bytes.len() - 3],
-            bytes[bytes.len() - 2],
-            bytes[bytes.len() - 1],
-        ]);
+        let frame_check_sequence = verify_frame_check_sequence(&bytes)?;
 
         Ok(Self {
             preamble,
@@ -1,9 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
 
 use super::*;
-use crate::localhost;
 use crate::network::ethernet::interface::EthernetInterface;
 use crate::network::ethernet::*;
+use crate::network::log::{DropReason, EventLog, LogEvent};
+use crate::network::trace::{TraceId, TraceStep};
+use crate::{ipv4_multicast_mac, is_ipv4_link_local_multicast, localhost};
 
 macro_rules! ipv4_multicast_addr {
     () => {
@@ -11,9 +14,15 @@ macro_rules! ipv4_multicast_addr {
     };
 }
 
-macro_rules! mac_multicast_addr {
+macro_rules! ipv4_all_hosts_addr {
     () => {
-        [0x01, 0x00, 0x5e, 0x00, 0x00, 0x00]
+        [224, 0, 0, 1]
+    };
+}
+
+macro_rules! ipv4_all_routers_addr {
+    () => {
+        [224, 0, 0, 2]
     };
 }
 
@@ -43,29 +52,36 @@ macro_rules! network_address {
     }};
 }
 
-#[derive(Debug)]
+/// Time to wait for an ARP reply before retrying the request.
+const ARP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
 struct WaitForArpResolve {
-    ip: Ipv4Address, // The address needed to resolve
-    ttl: u8,         // ticks to live ; TODO: Use a tick timer here instead
+    ip: Ipv4Address,      // The address needed to resolve
+    retry_at: SystemTime, // Time the request is retried, or given up on
     retry: u8,
+    done: bool, // Resolved, or given up on
     frame: Ipv4Frame,
+    trace: TraceId, // The trace of the frame, joined by the ARP requests resolving it
 }
 
 impl WaitForArpResolve {
-    fn new(ip: Ipv4Address, frame: Ipv4Frame) -> Self {
+    fn new(ip: Ipv4Address, frame: Ipv4Frame, now: SystemTime, trace: TraceId) -> Self {
         Self {
             ip,
-            ttl: 30, // roughly 1 second in a 30 tick per second simulation
+            retry_at: now + ARP_RETRY_INTERVAL,
             retry: 3,
+            done: false,
             frame,
+            trace,
         }
     }
 }
 
 /// A layer 3 interface for Ipv4 actions, sending and receiving Ipv4Frames through an EthernetInterface.
 ///
-/// Contains an ARP table to map IP addresses to MAC addresses.
-#[derive(Debug)]
+/// Contains an ARP table to map IP addresses to MAC addresses, and the multicast groups joined with IGMP.
+#[derive(Debug, Clone)]
 pub struct Ipv4Interface {
     pub ethernet: EthernetInterface,
     pub ip_address: Ipv4Address,
@@ -73,7 +89,9 @@ pub struct Ipv4Interface {
     pub default_gateway: Option<Ipv4Address>,
     arp_buf: Vec<WaitForArpResolve>,
     arp_table: HashMap<Ipv4Address, MacAddress>,
+    groups: HashSet<Ipv4Address>, // Multicast groups joined by this interface
     router_interface: bool,
+    log: EventLog,
 }
 
 impl Ipv4Interface {
@@ -92,7 +110,9 @@ impl Ipv4Interface {
             default_gateway,
             arp_buf,
             arp_table,
+            groups: HashSet::new(),
             router_interface: false,
+            log: EventLog::default(),
         }
     }
 
@@ -124,7 +144,9 @@ impl Ipv4Interface {
             default_gateway,
             arp_buf,
             arp_table,
+            groups: HashSet::new(),
             router_interface: false,
+            log: EventLog::default(),
         }
     }
 
@@ -146,7 +168,8 @@ impl Ipv4Interface {
     ///
     /// # Remarks
     /// Will send an ARP request if the destination MAC address is not in the ARP table.
-    /// The original packet is placed in a buffer to send after the ARP request is resolved within the next 30 ticks
+    /// The original packet is placed in a buffer to send after the ARP request is resolved, retrying the request every
+    /// second up to 3 times
     ///
     /// The key will be either:
     /// 1. The destination IP address if the destination is on the same subnet.
@@ -157,6 +180,8 @@ impl Ipv4Interface {
     /// would require the default gateway, but a router port does not have a default gateway. Instead, proxied_destination is used to override the
     /// default gateway, resolving to the correct MAC address of the interface the router knows the destination is reachable through.
     ///
+    /// The frame and its ARP requests join one trace, see `Tracer::origin`.
+    ///
     /// # Returns
     /// True if the address was found in the ARP table and the frame was sent, false if buffering the frame.
     /// Err if the destination is unreachable (no default gateway).
@@ -168,6 +193,30 @@ impl Ipv4Interface {
         ttl: u8,
         data: Vec<u8>,
         protocol: Ipv4Protocol,
+    ) -> Result<bool, &'static str> {
+        self.ethernet.tracer().origin(|trace| {
+            self._sendv(
+                source,
+                destination,
+                proxied_destination,
+                ttl,
+                data,
+                protocol,
+                trace,
+            )
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn _sendv(
+        &mut self,
+        source: Ipv4Address,
+        destination: Ipv4Address,
+        proxied_destination: Option<Ipv4Address>,
+        ttl: u8,
+        data: Vec<u8>,
+        protocol: Ipv4Protocol,
+        trace: TraceId,
     ) -> Result<bool, &'static str> {
         if self._ip_is_self(destination) {
             let frame = Ipv4Frame::new(source, destination, ttl, data, protocol);
@@ -194,7 +243,10 @@ impl Ipv4Interface {
 
                 // Send an ARP request to find the MAC address of the target IP address
                 // Buffer the frame to send after the ARP request is resolved
-                self.arp_buf.push(WaitForArpResolve::new(arp_key, frame));
+                let clock = self.ethernet.port().borrow().clock().clone();
+                let wait = WaitForArpResolve::new(arp_key, frame, clock.now(), trace);
+                clock.schedule(wait.retry_at, "ArpRetry");
+                self.arp_buf.push(wait);
                 self.ethernet.arp_request(self.ip_address, arp_key);
 
                 Ok(false)
@@ -264,6 +316,7 @@ impl Ipv4Interface {
             IcmpType::EchoRequest => IcmpFrame::echo_request(0, 0, vec![]),
             IcmpType::EchoReply => IcmpFrame::echo_reply(0, 0, vec![]),
             IcmpType::Unreachable => IcmpFrame::destination_unreachable(0, vec![]),
+            IcmpType::TimeExceeded => IcmpFrame::time_exceeded(0, vec![]),
         };
         self.sendv(
             self.ip_address,
@@ -277,15 +330,49 @@ impl Ipv4Interface {
 
     /// Sends data to the multicast address.
     pub fn multicast(&mut self, data: Vec<u8>, protocol: Ipv4Protocol) {
-        let frame = Ipv4Frame::new(
-            self.ip_address,
-            ipv4_multicast_addr!(),
-            64,
-            data.clone(),
-            protocol,
-        );
-        self.ethernet
-            .send(mac_multicast_addr!(), EtherType::Ipv4, frame.to_bytes());
+        self.multicast_to(ipv4_multicast_addr!(), data, protocol);
+    }
+
+    /// Sends data to a multicast group, using the group's MAC address.
+    pub fn multicast_to(&mut self, group: Ipv4Address, data: Vec<u8>, protocol: Ipv4Protocol) {
+        self._send_multicast(group, 64, data, protocol);
+    }
+
+    /// Joins a multicast group, announcing the membership with an IGMP report.
+    pub fn join_group(&mut self, group: Ipv4Address) -> Result<(), &'static str> {
+        if !(224..=239).contains(&group[0]) {
+            return Err("Not a multicast group address.");
+        }
+
+        self.groups.insert(group);
+        self._send_igmp(group, IgmpFrame::report(group));
+        Ok(())
+    }
+
+    /// Leaves a multicast group, telling the routers on the network with an IGMP leave.
+    pub fn leave_group(&mut self, group: Ipv4Address) -> Result<(), &'static str> {
+        if !self.groups.remove(&group) {
+            return Err("Not a member of the group.");
+        }
+
+        self._send_igmp(ipv4_all_routers_addr!(), IgmpFrame::leave(group));
+        Ok(())
+    }
+
+    /// Returns the multicast groups joined by this interface.
+    pub fn groups(&self) -> Vec<Ipv4Address> {
+        let mut groups: Vec<Ipv4Address> = self.groups.iter().copied().collect();
+        groups.sort();
+        groups
+    }
+
+    /// Asks the hosts on the network which groups they are members of.
+    /// * `group` - The group to query, None to query all groups.
+    pub fn send_igmp_query(&mut self, group: Option<Ipv4Address>) {
+        match group {
+            Some(group) => self._send_igmp(group, IgmpFrame::query(group)),
+            None => self._send_igmp(ipv4_all_hosts_addr!(), IgmpFrame::query([0, 0, 0, 0])),
+        }
     }
 
     /// Receives data from the ethernet interface. Processes ARP frames to the ARP table.
@@ -297,10 +384,22 @@ impl Ipv4Interface {
     /// # Returns
     /// A vector of Ipv4Frames received from the ethernet interface.
     pub fn receive(&mut self) -> Vec<Ipv4Frame> {
+        self.receive_traced()
+            .into_iter()
+            .map(|(_, frame)| frame)
+            .collect()
+    }
+
+    /// Receives data from the ethernet interface like `receive`, returning each Ipv4Frame with its trace.
+    ///
+    /// The frames sent while processing a received frame, such as an ARP or echo reply, join the frame's trace.
+    pub fn receive_traced(&mut self) -> Vec<(TraceId, Ipv4Frame)> {
         let mut ipv4_frames = Vec::new();
-        let frames = self.ethernet.receive();
+        let frames = self.ethernet.receive_traced();
+        let tracer = self.ethernet.tracer();
 
-        for frame in frames {
+        for (trace, frame) in frames {
+            tracer.enter(trace);
             let f = match frame {
                 EthernetFrame::Ethernet2(frame) => frame,
                 _ => continue, // Discard non-Ethernet2 frames
@@ -309,7 +408,7 @@ impl Ipv4Interface {
             match f.ether_type {
                 EtherType::Ipv4 => {
                     if let Ok(ipv4_frame) = Ipv4Frame::from_bytes(f.data) {
-                        self._receive_ipv4(ipv4_frame, f.source_address, &mut ipv4_frames);
+                        self._receive_ipv4(trace, ipv4_frame, f.source_address, &mut ipv4_frames);
                     }
                 }
                 EtherType::Arp => {
@@ -320,6 +419,7 @@ impl Ipv4Interface {
                 _ => {}
             }
         }
+        tracer.leave();
 
         self._process_arp_buf();
 
@@ -330,6 +430,44 @@ impl Ipv4Interface {
         self.arp_table.clone()
     }
 
+    /// Removes and returns the addresses learned and frames dropped by the interface, see `EventLog`.
+    pub fn take_events(&mut self) -> Vec<(Option<usize>, LogEvent)> {
+        self.log.take()
+    }
+
+    fn _send_multicast(
+        &mut self,
+        group: Ipv4Address,
+        ttl: u8,
+        data: Vec<u8>,
+        protocol: Ipv4Protocol,
+    ) {
+        let frame = Ipv4Frame::new(self.ip_address, group, ttl, data, protocol);
+        self.ethernet.send(
+            ipv4_multicast_mac!(group),
+            EtherType::Ipv4,
+            frame.to_bytes(),
+        );
+    }
+
+    /// IGMP messages never leave the local network (TTL 1)
+    fn _send_igmp(&mut self, destination: Ipv4Address, igmp: IgmpFrame) {
+        self._send_multicast(destination, 1, igmp.to_bytes(), Ipv4Protocol::Igmp);
+    }
+
+    /// Answers an IGMP query with a report for every queried group this interface is a member of.
+    fn _receive_igmp(&mut self, igmp: &IgmpFrame) {
+        if igmp.igmp_type != IgmpType::MembershipQuery as u8 {
+            return;
+        }
+
+        for group in self.groups() {
+            if igmp.group == [0, 0, 0, 0] || igmp.group == group {
+                self._send_igmp(group, IgmpFrame::report(group));
+            }
+        }
+    }
+
     fn _ip_is_self(&self, ip: Ipv4Address) -> bool {
         ip == self.ip_address || ip == localhost!()
     }
@@ -338,7 +476,9 @@ impl Ipv4Interface {
         if self._ip_is_self(ip) {
             return; // Don't add self to ARP table.
         }
-        self.arp_table.insert(ip, mac);
+        if self.arp_table.insert(ip, mac) != Some(mac) {
+            self.log.record(None, LogEvent::ArpLearned { ip, mac });
+        }
     }
 
     fn _subnets_match(&self, destination: Ipv4Address) -> bool {
@@ -349,12 +489,35 @@ impl Ipv4Interface {
 
     fn _receive_ipv4(
         &mut self,
+        trace: TraceId,
         frame: Ipv4Frame,
         source_mac: MacAddress,
-        ipv4_frames: &mut Vec<Ipv4Frame>,
+        ipv4_frames: &mut Vec<(TraceId, Ipv4Frame)>,
     ) {
         self._add_arp_entry(frame.source, source_mac);
 
+        if frame.protocol == Ipv4Protocol::Igmp as u8 {
+            self.ethernet.trace(TraceStep::Delivered);
+            if let Ok(igmp) = IgmpFrame::from_bytes(frame.data.clone()) {
+                self._receive_igmp(&igmp);
+            }
+            ipv4_frames.push((trace, frame)); // Routers keep track of the reports and leaves
+            return;
+        }
+
+        // Hosts only accept multicast of the groups they have joined
+        if !self.router_interface
+            && (224..=239).contains(&frame.destination[0])
+            && !is_ipv4_link_local_multicast!(frame.destination)
+            && !self.groups.contains(&frame.destination)
+        {
+            return;
+        }
+
+        if frame.destination == self.ip_address || (224..=239).contains(&frame.destination[0]) {
+            self.ethernet.trace(TraceStep::Delivered);
+        }
+
         // On ICMP echo request, reply with an echo reply if we are the intended target. Don't reply to self.
         if frame.destination == self.ip_address
             && frame.source != self.ip_address
@@ -365,7 +528,7 @@ impl Ipv4Interface {
                 return;
             }
         }
-        ipv4_frames.push(frame);
+        ipv4_frames.push((trace, frame));
     }
 
     fn _receive_arp(&mut self, frame: ArpFrame) {
@@ -391,38 +554,49 @@ impl Ipv4Interface {
     }
 
     fn _process_arp_buf(&mut self) {
+        if self.arp_buf.is_empty() {
+            return;
+        }
+
+        let clock = self.ethernet.port().borrow().clock().clone();
+        let tracer = self.ethernet.tracer();
+        let now = clock.now();
         for i in 0..self.arp_buf.len() {
             let w = &mut self.arp_buf[i];
+            tracer.enter(w.trace);
 
-            w.ttl = w.ttl.saturating_sub(1);
-            if w.ttl == 0 && w.retry == 0 {
+            if w.retry_at <= now && w.retry == 0 {
+                w.done = true;
+                self.ethernet
+                    .trace(TraceStep::Dropped(DropReason::ArpUnresolved));
+                self.log.record(
+                    None,
+                    LogEvent::Dropped {
+                        reason: DropReason::ArpUnresolved,
+                        destination: Some(w.frame.destination),
+                    },
+                );
                 continue;
             }
 
-            if w.ttl == 0 && w.retry > 0 {
+            if w.retry_at <= now && w.retry > 0 {
                 w.retry = w.retry.saturating_sub(1);
-                w.ttl = 30;
+                w.retry_at = now + ARP_RETRY_INTERVAL;
+                clock.schedule(w.retry_at, "ArpRetry");
 
                 // Retry ARP request
-                self.ethernet
-                    .arp_request(self.ip_address, w.frame.destination);
+                self.ethernet.arp_request(self.ip_address, w.ip);
             }
 
             if let Some(mac_address) = self.arp_table.get(&w.ip) {
                 self.ethernet
                     .send(*mac_address, EtherType::Ipv4, w.frame.to_bytes());
-                w.retry = 0;
-                w.ttl = 0;
+                w.done = true;
             }
         }
+        tracer.leave();
 
-        // Pop resolved ARP frames
-        while self
-            .arp_buf
-            .last()
-            .is_some_and(|w| w.ttl == 0 && w.retry == 0)
-        {
-            self.arp_buf.pop();
-        }
+        // Drop resolved and expired ARP frames
+        self.arp_buf.retain(|w| !w.done);
     }
 }
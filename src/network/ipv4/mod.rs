@@ -26,8 +26,25 @@ macro_rules! is_ipv4_multicast_or_broadcast {
     }};
 }
 
+/// 224.0.0.0 -> 224.0.0.255 ; Local network control block, never forwarded by routers and always flooded by switches
+#[macro_export]
+macro_rules! is_ipv4_link_local_multicast {
+    ($address:expr) => {
+        $address[0] == 224 && $address[1] == 0 && $address[2] == 0
+    };
+}
+
+/// Maps an IPv4 multicast group to its MAC address; 01:00:5E followed by the low 23 bits of the group.
+#[macro_export]
+macro_rules! ipv4_multicast_mac {
+    ($group:expr) => {
+        [0x01, 0x00, 0x5E, $group[1] & 0x7F, $group[2], $group[3]]
+    };
+}
+
 pub enum Ipv4Protocol {
     Icmp = 1,
+    Igmp = 2,
     Rip = 17,
     Test = 255,
 }
@@ -36,6 +53,7 @@ impl From<u8> for Ipv4Protocol {
     fn from(item: u8) -> Self {
         match item {
             1 => Self::Icmp,
+            2 => Self::Igmp,
             17 => Self::Rip,
             255 => Self::Test,
             _ => panic!("Invalid Ipv4 protocol"),
@@ -44,7 +62,7 @@ impl From<u8> for Ipv4Protocol {
 }
 
 /// A network layer frame for IPv4 communication
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Ipv4Frame {
     pub version_hlen: u8,  // 4 bits version, 4 bits header length
     pub tos: u8,           // Type of service
@@ -106,6 +124,14 @@ impl ByteSerializable for Ipv4Frame {
         }
 
         let version_hlen = bytes[0];
+        let header_length = (version_hlen & 0x0F) as usize * 4;
+        if header_length < 20 || header_length > bytes.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid Ipv4 header length.",
+            ));
+        }
+
         let tos = bytes[1];
         let total_length = u16::from_be_bytes([bytes[2], bytes[3]]);
         let id = u16::from_be_bytes([bytes[4], bytes[5]]);
@@ -115,8 +141,8 @@ impl ByteSerializable for Ipv4Frame {
         let checksum = u16::from_be_bytes([bytes[10], bytes[11]]);
         let source = [bytes[12], bytes[13], bytes[14], bytes[15]];
         let destination = [bytes[16], bytes[17], bytes[18], bytes[19]];
-        let option = bytes[20..(version_hlen & 0x0F) as usize * 4].to_vec();
-        let data = bytes[(version_hlen & 0x0F) as usize * 4..].to_vec();
+        let option = bytes[20..header_length].to_vec();
+        let data = bytes[header_length..].to_vec();
 
         Ok(Self {
             version_hlen,
@@ -206,7 +232,8 @@ impl ArpFrame {
 
 impl ByteSerializable for ArpFrame {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
-        if bytes.len() != 28 {
+        // ARP frames on the wire are padded to the minimum Ethernet frame size ; the padding is ignored
+        if bytes.len() < 28 {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
                 "Invalid ARP frame",
@@ -256,11 +283,12 @@ pub enum IcmpType {
     EchoRequest = 8,
     EchoReply = 0,
     Unreachable = 3,
+    TimeExceeded = 11,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct IcmpFrame {
-    pub icmp_type: u8, // 0: Echo reply, 3: destination unreachable, 8: Echo request
+    pub icmp_type: u8, // 0: Echo reply, 3: destination unreachable, 8: Echo request, 11: Time exceeded
     pub code: u8,
     pub checksum: u16,
     pub identifier: u16,
@@ -297,6 +325,10 @@ impl IcmpFrame {
     pub fn destination_unreachable(code: u8, data: Vec<u8>) -> Self {
         Self::new(3, code, 0, 0, data)
     }
+
+    pub fn time_exceeded(code: u8, data: Vec<u8>) -> Self {
+        Self::new(11, code, 0, 0, data)
+    }
 }
 
 impl ByteSerializable for IcmpFrame {
@@ -343,3 +375,70 @@ impl ByteSerializable for IcmpFrame {
         bytes
     }
 }
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IgmpType {
+    MembershipQuery = 0x11,
+    MembershipReport = 0x16, // IGMPv2 report
+    LeaveGroup = 0x17,
+}
+
+/// Internet Group Management Protocol version 2 (IGMPv2)
+#[derive(Debug, PartialEq)]
+pub struct IgmpFrame {
+    pub igmp_type: u8,
+    pub max_response_time: u8, // Tenths of a second, only used by queries
+    pub checksum: u16,
+    pub group: Ipv4Address, // 0.0.0.0 for a general query
+}
+
+impl IgmpFrame {
+    pub fn new(igmp_type: IgmpType, max_response_time: u8, group: Ipv4Address) -> Self {
+        Self {
+            igmp_type: igmp_type as u8,
+            max_response_time,
+            checksum: 0, // TODO: Calculate checksum
+            group,
+        }
+    }
+
+    /// A query for the members of a group, or of all groups if the group is 0.0.0.0.
+    pub fn query(group: Ipv4Address) -> Self {
+        Self::new(IgmpType::MembershipQuery, 100, group)
+    }
+
+    pub fn report(group: Ipv4Address) -> Self {
+        Self::new(IgmpType::MembershipReport, 0, group)
+    }
+
+    pub fn leave(group: Ipv4Address) -> Self {
+        Self::new(IgmpType::LeaveGroup, 0, group)
+    }
+}
+
+impl ByteSerializable for IgmpFrame {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self, std::io::Error> {
+        if bytes.len() < 8 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Insufficient bytes for IGMP frame; Runt frame.",
+            ));
+        }
+
+        Ok(Self {
+            igmp_type: bytes[0],
+            max_response_time: bytes[1],
+            checksum: u16::from_be_bytes([bytes[2], bytes[3]]),
+            group: [bytes[4], bytes[5], bytes[6], bytes[7]],
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.push(self.igmp_type);
+        bytes.push(self.max_response_time);
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes.extend_from_slice(&self.group);
+        bytes
+    }
+}
@@ -0,0 +1,200 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::{
+    ipv4_fmt, mac_fmt,
+    network::{
+        device::switch::{StpRole, StpState, TrafficClass},
+        ethernet::MacAddress,
+        ipv4::Ipv4Address,
+    },
+};
+
+/// Decisions a device keeps until they are collected, see `EventLog::take`. Older decisions are forgotten first.
+const EVENT_LOG_LIMIT: usize = 1000;
+
+/// Why a device, or the cable of one of its ports, discarded a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    TtlExpired,    // The frame's TTL ran out on a router
+    NoRoute,       // No route matches the destination of the frame
+    ArpUnresolved, // The next hop never answered the ARP requests
+    PortSecurity,  // The source address isn't allowed on the port
+    StormControl,  // The port received more frames of the class than its storm control level allows
+    QueueFull,     // The port's transmit queue was full
+    NoCarrier,     // The port is down, shut down or not cabled
+    Collisions,    // The frame collided too many times to be retransmitted
+    CableLoss,     // The cable lost the frame, or was cut with the frame on it
+    Corrupted,     // The frame failed its Frame Check Sequence
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::TtlExpired => "ttl_expired",
+            DropReason::NoRoute => "no_route",
+            DropReason::ArpUnresolved => "arp_unresolved",
+            DropReason::PortSecurity => "port_security",
+            DropReason::StormControl => "storm_control",
+            DropReason::QueueFull => "queue_full",
+            DropReason::NoCarrier => "no_carrier",
+            DropReason::Collisions => "collisions",
+            DropReason::CableLoss => "cable_loss",
+            DropReason::Corrupted => "corrupted",
+        }
+    }
+}
+
+/// A decision made by a device, such as learning an address or dropping a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEvent {
+    MacLearned {
+        mac: MacAddress,
+        vlan: u16,
+    },
+    Flooded {
+        destination: MacAddress,
+        vlan: u16,
+        class: TrafficClass,
+    },
+    StpChanged {
+        instance: u8,
+        role: Option<StpRole>,
+        state: StpState,
+    },
+    RouteInstalled {
+        network: Ipv4Address,
+        mask: Ipv4Address,
+        via: Option<Ipv4Address>, // The RIP neighbor the route was learned from ; None for a connected network
+        metric: u32,
+    },
+    ArpLearned {
+        ip: Ipv4Address,
+        mac: MacAddress,
+    },
+    Dropped {
+        reason: DropReason,
+        destination: Option<Ipv4Address>, // The destination of a dropped IPv4 frame
+    },
+}
+
+impl LogEvent {
+    /// The kind of every event, as returned by `kind`.
+    pub const KINDS: [&'static str; 6] = [
+        "mac_learned",
+        "flooded",
+        "stp_changed",
+        "route_installed",
+        "arp_learned",
+        "dropped",
+    ];
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LogEvent::MacLearned { .. } => "mac_learned",
+            LogEvent::Flooded { .. } => "flooded",
+            LogEvent::StpChanged { .. } => "stp_changed",
+            LogEvent::RouteInstalled { .. } => "route_installed",
+            LogEvent::ArpLearned { .. } => "arp_learned",
+            LogEvent::Dropped { .. } => "dropped",
+        }
+    }
+}
+
+impl fmt::Display for LogEvent {
+    /// Describes the decision and its reason in a sentence.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogEvent::MacLearned { mac, vlan } => {
+                write!(f, "Learned {} on VLAN {}", mac_fmt!(mac), vlan)
+            }
+            LogEvent::Flooded {
+                destination,
+                vlan,
+                class,
+            } => {
+                let reason = match class {
+                    TrafficClass::Broadcast => "broadcast",
+                    TrafficClass::Multicast => "multicast",
+                    TrafficClass::UnknownUnicast => "unknown unicast",
+                };
+                write!(
+                    f,
+                    "Flooded {} on VLAN {}, {}",
+                    mac_fmt!(destination),
+                    vlan,
+                    reason
+                )
+            }
+            LogEvent::StpChanged {
+                instance,
+                role,
+                state,
+            } => {
+                let role = role.map_or("no role".to_string(), |r| format!("{:?}", r));
+                write!(f, "Instance {} is {} and {:?}", instance, role, state)
+            }
+            LogEvent::RouteInstalled {
+                network,
+                mask,
+                via,
+                metric,
+            } => {
+                write!(f, "Route to {}/{}", ipv4_fmt!(network), ipv4_fmt!(mask))?;
+                match via {
+                    Some(via) => write!(
+                        f,
+                        " via {}, {} hops, learned with RIP",
+                        ipv4_fmt!(via),
+                        metric
+                    ),
+                    None => write!(f, ", connected"),
+                }
+            }
+            LogEvent::ArpLearned { ip, mac } => {
+                write!(f, "{} is at {}", ipv4_fmt!(ip), mac_fmt!(mac))
+            }
+            LogEvent::Dropped {
+                reason,
+                destination,
+            } => {
+                write!(f, "Dropped ")?;
+                if let Some(destination) = destination {
+                    write!(f, "a frame to {} ", ipv4_fmt!(destination))?;
+                }
+                match reason {
+                    DropReason::TtlExpired => write!(f, "as its TTL expired"),
+                    DropReason::NoRoute => write!(f, "with no route to its destination"),
+                    DropReason::ArpUnresolved => write!(f, "as ARP couldn't resolve its next hop"),
+                    DropReason::PortSecurity => write!(f, "from a source not allowed on the port"),
+                    DropReason::StormControl => write!(f, "over the storm control level"),
+                    DropReason::QueueFull => write!(f, "as the transmit queue was full"),
+                    DropReason::NoCarrier => write!(f, "as the port has no carrier"),
+                    DropReason::Collisions => write!(f, "after too many collisions"),
+                    DropReason::CableLoss => write!(f, "as the cable lost it"),
+                    DropReason::Corrupted => write!(f, "failing its Frame Check Sequence"),
+                }
+            }
+        }
+    }
+}
+
+/// The decisions a device made since they were last collected, each with the port it concerns.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    events: VecDeque<(Option<usize>, LogEvent)>,
+}
+
+impl EventLog {
+    /// Records a decision, concerning a port of the device or the whole device.
+    pub fn record(&mut self, port: Option<usize>, event: LogEvent) {
+        if self.events.len() >= EVENT_LOG_LIMIT {
+            self.events.pop_front();
+        }
+        self.events.push_back((port, event));
+    }
+
+    /// Removes and returns the recorded decisions, oldest first.
+    pub fn take(&mut self) -> Vec<(Option<usize>, LogEvent)> {
+        self.events.drain(..).collect()
+    }
+}
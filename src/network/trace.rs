@@ -0,0 +1,116 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+
+use super::{device::cable::EthernetPort, log::DropReason};
+
+/// Steps a tracer keeps until they are collected, see `Tracer::take`. Older steps are forgotten first.
+const TRACE_RECORD_LIMIT: usize = 10_000;
+
+/// Identifies one originated packet, and every frame sent on its behalf, across the network.
+pub type TraceId = u64;
+
+/// The bytes of a frame, with the trace it joined when it was sent.
+pub type TracedFrame = (TraceId, Vec<u8>);
+
+/// What happened to a traced frame at a port of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceStep {
+    Sent,                // Queued for transmission out of the port
+    Received,            // Taken in by the device from the port
+    Delivered,           // Reached the host it was addressed to
+    Dropped(DropReason), // Discarded by the device, or lost on the port's cable
+}
+
+/// A step of a traced frame, recorded by the port it happened at.
+#[derive(Debug, Clone)]
+pub struct TraceRecord {
+    pub trace: TraceId,
+    pub port: Weak<RefCell<EthernetPort>>, // Compared with `Weak::ptr_eq`, even once the port is gone
+    pub step: TraceStep,
+    pub frame: Option<Vec<u8>>, // None for a decision about the packet, rather than a frame on the port
+}
+
+/// The traces of a simulation: which trace the frames sent from now on join, and the steps of the traced frames.
+///
+/// Clones share the same traces. A simulation hands its tracer to every port it holds, like its `Clock`.
+///
+/// Every frame sent joins a trace. A device handling a frame enters the frame's trace, so every frame it sends on the
+/// frame's behalf, such as a forwarded copy, an ARP request for its next hop or a reply, joins the trace. Any other
+/// frame starts a trace of its own.
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    state: Rc<RefCell<TraceState>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TraceState {
+    next: TraceId,
+    current: Option<TraceId>, // The trace entered, see `enter`
+    records: VecDeque<TraceRecord>,
+}
+
+impl Tracer {
+    /// Copies the tracer, with its entered trace and uncollected steps, into a tracer of its own.
+    pub fn snapshot(&self) -> Tracer {
+        Tracer {
+            state: Rc::new(RefCell::new(self.state.borrow().clone())),
+        }
+    }
+
+    /// Returns the trace entered, if any.
+    pub fn current(&self) -> Option<TraceId> {
+        self.state.borrow().current
+    }
+
+    /// Returns the trace a frame sent now joins: the trace entered, or a new one.
+    pub fn join(&self) -> TraceId {
+        let mut state = self.state.borrow_mut();
+        match state.current {
+            Some(trace) => trace,
+            None => {
+                state.next += 1;
+                state.next
+            }
+        }
+    }
+
+    /// Makes the frames sent from now on join the trace, until `leave`.
+    pub fn enter(&self, trace: TraceId) {
+        self.state.borrow_mut().current = Some(trace);
+    }
+
+    /// Stops joining the frames sent to the trace entered. Each frame sent from now on starts a trace of its own.
+    pub fn leave(&self) {
+        self.state.borrow_mut().current = None;
+    }
+
+    /// Runs `f` within the trace entered, or within a new trace if none is, so the frames `f` sends join one trace.
+    pub fn origin<R>(&self, f: impl FnOnce(TraceId) -> R) -> R {
+        if let Some(trace) = self.current() {
+            return f(trace);
+        }
+
+        let trace = self.join();
+        self.enter(trace);
+        let result = f(trace);
+        self.leave();
+        result
+    }
+
+    /// Records a step of a traced frame at a port.
+    pub fn record(&self, record: TraceRecord) {
+        let mut state = self.state.borrow_mut();
+        if state.records.len() >= TRACE_RECORD_LIMIT {
+            state.records.pop_front();
+        }
+        state.records.push_back(record);
+    }
+
+    /// Removes and returns the recorded steps, oldest first.
+    pub fn take(&self) -> Vec<TraceRecord> {
+        self.state.borrow_mut().records.drain(..).collect()
+    }
+}
@@ -6,22 +6,24 @@ use std::{
 use raylib::prelude::*;
 
 use crate::{
+    engine::{
+        device::{DeviceId, DeviceKind, DeviceRepository, DeviceSetQuery, LogFilter},
+        utils::PacketKind,
+    },
     ipv4_fmt, mac_fmt,
     network::{
         device::{router::RipFrame, switch::BpduFrame},
-        ethernet::{ByteSerializable, Ethernet2Frame, Ethernet802_3Frame},
-        ipv4::{ArpFrame, IcmpFrame, Ipv4Frame},
-    },
-    simulation::{
-        device::DeviceAttributes,
-        utils::{self, rstr_from_string},
+        ethernet::{ByteSerializable, Ethernet2Frame, Ethernet802_3Frame, EthernetFrame},
+        ipv4::{ArpFrame, IcmpFrame, IgmpFrame, Ipv4Frame},
+        log::LogEvent,
+        trace::{TraceId, TraceStep},
     },
-    tick::TimeProvider,
+    simulation::utils::{self, rstr_from_string},
 };
 
 use super::{
-    device::{DeviceGetQuery, DeviceId, DeviceKind, DeviceRepository, DeviceSetQuery},
-    utils::PacketKind,
+    render::{self, DeviceGetQuery, DisplayedDevice},
+    storage::TopologyStorage,
 };
 
 #[derive(Clone)]
@@ -31,6 +33,7 @@ struct Packet {
     last: Option<DeviceId>,
     current: DeviceId,
     kind: PacketKind,
+    trace: TraceId,
     time: SystemTime,
 }
 
@@ -63,12 +66,41 @@ enum GuiButtonClickKind {
     Desktop,
     Switch,
     Router,
+    MultilayerSwitch,
+    Hub,
     Ethernet,
     PlayerPlay,
     PlayerNext,
+    PlayerPrevious,
     PlayerPause,
+    CaptureStart,
+    CaptureStop,
+    Save,
+    Open,
+    SpeedDown,
+    SpeedUp,
+    SkipAhead(u64), // Seconds
+    Snapshot,
+    Rollback,
+    EventLog,
+    LogDevice, // Filter the event log by the next device
+    LogKind,   // Filter the event log by the next kind of event
+    LogExport,
+    LogClear,
 }
 
+/// Speeds the simulation can run at, as multipliers of the wall clock.
+const SPEEDS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0];
+
+/// Seconds of simulated time the skip buttons jump ahead by.
+const SKIPS: [u64; 3] = [1, 10, 60];
+
+/// Traced steps that can be stepped back through.
+const HISTORY_LIMIT: usize = 100;
+
+/// Width of the event log panel, on the right of the screen.
+const LOG_PANEL_WIDTH: i32 = 480;
+
 pub struct Gui {
     mode: Option<GuiMode>,
     selection: Option<GuiButtonClickKind>,
@@ -94,6 +126,19 @@ pub struct Gui {
     pub tracer_enabled: bool,
     pub tracer_next: bool,
     tracer_blink: u8,
+
+    pub speed: f64,                   // Multiplier of the wall clock while not tracing
+    pub skip_ahead: Option<Duration>, // Simulated time to run through on the next frame
+
+    history: VecDeque<DeviceRepository>, // Snapshots taken before each traced step, to step backwards
+    snapshots: Vec<DeviceRepository>,    // Snapshots taken by the user, to roll back to
+    rollback_dropdown: Option<(i32, i32)>, // (Scroll Index, Value) of the list of snapshots
+
+    log_open: bool,
+    log_filter: LogFilter,
+    log_scroll: usize, // Entries of the event log scrolled back from the newest
+
+    storage: TopologyStorage,
 }
 
 impl Default for Gui {
@@ -117,6 +162,15 @@ impl Default for Gui {
             tracer_enabled: false,
             tracer_next: false,
             tracer_blink: 0,
+            speed: 1.0,
+            skip_ahead: None,
+            history: VecDeque::new(),
+            snapshots: Vec::new(),
+            rollback_dropdown: None,
+            log_open: false,
+            log_filter: LogFilter::default(),
+            log_scroll: 0,
+            storage: TopologyStorage::default(),
         }
     }
 }
@@ -130,9 +184,70 @@ impl Gui {
         self.selection = None;
         self.ethernet_dropdown = None;
         self.edit_dropdown = None;
+        self.rollback_dropdown = None;
         self.tracer_blink = 0;
     }
 
+    /// Replaces the simulation with a snapshot, forgetting the packets sniffed after it.
+    fn restore(&mut self, dr: &mut DeviceRepository, snapshot: DeviceRepository) {
+        *dr = snapshot;
+        let now = dr.clock().now();
+        self.packet_buffer.retain(|packet| packet.time <= now);
+        self.packet_selected = None;
+    }
+
+    /// Lists the packets on every port, to be called after each step of the simulation.
+    pub fn sniff(&mut self, dr: &DeviceRepository) {
+        for (
+            id,
+            ((incoming_device_id, incoming_packets), (outgoing_device_id, outgoing_packets)),
+        ) in dr.sniff()
+        {
+            let time = dr.clock().now();
+
+            for (trace, packet) in incoming_packets {
+                let incoming_id = if packet.loopback() {
+                    Some(id)
+                } else {
+                    incoming_device_id
+                };
+
+                self.packet_buffer.push_back(Packet {
+                    animating: self.tracer_enabled,
+                    pos: incoming_id
+                        .and_then(|id| {
+                            render::get(dr, DeviceGetQuery::Id(id)).map(|device| device.pos)
+                        })
+                        .unwrap_or(Vector2::new(0.0, 0.0)),
+                    last: incoming_id,
+                    current: id,
+                    kind: packet,
+                    trace,
+                    time,
+                })
+            }
+
+            for (trace, packet) in outgoing_packets {
+                if outgoing_device_id.is_some() {
+                    self.packet_buffer.push_back(Packet {
+                        animating: self.tracer_enabled,
+                        pos: render::get(dr, DeviceGetQuery::Id(id))
+                            .map_or(Vector2::new(0.0, 0.0), |device| device.pos),
+                        last: None,
+                        current: id,
+                        kind: packet,
+                        trace,
+                        time,
+                    });
+                }
+            }
+        }
+
+        while self.packet_buffer.len() > 10 {
+            self.packet_buffer.pop_front(); // take only top 10 packets
+        }
+    }
+
     pub fn render(&mut self, d: &mut RaylibDrawHandle, dr: &mut DeviceRepository) {
         const FONT_SIZE: i32 = 10;
         const PADDING: i32 = 10;
@@ -146,6 +261,48 @@ impl Gui {
         let mouse_pos = d.get_mouse_position();
         self.gui_bounds.clear();
 
+        // Packet Tracer Mode Draw Selected Packet's Path
+        // -----------------------------------
+        if self.tracer_enabled {
+            if let Some(trace) = self
+                .packet_selected
+                .as_ref()
+                .and_then(|p| dr.trace(p.trace))
+            {
+                let pos = |id| render::get(dr, DeviceGetQuery::Id(id)).map(|device| device.pos);
+
+                // Each cable crossed, numbered in the order the packet first crossed it
+                for (i, (d1, _, d2, _)) in dr.trace_links(trace).into_iter().enumerate() {
+                    if let (Some(pos1), Some(pos2)) = (pos(d1), pos(d2)) {
+                        d.draw_line_ex(pos1, pos2, 4.0, Color::ORANGE);
+
+                        let number = (i + 1).to_string();
+                        let middle = Vector2::new((pos1.x + pos2.x) / 2.0, (pos1.y + pos2.y) / 2.0);
+                        d.draw_circle_v(middle, FONT_SIZE as f32, Color::ORANGE);
+                        d.draw_text(
+                            &number,
+                            middle.x as i32 - d.measure_text(&number, FONT_SIZE) / 2,
+                            middle.y as i32 - FONT_SIZE / 2,
+                            FONT_SIZE,
+                            Color::BLACK,
+                        );
+                    }
+                }
+
+                // Circle the device the packet was delivered to, or dropped at
+                if let Some(fate) = trace.fate() {
+                    let color = match fate.step {
+                        TraceStep::Dropped(_) => Color::RED,
+                        _ => Color::GREEN,
+                    };
+                    if let Some(pos) = pos(fate.device) {
+                        d.draw_circle_lines(pos.x as i32, pos.y as i32, box_width as f32, color);
+                    }
+                }
+            }
+        }
+        // -----------------------------------
+
         // Packet Tracer Mode Draw Packets
         // -----------------------------------
         if self.tracer_enabled {
@@ -160,6 +317,9 @@ impl Gui {
                     PacketKind::Bpdu(_) => ("BPDU", Color::DARKBLUE),
                     PacketKind::Rip(_) => ("RIP", Color::DARKGREEN),
                     PacketKind::Icmp(_) => ("ICMP", Color::DARKPURPLE),
+                    PacketKind::Igmp(_) => ("IGMP", Color::DARKBROWN),
+                    PacketKind::Other(_) => ("ETH", Color::DARKGRAY),
+                    PacketKind::Corrupt(_) => ("FCS", Color::RED),
                 };
 
                 d.draw_rectangle(packet.pos.x as i32, packet.pos.y as i32, 20, 20, color);
@@ -181,8 +341,8 @@ impl Gui {
             if let (Some(d1), Some(d2)) =
                 (self.connect_d1, self.ethernet_dropdown.map(|d| d.device))
             {
-                let pos1 = dr.get(DeviceGetQuery::Id(d1.0)).map(|da| da.pos);
-                let pos2 = dr.get(DeviceGetQuery::Id(d2)).map(|da| da.pos);
+                let pos1 = render::get(dr, DeviceGetQuery::Id(d1.0)).map(|da| da.pos);
+                let pos2 = render::get(dr, DeviceGetQuery::Id(d2)).map(|da| da.pos);
 
                 if let (Some(pos1), Some(pos2)) = (pos1, pos2) {
                     d.draw_line_ex(pos1, pos2, 2.0, Color::WHITE);
@@ -190,7 +350,7 @@ impl Gui {
             }
             //
             else if let Some((device, _)) = self.connect_d1 {
-                if let Some(pos1) = dr.get(DeviceGetQuery::Id(device)).map(|da| da.pos) {
+                if let Some(pos1) = render::get(dr, DeviceGetQuery::Id(device)).map(|da| da.pos) {
                     d.draw_line_ex(pos1, mouse_pos, 2.0, Color::WHITE)
                 }
             }
@@ -200,12 +360,12 @@ impl Gui {
         // Edit Dropdown Menu
         // -----------------------------------
         if let Some(mut dropdown) = self.edit_dropdown {
-            if let Some(da) = dr.get(DeviceGetQuery::Id(dropdown.device)) {
+            if let Some(da) = render::get(dr, DeviceGetQuery::Id(dropdown.device)) {
                 let pos = da.pos;
                 let options = if self.tracer_enabled {
-                    "Terminal;Disconnect"
+                    "Terminal;Events;Disconnect"
                 } else {
-                    "Terminal;Disconnect;Delete"
+                    "Terminal;Events;Disconnect;Delete"
                 };
                 let height = options.split(';').count() as i32 * 3 * FONT_SIZE;
                 let bounds = Rectangle::new(
@@ -234,12 +394,18 @@ impl Gui {
                         self.reset_states();
                     }
                     1 => {
+                        self.log_open = true;
+                        self.log_filter.device = Some(dropdown.device);
+                        self.log_scroll = 0;
+                        self.reset_states();
+                    }
+                    2 => {
                         self.reset_states();
                         self.ethernet_dropdown = Some(Dropdown::new(dropdown.device));
                         self.mode = Some(GuiMode::EthernetDisconnect);
                         self.gui_consume_this_click = false;
                     }
-                    2 => {
+                    3 => {
                         dr.set(dropdown.device, DeviceSetQuery::Delete);
                         self.reset_states();
                     }
@@ -258,8 +424,8 @@ impl Gui {
         if !self.gui_consume_this_click {
             // checkmate rust
         } else if let Some(mut dropdown) = self.ethernet_dropdown.take() {
-            if let Some(da) = dr.get(DeviceGetQuery::Id(dropdown.device)) {
-                let DeviceAttributes { ports_len, pos, .. } = da;
+            if let Some(da) = render::get(dr, DeviceGetQuery::Id(dropdown.device)) {
+                let DisplayedDevice { ports_len, pos, .. } = da;
 
                 let height = std::cmp::min(DROPDOWN_MAX_HEIGHT, ports_len as i32 * (3 * FONT_SIZE));
                 let bounds = Rectangle::new(
@@ -271,7 +437,10 @@ impl Gui {
                 self.gui_bounds.push(bounds);
 
                 let label = match dropdown.device {
-                    DeviceId::Desktop(_) | DeviceId::Switch(_) => "Ethernet Port",
+                    DeviceId::Desktop(_)
+                    | DeviceId::Switch(_)
+                    | DeviceId::MultilayerSwitch(_)
+                    | DeviceId::Hub(_) => "Ethernet Port",
                     DeviceId::Router(_) => "GigabitEthernet",
                 };
                 let options = (0..ports_len)
@@ -347,7 +516,7 @@ impl Gui {
 
         // Left menu
         // -----------------------------------
-        const LEFT_MENU: [(GuiButtonClickKind, GuiIconName, &str); 4] = [
+        const LEFT_MENU: [(GuiButtonClickKind, GuiIconName, &str); 6] = [
             (
                 GuiButtonClickKind::Desktop,
                 GuiIconName::ICON_MONITOR,
@@ -363,6 +532,16 @@ impl Gui {
                 GuiIconName::ICON_SHUFFLE_FILL,
                 "Place Router (R)",
             ),
+            (
+                GuiButtonClickKind::MultilayerSwitch,
+                GuiIconName::ICON_LAYERS,
+                "Place Multilayer Switch (L)",
+            ),
+            (
+                GuiButtonClickKind::Hub,
+                GuiIconName::ICON_LINK_MULTI,
+                "Place Hub (H)",
+            ),
             (
                 GuiButtonClickKind::Ethernet,
                 GuiIconName::ICON_LINK_NET,
@@ -417,12 +596,17 @@ impl Gui {
 
         // Player controls
         // -----------------------------------
-        let right_corner_menu: [(GuiButtonClickKind, GuiIconName, &str); 2] = [
+        let right_corner_menu: [(GuiButtonClickKind, GuiIconName, &str); 9] = [
             (
                 GuiButtonClickKind::PlayerNext,
                 GuiIconName::ICON_PLAYER_NEXT,
                 "Next (N)",
             ),
+            (
+                GuiButtonClickKind::PlayerPrevious,
+                GuiIconName::ICON_PLAYER_PREVIOUS,
+                "Back (B)",
+            ),
             if self.tracer_enabled {
                 (
                     GuiButtonClickKind::PlayerPause,
@@ -436,6 +620,44 @@ impl Gui {
                     "Start (Space)",
                 )
             },
+            if dr.is_capturing() {
+                (
+                    GuiButtonClickKind::CaptureStop,
+                    GuiIconName::ICON_PLAYER_STOP,
+                    "Stop Capture (C)",
+                )
+            } else {
+                (
+                    GuiButtonClickKind::CaptureStart,
+                    GuiIconName::ICON_PLAYER_RECORD,
+                    "Capture (C)",
+                )
+            },
+            (
+                GuiButtonClickKind::Save,
+                GuiIconName::ICON_FILE_SAVE,
+                "Save (Ctrl+S)",
+            ),
+            (
+                GuiButtonClickKind::Open,
+                GuiIconName::ICON_FILE_OPEN,
+                "Open (Ctrl+O)",
+            ),
+            (
+                GuiButtonClickKind::EventLog,
+                GuiIconName::ICON_FILETYPE_TEXT,
+                "Event Log (G)",
+            ),
+            (
+                GuiButtonClickKind::Snapshot,
+                GuiIconName::ICON_PHOTO_CAMERA,
+                "Snapshot (K)",
+            ),
+            (
+                GuiButtonClickKind::Rollback,
+                GuiIconName::ICON_UNDO,
+                "Roll Back (Z)",
+            ),
         ];
 
         for (i, (kind, icon, label)) in right_corner_menu.iter().enumerate() {
@@ -483,7 +705,226 @@ impl Gui {
             );
             self.tracer_blink = self.tracer_blink.wrapping_sub(5);
         }
+        // -----------------------------------
 
+        // Snapshots to roll back to, under the rollback button
+        // -----------------------------------
+        if let Some((mut scroll_index, mut value)) = self.rollback_dropdown {
+            let i = right_corner_menu.len() as i32 - 1;
+            let x = (screen_width - PADDING) - (PADDING + box_width) * i - box_width;
+            let height = std::cmp::min(
+                DROPDOWN_MAX_HEIGHT,
+                self.snapshots.len() as i32 * (3 * FONT_SIZE),
+            );
+            let bounds = Rectangle::new(
+                x as f32,
+                (PADDING + box_height + PADDING) as f32,
+                DROPDOWN_WIDTH as f32,
+                height as f32,
+            );
+            self.gui_bounds.push(bounds);
+
+            let options = self
+                .snapshots
+                .iter()
+                .enumerate()
+                .map(|(i, snapshot)| {
+                    format!("#{} {}", i + 1, format_elapsed(snapshot.clock().elapsed()))
+                })
+                .collect::<Vec<String>>();
+
+            d.gui_list_view(
+                bounds,
+                Some(utils::rstr_from_string(options.join(";")).as_c_str()),
+                &mut scroll_index,
+                &mut value,
+            );
+
+            if value >= 0 {
+                let snapshot = self.snapshots[value as usize].snapshot();
+                self.restore(dr, snapshot);
+                self.history.clear();
+                self.terminal_out
+                    .push_back(format!("Rolled back to snapshot #{}", value + 1));
+                self.rollback_dropdown = None;
+            } else {
+                self.rollback_dropdown = Some((scroll_index, value));
+            }
+        }
+        // -----------------------------------
+
+        // Simulated time and speed, left of the player controls
+        // -----------------------------------
+        let panel_width = 4 * box_width;
+        let panel_x = (screen_width - PADDING)
+            - (PADDING + box_width) * right_corner_menu.len() as i32
+            - panel_width;
+
+        d.draw_text(
+            &format_elapsed(dr.clock().elapsed()),
+            panel_x,
+            PADDING,
+            2 * FONT_SIZE,
+            Color::WHITE,
+        );
+
+        let speed = if self.tracer_enabled {
+            "Paused".to_string()
+        } else {
+            format!("{}x", self.speed)
+        };
+        d.draw_text(
+            &speed,
+            panel_x + panel_width - d.measure_text(&speed, 2 * FONT_SIZE),
+            PADDING,
+            2 * FONT_SIZE,
+            Color::WHITE,
+        );
+
+        let buttons = [
+            (GuiButtonClickKind::SpeedDown, "-".to_string(), "Slower ([)"),
+            (GuiButtonClickKind::SpeedUp, "+".to_string(), "Faster (])"),
+        ]
+        .into_iter()
+        .chain(SKIPS.iter().map(|secs| {
+            let label = match secs {
+                60.. => format!("+{}m", secs / 60),
+                _ => format!("+{}s", secs),
+            };
+            let hint = match secs {
+                10 => "Skip ahead (F)",
+                _ => "Skip ahead",
+            };
+            (GuiButtonClickKind::SkipAhead(*secs), label, hint)
+        }));
+
+        let button_width = (panel_width - 4 * PADDING / 2) / 5;
+        for (i, (kind, text, hint)) in buttons.enumerate() {
+            let bounds = Rectangle::new(
+                (panel_x + (button_width + PADDING / 2) * i as i32) as f32,
+                (PADDING + 2 * FONT_SIZE + PADDING) as f32,
+                button_width as f32,
+                (box_height - 2 * FONT_SIZE - PADDING) as f32,
+            );
+
+            if bounds.check_collision_point_rec(mouse_pos) {
+                d.draw_text(
+                    hint,
+                    panel_x,
+                    PADDING + box_height + PADDING,
+                    FONT_SIZE,
+                    Color::WHITE,
+                );
+            }
+
+            self.gui_bounds.push(bounds);
+
+            if d.gui_button(bounds, Some(rstr_from_string(text).as_c_str()))
+                && self.gui_consume_this_click
+            {
+                self.selection = Some(kind);
+            }
+        }
+
+        // -----------------------------------
+
+        // Event Log, under the player controls, hidden behind the list of snapshots
+        // -----------------------------------
+        if self.log_open && self.rollback_dropdown.is_none() {
+            let top = PADDING + box_height + 3 * PADDING;
+            let bounds = Rectangle::new(
+                (screen_width - PADDING - LOG_PANEL_WIDTH) as f32,
+                top as f32,
+                LOG_PANEL_WIDTH as f32,
+                (3.0 / 4.0) * screen_height as f32 - (top + PADDING) as f32,
+            );
+            self.gui_bounds.push(bounds);
+            d.draw_rectangle_rec(bounds, Color::BLACK);
+            d.draw_rectangle_lines_ex(bounds, 1.0, Color::WHITE);
+
+            let x = bounds.x as i32 + PADDING;
+            let mut y = bounds.y as i32 + PADDING;
+            d.draw_text("Event Log", x, y, 2 * FONT_SIZE, Color::WHITE);
+            y += 2 * FONT_SIZE + PADDING;
+
+            let device = self
+                .log_filter
+                .device
+                .map_or("All devices".to_string(), |id| {
+                    render::get(dr, DeviceGetQuery::Id(id))
+                        .map_or("Deleted device".to_string(), |device| device.label.clone())
+                });
+            let buttons = [
+                (GuiButtonClickKind::LogDevice, device),
+                (
+                    GuiButtonClickKind::LogKind,
+                    self.log_filter.kind.unwrap_or("All events").to_string(),
+                ),
+                (GuiButtonClickKind::LogExport, "Export".to_string()),
+                (GuiButtonClickKind::LogClear, "Clear".to_string()),
+            ];
+
+            let button_width = (LOG_PANEL_WIDTH - 2 * PADDING - 3 * PADDING / 2) / 4;
+            for (i, (kind, text)) in buttons.into_iter().enumerate() {
+                let bounds = Rectangle::new(
+                    (x + (button_width + PADDING / 2) * i as i32) as f32,
+                    y as f32,
+                    button_width as f32,
+                    (2 * FONT_SIZE) as f32,
+                );
+
+                if d.gui_button(bounds, Some(rstr_from_string(text).as_c_str()))
+                    && self.gui_consume_this_click
+                {
+                    self.selection = Some(kind);
+                }
+            }
+            y += 2 * FONT_SIZE + PADDING;
+
+            let entries = dr.log(&self.log_filter);
+            let lines = (((bounds.y + bounds.height) as i32 - y - PADDING)
+                / (FONT_SIZE + PADDING / 2))
+                .max(0) as usize;
+
+            // The wheel scrolls back to older entries
+            if bounds.check_collision_point_rec(mouse_pos) {
+                let wheel = d.get_mouse_wheel_move();
+                if wheel > 0.0 {
+                    self.log_scroll += wheel.ceil() as usize;
+                } else if wheel < 0.0 {
+                    self.log_scroll = self.log_scroll.saturating_sub((-wheel).ceil() as usize);
+                }
+            }
+            self.log_scroll = self.log_scroll.min(entries.len().saturating_sub(lines));
+
+            if entries.is_empty() {
+                d.draw_text("No events", x, y, FONT_SIZE, Color::GRAY);
+            }
+
+            for entry in entries.iter().rev().skip(self.log_scroll).take(lines) {
+                let port = entry
+                    .port
+                    .map_or(String::new(), |port| format!(" {}", port));
+                let line = format!(
+                    "{} {}{}: {}",
+                    format_elapsed(entry.time),
+                    entry.label,
+                    port,
+                    entry.event
+                );
+                let color = match entry.event {
+                    LogEvent::Dropped { .. } => Color::RED,
+                    LogEvent::StpChanged { .. } => Color::YELLOW,
+                    LogEvent::RouteInstalled { .. } => Color::GREEN,
+                    LogEvent::ArpLearned { .. } => Color::SKYBLUE,
+                    LogEvent::MacLearned { .. } | LogEvent::Flooded { .. } => Color::WHITE,
+                };
+
+                let line = fit_text(d, &line, FONT_SIZE, LOG_PANEL_WIDTH - 2 * PADDING);
+                d.draw_text(&line, x, y, FONT_SIZE, color);
+                y += FONT_SIZE + PADDING / 2;
+            }
+        }
         // -----------------------------------
 
         let bottom_panel_bounds = Rectangle::new(
@@ -578,7 +1019,7 @@ impl Gui {
 
         if let Some(Some(da)) = self
             .terminal_device
-            .map(|id| dr.get(DeviceGetQuery::Id(id)))
+            .map(|id| render::get(dr, DeviceGetQuery::Id(id)))
         {
             // edit terminal iff mouse is in the terminal bounds
             if bottom_panel_bounds.check_collision_point_rec(mouse_pos) {
@@ -658,58 +1099,6 @@ impl Gui {
             height: screen_height as f32 - table_y,
         };
 
-        if !self.tracer_enabled || self.tracer_next {
-            // Only sniff packets
-            for (
-                id,
-                ((incoming_device_id, incoming_packets), (outgoing_device_id, outgoing_packets)),
-            ) in dr.sniff()
-            {
-                let time = {
-                    let tp = TimeProvider::instance().lock().unwrap();
-                    tp.now()
-                };
-
-                for packet in incoming_packets {
-                    let incoming_id = if packet.loopback() {
-                        Some(id)
-                    } else {
-                        incoming_device_id
-                    };
-
-                    self.packet_buffer.push_back(Packet {
-                        animating: self.tracer_enabled,
-                        pos: incoming_id
-                            .and_then(|id| dr.get(DeviceGetQuery::Id(id)).map(|device| device.pos))
-                            .unwrap_or(Vector2::new(0.0, 0.0)),
-                        last: incoming_id,
-                        current: id,
-                        kind: packet,
-                        time,
-                    })
-                }
-
-                for packet in outgoing_packets {
-                    if outgoing_device_id.is_some() {
-                        self.packet_buffer.push_back(Packet {
-                            animating: self.tracer_enabled,
-                            pos: dr
-                                .get(DeviceGetQuery::Id(id))
-                                .map_or(Vector2::new(0.0, 0.0), |device| device.pos),
-                            last: None,
-                            current: id,
-                            kind: packet,
-                            time,
-                        });
-                    }
-                }
-            }
-        }
-
-        while self.packet_buffer.len() > 10 {
-            self.packet_buffer.pop_front(); // take only top 10 packets
-        }
-
         let col_width = table_bounds.width / 6.0;
 
         const COLUMN_HEADERS: [&str; 4] = ["Time (ms)", "Last Device", "At Device", "Type"];
@@ -754,19 +1143,13 @@ impl Gui {
                 .to_string();
 
             let last_device = packet.last.map_or("-----".to_string(), |id| {
-                dr.get(DeviceGetQuery::Id(id))
+                render::get(dr, DeviceGetQuery::Id(id))
                     .map_or("Unknown".to_string(), |device| device.label.clone())
             });
-            let at_device = dr
-                .get(DeviceGetQuery::Id(packet.current))
+            let at_device = render::get(dr, DeviceGetQuery::Id(packet.current))
                 .map_or("Unknown".to_string(), |device| device.label.clone());
 
-            let packet_type = match packet.kind {
-                PacketKind::Arp(_) => "ARP",
-                PacketKind::Bpdu(_) => "BPDU",
-                PacketKind::Rip(_) => "RIP",
-                PacketKind::Icmp(_) => "ICMP",
-            };
+            let packet_type = packet.kind.name();
 
             let mut label_clicked = false;
 
@@ -997,6 +1380,28 @@ impl Gui {
         if let Some(packet) = &self.packet_selected {
             let mut y = table_bounds.y as i32 + 4 * FONT_SIZE;
             let x = (table_bounds.x + 4.0 * col_width) as i32 + 10;
+
+            // Where the packet's journey ended, if it has
+            let fate = dr.trace(packet.trace).and_then(|trace| trace.fate());
+            let journey = match fate.map(|hop| (hop.step, hop.label.clone())) {
+                Some((TraceStep::Dropped(reason), label)) => {
+                    let event = LogEvent::Dropped {
+                        reason,
+                        destination: None,
+                    };
+                    format!("{} at {}", event, label)
+                }
+                Some((_, label)) => format!("Delivered to {}", label),
+                None => "In flight".to_string(),
+            };
+            d.draw_text(
+                &format!("Trace {}: {}", packet.trace, journey),
+                x,
+                y,
+                FONT_SIZE,
+                Color::WHITE,
+            );
+            y += FONT_SIZE + PADDING;
             match &packet.kind {
                 PacketKind::Arp(eth) => {
                     display_eth2_info(&mut y, x, eth, d);
@@ -1069,7 +1474,7 @@ impl Gui {
 
                     y += (1.5 * PADDING as f32) as i32;
 
-                    let bpdu_frame = BpduFrame::from_bytes(eth.data.clone()).unwrap();
+                    let bpdu_frame = BpduFrame::from_frame(eth).unwrap();
                     d.draw_text("BPDU", x, y, FONT_SIZE, Color::WHITE);
                     d.draw_line(
                         x,
@@ -1188,6 +1593,78 @@ impl Gui {
                         Color::WHITE,
                     );
                 }
+                PacketKind::Igmp(eth) => {
+                    display_eth2_info(&mut y, x, eth, d);
+
+                    y += (1.5 * PADDING as f32) as i32;
+
+                    let ipv4_frame = Ipv4Frame::from_bytes(eth.data.clone()).unwrap();
+                    display_ipv4_info(&mut y, x, &ipv4_frame, d);
+
+                    // Switch to column 2
+                    y = table_bounds.y as i32 + 4 * FONT_SIZE;
+                    let x = (table_bounds.x + 5.0 * col_width) as i32 + 10;
+                    let igmp_frame = IgmpFrame::from_bytes(ipv4_frame.data.clone()).unwrap();
+
+                    d.draw_text("IGMP", x, y, FONT_SIZE, Color::WHITE);
+                    d.draw_line(
+                        x,
+                        y + FONT_SIZE,
+                        x + d.measure_text("IGMP", FONT_SIZE),
+                        y + FONT_SIZE,
+                        Color::WHITE,
+                    );
+
+                    y += FONT_SIZE + PADDING / 2;
+
+                    d.draw_text(
+                        &format!("Type: 0x{:X}", igmp_frame.igmp_type),
+                        x,
+                        y,
+                        FONT_SIZE,
+                        Color::WHITE,
+                    );
+
+                    y += FONT_SIZE;
+
+                    d.draw_text(
+                        &format!("Group: {}", ipv4_fmt!(igmp_frame.group)),
+                        x,
+                        y,
+                        FONT_SIZE,
+                        Color::WHITE,
+                    );
+                }
+                PacketKind::Other(EthernetFrame::Ethernet2(eth)) => {
+                    display_eth2_info(&mut y, x, eth, d);
+                }
+                PacketKind::Other(EthernetFrame::Ethernet802_3(eth)) => {
+                    display_eth802_3_info(&mut y, x, eth, d);
+                }
+                PacketKind::Corrupt(bytes) => {
+                    d.draw_text("FCS Error", x, y, FONT_SIZE, Color::RED);
+                    d.draw_line(
+                        x,
+                        y + FONT_SIZE,
+                        x + d.measure_text("FCS Error", FONT_SIZE),
+                        y + FONT_SIZE,
+                        Color::RED,
+                    );
+
+                    y += FONT_SIZE + PADDING / 2;
+
+                    d.draw_text(
+                        &format!("Length: {} bytes", bytes.len()),
+                        x,
+                        y,
+                        FONT_SIZE,
+                        Color::WHITE,
+                    );
+
+                    y += FONT_SIZE;
+
+                    d.draw_text("Rejected by the receiver", x, y, FONT_SIZE, Color::WHITE);
+                }
             }
         } else {
             let message = "Click a table row\n to view details";
@@ -1214,13 +1691,27 @@ impl Gui {
         let is_left_mouse_down = rl.is_mouse_button_down(MouseButton::MOUSE_BUTTON_LEFT);
         let is_right_mouse_clicked = rl.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT);
 
-        if !self.terminal_edit_mode {
+        let is_ctrl_down = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        if is_ctrl_down {
+            if rl.is_key_pressed(KeyboardKey::KEY_S) {
+                self.selection = Some(GuiButtonClickKind::Save);
+            } else if rl.is_key_pressed(KeyboardKey::KEY_O) {
+                self.selection = Some(GuiButtonClickKind::Open);
+            }
+        } else if !self.terminal_edit_mode {
             if let Some((_, kind)) = [
                 (KeyboardKey::KEY_D, GuiButtonClickKind::Desktop),
                 (KeyboardKey::KEY_S, GuiButtonClickKind::Switch),
                 (KeyboardKey::KEY_R, GuiButtonClickKind::Router),
+                (KeyboardKey::KEY_L, GuiButtonClickKind::MultilayerSwitch),
+                (KeyboardKey::KEY_H, GuiButtonClickKind::Hub),
                 (KeyboardKey::KEY_E, GuiButtonClickKind::Ethernet),
                 (KeyboardKey::KEY_N, GuiButtonClickKind::PlayerNext),
+                (KeyboardKey::KEY_B, GuiButtonClickKind::PlayerPrevious),
+                (KeyboardKey::KEY_K, GuiButtonClickKind::Snapshot),
+                (KeyboardKey::KEY_Z, GuiButtonClickKind::Rollback),
+                (KeyboardKey::KEY_G, GuiButtonClickKind::EventLog),
             ]
             .iter()
             .find(|(key, _)| rl.is_key_pressed(*key))
@@ -1232,6 +1723,43 @@ impl Gui {
                 } else {
                     GuiButtonClickKind::PlayerPlay
                 });
+            } else if rl.is_key_pressed(KeyboardKey::KEY_C) {
+                self.selection = Some(if dr.is_capturing() {
+                    GuiButtonClickKind::CaptureStop
+                } else {
+                    GuiButtonClickKind::CaptureStart
+                });
+            } else if rl.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+                self.selection = Some(GuiButtonClickKind::SpeedDown);
+            } else if rl.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+                self.selection = Some(GuiButtonClickKind::SpeedUp);
+            } else if rl.is_key_pressed(KeyboardKey::KEY_F) {
+                self.selection = Some(GuiButtonClickKind::SkipAhead(10));
+            }
+        }
+        // -----------------------------------
+
+        // Opened Topology
+        // -----------------------------------
+        if let Some(topology) = self.storage.poll() {
+            match topology.and_then(|topology| DeviceRepository::load(&topology)) {
+                Ok(mut topology) => {
+                    // Devices of the previous topology are gone, its clock stays frozen while tracing
+                    topology.set_clock(&dr.clock().clone());
+                    *dr = topology;
+                    self.reset_states();
+                    self.terminal_device = None;
+                    self.terminal_edit_mode = false;
+                    self.packet_buffer.clear();
+                    self.packet_selected = None;
+                    self.history.clear();
+                    self.snapshots.clear();
+                    self.log_filter = LogFilter::default();
+                    self.log_scroll = 0;
+                }
+                Err(e) => self
+                    .terminal_out
+                    .push_back(format!("Error: Could not open the topology: {}", e)),
             }
         }
         // -----------------------------------
@@ -1244,7 +1772,7 @@ impl Gui {
                     continue;
                 }
 
-                if let Some(da) = dr.get(DeviceGetQuery::Id(packet.current)) {
+                if let Some(da) = render::get(dr, DeviceGetQuery::Id(packet.current)) {
                     let pos = da.pos;
                     if packet.pos.distance_to(pos) < 1.0 {
                         continue;
@@ -1261,8 +1789,7 @@ impl Gui {
         // Edit Dropdown
         // -----------------------------------
         if is_right_mouse_clicked {
-            self.edit_dropdown = dr
-                .get(DeviceGetQuery::Pos(mouse_pos))
+            self.edit_dropdown = render::get(dr, DeviceGetQuery::Pos(mouse_pos))
                 .map(|da| Dropdown::new(da.id))
                 .or(None);
             return;
@@ -1278,7 +1805,7 @@ impl Gui {
 
         if is_left_mouse_down && self.mode == Some(GuiMode::Drag) {
             if let Some(device) = self.drag_device {
-                dr.set(device, DeviceSetQuery::Pos(mouse_pos));
+                dr.set(device, DeviceSetQuery::Pos(mouse_pos.into()));
             } else {
                 self.reset_states();
             }
@@ -1290,7 +1817,7 @@ impl Gui {
             && self.drag_device.is_none()
             && self.selection.is_none()
         {
-            if let Some(da) = dr.get(DeviceGetQuery::Pos(mouse_pos)) {
+            if let Some(da) = render::get(dr, DeviceGetQuery::Pos(mouse_pos)) {
                 self.mode = Some(GuiMode::Drag);
                 self.drag_device = Some(da.id);
                 return;
@@ -1317,7 +1844,7 @@ impl Gui {
                 return;
             }
 
-            if self.edit_dropdown.is_some() {
+            if self.edit_dropdown.is_some() || self.rollback_dropdown.is_some() {
                 self.reset_states();
                 return;
             }
@@ -1331,7 +1858,7 @@ impl Gui {
             if !is_left_mouse_clicked {
                 return;
             }
-            if let Some(da) = dr.get(DeviceGetQuery::Pos(mouse_pos)) {
+            if let Some(da) = render::get(dr, DeviceGetQuery::Pos(mouse_pos)) {
                 self.ethernet_dropdown = Some(Dropdown::new(da.id));
             } else {
                 self.reset_states();
@@ -1345,20 +1872,26 @@ impl Gui {
         if let Some(selection) = self.selection {
             match selection {
                 GuiButtonClickKind::Desktop if is_left_mouse_clicked => {
-                    dr.add(DeviceKind::Desktop, mouse_pos);
+                    dr.add(DeviceKind::Desktop, mouse_pos.into());
                     self.reset_states();
                 }
                 GuiButtonClickKind::Switch if is_left_mouse_clicked => {
-                    dr.add(DeviceKind::Switch, mouse_pos);
+                    dr.add(DeviceKind::Switch, mouse_pos.into());
                     self.reset_states();
                 }
                 GuiButtonClickKind::Router if is_left_mouse_clicked => {
-                    dr.add(DeviceKind::Router, mouse_pos);
+                    dr.add(DeviceKind::Router, mouse_pos.into());
+                    self.reset_states();
+                }
+                GuiButtonClickKind::MultilayerSwitch if is_left_mouse_clicked => {
+                    dr.add(DeviceKind::MultilayerSwitch, mouse_pos.into());
+                    self.reset_states();
+                }
+                GuiButtonClickKind::Hub if is_left_mouse_clicked => {
+                    dr.add(DeviceKind::Hub, mouse_pos.into());
                     self.reset_states();
                 }
                 GuiButtonClickKind::PlayerPlay => {
-                    let mut tp = TimeProvider::instance().lock().unwrap();
-                    tp.freeze();
                     self.tracer_enabled = true;
                     self.reset_states();
                     for packet in self.packet_buffer.iter_mut() {
@@ -1366,20 +1899,148 @@ impl Gui {
                     }
                 }
                 GuiButtonClickKind::PlayerPause => {
-                    let mut tp = TimeProvider::instance().lock().unwrap();
-                    tp.unfreeze();
                     self.tracer_enabled = false;
+                    self.history.clear();
+                    self.reset_states();
+                    for packet in self.packet_buffer.iter_mut() {
+                        packet.animating = false;
+                    }
+                }
+                GuiButtonClickKind::CaptureStart => {
+                    dr.start_capture();
+                    self.reset_states();
+                }
+                GuiButtonClickKind::CaptureStop => {
+                    let secs = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let path = format!("capture-{}.pcapng", secs);
+                    self.terminal_out.push_back(match dr.stop_capture() {
+                        Some(capture) => match capture.write(&path) {
+                            Ok(()) => {
+                                format!("{} frames written to {}", capture.frames().len(), path)
+                            }
+                            Err(e) => format!("Error: Could not write {}: {}", path, e),
+                        },
+                        None => "Error: No capture is running".to_string(),
+                    });
+                    self.reset_states();
+                }
+                GuiButtonClickKind::Save => {
+                    self.terminal_out
+                        .push_back(match self.storage.save(&dr.save()) {
+                            Ok(path) => format!("Topology saved to {}", path),
+                            Err(e) => format!("Error: Could not save the topology: {}", e),
+                        });
+                    self.reset_states();
+                }
+                GuiButtonClickKind::Open => {
+                    self.storage.open();
+                    self.reset_states();
+                }
+                GuiButtonClickKind::SpeedDown => {
+                    self.speed = SPEEDS
+                        .iter()
+                        .rev()
+                        .find(|speed| **speed < self.speed)
+                        .map_or(self.speed, |speed| *speed);
+                    self.reset_states();
+                }
+                GuiButtonClickKind::SpeedUp => {
+                    self.speed = SPEEDS
+                        .iter()
+                        .find(|speed| **speed > self.speed)
+                        .map_or(self.speed, |speed| *speed);
+                    self.reset_states();
+                }
+                GuiButtonClickKind::PlayerPrevious => {
+                    if !self.tracer_enabled {
+                        return;
+                    }
+                    if let Some(previous) = self.history.pop_back() {
+                        self.restore(dr, previous);
+                    }
                     self.reset_states();
                     for packet in self.packet_buffer.iter_mut() {
                         packet.animating = false;
                     }
                 }
+                GuiButtonClickKind::Snapshot => {
+                    self.snapshots.push(dr.snapshot());
+                    self.terminal_out.push_back(format!(
+                        "Snapshot #{} taken at {}",
+                        self.snapshots.len(),
+                        format_elapsed(dr.clock().elapsed())
+                    ));
+                    self.reset_states();
+                }
+                GuiButtonClickKind::Rollback => {
+                    self.reset_states();
+                    if self.snapshots.is_empty() {
+                        self.terminal_out
+                            .push_back("Error: No snapshot to roll back to".to_string());
+                    } else {
+                        self.rollback_dropdown = Some((0, -1));
+                    }
+                }
+                GuiButtonClickKind::EventLog => {
+                    self.log_open = !self.log_open;
+                    self.reset_states();
+                }
+                GuiButtonClickKind::LogDevice => {
+                    // All devices, then each device in turn
+                    let devices = dr.devices();
+                    let next = match self.log_filter.device {
+                        None => devices.first(),
+                        Some(id) => devices.iter().skip_while(|device| device.id != id).nth(1),
+                    };
+                    self.log_filter.device = next.map(|device| device.id);
+                    self.log_scroll = 0;
+                    self.reset_states();
+                }
+                GuiButtonClickKind::LogKind => {
+                    // All kinds, then each kind in turn
+                    let next = match self.log_filter.kind {
+                        None => LogEvent::KINDS.first(),
+                        Some(kind) => LogEvent::KINDS.iter().skip_while(|k| **k != kind).nth(1),
+                    };
+                    self.log_filter.kind = next.copied();
+                    self.log_scroll = 0;
+                    self.reset_states();
+                }
+                GuiButtonClickKind::LogExport => {
+                    let secs = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let path = format!("events-{}.jsonl", secs);
+                    let events = dr.log(&self.log_filter).len();
+                    self.terminal_out.push_back(
+                        match std::fs::write(&path, dr.export_log(&self.log_filter)) {
+                            Ok(()) => format!("{} events written to {}", events, path),
+                            Err(e) => format!("Error: Could not write {}: {}", path, e),
+                        },
+                    );
+                    self.reset_states();
+                }
+                GuiButtonClickKind::LogClear => {
+                    dr.clear_log();
+                    self.log_scroll = 0;
+                    self.reset_states();
+                }
+                GuiButtonClickKind::SkipAhead(secs) => {
+                    self.skip_ahead = Some(Duration::from_secs(secs));
+                    self.reset_states();
+                }
                 GuiButtonClickKind::PlayerNext => {
                     if !self.tracer_enabled {
                         return;
                     }
-                    let mut tp = TimeProvider::instance().lock().unwrap();
-                    tp.advance(Duration::from_millis(1));
+                    if self.history.len() == HISTORY_LIMIT {
+                        self.history.pop_front();
+                    }
+                    self.history.push_back(dr.snapshot());
                     self.tracer_next = true;
                     self.reset_states();
                     for packet in self.packet_buffer.iter_mut() {
@@ -1392,3 +2053,28 @@ impl Gui {
         // -----------------------------------
     }
 }
+
+/// Formats simulated time as hours, minutes, seconds and milliseconds.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// Shortens text with an ellipsis to fit in a width.
+fn fit_text(d: &RaylibDrawHandle, text: &str, font_size: i32, width: i32) -> String {
+    if d.measure_text(text, font_size) <= width {
+        return text.to_string();
+    }
+
+    let mut text = text.to_string();
+    while !text.is_empty() && d.measure_text(&format!("{}...", text), font_size) > width {
+        text.pop();
+    }
+    format!("{}...", text)
+}
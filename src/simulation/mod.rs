@@ -1,8 +1,15 @@
-mod device;
 mod gui;
+mod render;
+mod storage;
 
 use gui::Gui;
 use raylib::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    engine::device::DeviceRepository,
+    tick::{Clock, ClockMode},
+};
 
 mod utils;
 
@@ -16,19 +23,31 @@ pub fn run() {
     rl.set_target_fps(30);
 
     let mut gui = Gui::default();
-    let mut dr = device::DeviceRepository::default();
+    let mut dr = DeviceRepository::default();
+    dr.set_clock(&Clock::new(ClockMode::Frozen)); // Moved by the frames, event to event
 
     while !rl.window_should_close() {
+        let mut until = dr.clock().now();
         if !gui.tracer_enabled {
-            dr.update();
-        } else if gui.tracer_next {
-            dr.update();
+            until += Duration::from_secs_f32(rl.get_frame_time()).mul_f64(gui.speed);
+        }
+        if let Some(skip) = gui.skip_ahead.take() {
+            until += skip;
+        }
+        while dr.step_until(until).is_some() {
+            gui.sniff(&dr);
+        }
+        dr.clock().advance_to(until);
+
+        if gui.tracer_enabled && gui.tracer_next {
+            dr.step();
+            gui.sniff(&dr);
             gui.tracer_next = false;
         }
         gui.update(&rl, &mut dr);
 
         let mut d = rl.begin_drawing(&thread);
-        dr.render(&mut d);
+        render::render(&mut d, &dr);
         gui.render(&mut d, &mut dr);
         d.clear_background(Color::BLACK);
     }
@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+
+use raylib::prelude::*;
+
+use crate::engine::device::{DeviceAttributes, DeviceId, DeviceRepository, Position};
+
+use super::utils;
+
+const ROUTER_DISPLAY_RADIUS: f32 = 25.0;
+const SWITCH_DISPLAY_LENGTH: i32 = 45;
+const DESKTOP_DISPLAY_SIZE: i32 = SWITCH_DISPLAY_LENGTH; // roughly
+
+pub enum DeviceGetQuery {
+    Pos(Vector2),
+    Id(DeviceId),
+}
+
+/// A device as displayed, positioned at the center of its icon.
+#[derive(Clone)]
+pub struct DisplayedDevice {
+    pub id: DeviceId,
+    pub label: String,
+    pub pos: Vector2,
+    pub ports_len: usize,
+}
+
+impl From<Position> for Vector2 {
+    fn from(pos: Position) -> Self {
+        Vector2::new(pos.x, pos.y)
+    }
+}
+
+impl From<Vector2> for Position {
+    fn from(pos: Vector2) -> Self {
+        Position { x: pos.x, y: pos.y }
+    }
+}
+
+impl From<&DeviceAttributes> for DisplayedDevice {
+    fn from(attributes: &DeviceAttributes) -> Self {
+        let offset = match attributes.id {
+            DeviceId::Router(_) => 0.0, // Drawn around its position
+            DeviceId::Desktop(_) => (DESKTOP_DISPLAY_SIZE / 2) as f32,
+            _ => (SWITCH_DISPLAY_LENGTH / 2) as f32,
+        };
+
+        Self {
+            id: attributes.id,
+            label: attributes.label.clone(),
+            pos: Vector2::new(attributes.pos.x + offset, attributes.pos.y + offset),
+            ports_len: attributes.ports_len,
+        }
+    }
+}
+
+/// Finds a device by id, or by the position of a point over its icon.
+pub fn get(dr: &DeviceRepository, query: DeviceGetQuery) -> Option<DisplayedDevice> {
+    match query {
+        // Linear search, no point in optimizing this for now.
+        DeviceGetQuery::Pos(pos) => dr
+            .devices()
+            .iter()
+            .find(|attributes| {
+                let (x, y) = (attributes.pos.x, attributes.pos.y);
+                let size = match attributes.id {
+                    DeviceId::Router(_) => {
+                        return Vector2::new(x, y).distance_to(pos) < ROUTER_DISPLAY_RADIUS
+                    }
+                    DeviceId::Desktop(_) => DESKTOP_DISPLAY_SIZE as f32,
+                    _ => SWITCH_DISPLAY_LENGTH as f32,
+                };
+                Rectangle::new(x, y, size, size).check_collision_point_rec(pos)
+            })
+            .map(DisplayedDevice::from),
+        DeviceGetQuery::Id(id) => dr.get(id).as_ref().map(DisplayedDevice::from),
+    }
+}
+
+/// Draws the devices of the repository and the cables between them.
+pub fn render(d: &mut RaylibDrawHandle, dr: &DeviceRepository) {
+    const FONT_SIZE: i32 = 10;
+    const PADDING: i32 = 10;
+
+    // Draw ethernet (adjacencies)
+    let mut set: HashSet<DeviceId> = HashSet::new(); // Only need to draw a line once per device
+    for (id, port, adj_id, adj_port) in dr.links() {
+        let start_pos = get(dr, DeviceGetQuery::Id(id)).unwrap().pos;
+        let end_pos = get(dr, DeviceGetQuery::Id(adj_id)).unwrap().pos;
+
+        let is_shutdown = dr.is_shutdown(id, port);
+        let admin_down = is_shutdown || dr.is_shutdown(adj_id, adj_port);
+
+        if !set.contains(&adj_id) {
+            if admin_down {
+                // Administratively down links are dashed
+                let length = start_pos.distance_to(end_pos);
+                let dir = (end_pos - start_pos).normalized();
+                let mut t = 0.0;
+                while t < length {
+                    let dash_end = (t + 8.0f32).min(length);
+                    d.draw_line_ex(
+                        Vector2::new(start_pos.x + dir.x * t, start_pos.y + dir.y * t),
+                        Vector2::new(
+                            start_pos.x + dir.x * dash_end,
+                            start_pos.y + dir.y * dash_end,
+                        ),
+                        2.5,
+                        Color::GRAY,
+                    );
+                    t += 16.0;
+                }
+            } else {
+                d.draw_line_ex(start_pos, end_pos, 2.5, Color::RAYWHITE);
+            }
+        }
+        set.insert(id);
+
+        let dir_e = (end_pos - start_pos).normalized();
+        d.draw_circle(
+            (start_pos.x + dir_e.x * 35.0) as i32,
+            (start_pos.y + dir_e.y * 35.0) as i32,
+            5.0,
+            if is_shutdown {
+                Color::GRAY
+            } else if dr.is_port_up(id, port) {
+                Color::LIMEGREEN
+            } else {
+                Color::RED
+            },
+        );
+    }
+
+    for attributes in dr.devices() {
+        let (x, y) = (attributes.pos.x, attributes.pos.y);
+        let label = attributes.label.as_str();
+        match attributes.id {
+            DeviceId::Router(_) => {
+                d.draw_circle(
+                    x as i32,
+                    y as i32,
+                    ROUTER_DISPLAY_RADIUS + 2.0,
+                    Color::WHITE,
+                );
+                d.draw_circle(x as i32, y as i32, ROUTER_DISPLAY_RADIUS, Color::BLACK);
+
+                utils::draw_icon(
+                    GuiIconName::ICON_SHUFFLE_FILL,
+                    (x - (ROUTER_DISPLAY_RADIUS / 1.5)) as i32,
+                    (y - (ROUTER_DISPLAY_RADIUS / 1.5)) as i32,
+                    2,
+                    Color::WHITE,
+                );
+
+                d.draw_text(
+                    label,
+                    x as i32 - d.measure_text(label, FONT_SIZE) / 2,
+                    (y + ROUTER_DISPLAY_RADIUS) as i32 + PADDING,
+                    FONT_SIZE,
+                    Color::WHITE,
+                );
+            }
+            DeviceId::Desktop(_) => {
+                d.draw_rectangle(
+                    x as i32,
+                    y as i32,
+                    DESKTOP_DISPLAY_SIZE,
+                    DESKTOP_DISPLAY_SIZE,
+                    Color::BLACK,
+                );
+
+                utils::draw_icon(
+                    GuiIconName::ICON_MONITOR,
+                    x as i32,
+                    y as i32,
+                    3,
+                    Color::WHITE,
+                );
+
+                d.draw_text(
+                    label,
+                    x as i32,
+                    y as i32 + 5 * PADDING,
+                    FONT_SIZE,
+                    Color::WHITE,
+                );
+            }
+            id => {
+                let icon = match id {
+                    DeviceId::Switch(_) => GuiIconName::ICON_CURSOR_SCALE_FILL,
+                    DeviceId::MultilayerSwitch(_) => GuiIconName::ICON_LAYERS,
+                    _ => GuiIconName::ICON_LINK_MULTI,
+                };
+
+                d.draw_rectangle(
+                    x as i32,
+                    y as i32,
+                    SWITCH_DISPLAY_LENGTH,
+                    SWITCH_DISPLAY_LENGTH,
+                    Color::BLACK,
+                );
+                d.draw_rectangle_lines(
+                    x as i32,
+                    y as i32,
+                    SWITCH_DISPLAY_LENGTH,
+                    SWITCH_DISPLAY_LENGTH,
+                    Color::WHITE,
+                );
+
+                utils::draw_icon(
+                    icon,
+                    x as i32 + (SWITCH_DISPLAY_LENGTH / 6),
+                    y as i32 + (SWITCH_DISPLAY_LENGTH / 6),
+                    2,
+                    Color::WHITE,
+                );
+
+                d.draw_text(
+                    label,
+                    x as i32,
+                    y as i32 + SWITCH_DISPLAY_LENGTH + PADDING,
+                    FONT_SIZE,
+                    Color::WHITE,
+                );
+            }
+        }
+    }
+}
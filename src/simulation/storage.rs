@@ -0,0 +1,103 @@
+use std::io;
+
+/// Name of the topology file written by Ctrl+S and read by Ctrl+O.
+pub const TOPOLOGY_FILE: &str = "topology.json";
+
+/// Saves and opens topology files.
+///
+/// On desktop the file is kept in the working directory. In the WASM build it is downloaded through the browser, and
+/// opened with the browser's file picker. As the picker is asynchronous, an opened file is polled for every frame.
+#[derive(Default)]
+pub struct TopologyStorage {
+    opening: bool,
+}
+
+impl TopologyStorage {
+    /// Saves a topology, returning where it was saved to.
+    pub fn save(&self, contents: &str) -> io::Result<String> {
+        std::fs::write(TOPOLOGY_FILE, contents)?;
+
+        #[cfg(target_os = "emscripten")]
+        browser::run_script(&format!(
+            "(function () {{
+                var blob = new Blob([FS.readFile('{file}')], {{ type: 'application/json' }});
+                var a = document.createElement('a');
+                a.href = URL.createObjectURL(blob);
+                a.download = '{file}';
+                a.click();
+                URL.revokeObjectURL(a.href);
+            }})();",
+            file = TOPOLOGY_FILE
+        ));
+
+        Ok(TOPOLOGY_FILE.to_string())
+    }
+
+    /// Starts opening a topology, to be returned by `poll`.
+    pub fn open(&mut self) {
+        #[cfg(target_os = "emscripten")]
+        {
+            let _ = std::fs::remove_file(browser::UPLOAD_FILE);
+            browser::run_script(&format!(
+                "(function () {{
+                    var input = document.createElement('input');
+                    input.type = 'file';
+                    input.accept = '.json,application/json';
+                    input.onchange = function () {{
+                        if (input.files.length === 0) return;
+                        input.files[0].arrayBuffer().then(function (buffer) {{
+                            FS.writeFile('{file}', new Uint8Array(buffer));
+                        }});
+                    }};
+                    input.click();
+                }})();",
+                file = browser::UPLOAD_FILE
+            ));
+        }
+
+        self.opening = true;
+    }
+
+    /// Returns the contents of the topology being opened, once available.
+    pub fn poll(&mut self) -> Option<io::Result<String>> {
+        if !self.opening {
+            return None;
+        }
+
+        #[cfg(target_os = "emscripten")]
+        {
+            if !std::path::Path::new(browser::UPLOAD_FILE).exists() {
+                return None;
+            }
+
+            self.opening = false;
+            let contents = std::fs::read_to_string(browser::UPLOAD_FILE);
+            let _ = std::fs::remove_file(browser::UPLOAD_FILE);
+            Some(contents)
+        }
+
+        #[cfg(not(target_os = "emscripten"))]
+        {
+            self.opening = false;
+            Some(std::fs::read_to_string(TOPOLOGY_FILE))
+        }
+    }
+}
+
+#[cfg(target_os = "emscripten")]
+mod browser {
+    use std::ffi::{c_char, CString};
+
+    /// Where the file picked in the browser is written to in the Emscripten file system.
+    pub const UPLOAD_FILE: &str = "/upload.json";
+
+    extern "C" {
+        fn emscripten_run_script(script: *const c_char);
+    }
+
+    /// Evaluates JavaScript in the page, with access to the Emscripten file system as `FS`.
+    pub fn run_script(script: &str) {
+        let script = CString::new(script).expect("Script contains a nul byte");
+        unsafe { emscripten_run_script(script.as_ptr()) };
+    }
+}
@@ -0,0 +1,473 @@
+#![allow(non_snake_case)]
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{
+    engine::device::{DeviceId, DeviceKind, DeviceRepository, DeviceSetQuery, LogFilter, Position},
+    json::Json,
+    network::{
+        log::{DropReason, LogEvent},
+        trace::TraceStep,
+    },
+    tick::{Clock, ClockMode},
+};
+
+/// Runs a terminal command on a device, returning its output.
+fn execute(dr: &mut DeviceRepository, id: DeviceId, command: &str) -> Vec<String> {
+    dr.set(id, DeviceSetQuery::TerminalInput(command.to_string()));
+    dr.get_terminal_output(id)
+}
+
+/// Two desktops cabled together, with addresses in the same network.
+fn desktops() -> (DeviceRepository, DeviceId, DeviceId) {
+    let mut dr = DeviceRepository::default();
+    let d1 = dr.add(DeviceKind::Desktop, Position { x: 10.0, y: 20.0 });
+    let d2 = dr.add(DeviceKind::Desktop, Position { x: 30.0, y: 40.0 });
+    dr.set(d1, DeviceSetQuery::Connect(d2, 0, 0));
+
+    execute(&mut dr, d1, "ipset 10.0.0.1 255.255.255.0");
+    execute(&mut dr, d2, "ipset 10.0.0.2 255.255.255.0");
+    (dr, d1, d2)
+}
+
+#[test]
+fn DeviceRepository_Headless_PingsBetweenDesktops() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+
+    // Act
+    let sent = execute(&mut dr, d1, "ping 10.0.0.2");
+    dr.run(10);
+    let output = dr.get_terminal_output(d1);
+
+    // Assert
+    assert_eq!(sent, vec!["Pinging 10.0.0.2..."]);
+    assert!(output.iter().any(|line| line.starts_with("Pong!")));
+}
+
+#[test]
+fn DeviceRepository_Find_ReturnsDeviceByLabel() {
+    // Arrange
+    let (dr, d1, d2) = desktops();
+
+    // Act
+    let found = (
+        dr.find("Desktop 1"),
+        dr.find("Desktop 2"),
+        dr.find("Router 1"),
+    );
+
+    // Assert
+    assert_eq!(found, (Some(d1), Some(d2), None));
+    assert_eq!(dr.links().len(), 2); // Once from each end
+    assert_eq!(dr.get(d2).unwrap().pos, Position { x: 30.0, y: 40.0 });
+}
+
+#[test]
+fn DeviceRepository_SaveAndLoad_RebuildsTopology() {
+    // Arrange
+    let (mut dr, d1, d2) = desktops();
+    let r = dr.add(DeviceKind::Router, Position::default());
+    let s = dr.add(DeviceKind::Switch, Position::default());
+    dr.set(r, DeviceSetQuery::Connect(s, 3, 0));
+    execute(&mut dr, r, "enable 3 10.0.0.254 255.255.255.0");
+    execute(&mut dr, d1, "dgateway 10.0.0.254");
+    execute(&mut dr, s, "stp 4096");
+
+    // Act
+    let saved = dr.save();
+    let mut loaded = DeviceRepository::load(&saved).unwrap();
+    let resaved = loaded.save();
+    let next = loaded.add(DeviceKind::Desktop, Position::default());
+
+    // Assert
+    assert_eq!(resaved, saved);
+    assert_eq!(loaded.find("Router 1"), Some(r));
+    assert_eq!(loaded.links().len(), 4);
+    assert!(loaded.links().contains(&(d2, 0, d1, 0)));
+    assert!(loaded.links().contains(&(r, 3, s, 0)));
+    assert_eq!(loaded.get(next).unwrap().label, "Desktop 3");
+    assert_ne!(next, d1);
+    assert!(saved.contains("\"gateway\": \"10.0.0.254\""));
+    assert!(saved.contains("\"priority\": 4096"));
+}
+
+#[test]
+fn DeviceRepository_LoadInvalidTopology_ReturnsError() {
+    // Arrange
+    let documents = [
+        r#"{"version": 99, "devices": [], "links": []}"#,
+        r#"{"version": 1, "devices": [{"id": 1, "kind": "toaster", "label": "T", "pos": [0, 0]}], "links": []}"#,
+        r#"{"version": 1, "devices": [], "links": [{"device": 1, "port": 0, "peer": 2, "peer_port": 0}]}"#,
+        r#"{"version": 1, "devices": []"#,
+        r#"{"version": 1, "devices": [{"id": 18446744073709551615, "kind": "router", "label": "R", "pos": [0, 0], "interfaces": []}], "links": []}"#,
+        r#"{"version": 1, "devices": [{"id": 1, "kind": "switch", "label": "S1", "pos": [0, 0], "priority": 32768}, {"id": 2, "kind": "switch", "label": "S2", "pos": [0, 0], "priority": 32768}], "links": []}"#,
+        r#"{"version": 1, "devices": [{"id": 1, "kind": "hub", "label": "H", "pos": [0, 0]}, {"id": 10, "kind": "hub", "label": "H", "pos": [0, 0]}], "links": [{"device": 1, "port": 0, "peer": 10, "peer_port": 0}, {"device": 1, "port": 0, "peer": 10, "peer_port": 1}]}"#,
+    ];
+
+    // Act
+    let results: Vec<_> = documents
+        .iter()
+        .map(|d| DeviceRepository::load(d))
+        .collect();
+
+    // Assert
+    for (document, result) in documents.iter().zip(results) {
+        assert!(result.is_err(), "{} should not load", document);
+    }
+}
+
+#[test]
+fn DeviceRepository_SeparateClocks_KeepSimulationsApart() {
+    // Arrange
+    let (mut dr1, d1, _) = desktops();
+    let (mut dr2, d2, _) = desktops();
+    dr1.set_clock(&Clock::new(ClockMode::Virtual));
+    dr2.set_clock(&Clock::new(ClockMode::Virtual));
+    execute(&mut dr1, d1, "ping 10.0.0.9");
+    execute(&mut dr2, d2, "ping 10.0.0.9");
+
+    // Act
+    dr1.clock().advance(Duration::from_secs(3));
+    dr1.run(2);
+    dr2.run(2);
+
+    // Assert
+    assert_eq!(dr1.get_terminal_output(d1), vec!["'ping' timed out."]);
+    assert!(dr2.get_terminal_output(d2).is_empty());
+}
+
+#[test]
+fn DeviceRepository_Step_JumpsFromEventToEvent() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(1)); // Settle
+
+    // Act
+    let idle = dr.step();
+    execute(&mut dr, d1, "ping 10.0.0.2");
+    let mut steps = 0;
+    while !dr
+        .get_terminal_output(d1)
+        .iter()
+        .any(|line| line.starts_with("Pong!"))
+    {
+        dr.step().expect("The ping has work scheduled");
+        steps += 1;
+    }
+
+    // Assert
+    assert!(idle.is_none());
+    assert!(steps < 20);
+    assert!(dr.clock().now() < UNIX_EPOCH + Duration::from_millis(1020));
+}
+
+#[test]
+fn DeviceRepository_FastForward_SkipsIdleTime() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    execute(&mut dr, d1, "ping 10.0.0.9");
+
+    // Act
+    let steps = dr.fast_forward(Duration::from_millis(500), 10.0);
+    let output = dr.get_terminal_output(d1);
+
+    // Assert
+    assert_eq!(dr.clock().now(), UNIX_EPOCH + Duration::from_secs(5));
+    assert_eq!(output, vec!["'ping' timed out."]);
+    assert!(steps < 20); // ARP retries and the ping timeout, not every millisecond
+}
+
+#[test]
+fn DeviceRepository_Snapshot_RunsApartFromOriginal() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    let mut snapshot = dr.snapshot();
+
+    // Act
+    execute(&mut dr, d1, "ping 10.0.0.2");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(1));
+    snapshot.run_until(UNIX_EPOCH + Duration::from_secs(1));
+
+    // Assert
+    assert!(dr
+        .get_terminal_output(d1)
+        .iter()
+        .any(|line| line.starts_with("Pong!")));
+    assert!(snapshot.get_terminal_output(d1).is_empty());
+    assert_eq!(dr.clock().now(), snapshot.clock().now());
+}
+
+#[test]
+fn DeviceRepository_LinkBandwidthOverflow_ReturnsError() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+
+    // Act
+    let output = execute(&mut dr, d1, "link 20 18446744073709552 off"); // Over u64::MAX bits per second
+
+    // Assert
+    assert_eq!(
+        output,
+        vec!["Error: '18446744073709552' is not a valid bandwidth"]
+    );
+}
+
+#[test]
+fn DeviceRepository_RollBack_RepeatsTheSameRun() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    execute(&mut dr, d1, "link 20 1000 off");
+    execute(&mut dr, d1, "impair 30 0 0 0");
+    execute(&mut dr, d1, "ping 10.0.0.2");
+    for _ in 0..3 {
+        dr.step(); // Frames on the cable
+    }
+    let snapshot = dr.snapshot();
+
+    // Act
+    let mut runs = vec![];
+    for mut run in [dr, snapshot.snapshot(), snapshot] {
+        let mut output = vec![];
+        for time in [4, 8, 12, 16] {
+            run.run_until(UNIX_EPOCH + Duration::from_secs(time)); // Past the ping timeout
+            output.extend(run.get_terminal_output(d1));
+            output.extend(execute(&mut run, d1, "ping 10.0.0.2"));
+        }
+        runs.push((output, run.clock().now()));
+    }
+
+    // Assert
+    let pongs = runs[0].0.iter().filter(|line| line.starts_with("Pong!"));
+    let timeouts = runs[0].0.iter().filter(|line| line.ends_with("timed out."));
+    assert!(pongs.count() > 0 && timeouts.count() > 0); // Some pings were lost
+    assert_eq!(runs[0], runs[1]);
+    assert_eq!(runs[1], runs[2]);
+}
+
+#[test]
+fn DeviceRepository_CaptureWithSnapshot_StopReturnsTheFrames() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    dr.start_capture();
+    let _snapshot = dr.snapshot();
+
+    // Act
+    execute(&mut dr, d1, "ping 10.0.0.2");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(1));
+    let capture = dr.stop_capture();
+
+    // Assert
+    let capture = capture.expect("the capture is shared with the snapshot");
+    assert_eq!(capture.interfaces().len(), 2);
+    assert!(!capture.frames().is_empty());
+    assert!(!dr.is_capturing());
+}
+
+#[test]
+fn DeviceRepository_Snapshot_CopiesEveryPort() {
+    // Arrange
+    let mut dr = DeviceRepository::default();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    let d1 = dr.add(DeviceKind::Desktop, Position::default());
+    let d2 = dr.add(DeviceKind::Desktop, Position::default());
+    let hub = dr.add(DeviceKind::Hub, Position::default());
+    let mls = dr.add(DeviceKind::MultilayerSwitch, Position::default());
+    dr.set(d1, DeviceSetQuery::Connect(hub, 0, 0));
+    dr.set(hub, DeviceSetQuery::Connect(mls, 1, 0));
+    dr.set(mls, DeviceSetQuery::Connect(d2, 1, 0));
+    execute(&mut dr, d1, "ipset 10.0.0.1 255.255.255.0");
+    execute(&mut dr, d2, "ipset 10.0.0.2 255.255.255.0");
+    let mut snapshot = dr.snapshot();
+
+    // Act
+    execute(&mut snapshot, d1, "ping 10.0.0.2");
+    snapshot.run_until(UNIX_EPOCH + Duration::from_secs(2));
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(2));
+
+    // Assert
+    assert!(snapshot
+        .get_terminal_output(d1)
+        .iter()
+        .any(|line| line.starts_with("Pong!")));
+    assert_eq!(
+        execute(&mut dr, d1, "ping 10.0.0.2"),
+        vec!["Pinging 10.0.0.2..."]
+    ); // The original never saw the first ping
+    assert!(dr.get_terminal_output(d1).is_empty());
+}
+
+#[test]
+fn DeviceRepository_Log_StampsDecisionsWithTimeAndDevice() {
+    // Arrange
+    let (mut dr, d1, d2) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(1));
+
+    // Act
+    execute(&mut dr, d1, "ping 10.0.0.2");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(2));
+    let filter = LogFilter {
+        device: Some(d1),
+        kind: Some("arp_learned"),
+    };
+    let entries = dr.log(&filter);
+
+    // Assert
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].device, d1);
+    assert_eq!(entries[0].label, "Desktop 1");
+    assert_eq!(entries[0].port, Some(0));
+    assert!(matches!(
+        entries[0].event,
+        LogEvent::ArpLearned {
+            ip: [10, 0, 0, 2],
+            ..
+        }
+    ));
+    assert!(entries[0].time > Duration::from_secs(1));
+    assert!(entries[0].time < Duration::from_millis(1100));
+    assert!(dr
+        .log(&LogFilter::default())
+        .iter()
+        .any(|entry| entry.device == d2));
+}
+
+#[test]
+fn DeviceRepository_ExportLog_WritesJsonLines() {
+    // Arrange
+    let (mut dr, d1, _) = desktops();
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    execute(&mut dr, d1, "ping 10.0.0.9");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(5));
+    let filter = LogFilter {
+        device: Some(d1),
+        kind: Some("dropped"),
+    };
+
+    // Act
+    let export = dr.export_log(&filter);
+    let lines = export
+        .lines()
+        .map(|line| Json::parse(line).unwrap())
+        .collect::<Vec<Json>>();
+
+    // Assert
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert_eq!(line.get("label").and_then(Json::as_str), Some("Desktop 1"));
+    assert_eq!(line.get("port").and_then(Json::as_u64), Some(0));
+    assert_eq!(line.get("event").and_then(Json::as_str), Some("dropped"));
+    assert_eq!(
+        line.get("drop_reason").and_then(Json::as_str),
+        Some("arp_unresolved")
+    );
+    assert_eq!(
+        line.get("destination").and_then(Json::as_str),
+        Some("10.0.0.9")
+    );
+    assert!(line.get("time").and_then(Json::as_f64).unwrap() >= 4.0);
+}
+
+/// A desktop on a switch, routed to a desktop in another network.
+fn routed() -> (DeviceRepository, [DeviceId; 4]) {
+    let mut dr = DeviceRepository::default();
+    let d1 = dr.add(DeviceKind::Desktop, Position::default());
+    let s = dr.add(DeviceKind::Switch, Position::default());
+    let r = dr.add(DeviceKind::Router, Position::default());
+    let d2 = dr.add(DeviceKind::Desktop, Position::default());
+    dr.set(d1, DeviceSetQuery::Connect(s, 0, 0));
+    dr.set(s, DeviceSetQuery::Connect(r, 1, 0));
+    dr.set(r, DeviceSetQuery::Connect(d2, 1, 0));
+
+    execute(&mut dr, r, "enable 0 10.0.0.254 255.255.255.0");
+    execute(&mut dr, r, "enable 1 10.0.1.254 255.255.255.0");
+    execute(&mut dr, d1, "ipset 10.0.0.1 255.255.255.0");
+    execute(&mut dr, d1, "dgateway 10.0.0.254");
+    execute(&mut dr, d2, "ipset 10.0.1.1 255.255.255.0");
+    execute(&mut dr, d2, "dgateway 10.0.1.254");
+    dr.set_clock(&Clock::new(ClockMode::Virtual));
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(5)); // Let the spanning tree settle
+    (dr, [d1, s, r, d2])
+}
+
+#[test]
+fn DeviceRepository_Trace_FollowsPingAcrossEveryHop() {
+    // Arrange
+    let (mut dr, [d1, s, r, d2]) = routed();
+
+    // Act
+    execute(&mut dr, d1, "ping 10.0.1.1");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(6));
+    let trace = dr
+        .traces()
+        .into_iter()
+        .find(|trace| trace.hops[0].device == d1)
+        .unwrap()
+        .clone();
+
+    // Assert
+    let fate = trace.fate().unwrap();
+    assert_eq!(fate.device, d2);
+    assert_eq!(fate.step, TraceStep::Delivered);
+    assert_eq!(
+        dr.trace_links(&trace),
+        vec![(d1, 0, s, 0), (s, 1, r, 0), (r, 1, d2, 0)]
+    );
+
+    // The router resolved the next hop with ARP on the packet's behalf, and the echo reply came back
+    assert!(trace.hops.iter().any(|hop| hop.device == r
+        && hop.port == Some(1)
+        && hop.step == TraceStep::Sent
+        && hop.frame == Some("ARP")));
+    let last = trace.hops.last().unwrap();
+    assert_eq!((last.device, last.step), (d1, TraceStep::Delivered));
+}
+
+#[test]
+fn DeviceRepository_Trace_EndsWithTheDropReason() {
+    // Arrange
+    let (mut dr, [d1, _, r, _]) = routed();
+
+    // Act
+    execute(&mut dr, d1, "ping 10.0.9.9");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(6));
+    let trace = dr
+        .traces()
+        .into_iter()
+        .find(|trace| trace.hops[0].device == d1)
+        .unwrap();
+
+    // Assert
+    let fate = trace.fate().unwrap();
+    assert_eq!(fate.device, r);
+    assert_eq!(fate.port, Some(0));
+    assert_eq!(fate.step, TraceStep::Dropped(DropReason::NoRoute));
+}
+
+#[test]
+fn DeviceRepository_Trace_KeepsBackgroundFramesApart() {
+    // Arrange
+    let (mut dr, [d1, s, _, _]) = routed();
+
+    // Act
+    execute(&mut dr, d1, "ping 10.0.1.1");
+    dr.run_until(UNIX_EPOCH + Duration::from_secs(6));
+    let traces = dr.traces();
+
+    // Assert
+    let pings = traces
+        .iter()
+        .filter(|trace| trace.hops.iter().any(|hop| hop.frame == Some("ICMP")))
+        .count();
+    assert_eq!(pings, 1);
+    assert!(traces
+        .iter()
+        .filter(|trace| trace.hops.iter().any(|hop| hop.frame == Some("BPDU")))
+        .all(|trace| trace.hops[0].device == s && trace.hops.len() <= 3));
+}
@@ -0,0 +1,135 @@
+#![allow(non_snake_case)]
+
+use std::path::Path;
+
+use crate::engine::scenario::{Report, Scenario};
+
+const PING: &str = "
+# Two desktops through a hub
+add hub Hub 1
+add desktop Desktop 1
+add desktop Desktop 2
+link Desktop 1:0 Hub 1:0
+link Desktop 2:0 Hub 1:1
+
+at 0s Desktop 1: ipset 10.0.0.1 255.255.255.0
+at 0s Desktop 2: ipset 10.0.0.2 255.255.255.0
+at 1s Desktop 1: ping 10.0.0.2
+at 3s expect Desktop 1: Pong!
+at 4s Desktop 1: ping 10.0.0.3
+at 500ms expect Desktop 2: IP address set
+at 8s expect Desktop 1: Pong!
+";
+
+fn run(text: &str) -> (Report, String) {
+    let scenario = Scenario::parse(text, Path::new(".")).unwrap();
+    let mut out = vec![];
+    let report = scenario.run(&mut out).unwrap();
+    (report, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn Scenario_Parse_InvalidLines_ReturnsErrorWithLine() {
+    // Arrange
+    let scenarios = [
+        (
+            "add toaster Toaster",
+            "Line 1: Unknown device kind 'toaster'",
+        ),
+        ("add desktop D\nlink D 0 D 1", "Line 2: Usage: link"),
+        (
+            "\nat soon D: ping 10.0.0.1",
+            "Line 2: 'soon' is not a valid time",
+        ),
+        ("at 1s D ping", "Line 1: Usage: at"),
+        (
+            "# Comment\nping 10.0.0.1",
+            "Line 2: 'ping 10.0.0.1' is not a valid scenario line",
+        ),
+    ];
+
+    // Act
+    let errors: Vec<String> = scenarios
+        .iter()
+        .map(|(text, _)| {
+            Scenario::parse(text, Path::new("."))
+                .err()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+
+    // Assert
+    for ((_, expected), error) in scenarios.iter().zip(errors) {
+        assert!(
+            error.starts_with(expected),
+            "'{}' should start with '{}'",
+            error,
+            expected
+        );
+    }
+}
+
+#[test]
+fn Scenario_Run_UnknownDevice_ReturnsErrorBeforeRunning() {
+    // Arrange
+    let scenario = Scenario::parse(
+        "add desktop Desktop 1\nat 1s Desktop 1: ipconfig\nat 2s Desktop 2: ipconfig",
+        Path::new("."),
+    )
+    .unwrap();
+    let mut out = vec![];
+
+    // Act
+    let error = scenario.run(&mut out).err().unwrap();
+
+    // Assert
+    assert_eq!(
+        error.to_string(),
+        "Line 3: Device 'Desktop 2' does not exist"
+    );
+    assert!(out.is_empty());
+}
+
+#[test]
+fn Scenario_Run_ReportsExpectations() {
+    // Act
+    let (report, out) = run(PING);
+
+    // Assert
+    assert_eq!(
+        report,
+        Report {
+            expectations: 3,
+            failures: 1
+        }
+    );
+    assert!(!report.passed());
+    assert!(out.contains("[   0.500s] expect Desktop 2: \"IP address set\" ok"));
+    assert!(out.contains("[   1.000s] Desktop 1> ping 10.0.0.2"));
+    assert!(out.contains("[   3.000s] expect Desktop 1: \"Pong!\" ok"));
+    assert!(out.contains("[   8.000s] expect Desktop 1: \"Pong!\" FAILED"));
+    assert!(out.ends_with("2 of 3 expectations passed\n"));
+}
+
+#[test]
+fn Scenario_Run_PrintsDeviceDecisions() {
+    // Act
+    let (_, out) = run(PING);
+
+    // Assert
+    assert!(out.contains("] Desktop 2 port 0 log: 10.0.0.1 is at "));
+    assert!(out.contains(
+        "] Desktop 1 port 0 log: Dropped a frame to 10.0.0.3 as ARP couldn't resolve its next hop"
+    ));
+}
+
+#[test]
+fn Scenario_Run_IsDeterministic() {
+    // Act
+    let (_, first) = run(PING);
+    let (_, second) = run(PING);
+
+    // Assert
+    assert_eq!(first, second);
+}
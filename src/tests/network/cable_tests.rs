@@ -1,8 +1,19 @@
 #![allow(non_snake_case)]
 
-use crate::network::device::cable::CableSimulator;
-use crate::network::ethernet::{interface::*, EtherType};
-use crate::{eth2_data, mac_addr};
+use std::{
+    rc::{Rc, Weak},
+    time::Duration,
+};
+
+use crate::network::device::cable::{
+    CableSimulator, EthernetPort, Impairments, LinkProperties, PortCounters,
+};
+use crate::network::ethernet::{interface::*, EtherType, EthernetFrame};
+use crate::network::log::DropReason;
+use crate::network::trace::{TraceId, TraceStep};
+use crate::tick::{Clock, ClockMode};
+use crate::{eth2_data, mac_addr, mac_broadcast_addr};
+use rand::{rngs::StdRng, SeedableRng};
 
 #[test]
 fn CableSimulator_Tick_ConsumesAllOutgoing() {
@@ -28,3 +39,414 @@ fn CableSimulator_Tick_ConsumesAllOutgoing() {
     assert!(!i2.port().borrow().has_outgoing());
     assert!(!uc_interface.port().borrow().has_outgoing());
 }
+
+#[test]
+fn EthernetPort_SixteenCollisions_FramesDiscarded() {
+    // Arrange
+    let mut rng = StdRng::seed_from_u64(1);
+    let i1 = EthernetInterface::new(mac_addr!(1));
+    let i2 = EthernetInterface::new(mac_addr!(2));
+    i1.connect(&i2);
+
+    // Act
+    let mut backing_off = vec![];
+    for _ in 0..16 {
+        i2.port().borrow().jam(vec![(0, eth2_data!(1))], &mut rng);
+        backing_off.push(i1.port().borrow().is_backing_off());
+    }
+
+    // Assert
+    assert!(backing_off[..15].iter().all(|b| *b));
+    assert!(!backing_off[15]);
+}
+
+#[test]
+fn EthernetPort_QueueFull_TailDrops() {
+    // Arrange
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let i2 = EthernetInterface::new(mac_addr!(2));
+    i1.connect(&i2);
+    EthernetPort::set_link(
+        &i1.port(),
+        LinkProperties {
+            queue_limit: Some(2),
+            ..Default::default()
+        },
+    );
+
+    // Act
+    for i in 0..3 {
+        i1.send(i2.mac_address, EtherType::Debug, eth2_data!(i));
+    }
+
+    // Assert
+    assert_eq!(i1.port().borrow().sniff().1.len(), 2);
+    assert_eq!(i1.port().borrow().queue_drops(), 1);
+    assert_eq!(i2.port().borrow().link().queue_limit, Some(2)); // Both ends of the cable
+}
+
+#[test]
+fn CableSimulator_Latency_DeliversAfterPropagationDelay() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    sim.adds(vec![i1.port(), i2.port()]);
+    i1.connect(&i2);
+    EthernetPort::set_link(
+        &i1.port(),
+        LinkProperties {
+            latency: Duration::from_millis(100),
+            ..Default::default()
+        },
+    );
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    let sent = i2.receive().len();
+
+    clock.advance(Duration::from_millis(50));
+    sim.transmit();
+    let halfway = i2.receive().len();
+
+    clock.advance(Duration::from_millis(50));
+    sim.transmit();
+    let arrived = i2.receive().len();
+
+    // Assert
+    assert_eq!(sent, 0);
+    assert_eq!(halfway, 0);
+    assert_eq!(arrived, 1);
+}
+
+#[test]
+fn CableSimulator_LinkReset_DeliversFramesInFlight() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    sim.adds(vec![i1.port(), i2.port()]);
+    i1.connect(&i2);
+    EthernetPort::set_link(
+        &i1.port(),
+        LinkProperties {
+            latency: Duration::from_millis(100),
+            ..Default::default()
+        },
+    );
+
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    let in_flight = i2.receive().len();
+
+    // Act
+    EthernetPort::set_link(&i1.port(), LinkProperties::default());
+    sim.transmit();
+    let arrived = i2.receive().len();
+
+    // Assert
+    assert_eq!(in_flight, 0);
+    assert_eq!(arrived, 1);
+    assert_eq!(i1.port().borrow().counters().drops, 0);
+}
+
+#[test]
+fn CableSimulator_Bandwidth_SerializesFramesOneAfterAnother() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    sim.adds(vec![i1.port(), i2.port()]);
+    i1.connect(&i2);
+
+    for i in 0..3 {
+        i1.send(i2.mac_address, EtherType::Debug, eth2_data!(i));
+    }
+
+    // 1 millisecond per byte
+    let frame_time = Duration::from_millis(i1.port().borrow().sniff().1[0].len() as u64);
+    EthernetPort::set_link(
+        &i1.port(),
+        LinkProperties {
+            bandwidth: Some(8000),
+            ..Default::default()
+        },
+    );
+
+    // Act
+    let mut received = vec![];
+    for _ in 0..4 {
+        sim.transmit();
+        received.push(i2.receive().len());
+        clock.advance(frame_time);
+    }
+
+    // Assert
+    assert_eq!(received, vec![0, 1, 1, 1]);
+}
+
+/// Two connected interfaces with impairments on the cable between them.
+fn impaired_link(
+    impairments: Impairments,
+) -> (CableSimulator, EthernetInterface, EthernetInterface) {
+    let mut sim = CableSimulator::default();
+    let i1 = EthernetInterface::new(mac_addr!(1));
+    let i2 = EthernetInterface::new(mac_addr!(2));
+    sim.adds(vec![i1.port(), i2.port()]);
+    i1.connect(&i2);
+    EthernetPort::set_impairments(&i1.port(), impairments);
+    (sim, i1, i2)
+}
+
+#[test]
+fn CableSimulator_FullLoss_DropsEveryFrame() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = impaired_link(Impairments {
+        loss: 100.0,
+        ..Default::default()
+    });
+
+    // Act
+    for i in 0..5 {
+        i1.send(i2.mac_address, EtherType::Debug, eth2_data!(i));
+    }
+    sim.transmit();
+
+    // Assert
+    assert!(i2.receive().is_empty());
+    assert_eq!(i2.port().borrow().impairments().loss, 100.0); // Both ends of the cable
+}
+
+#[test]
+fn CableSimulator_FullLoss_TracesTheLoss() {
+    // Arrange
+    let (mut sim, mut i1, i2) = impaired_link(Impairments {
+        loss: 100.0,
+        ..Default::default()
+    });
+    let tracer = sim.tracer().clone();
+
+    // Act
+    tracer.enter(7);
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    tracer.leave();
+    sim.transmit();
+    let records = tracer.take();
+
+    // Assert
+    let steps: Vec<(TraceId, TraceStep)> = records.iter().map(|r| (r.trace, r.step)).collect();
+    assert_eq!(
+        steps,
+        vec![
+            (7, TraceStep::Sent),
+            (7, TraceStep::Dropped(DropReason::CableLoss))
+        ]
+    );
+    assert!(records
+        .iter()
+        .all(|r| Weak::ptr_eq(&r.port, &Rc::downgrade(&i1.port()))));
+}
+
+#[test]
+fn CableSimulator_Transmit_FrameKeepsItsTrace() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    sim.adds(vec![i1.port(), i2.port()]);
+    i1.connect(&i2);
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    let sent: Vec<TraceId> = sim.tracer().take().iter().map(|r| r.trace).collect();
+    let received: Vec<TraceId> = i2
+        .receive_traced()
+        .into_iter()
+        .map(|(trace, _)| trace)
+        .collect();
+
+    // Assert
+    assert_eq!(received, sent);
+    assert_ne!(received[0], received[1]); // Frames sent outside of a trace start their own
+}
+
+#[test]
+fn CableSimulator_Corruption_ReceiverRejectsFrames() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = impaired_link(Impairments {
+        corruption: 100.0,
+        ..Default::default()
+    });
+
+    // Act
+    for i in 0..3 {
+        i1.send(i2.mac_address, EtherType::Debug, eth2_data!(i));
+    }
+    sim.transmit();
+    let (arrived, _) = i2.port().borrow().sniff();
+
+    // Assert
+    assert_eq!(arrived.len(), 3);
+    assert!(i2.receive().is_empty());
+}
+
+#[test]
+fn CableSimulator_Duplication_DeliversFramesTwice() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = impaired_link(Impairments {
+        duplication: 100.0,
+        ..Default::default()
+    });
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    let received = i2.receive();
+
+    // Assert
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0], received[1]);
+}
+
+#[test]
+fn CableSimulator_Reordering_HeldFrameArrivesAfterNextFrame() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = impaired_link(Impairments {
+        reordering: 100.0,
+        ..Default::default()
+    });
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    let held = i2.receive();
+
+    EthernetPort::set_impairments(&i1.port(), Impairments::default());
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    let received = i2.receive();
+
+    // Assert
+    assert!(held.is_empty());
+    assert_eq!(received.len(), 2);
+    let data: Vec<u8> = received
+        .iter()
+        .map(|f| match f {
+            EthernetFrame::Ethernet2(f) => f.data[0],
+            _ => panic!("Expected an Ethernet II frame"),
+        })
+        .collect();
+    assert_eq!(data, vec![2, 1]);
+}
+
+#[test]
+fn CableSimulator_SameSeed_ReproducesLoss() {
+    // Arrange
+    let impairments = Impairments {
+        loss: 50.0,
+        ..Default::default()
+    };
+    let (mut sim1, mut a1, mut b1) = impaired_link(impairments);
+    let (mut sim2, mut a2, mut b2) = impaired_link(impairments);
+    sim1.seed(7);
+    sim2.seed(7);
+
+    // Act
+    let mut received1 = vec![];
+    let mut received2 = vec![];
+    for i in 0..20 {
+        a1.send(b1.mac_address, EtherType::Debug, eth2_data!(i));
+        a2.send(b2.mac_address, EtherType::Debug, eth2_data!(i));
+        sim1.transmit();
+        sim2.transmit();
+        received1.push(b1.receive().len());
+        received2.push(b2.receive().len());
+    }
+
+    // Assert
+    assert_eq!(received1, received2);
+    assert!(received1.contains(&0));
+    assert!(received1.contains(&1));
+}
+
+#[test]
+fn EthernetPort_Traffic_CountsFramesBytesAndBroadcasts() {
+    // Arrange
+    let (mut sim, mut i1, i2) = impaired_link(Impairments::default());
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    i1.send(mac_broadcast_addr!(), EtherType::Debug, eth2_data!(2));
+    i1.send([0x01, 0, 0x5E, 0, 0, 1], EtherType::Debug, eth2_data!(3));
+    let bytes: u64 = i1
+        .port()
+        .borrow()
+        .sniff()
+        .1
+        .iter()
+        .map(|f| f.len() as u64)
+        .sum();
+    sim.transmit();
+
+    // Assert
+    let sent = i1.counters();
+    let received = i2.counters();
+    assert_eq!((sent.frames_out, sent.bytes_out), (3, bytes));
+    assert_eq!((received.frames_in, received.bytes_in), (3, bytes));
+    assert_eq!(received.broadcasts, 1);
+    assert_eq!(received.multicasts, 1);
+    assert_eq!(sent.frames_in, 0);
+}
+
+#[test]
+fn EthernetPort_InvalidFrames_CountsRuntsGiantsAndFcsErrors() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = impaired_link(Impairments {
+        corruption: 100.0,
+        ..Default::default()
+    });
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+
+    EthernetPort::set_impairments(&i1.port(), Impairments::default());
+    i1.send(i2.mac_address, EtherType::Debug, vec![1; 2000]);
+    i1.port().borrow_mut().send(vec![0x55; 20]);
+    sim.transmit();
+    let received = i2.receive();
+
+    // Assert
+    let counters = i2.counters();
+    assert!(received.is_empty());
+    assert_eq!(counters.frames_in, 3);
+    assert_eq!(counters.fcs_errors, 1);
+    assert_eq!(counters.giants, 1);
+    assert_eq!(counters.runts, 1);
+}
+
+#[test]
+fn EthernetPort_ClearCounters_ResetsToZero() {
+    // Arrange
+    let (mut sim, mut i1, i2) = impaired_link(Impairments::default());
+    i1.port().borrow_mut().set_shutdown(true);
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    let dropped = i1.counters().drops;
+
+    // Act
+    i1.port().borrow_mut().clear_counters();
+
+    // Assert
+    assert_eq!(dropped, 1);
+    assert_eq!(i1.counters(), PortCounters::default());
+}
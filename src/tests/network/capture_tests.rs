@@ -0,0 +1,382 @@
+#![allow(non_snake_case)]
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    eth2_data, mac_addr,
+    network::{
+        capture::{Capture, Direction, Replay},
+        device::{
+            cable::{CableSimulator, EthernetPort},
+            switch::{BpduFrame, Switch},
+        },
+        ethernet::{check_frame, crc32, interface::EthernetInterface, ByteSerializable, EtherType},
+        ipv4::interface::Ipv4Interface,
+    },
+};
+
+/// Splits pcapng bytes into (Block Type, Block Body) pairs.
+fn blocks(bytes: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let mut blocks = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let kind = u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let len = u32::from_le_bytes(bytes[i + 4..i + 8].try_into().unwrap()) as usize;
+        let trailing_len = u32::from_le_bytes(bytes[i + len - 4..i + len].try_into().unwrap());
+        assert_eq!(len, trailing_len as usize);
+        assert_eq!(len % 4, 0);
+        blocks.push((kind, bytes[i + 8..i + len - 4].to_vec()));
+        i += len;
+    }
+    blocks
+}
+
+/// Writes frames as captured on the wire, without a Frame Check Sequence, to a little endian pcap file.
+fn pcap(frames: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&0xA1B2C3D4u32.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes()); // Major version
+    bytes.extend_from_slice(&4u16.to_le_bytes()); // Minor version
+    bytes.extend_from_slice(&[0; 8]); // Time zone and accuracy
+    bytes.extend_from_slice(&65535u32.to_le_bytes()); // Snap length
+    bytes.extend_from_slice(&1u32.to_le_bytes()); // LINKTYPE_ETHERNET
+    for (i, frame) in frames.iter().enumerate() {
+        bytes.extend_from_slice(&(i as u32).to_le_bytes()); // Seconds
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // Microseconds
+        bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes()); // Captured length
+        bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes()); // Original length
+        bytes.extend_from_slice(frame);
+    }
+    bytes
+}
+
+#[test]
+fn Capture_ToPcapng_WritesSectionInterfaceAndPacketBlocks() {
+    // Arrange
+    let mut capture = Capture::default();
+    let interface = capture.add_interface("Port 0");
+    let mut frame = vec![0x55; 7];
+    frame.push(0xD5);
+    frame.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+    let fcs = crc32(&frame[8..]);
+    frame.extend_from_slice(&fcs.to_be_bytes());
+    let time = UNIX_EPOCH + Duration::from_micros(0x1_0000_0002);
+
+    // Act
+    capture.record(interface, Direction::Outbound, time, &frame);
+    let bytes = capture.to_pcapng();
+
+    // Assert
+    let blocks = blocks(&bytes);
+    assert_eq!(
+        blocks.iter().map(|(kind, _)| *kind).collect::<Vec<_>>(),
+        vec![0x0A0D0D0A, 1, 6]
+    );
+
+    let shb = &blocks[0].1;
+    assert_eq!(shb[0..4], 0x1A2B3C4Du32.to_le_bytes());
+
+    let idb = &blocks[1].1;
+    assert_eq!(idb[0..2], 1u16.to_le_bytes()); // LINKTYPE_ETHERNET
+
+    let epb = &blocks[2].1;
+    assert_eq!(epb[0..4], 0u32.to_le_bytes()); // Interface id
+    assert_eq!(epb[4..8], 1u32.to_le_bytes()); // Timestamp (high)
+    assert_eq!(epb[8..12], 2u32.to_le_bytes()); // Timestamp (low)
+    assert_eq!(epb[12..16], 11u32.to_le_bytes()); // Captured length, without the preamble
+    assert_eq!(epb[20..27], [1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(epb[27..31], fcs.to_le_bytes()); // The wire's byte order
+}
+
+#[test]
+fn EthernetPort_Tapped_CapturesBothDirections() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    sim.adds(vec![i1.port(), i2.port()]);
+    i1.connect(&i2);
+
+    let capture = Rc::new(RefCell::new(Capture::default()));
+    let interface = capture.borrow_mut().add_interface("i1");
+    i1.port().borrow_mut().tap(&capture, interface);
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    let sent = i1.port().borrow().sniff().1[0].clone();
+    i2.send(i1.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+
+    i1.port().borrow_mut().untap(&capture);
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(3));
+    sim.transmit();
+
+    // Assert
+    let capture = capture.borrow();
+    let frames = capture.frames();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].direction, Direction::Outbound);
+    assert_eq!(frames[0].data[..sent.len() - 12], sent[8..sent.len() - 4]);
+    assert_eq!(
+        frames[0].data[sent.len() - 12..],
+        sent[sent.len() - 4..]
+            .iter()
+            .rev()
+            .copied()
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(frames[1].direction, Direction::Inbound);
+    assert_eq!(frames[1].data[..6], i1.mac_address);
+}
+
+#[test]
+fn Capture_FromPcapng_ReadsWrittenCapture() {
+    // Arrange
+    let mut capture = Capture::default();
+    let p0 = capture.add_interface("Port 0");
+    let p1 = capture.add_interface("Port 1");
+    let mut frame = vec![0x55; 7];
+    frame.push(0xD5);
+    frame.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7]);
+    frame.extend_from_slice(&crc32(&frame[8..]).to_be_bytes());
+    capture.record(
+        p0,
+        Direction::Outbound,
+        UNIX_EPOCH + Duration::from_micros(5),
+        &frame,
+    );
+    capture.record(
+        p1,
+        Direction::Inbound,
+        UNIX_EPOCH + Duration::from_secs(7),
+        &frame,
+    );
+
+    // Act
+    let read = Capture::from_bytes(&capture.to_pcapng()).unwrap();
+
+    // Assert
+    assert_eq!(read.interfaces(), capture.interfaces());
+    assert_eq!(read.frames(), capture.frames());
+}
+
+#[test]
+fn Capture_FromPcap_ReadsFramesAndAppendsFcs() {
+    // Arrange
+    let data: Vec<u8> = (0..60).collect();
+    let mut bytes = vec![];
+    bytes.extend_from_slice(&0xA1B2C3D4u32.to_be_bytes()); // Big endian file
+    bytes.extend_from_slice(&2u16.to_be_bytes()); // Major version
+    bytes.extend_from_slice(&4u16.to_be_bytes()); // Minor version
+    bytes.extend_from_slice(&[0; 8]); // Time zone and accuracy
+    bytes.extend_from_slice(&65535u32.to_be_bytes()); // Snap length
+    bytes.extend_from_slice(&1u32.to_be_bytes()); // LINKTYPE_ETHERNET
+    bytes.extend_from_slice(&10u32.to_be_bytes()); // Seconds
+    bytes.extend_from_slice(&250u32.to_be_bytes()); // Microseconds
+    bytes.extend_from_slice(&60u32.to_be_bytes()); // Captured length
+    bytes.extend_from_slice(&60u32.to_be_bytes()); // Original length
+    bytes.extend_from_slice(&data);
+
+    // Act
+    let capture = Capture::from_bytes(&bytes).unwrap();
+    let truncated = Capture::from_bytes(&bytes[..bytes.len() - 1]);
+    let not_ethernet = Capture::from_bytes(&[&bytes[..20], &105u32.to_be_bytes()[..]].concat());
+
+    // Assert
+    assert_eq!(capture.interfaces(), ["pcap"]);
+    let frame = &capture.frames()[0];
+    assert_eq!(frame.time, UNIX_EPOCH + Duration::from_micros(10_000_250));
+    assert_eq!(frame.direction, Direction::Inbound);
+    assert_eq!(frame.data[..60], data);
+    assert_eq!(frame.data[60..], crc32(&data).to_le_bytes());
+
+    let mut replay = Replay::new(&capture, 0.0);
+    let mut port = EthernetPort::default();
+    replay.inject(&mut port, UNIX_EPOCH);
+    let (incoming, _) = port.sniff();
+    assert_eq!(check_frame(&incoming[0]), Ok(()));
+
+    assert!(truncated.is_err());
+    assert!(not_ethernet.is_err());
+}
+
+#[test]
+fn Capture_FromPcapngTimestampOutOfRange_ReturnsError() {
+    // Arrange
+    let block = |kind: u32, body: Vec<u8>| {
+        let len = (body.len() as u32 + 12).to_le_bytes();
+        [&kind.to_le_bytes()[..], &len, &body, &len].concat()
+    };
+    let pcapng = |resolution: u8| {
+        let shb = [
+            &0x1A2B3C4Du32.to_le_bytes()[..],
+            &1u16.to_le_bytes(), // Major version
+            &0u16.to_le_bytes(), // Minor version
+            &(-1i64).to_le_bytes(),
+        ]
+        .concat();
+        let idb = [
+            &1u16.to_le_bytes()[..], // LINKTYPE_ETHERNET
+            &[0; 6],                 // Reserved and snap length
+            &9u16.to_le_bytes(),     // if_tsresol
+            &1u16.to_le_bytes(),
+            &[resolution, 0, 0, 0],
+            &[0; 4], // opt_endofopt
+        ]
+        .concat();
+        let epb = [
+            &0u32.to_le_bytes()[..], // Interface
+            &[0xFF; 8],              // Timestamp
+            &60u32.to_le_bytes(),    // Captured length
+            &60u32.to_le_bytes(),    // Original length
+            &[0; 60],
+        ]
+        .concat();
+        [block(0x0A0D0D0A, shb), block(1, idb), block(6, epb)].concat()
+    };
+
+    // Act
+    let seconds = Capture::from_bytes(&pcapng(0));
+    let binary_seconds = Capture::from_bytes(&pcapng(0x80));
+    let micros = Capture::from_bytes(&pcapng(6));
+
+    // Assert
+    assert!(seconds.is_err());
+    assert!(binary_seconds.is_err());
+    assert!(micros.is_err());
+}
+
+#[test]
+fn Replay_HugeScale_WaitsWithoutPanicking() {
+    // Arrange
+    let mut capture = Capture::default();
+    let interface = capture.add_interface("sender");
+    let frame = eth2_data!(1);
+    capture.record(interface, Direction::Outbound, UNIX_EPOCH, &frame);
+    capture.record(
+        interface,
+        Direction::Outbound,
+        UNIX_EPOCH + Duration::from_secs(1),
+        &frame,
+    );
+    let mut replay = Replay::new(&capture, f64::MAX);
+    let mut port = EthernetPort::default();
+
+    // Act
+    let injected = replay.inject(&mut port, UNIX_EPOCH);
+
+    // Assert
+    assert_eq!(injected, 1);
+    assert_eq!(replay.remaining(), 1);
+    assert_eq!(replay.next_due(), None);
+}
+
+#[test]
+fn Replay_Inject_DeliversFramesAtScaledTimes() {
+    // Arrange
+    let mut sender = Ipv4Interface::new(mac_addr!(1), [10, 0, 0, 1], [255, 255, 255, 0], None);
+    let mut receiver = Ipv4Interface::new(mac_addr!(2), [10, 0, 0, 2], [255, 255, 255, 0], None);
+    sender.send_t([10, 0, 0, 2], 7); // ARP request, as the address is unknown
+    let arp_request = sender.ethernet.port().borrow().sniff().1[0].clone();
+
+    let mut capture = Capture::default();
+    let interface = capture.add_interface("sender");
+    let recorded = UNIX_EPOCH + Duration::from_secs(100);
+    capture.record(interface, Direction::Outbound, recorded, &arp_request);
+    capture.record(
+        interface,
+        Direction::Outbound,
+        recorded + Duration::from_secs(1),
+        &arp_request,
+    );
+
+    let mut replay = Replay::new(&capture, 0.5);
+    let port = receiver.ethernet.port();
+    let start = UNIX_EPOCH + Duration::from_secs(5000);
+
+    // Act
+    let first = replay.inject(&mut port.borrow_mut(), start);
+    let early = replay.inject(&mut port.borrow_mut(), start + Duration::from_millis(400));
+    let second = replay.inject(&mut port.borrow_mut(), start + Duration::from_millis(500));
+    receiver.receive();
+
+    // Assert
+    assert_eq!((first, early, second), (1, 0, 1));
+    assert!(replay.is_finished());
+    assert_eq!(
+        receiver.arp_table().get(&[10, 0, 0, 1]),
+        Some(&mac_addr!(1))
+    );
+    assert!(port.borrow().has_outgoing()); // ARP replies
+}
+
+#[test]
+fn Replay_PaddedArpRequest_LearnsSender() {
+    // Arrange
+    let mut receiver = Ipv4Interface::new(mac_addr!(2), [10, 0, 0, 2], [255, 255, 255, 0], None);
+
+    // An ARP request for 10.0.0.2 from 10.0.0.1, padded to 60 bytes
+    let mut frame = vec![0xFF; 6];
+    frame.extend_from_slice(&mac_addr!(1));
+    frame.extend_from_slice(&[0x08, 0x06]); // ARP
+    frame.extend_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x01]); // Ethernet, Ipv4, Request
+    frame.extend_from_slice(&mac_addr!(1));
+    frame.extend_from_slice(&[10, 0, 0, 1]);
+    frame.extend_from_slice(&[0; 6]);
+    frame.extend_from_slice(&[10, 0, 0, 2]);
+    frame.resize(60, 0);
+
+    let capture = Capture::from_bytes(&pcap(&[frame])).unwrap();
+    let mut replay = Replay::new(&capture, 0.0);
+    let port = receiver.ethernet.port();
+
+    // Act
+    replay.inject(&mut port.borrow_mut(), UNIX_EPOCH);
+    receiver.receive();
+
+    // Assert
+    assert_eq!(
+        receiver.arp_table().get(&[10, 0, 0, 1]),
+        Some(&mac_addr!(1))
+    );
+    assert!(port.borrow().has_outgoing()); // ARP reply
+}
+
+#[test]
+fn Replay_RstpBpdu_SwitchLearnsRoot() {
+    // Arrange
+    let mut switch = Switch::from_seed(2, 32768);
+    switch.init_stp();
+
+    // An RSTP BPDU from a root bridge with the lowest possible ID, padded to 60 bytes
+    let mut frame = vec![0x01, 0x80, 0xC2, 0x00, 0x00, 0x00];
+    frame.extend_from_slice(&mac_addr!(9));
+    frame.extend_from_slice(&39u16.to_be_bytes()); // Length of the LLC header and BPDU
+    frame.extend_from_slice(&[0x42, 0x42, 0x03]); // LLC
+    frame.extend_from_slice(&[0x00, 0x00, 0x02, 0x02, 0x3C]); // Protocol, RSTP, RST BPDU, Designated forwarding
+    frame.extend_from_slice(&1u64.to_be_bytes()); // Root ID
+    frame.extend_from_slice(&0u32.to_be_bytes()); // Root path cost
+    frame.extend_from_slice(&1u64.to_be_bytes()); // Bridge ID
+    frame.extend_from_slice(&0x8001u16.to_be_bytes()); // Port ID
+    frame.extend_from_slice(&[0x00, 0x00, 0x14, 0x00, 0x02, 0x00, 0x0F, 0x00]); // Ages and times, in 1/256 seconds
+    frame.push(0x00); // Version 1 Length
+    frame.resize(60, 0);
+
+    let capture = Capture::from_bytes(&pcap(&[frame.clone()])).unwrap();
+    let mut replay = Replay::new(&capture, 0.0);
+    let port = switch.ports()[0].clone();
+
+    // Act
+    replay.inject(&mut port.borrow_mut(), UNIX_EPOCH);
+    switch.forward();
+
+    // Assert
+    assert_eq!(switch.root_bid(), 1);
+    assert_eq!(switch.root_port(), Some(0));
+    assert!(BpduFrame::from_bytes(frame[17..51].to_vec()).is_err()); // Ends before the forward delay
+    assert!(BpduFrame::from_bytes(frame[17..52].to_vec()).is_err()); // Ends before the Version 1 Length
+}
@@ -39,7 +39,7 @@ mod EthernetFrameTests {
 
         assert_eq!(bytes[20..22], [0xFF, 0xFF]); // EtherType
         assert_eq!(bytes[22..50], eth2_data!(1)); // Data
-        assert_eq!(bytes[50..54], [0x00, 0x00, 0x00, 0x00]); // Frame Check Sequence
+        assert_eq!(bytes[50..54], crc32(&bytes[8..50]).to_be_bytes()); // Frame Check Sequence
     }
 
     #[test]
@@ -62,6 +62,56 @@ mod EthernetFrameTests {
         assert_eq!(result.unwrap(), ethernet_frame);
     }
 
+    #[test]
+    fn Ethernet2Frame_FromBytesWithFlippedBit_FcsError() {
+        // Arrange
+        let ethernet_frame = Ethernet2Frame::new(
+            mac_broadcast_addr!(),
+            [0x01, 0x01, 0x01, 0x01, 0x01, 0x01],
+            eth2_data!(1),
+            EtherType::Debug,
+        );
+
+        let mut bytes = ethernet_frame.to_bytes();
+        bytes[30] ^= 0x04;
+
+        // Act
+        let result = Ethernet2Frame::from_bytes(bytes);
+
+        // Assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn Crc32_CheckValue_MatchesIeee() {
+        // Act
+        let crc = crc32(b"123456789");
+
+        // Assert
+        assert_eq!(crc, 0xCBF43926);
+    }
+
+    #[test]
+    fn Ethernet2Frame_TagThenUntag_RestoresFrame() {
+        // Arrange
+        let ethernet_frame = Ethernet2Frame::new(
+            mac_broadcast_addr!(),
+            [0x01, 0x01, 0x01, 0x01, 0x01, 0x01],
+            eth2_data!(1),
+            EtherType::Ipv4,
+        );
+
+        // Act
+        let tagged = ethernet_frame.clone().tag(10);
+        let (vlan, untagged) = tagged.clone().untag();
+
+        // Assert
+        assert_eq!(tagged.ether_type, EtherType::Vlan);
+        assert_eq!(tagged.data[0..4], [0x00, 0x0A, 0x08, 0x00]); // TCI, EtherType
+        assert_eq!(vlan, Some(10));
+        assert_eq!(untagged, ethernet_frame);
+    }
+
     #[test]
     fn Ethernet802_3Frame_ToBytes_ReturnsValidByteArray() {
         // Arrange
@@ -92,7 +142,7 @@ mod EthernetFrameTests {
 
         assert_eq!(bytes[20..22], [0xFF, 0xFF]); // EtherType
         assert_eq!(bytes[22..50], eth2_data!(1)); // Data
-        assert_eq!(bytes[50..54], [0x00, 0x00, 0x00, 0x00]); // Frame Check Sequence
+        assert_eq!(bytes[50..54], crc32(&bytes[8..50]).to_be_bytes()); // Frame Check Sequence
     }
 
     #[test]
@@ -0,0 +1,102 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    mac_addr,
+    network::{
+        device::{
+            cable::{CableSimulator, EthernetPort},
+            hub::Hub,
+            multilayer_switch::MultilayerSwitch,
+            switch::Switch,
+        },
+        ethernet::interface::EthernetInterface,
+        ipv4::interface::Ipv4Interface,
+    },
+};
+
+/// A device under test, with ports that hosts are cabled to.
+pub trait Device {
+    fn cable(&mut self, port: usize, host: &mut EthernetInterface);
+    fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>>;
+}
+
+impl Device for Switch {
+    fn cable(&mut self, port: usize, host: &mut EthernetInterface) {
+        self.connect(port, host);
+    }
+
+    fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
+        Switch::ports(self)
+    }
+}
+
+impl Device for MultilayerSwitch {
+    fn cable(&mut self, port: usize, host: &mut EthernetInterface) {
+        self.connect(port, host);
+    }
+
+    fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
+        MultilayerSwitch::ports(self)
+    }
+}
+
+impl Device for Hub {
+    fn cable(&mut self, port: usize, host: &mut EthernetInterface) {
+        self.connect(port, &host.port());
+    }
+
+    fn ports(&self) -> Vec<Rc<RefCell<EthernetPort>>> {
+        Hub::ports(self)
+    }
+}
+
+/// A host cabled to the device under test.
+pub trait Host {
+    fn ethernet(&mut self) -> &mut EthernetInterface;
+}
+
+impl Host for EthernetInterface {
+    fn ethernet(&mut self) -> &mut EthernetInterface {
+        self
+    }
+}
+
+impl Host for Ipv4Interface {
+    fn ethernet(&mut self) -> &mut EthernetInterface {
+        &mut self.ethernet
+    }
+}
+
+/// Cables each host to a port of the device, and adds the device and hosts to a simulator.
+/// * `ports` - The port of the device each host is cabled to.
+pub fn network<D: Device, H: Host, const N: usize>(
+    mut device: D,
+    mut hosts: [H; N],
+    ports: [usize; N],
+) -> (CableSimulator, D, [H; N]) {
+    let mut sim = CableSimulator::default();
+    for (host, port) in hosts.iter_mut().zip(ports) {
+        device.cable(port, host.ethernet());
+        sim.add(host.ethernet().port());
+    }
+    sim.adds(device.ports());
+
+    (sim, device, hosts)
+}
+
+/// Ethernet interfaces with the MAC addresses 1 to N.
+pub fn desktops<const N: usize>() -> [EthernetInterface; N] {
+    std::array::from_fn(|i| EthernetInterface::new(mac_addr!(i as u64 + 1)))
+}
+
+/// Ipv4 interfaces with the MAC addresses 1 to N and the addresses 10.0.0.1 to 10.0.0.N/24, without a gateway.
+pub fn ipv4_desktops<const N: usize>() -> [Ipv4Interface; N] {
+    std::array::from_fn(|i| {
+        Ipv4Interface::new(
+            mac_addr!(i as u64 + 1),
+            [10, 0, 0, i as u8 + 1],
+            [255, 255, 255, 0],
+            None,
+        )
+    })
+}
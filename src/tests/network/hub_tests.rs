@@ -0,0 +1,71 @@
+#![allow(non_snake_case)]
+
+use crate::{
+    eth2_data,
+    network::{device::hub::Hub, ethernet::EtherType},
+    tests::network::fixtures::{desktops, network},
+};
+
+#[test]
+fn Hub_FrameReceived_RepeatedOutAllOtherPorts() {
+    // Arrange
+    let (mut sim, mut hub, mut interfaces) = network(Hub::from_seed(1), desktops(), [0, 1, 2]);
+    let destination = interfaces[1].mac_address;
+
+    // Act
+    interfaces[0].send(destination, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    hub.repeat();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(interfaces[0].receive().len(), 0);
+    assert_eq!(interfaces[1].receive().len(), 1);
+    assert_eq!(interfaces[2].receive().len(), 1); // No learning, every port gets the frame
+    assert_eq!(hub.collisions(), 0);
+}
+
+#[test]
+fn Hub_TwoPortsTransmitInSameTick_Collision() {
+    // Arrange
+    let (mut sim, mut hub, mut interfaces) = network(Hub::from_seed(1), desktops(), [0, 1, 2]);
+    let destination = interfaces[2].mac_address;
+
+    // Act
+    interfaces[0].send(destination, EtherType::Debug, eth2_data!(1));
+    interfaces[1].send(destination, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    hub.repeat();
+
+    // Assert
+    assert_eq!(hub.collisions(), 1);
+    assert!(hub.ports().iter().all(|p| !p.borrow().has_outgoing())); // Nothing is repeated
+    assert!(interfaces[0].port().borrow().is_backing_off());
+    assert!(interfaces[1].port().borrow().is_backing_off());
+}
+
+#[test]
+fn Hub_Collision_SendersRetransmitAfterBackoff() {
+    // Arrange
+    let (mut sim, mut hub, mut interfaces) = network(Hub::from_seed(1), desktops(), [0, 1, 2]);
+    let destination = interfaces[2].mac_address;
+
+    // Act
+    interfaces[0].send(destination, EtherType::Debug, eth2_data!(1));
+    interfaces[1].send(destination, EtherType::Debug, eth2_data!(2));
+
+    let mut received = vec![];
+    for _ in 0..100 {
+        sim.transmit();
+        hub.repeat();
+        received.extend(interfaces[2].receive_eth2());
+    }
+
+    // Assert
+    assert!(hub.collisions() >= 1);
+    assert_eq!(received.len(), 2);
+    assert!(received.iter().any(|f| f.data == eth2_data!(1)));
+    assert!(received.iter().any(|f| f.data == eth2_data!(2)));
+    assert!(!interfaces[0].port().borrow().is_backing_off());
+    assert!(!interfaces[1].port().borrow().is_backing_off());
+}
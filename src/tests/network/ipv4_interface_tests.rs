@@ -1,11 +1,13 @@
 #![allow(non_snake_case)]
 
-use crate::network::device::cable::CableSimulator;
+use crate::network::device::cable::{CableSimulator, EthernetPort, Impairments};
 use crate::network::ethernet::ByteSerializable;
 use crate::network::ethernet::{interface::*, EtherType};
 use crate::network::ipv4::interface::*;
+use crate::tick::{Clock, ClockMode};
 use crate::{arp_table, network::ipv4::*};
 use crate::{eth2, eth2_data, mac_addr, mac_broadcast_addr};
+use std::time::Duration;
 
 fn same_subnet_filled_arp_tables() -> (CableSimulator, Ipv4Interface, Ipv4Interface) {
     let mut sim = CableSimulator::default();
@@ -146,6 +148,8 @@ fn Send_UnknownIpV4AfterMultipleRetries_ReceiveMultipleArpRequests() {
     let mut i1 = Ipv4Interface::new(mac_addr!(1), [192, 168, 1, 1], [255, 255, 255, 0], None);
     let mut i2 = Ipv4Interface::new(mac_addr!(2), [192, 168, 1, 2], [255, 255, 255, 0], None);
 
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
     sim.adds(vec![i1.ethernet.port(), i2.ethernet.port()]);
     i1.connect(&mut i2);
 
@@ -153,15 +157,16 @@ fn Send_UnknownIpV4AfterMultipleRetries_ReceiveMultipleArpRequests() {
     i1.send_t(i2.ip_address, 1); // Fails, places in buffer
     sim.transmit();
 
-    for _ in 0..90 {
+    for _ in 0..5 {
+        clock.advance(Duration::from_secs(1)); // 1 second to retry
         i1.receive();
-        sim.transmit(); // 30 ticks to retry
+        sim.transmit();
     }
 
     let i2_data = i2.ethernet.receive();
 
     // Assert
-    assert_eq!(i2_data.len(), 4); // 1 + (90 / 30) = 4
+    assert_eq!(i2_data.len(), 4); // 1 + 3 retries
 }
 
 #[test]
@@ -219,6 +224,54 @@ fn Send_UnknownIpv4AfterMultipleRetries_ReturnsOriginalRequest() {
     );
 }
 
+#[test]
+fn Send_ArpRequestLostToDefaultGateway_RetriesForDefaultGateway() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut default_gateway =
+        Ipv4Interface::new(mac_addr!(1), [192, 168, 1, 254], [255, 255, 255, 0], None);
+    let mut i1 = Ipv4Interface::new(
+        mac_addr!(2),
+        [192, 168, 1, 1],
+        [255, 255, 255, 0],
+        Some(default_gateway.ip_address),
+    );
+
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    i1.connect(&mut default_gateway);
+    sim.adds(vec![i1.ethernet.port(), default_gateway.ethernet.port()]);
+
+    EthernetPort::set_impairments(
+        &i1.ethernet.port(),
+        Impairments {
+            loss: 100.0,
+            ..Default::default()
+        },
+    );
+
+    // Act
+    i1.send_t([192, 168, 2, 1], 1); // ARP request is lost
+    for _ in 0..10 {
+        sim.transmit();
+        default_gateway.receive();
+        i1.receive();
+    }
+    let resolved_before_retry = i1.arp_table().contains_key(&default_gateway.ip_address);
+
+    EthernetPort::set_impairments(&i1.ethernet.port(), Impairments::default());
+    clock.advance(Duration::from_secs(1)); // 1 second to retry
+    for _ in 0..10 {
+        sim.transmit();
+        default_gateway.receive();
+        i1.receive();
+    }
+
+    // Assert
+    assert!(!resolved_before_retry);
+    assert!(i1.arp_table().contains_key(&default_gateway.ip_address));
+}
+
 #[test]
 fn Send_DifferentSubnet_SendsToDefaultGateway() {
     // Arrange
@@ -399,3 +452,82 @@ fn Ping_Self_ReceiveIcmpEchoReply() {
         )
     );
 }
+
+#[test]
+fn Igmp_JoinGroup_SendsReportToGroupMac() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = same_subnet_filled_arp_tables();
+    let group = [239, 1, 2, 3];
+
+    // Act
+    i1.join_group(group).unwrap();
+    sim.transmit();
+
+    let frames = i2.ethernet.receive_eth2();
+
+    // Assert
+    assert_eq!(frames.len(), 1);
+    assert_eq!(
+        frames[0].destination_address,
+        [0x01, 0x00, 0x5E, 0x01, 0x02, 0x03]
+    );
+
+    let ipv4_frame = Ipv4Frame::from_bytes(frames[0].data.clone()).unwrap();
+    assert_eq!(ipv4_frame.destination, group);
+    assert_eq!(ipv4_frame.ttl, 1);
+    assert_eq!(
+        IgmpFrame::from_bytes(ipv4_frame.data).unwrap(),
+        IgmpFrame::report(group)
+    );
+}
+
+#[test]
+fn Igmp_GeneralQuery_ReportsJoinedGroups() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = same_subnet_filled_arp_tables();
+    i1.join_group([239, 0, 0, 1]).unwrap();
+    i1.join_group([239, 0, 0, 2]).unwrap();
+    sim.transmit();
+    i2.ethernet.receive();
+
+    // Act
+    i2.send_igmp_query(None);
+    sim.transmit();
+    i1.receive();
+    sim.transmit();
+
+    let reports = i2
+        .receive()
+        .into_iter()
+        .filter_map(|f| IgmpFrame::from_bytes(f.data).ok())
+        .collect::<Vec<_>>();
+
+    // Assert
+    assert_eq!(
+        reports,
+        vec![
+            IgmpFrame::report([239, 0, 0, 1]),
+            IgmpFrame::report([239, 0, 0, 2])
+        ]
+    );
+}
+
+#[test]
+fn Multicast_NotAMemberOfGroup_DropsFrame() {
+    // Arrange
+    let (mut sim, mut i1, mut i2) = same_subnet_filled_arp_tables();
+    i2.join_group([239, 0, 0, 1]).unwrap();
+    sim.transmit();
+    i1.receive();
+
+    // Act
+    i1.multicast_to([239, 0, 0, 1], vec![1], Ipv4Protocol::Test);
+    i1.multicast_to([239, 0, 0, 2], vec![2], Ipv4Protocol::Test);
+    sim.transmit();
+
+    let i2_frames = i2.receive();
+
+    // Assert
+    assert_eq!(i2_frames.len(), 1);
+    assert_eq!(i2_frames[0].destination, [239, 0, 0, 1]);
+}
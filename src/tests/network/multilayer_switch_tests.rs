@@ -0,0 +1,170 @@
+#![allow(non_snake_case)]
+
+use crate::{
+    mac_addr,
+    network::{
+        device::{cable::CableSimulator, multilayer_switch::MultilayerSwitch},
+        ipv4::{interface::Ipv4Interface, IcmpType, Ipv4Frame, Ipv4Protocol},
+    },
+    tests::network::fixtures::network,
+    tick::Tickable,
+};
+
+/// Hosts in VLAN 10 on ports 0 and 2, and a host in VLAN 20 on port 1. Each VLAN has an SVI as its default gateway.
+fn svi_network() -> (CableSimulator, MultilayerSwitch, Vec<Ipv4Interface>) {
+    let hosts = [
+        Ipv4Interface::new(
+            mac_addr!(1),
+            [10, 0, 10, 2],
+            [255, 255, 255, 0],
+            Some([10, 0, 10, 1]),
+        ),
+        Ipv4Interface::new(
+            mac_addr!(2),
+            [10, 0, 20, 2],
+            [255, 255, 255, 0],
+            Some([10, 0, 20, 1]),
+        ),
+        Ipv4Interface::new(
+            mac_addr!(3),
+            [10, 0, 10, 3],
+            [255, 255, 255, 0],
+            Some([10, 0, 10, 1]),
+        ),
+    ];
+    let (sim, mut mls, hosts) = network(MultilayerSwitch::from_seed(100, 1), hosts, [0, 1, 2]);
+
+    for (port, vlan) in [10, 20, 10].into_iter().enumerate() {
+        mls.switch_mut().set_access_vlan(port, vlan);
+    }
+    mls.set_svi(10, [10, 0, 10, 1], [255, 255, 255, 0]).unwrap();
+    mls.set_svi(20, [10, 0, 20, 1], [255, 255, 255, 0]).unwrap();
+
+    (sim, mls, hosts.into())
+}
+
+/// Runs the network for a number of ticks, returning the frames each host received.
+fn run(
+    sim: &mut CableSimulator,
+    mls: &mut MultilayerSwitch,
+    hosts: &mut [Ipv4Interface],
+    ticks: usize,
+) -> Vec<Vec<Ipv4Frame>> {
+    let mut received: Vec<Vec<Ipv4Frame>> = hosts.iter().map(|_| vec![]).collect();
+    for _ in 0..ticks {
+        sim.transmit();
+        for (host, frames) in hosts.iter_mut().zip(received.iter_mut()) {
+            frames.extend(host.receive());
+        }
+        mls.tick();
+    }
+    received
+}
+
+#[test]
+fn MultilayerSwitch_HostsInDifferentVlans_RoutedBetweenSvis() {
+    // Arrange
+    let (mut sim, mut mls, mut hosts) = svi_network();
+
+    // Act
+    hosts[0].send_t([10, 0, 20, 2], 7);
+    let received = run(&mut sim, &mut mls, &mut hosts, 20);
+
+    // Assert
+    let frames: Vec<_> = received[1]
+        .iter()
+        .filter(|f| f.protocol == Ipv4Protocol::Test as u8)
+        .collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].source, [10, 0, 10, 2]);
+    assert_eq!(frames[0].data, vec![7]);
+    assert_eq!(frames[0].ttl, 63);
+    assert!(received[2]
+        .iter()
+        .all(|f| f.protocol != Ipv4Protocol::Test as u8));
+}
+
+#[test]
+fn MultilayerSwitch_HostsInSameVlan_SwitchedWithoutRouting() {
+    // Arrange
+    let (mut sim, mut mls, mut hosts) = svi_network();
+
+    // Act
+    hosts[0].send_t([10, 0, 10, 3], 7);
+    let received = run(&mut sim, &mut mls, &mut hosts, 20);
+
+    // Assert
+    let frames: Vec<_> = received[2]
+        .iter()
+        .filter(|f| f.protocol == Ipv4Protocol::Test as u8)
+        .collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].ttl, 64);
+    assert!(received[1]
+        .iter()
+        .all(|f| f.protocol != Ipv4Protocol::Test as u8));
+}
+
+#[test]
+fn MultilayerSwitch_RoutedPort_RepliesToPingAndLeavesSwitching() {
+    // Arrange
+    let (mut sim, mut mls, mut hosts) = svi_network();
+    let mut host = Ipv4Interface::new(
+        mac_addr!(4),
+        [10, 0, 30, 2],
+        [255, 255, 255, 0],
+        Some([10, 0, 30, 1]),
+    );
+    mls.connect(3, &mut host.ethernet);
+    sim.add(host.ethernet.port());
+    hosts.push(host);
+
+    mls.set_routed_port(3, [10, 0, 30, 1], [255, 255, 255, 0])
+        .unwrap();
+
+    // Act
+    hosts[3]
+        .send_icmp([10, 0, 30, 1], IcmpType::EchoRequest)
+        .unwrap();
+    hosts[3].send_t([10, 0, 20, 2], 7);
+    let received = run(&mut sim, &mut mls, &mut hosts, 20);
+
+    // Assert
+    assert!(mls.is_routed_port(3));
+    assert!(received[3]
+        .iter()
+        .any(|f| f.protocol == Ipv4Protocol::Icmp as u8 && f.source == [10, 0, 30, 1]));
+    assert!(received[1]
+        .iter()
+        .any(|f| f.protocol == Ipv4Protocol::Test as u8 && f.source == [10, 0, 30, 2]));
+    assert!(mls
+        .routing_table()
+        .iter()
+        .any(|(network, _, _)| *network == [10, 0, 30, 0]));
+
+    assert!(mls.set_switchport(3).is_ok());
+    assert!(!mls.is_routed_port(3));
+    assert_eq!(mls.switch().port_vlan(3), (1, false));
+}
+
+#[test]
+fn MultilayerSwitch_InvalidSvi_ReturnsError() {
+    // Arrange
+    let (_, mut mls, _) = svi_network();
+
+    // Act
+    let overlapping = mls.set_svi(30, [10, 0, 10, 254], [255, 255, 255, 0]);
+    let reserved = mls.set_svi(4064, [10, 0, 99, 1], [255, 255, 255, 0]);
+    let missing = mls.remove_svi(30);
+    let remaining: Vec<_> = (30..40)
+        .map(|vlan| mls.set_svi(vlan, [10, 0, vlan as u8, 1], [255, 255, 255, 0]))
+        .collect();
+
+    // Assert
+    assert_eq!(overlapping, Err("Network overlaps with another SVI."));
+    assert_eq!(reserved, Err("VLAN is not valid for an SVI."));
+    assert_eq!(missing, Err("VLAN has no SVI."));
+    assert_eq!(remaining.iter().filter(|r| r.is_ok()).count(), 6);
+    assert_eq!(remaining.last().unwrap(), &Err("No SVIs left."));
+    assert_eq!(mls.svis().len(), 8);
+}
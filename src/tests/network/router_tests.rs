@@ -6,6 +6,7 @@ use crate::{
         device::{cable::CableSimulator, router::Router},
         ethernet::ByteSerializable,
         ipv4::{interface::Ipv4Interface, IcmpFrame, IcmpType, Ipv4Frame, Ipv4Protocol},
+        log::{DropReason, LogEvent},
     },
 };
 
@@ -428,3 +429,227 @@ fn Route_PingAcrossRouter_ReceiveFrame() {
         )
     );
 }
+
+#[test]
+fn Igmp_HostJoinsAndLeavesGroup_RouterTracksMembership() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = Ipv4Interface::new(
+        mac_addr!(10),
+        [192, 168, 1, 2],
+        [255, 255, 255, 0],
+        Some([192, 168, 1, 1]),
+    );
+    let mut r = Router::from_seed(1);
+
+    r.enable_interface(0, [192, 168, 1, 1], [255, 255, 255, 0]);
+    r.connect(0, &mut i1);
+    r.enable_igmp(0).unwrap();
+
+    sim.add(i1.ethernet.port());
+    sim.adds(r.ports());
+
+    // Act
+    i1.join_group([239, 1, 1, 1]).unwrap();
+    sim.transmit();
+    r.route();
+    let joined = r.igmp_groups(0);
+
+    i1.leave_group([239, 1, 1, 1]).unwrap();
+    sim.transmit();
+    r.route();
+    let left = r.igmp_groups(0);
+
+    // Assert
+    assert_eq!(joined, vec![[239, 1, 1, 1]]);
+    assert!(left.is_empty());
+}
+
+#[test]
+fn Route_PeerShutsDownPort_RemovesRoutesUntilNoShutdown() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i2 = Ipv4Interface::new(
+        mac_addr!(2),
+        [192, 168, 2, 2],
+        [255, 255, 255, 0],
+        Some([192, 168, 2, 1]),
+    );
+    let mut r1 = Router::from_seed(3);
+    let mut r2 = Router::from_seed(12);
+
+    r2.enable_interface(0, i2.default_gateway.unwrap(), [255, 255, 255, 0]);
+    r2.connect(0, &mut i2);
+
+    r1.enable_interface(1, [10, 0, 0, 1], [255, 255, 255, 252]);
+    r2.enable_interface(1, [10, 0, 0, 2], [255, 255, 255, 252]);
+    r1.enable_rip(1).expect("Failed to enable RIP on r1");
+    r2.enable_rip(1).expect("Failed to enable RIP on r2");
+    r1.connect_router(1, &mut r2, 1)
+        .expect("Failed to connect r1 to r2");
+
+    sim.add(i2.ethernet.port());
+    sim.adds(r1.ports());
+    sim.adds(r2.ports());
+
+    for _ in 0..3 {
+        sim.transmit();
+        r1.route();
+        r2.route();
+    }
+    let learned = r1.routing_table().contains_key(&[192, 168, 2, 0]);
+
+    // Act
+    r2.set_shutdown(1, true);
+    r1.route(); // Carrier loss, no routing update needed
+    let after_shutdown = r1.routing_table();
+
+    r2.set_shutdown(1, false);
+    for _ in 0..3 {
+        sim.transmit();
+        r1.route();
+        r2.route();
+    }
+
+    // Assert
+    assert!(learned);
+    assert!(!r2.is_shutdown(1));
+    assert!(!after_shutdown.contains_key(&[192, 168, 2, 0]));
+    assert!(!after_shutdown.contains_key(&[10, 0, 0, 0]));
+    assert!(r1.routing_table().contains_key(&[192, 168, 2, 0]));
+    assert!(r1.routing_table().contains_key(&[10, 0, 0, 0]));
+}
+
+#[test]
+fn Log_EnableInterface_LogsConnectedRoute() {
+    // Arrange
+    let mut r = Router::from_seed(1);
+
+    // Act
+    r.enable_interface(2, [10, 0, 0, 1], [255, 255, 0, 0]);
+
+    // Assert
+    assert_eq!(
+        r.take_events(),
+        vec![(
+            Some(2),
+            LogEvent::RouteInstalled {
+                network: [10, 0, 0, 0],
+                mask: [255, 255, 0, 0],
+                via: None,
+                metric: 0
+            }
+        )]
+    );
+}
+
+#[test]
+fn Route_TtlExpires_DropsFrameAndReturnsTimeExceeded() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = Ipv4Interface::new(
+        mac_addr!(1),
+        [192, 168, 1, 2],
+        [255, 255, 255, 0],
+        Some([192, 168, 1, 1]),
+    );
+    let mut r = Router::from_seed(2);
+
+    r.enable_interface(0, [192, 168, 1, 1], [255, 255, 255, 0]);
+    r.enable_interface(1, [192, 168, 2, 1], [255, 255, 255, 0]);
+    r.connect(0, &mut i1);
+
+    sim.add(i1.ethernet.port());
+    sim.adds(r.ports());
+    r.take_events();
+
+    // Act
+    i1.sendv(
+        i1.ip_address,
+        [192, 168, 2, 2],
+        None,
+        1,
+        vec![1],
+        Ipv4Protocol::Test,
+    )
+    .unwrap(); // ---- i1 -> r ARP
+    sim.transmit();
+
+    r.route();
+    sim.transmit();
+
+    i1.receive(); // ---- i1 -> r send frame
+    sim.transmit();
+
+    r.route();
+    sim.transmit();
+
+    let i1_data = i1.receive();
+    let events = r.take_events();
+
+    // Assert
+    assert_eq!(
+        i1_data,
+        vec![Ipv4Frame::new(
+            i1.default_gateway.unwrap(),
+            i1.ip_address,
+            64,
+            IcmpFrame::time_exceeded(0, vec![]).to_bytes(),
+            Ipv4Protocol::Icmp,
+        )]
+    );
+    assert!(events.contains(&(
+        Some(0),
+        LogEvent::Dropped {
+            reason: DropReason::TtlExpired,
+            destination: Some([192, 168, 2, 2])
+        }
+    )));
+    assert!(events.contains(&(
+        Some(0),
+        LogEvent::ArpLearned {
+            ip: i1.ip_address,
+            mac: i1.ethernet.mac_address
+        }
+    )));
+}
+
+#[test]
+fn Log_RouteDoesNotExist_LogsDrop() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = Ipv4Interface::new(
+        mac_addr!(1),
+        [192, 168, 1, 2],
+        [255, 255, 255, 0],
+        Some([192, 168, 1, 1]),
+    );
+    let mut r = Router::from_seed(2);
+
+    r.enable_interface(0, [192, 168, 1, 1], [255, 255, 255, 0]);
+    r.connect(0, &mut i1);
+
+    sim.add(i1.ethernet.port());
+    sim.adds(r.ports());
+
+    // Act
+    i1.send_t([192, 168, 2, 1], 1);
+    sim.transmit();
+
+    r.route();
+    sim.transmit();
+
+    i1.receive();
+    sim.transmit();
+
+    r.route();
+
+    // Assert
+    assert!(r.take_events().contains(&(
+        Some(0),
+        LogEvent::Dropped {
+            reason: DropReason::NoRoute,
+            destination: Some([192, 168, 2, 1])
+        }
+    )));
+}
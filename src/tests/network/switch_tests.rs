@@ -3,10 +3,18 @@
 use std::time::Duration;
 
 use crate::network::device::cable::CableSimulator;
-use crate::network::device::switch::{BpduFrame, Switch};
-use crate::network::ethernet::{interface::*, ByteSerializable, EtherType, EthernetFrame};
-use crate::tick::{Tickable, TimeProvider};
-use crate::{eth2, eth2_data, mac_addr, mac_bpdu_addr, mac_broadcast_addr};
+use crate::network::device::switch::{
+    BpduFrame, LoadBalance, MirrorDirection, StormAction, StpRole, StpState, Switch, TrafficClass,
+    ViolationMode, CIST,
+};
+use crate::network::ethernet::{
+    interface::*, ByteSerializable, EtherType, Ethernet802_3Frame, EthernetFrame,
+};
+use crate::network::ipv4::Ipv4Protocol;
+use crate::network::log::{DropReason, LogEvent};
+use crate::tests::network::fixtures::{desktops, ipv4_desktops, network};
+use crate::tick::{Clock, ClockMode, Tickable};
+use crate::{eth2, eth2_data, eth802_3_data, mac_addr, mac_bpdu_addr, mac_broadcast_addr};
 
 #[test]
 pub fn Forward_ReceiveNotInTable_FloodsFrame() {
@@ -199,7 +207,7 @@ fn SpanningTree_Init_SendsBpdus() {
 
     assert_eq!(e802_3.destination_address, mac_bpdu_addr!());
 
-    let bpdu = match BpduFrame::from_bytes(e802_3.data.clone()) {
+    let bpdu = match BpduFrame::from_frame(e802_3) {
         Ok(bpdu) => bpdu,
         Err(_) => panic!("Expected BpduFrame"),
     };
@@ -207,8 +215,6 @@ fn SpanningTree_Init_SendsBpdus() {
     assert_eq!(
         bpdu,
         BpduFrame::new(
-            mac_bpdu_addr!(),
-            switch.mac_address,
             false,
             BpduFrame::flags(true, true, 0, false, true, false),
             switch.bid(),
@@ -893,7 +899,61 @@ fn SpanningTree_RemoveRedundancy_UnblocksPort() {
     assert!(s3.discarding_ports().is_empty());
 }
 
-#[ignore = "Messes with the global TimeProvider instance"]
+#[test]
+fn SpanningTree_PeerShutsDownRootPort_ReconvergesWithoutMissedHellos() {
+    // Arrange
+    let (mut sim, mut s1, mut s2, mut s3, _, _, (s1_s2_port, _, s2_s1_port, s2_s3_port, _, _)) =
+        stp_complete_network();
+
+    // Act
+    s1.set_shutdown(s1_s2_port, true); // s2 is never told, it only loses the carrier
+
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+        s3.forward();
+    }
+
+    // Assert
+    assert!(s1.is_shutdown(s1_s2_port));
+    assert!(!s1.is_port_up(s1_s2_port));
+    assert!(!s2.is_shutdown(s2_s1_port));
+    assert_eq!(s2.root_bid(), s1.bid());
+    assert_eq!(s2.root_port(), Some(s2_s3_port));
+    assert!(s2.discarding_ports().is_empty());
+}
+
+#[test]
+fn SpanningTree_NoShutdown_PortRejoinsSpanningTree() {
+    // Arrange
+    let (mut sim, mut s1, mut s2, mut s3, _, _, (s1_s2_port, _, _, _, _, _)) =
+        stp_complete_network();
+
+    s1.set_shutdown(s1_s2_port, true);
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+        s3.forward();
+    }
+
+    // Act
+    s1.set_shutdown(s1_s2_port, false);
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+        s3.forward();
+    }
+
+    // Assert
+    assert!(!s1.is_shutdown(s1_s2_port));
+    assert!(s1.is_port_up(s1_s2_port));
+    assert!(s1.designated_ports().contains(&s1_s2_port));
+    assert_eq!(s2.root_bid(), s1.bid());
+}
+
 #[test]
 fn SpanningTree_AfterTick_MaintainsTopology() {
     let (
@@ -906,17 +966,15 @@ fn SpanningTree_AfterTick_MaintainsTopology() {
         (s1_s2_port, s1_s3_port, s2_s1_port, s2_s3_port, s3_s1_port, s3_s2_port),
     ) = stp_complete_network();
 
-    {
-        let mut tp = TimeProvider::instance().lock().unwrap();
-        tp.freeze();
-    }
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    s1.set_clock(&clock);
+    s2.set_clock(&clock);
+    s3.set_clock(&clock);
 
     // Act
     for _ in 0..4 {
-        {
-            let mut tp = TimeProvider::instance().lock().unwrap();
-            tp.advance(Duration::from_secs(2));
-        }
+        clock.advance(Duration::from_secs(2));
 
         sim.tick();
         s1.tick();
@@ -942,3 +1000,1224 @@ fn SpanningTree_AfterTick_MaintainsTopology() {
     assert!(!s3.designated_ports().contains(&s3_s2_port));
     assert!(s3.discarding_ports().contains(&s3_s2_port));
 }
+
+#[test]
+fn SpanningTree_InitPortFast_ForwardsEndDevices() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    let mut switch = Switch::from_seed(3, 1);
+
+    let i1_s_port = 0;
+    let i2_s_port = 1;
+    switch.connect(i1_s_port, &mut i1);
+    switch.connect(i2_s_port, &mut i2);
+    switch.set_portfast(i1_s_port, true);
+    switch.set_portfast(i2_s_port, true);
+
+    sim.adds(vec![i1.port(), i2.port()]);
+    sim.adds(switch.ports());
+
+    // Act
+    switch.init_stp();
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    let i2_data = i2.receive_eth2();
+
+    // Assert
+    assert_eq!(i2_data.len(), 1);
+    assert!(switch.is_port_up(i1_s_port));
+    assert!(switch.is_port_up(i2_s_port));
+    assert!(!switch.is_port_up(2));
+}
+
+#[test]
+fn SpanningTree_BpduGuardReceivesBpdu_ErrDisablesPort() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+    let mut i1 = EthernetInterface::new(mac_addr!(100));
+
+    let s1_s2_port = 0;
+    let s2_s1_port = 0;
+    let s1_i1_port = 1;
+    s1.connect_switch(s1_s2_port, &mut s2, s2_s1_port);
+    s1.connect(s1_i1_port, &mut i1);
+    s1.set_portfast(s1_s2_port, true);
+    s1.set_bpdu_guard(s1_s2_port, true);
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+    sim.add(i1.port());
+
+    // Act
+    s1.init_stp();
+    s2.init_stp();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Assert
+    assert!(s1.is_err_disabled(s1_s2_port));
+    assert!(!s1.is_port_up(s1_s2_port));
+    assert!(!s1.is_err_disabled(s1_i1_port));
+    assert!(s1.is_root_bridge());
+}
+
+#[test]
+fn SpanningTree_RootGuardReceivesSuperiorBpdu_BlocksPort() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 2);
+    let mut s2 = Switch::from_seed(33, 1); // Superior bridge
+
+    let s1_s2_port = 0;
+    let s2_s1_port = 0;
+    s1.connect_switch(s1_s2_port, &mut s2, s2_s1_port);
+    s1.set_root_guard(s1_s2_port, true);
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    // Act
+    s1.init_stp();
+    s2.init_stp();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    s1.finish_init_stp();
+    s2.finish_init_stp();
+
+    // Assert
+    assert!(s1.is_root_inconsistent(s1_s2_port));
+    assert!(!s1.is_port_up(s1_s2_port));
+    assert!(s1.is_root_bridge());
+    assert!(s1.root_port().is_none());
+}
+
+#[test]
+fn SpanningTree_ErrDisableRecoveryAfterTick_RecoversPort() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    let s1_s2_port = 0;
+    let s2_s1_port = 0;
+    s1.connect_switch(s1_s2_port, &mut s2, s2_s1_port);
+    s1.set_portfast(s1_s2_port, true);
+    s1.set_bpdu_guard(s1_s2_port, true);
+    s1.set_errdisable_recovery(Some(30));
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    s1.set_clock(&clock);
+    s2.set_clock(&clock);
+
+    s1.init_stp();
+    s2.init_stp();
+    sim.transmit();
+    s1.forward();
+    assert!(s1.is_err_disabled(s1_s2_port));
+
+    // Act
+    clock.advance(Duration::from_secs(30));
+    s1.tick();
+
+    // Assert
+    assert!(!s1.is_err_disabled(s1_s2_port));
+    assert!(s1.is_port_up(s1_s2_port));
+}
+
+#[test]
+fn SpanningTree_ErrDisableRecoveryTurnedOffWhilePending_KeepsPortDown() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    let s1_s2_port = 0;
+    let s2_s1_port = 0;
+    s1.connect_switch(s1_s2_port, &mut s2, s2_s1_port);
+    s1.set_portfast(s1_s2_port, true);
+    s1.set_bpdu_guard(s1_s2_port, true);
+    s1.set_errdisable_recovery(Some(30));
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    s1.set_clock(&clock);
+    s2.set_clock(&clock);
+
+    s1.init_stp();
+    s2.init_stp();
+    sim.transmit();
+    s1.forward();
+    assert!(s1.is_err_disabled(s1_s2_port));
+
+    // Act
+    clock.advance(Duration::from_secs(10));
+    s1.set_errdisable_recovery(None);
+    clock.advance(Duration::from_secs(20));
+    s1.tick();
+    let off = s1.is_err_disabled(s1_s2_port);
+
+    s1.set_errdisable_recovery(Some(30));
+    clock.advance(Duration::from_secs(20));
+    s1.tick();
+    let restarted = s1.is_err_disabled(s1_s2_port);
+
+    // Assert
+    assert!(off);
+    assert!(restarted); // The recovery restarts when turned back on, instead of firing the old timer
+    assert!(!s1.is_port_up(s1_s2_port));
+}
+
+#[test]
+fn Forward_DifferentAccessVlans_DoesNotFloodAcrossVlans() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    let mut i3 = EthernetInterface::new(mac_addr!(3));
+    let mut switch = Switch::from_seed(4, 1);
+
+    switch.connect(0, &mut i1);
+    switch.connect(1, &mut i2);
+    switch.connect(2, &mut i3);
+    switch.set_access_vlan(0, 10);
+    switch.set_access_vlan(1, 10);
+    switch.set_access_vlan(2, 20);
+
+    sim.adds(vec![i1.port(), i2.port(), i3.port()]);
+    sim.adds(switch.ports());
+
+    // Act
+    i1.send(mac_broadcast_addr!(), EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(i2.receive().len(), 1);
+    assert!(i3.receive().is_empty());
+    assert_eq!(switch.mac_table().get(&(i1.mac_address, 10)), Some(&0));
+    assert_eq!(switch.mac_table().get(&(i1.mac_address, 20)), None);
+}
+
+#[test]
+fn Forward_TrunkPortNonNativeVlan_TagsFrame() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    let mut switch = Switch::from_seed(4, 1);
+
+    switch.connect(0, &mut i1);
+    switch.connect(1, &mut i2);
+    switch.set_access_vlan(0, 10);
+    switch.set_trunk(1, true);
+
+    sim.adds(vec![i1.port(), i2.port()]);
+    sim.adds(switch.ports());
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    let i2_data = i2.receive();
+
+    // Assert
+    assert_eq!(i2_data.len(), 1);
+    match i2_data.into_iter().next().unwrap() {
+        EthernetFrame::Ethernet2(f) => {
+            assert_eq!(f.ether_type, EtherType::Vlan);
+            let (vlan, f) = f.untag();
+            assert_eq!(vlan, Some(10));
+            assert_eq!(f.ether_type, EtherType::Debug);
+            assert_eq!(f.data, eth2_data!(1));
+        }
+        _ => panic!("Expected an Ethernet II frame"),
+    }
+}
+
+#[test]
+fn Forward_TwoSwitchesOverTrunk_KeepsVlansSeparate() {
+    // Arrange
+    //
+    // i1 (VLAN 10) - s1 ==trunk== s2 - i2 (VLAN 10)
+    //                              \
+    //                               i3 (VLAN 1)
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+    let mut i1 = EthernetInterface::new(mac_addr!(100));
+    let mut i2 = EthernetInterface::new(mac_addr!(200));
+    let mut i3 = EthernetInterface::new(mac_addr!(300));
+
+    s1.connect_switch(0, &mut s2, 0);
+    s1.set_trunk(0, true);
+    s2.set_trunk(0, true);
+
+    s1.connect(1, &mut i1);
+    s1.set_access_vlan(1, 10);
+    s2.connect(1, &mut i2);
+    s2.set_access_vlan(1, 10);
+    s2.connect(2, &mut i3);
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+    sim.adds(vec![i1.port(), i2.port(), i3.port()]);
+
+    // Act
+    i1.send(mac_broadcast_addr!(), EtherType::Debug, eth2_data!(1));
+    for _ in 0..2 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    sim.transmit();
+
+    let i2_data = i2.receive();
+
+    // Assert
+    assert_eq!(i2_data.len(), 1);
+    assert_eq!(
+        i2_data[0],
+        eth2!(
+            mac_broadcast_addr!(),
+            i1.mac_address,
+            eth2_data!(1),
+            EtherType::Debug
+        )
+    );
+    assert!(i3.receive().is_empty());
+    assert_eq!(s2.mac_table().get(&(i1.mac_address, 10)), Some(&0));
+}
+
+#[test]
+fn SpanningTree_MstInstancesWithDifferentRoots_LoadShareRedundantLinks() {
+    // Arrange
+    //
+    // CIST (VLAN 1)        MSTI 1 (VLAN 10)
+    //      s1                    s1
+    //     /  \                     \
+    //   s2 -x- s3            s2 -x- s3 (root)
+    //
+    let (
+        mut sim,
+        mut s1,
+        mut s2,
+        mut s3,
+        _,
+        _,
+        (s1_s2_port, _, s2_s1_port, s2_s3_port, s3_s1_port, s3_s2_port),
+    ) = complete_network();
+
+    for s in [&mut s1, &mut s2, &mut s3] {
+        s.set_mst_region("region", 1);
+        s.map_mst_instance(1, &[10]).unwrap();
+        s.set_trunk(0, true);
+        s.set_trunk(1, true);
+    }
+    s3.set_instance_priority(1, 0).unwrap();
+
+    // Act
+    s1.init_stp();
+    s2.init_stp();
+    s3.init_stp();
+
+    for _ in 0..10 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+        s3.forward();
+    }
+
+    s1.finish_init_stp();
+    s2.finish_init_stp();
+    s3.finish_init_stp();
+
+    // Assert
+    assert!(s1.is_root_bridge());
+    assert_eq!(s3.root_port(), Some(s3_s1_port));
+    assert!(s3.discarding_ports().contains(&s3_s2_port));
+
+    assert_eq!(s1.instance_root_bid(1), s3.instance_root_bid(1));
+    assert_eq!(s2.instance_root_port(1), Some(s2_s3_port));
+    assert!(s2.instance_discarding_ports(1).contains(&s2_s1_port));
+    assert!(s3.instance_discarding_ports(1).is_empty());
+    assert!(s1.instance_designated_ports(1).contains(&s1_s2_port));
+
+    assert!(!s3.is_port_forwarding(s3_s2_port, 1));
+    assert!(s3.is_port_forwarding(s3_s2_port, 10));
+    assert!(!s2.is_port_forwarding(s2_s1_port, 10));
+}
+
+#[test]
+fn SpanningTree_NeighborOutsideRegion_UsesCistForAllVlans() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+    s1.connect_switch(0, &mut s2, 0);
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    s1.set_mst_region("region", 1);
+    s1.map_mst_instance(1, &[10]).unwrap();
+    s2.set_mst_region("other", 1);
+    s2.map_mst_instance(1, &[10]).unwrap();
+    s2.set_instance_priority(1, 0).unwrap();
+
+    // Act
+    s1.init_stp();
+    s2.init_stp();
+
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Assert
+    assert!(s1.is_root_bridge());
+    assert_eq!(s2.root_port(), Some(0));
+    assert_ne!(s1.instance_root_bid(1), s2.instance_root_bid(1)); // MSTI BPDUs from another region are ignored
+    assert_eq!(s2.is_port_forwarding(0, 10), s2.is_port_forwarding(0, 1));
+}
+
+#[test]
+fn Forward_RstpDisabledReceives8023Frame_FloodsFrame() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    let mut i3 = EthernetInterface::new(mac_addr!(3));
+    let mut switch = Switch::from_seed(4, 1);
+
+    switch.connect(0, &mut i1);
+    switch.connect(1, &mut i2);
+    switch.connect(2, &mut i3);
+
+    sim.adds(vec![i1.port(), i2.port(), i3.port()]);
+    sim.adds(switch.ports());
+
+    let mut frame = Ethernet802_3Frame::new(mac_bpdu_addr!(), i1.mac_address, eth802_3_data!(1));
+    frame.dsap = 0xAA;
+    frame.ssap = 0xAA;
+    frame.update_fcs();
+
+    // Act
+    i1.send8023_frame(&frame);
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    let i2_data = i2.receive();
+    let i3_data = i3.receive();
+
+    // Assert
+    assert_eq!(i2_data.len(), 1);
+    assert_eq!(i2_data[0], EthernetFrame::Ethernet802_3(frame.clone()));
+    assert_eq!(i3_data.len(), 1);
+    assert_eq!(i3_data[0], EthernetFrame::Ethernet802_3(frame));
+    assert_eq!(switch.mac_table().get(&(i1.mac_address, 1)), Some(&0));
+}
+
+#[test]
+fn SpanningTree_LoopThroughSwitchWithoutRstp_BlocksPort() {
+    // Arrange
+    //
+    //  (DP) s1 (BP)
+    //     \    /
+    //       s2      (RSTP disabled)
+    //
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    s1.connect_switch(0, &mut s2, 0);
+    s1.connect_switch(1, &mut s2, 1);
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    s1.set_clock(&clock);
+    s2.set_clock(&clock);
+
+    // Act
+    s1.init_stp();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    s1.finish_init_stp();
+
+    // Past 3 hello intervals, the looped BPDUs keep the port blocked
+    for _ in 0..30 {
+        clock.advance(Duration::from_secs(1));
+        s1.tick();
+        s2.tick();
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Assert
+    assert!(s1.is_root_bridge());
+    assert!(s1.is_port_up(0));
+    assert_eq!(s1.discarding_ports(), vec![1]);
+}
+
+#[test]
+fn SpanningTree_PortStpDisabled_DropsBpdusAndForwards() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    s1.connect_switch(0, &mut s2, 0);
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    s1.set_port_stp(0, false);
+
+    // Act
+    s1.init_stp();
+    s2.init_stp();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Assert
+    assert!(!s1.is_port_stp_enabled(0));
+    assert!(s1.is_port_up(0));
+    assert!(!s1.ports()[0].borrow().has_outgoing());
+    assert!(s1.is_root_bridge());
+    assert!(s2.is_root_bridge()); // Never heard from s1
+}
+
+#[test]
+fn PortSecurity_ExceedsMaximumRestrict_DropsFrameAndCountsViolation() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_port_security(0, true);
+    switch
+        .set_port_security_violation(0, ViolationMode::Restrict)
+        .unwrap();
+
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+    i2.receive();
+
+    // Act
+    i1.sendv(
+        mac_addr!(50),
+        i2.mac_address,
+        EtherType::Debug,
+        eth2_data!(2),
+    ); // Second address on the port
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert!(i2.receive().is_empty());
+    assert_eq!(switch.security_violations(0), 1);
+    assert!(!switch.is_err_disabled(0));
+    assert!(!switch.mac_table().contains_key(&(mac_addr!(50), 1)));
+}
+
+#[test]
+fn PortSecurity_ExceedsMaximumShutdown_ErrDisablesPort() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_port_security(0, true);
+    switch.add_secure_mac(0, i1.mac_address).unwrap();
+
+    // Act
+    i1.sendv(
+        mac_addr!(50),
+        i2.mac_address,
+        EtherType::Debug,
+        eth2_data!(1),
+    );
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert!(i2.receive().is_empty());
+    assert_eq!(switch.security_violations(0), 1);
+    assert!(switch.is_err_disabled(0));
+}
+
+#[test]
+fn PortSecurity_SpoofedSecureMacOnOtherPort_DropsFrame() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut i3]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_port_security(0, true);
+    switch.add_secure_mac(0, i1.mac_address).unwrap();
+
+    // Act
+    i3.sendv(
+        i1.mac_address,
+        i2.mac_address,
+        EtherType::Debug,
+        eth2_data!(1),
+    );
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert!(i1.receive().is_empty());
+    assert!(i2.receive().is_empty());
+    assert!(!switch.mac_table().contains_key(&(i1.mac_address, 1)));
+    assert_eq!(switch.security_violations(2), 0); // Port 2 has no port security
+}
+
+#[test]
+fn PortSecurity_SecureMacOnOtherVlan_Forwards() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut i3]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_port_security(0, true);
+    switch.add_secure_mac(0, i1.mac_address).unwrap();
+    switch.set_access_vlan(1, 2);
+    switch.set_access_vlan(2, 2);
+
+    // Act
+    i3.sendv(
+        i1.mac_address,
+        i2.mac_address,
+        EtherType::Debug,
+        eth2_data!(1),
+    );
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert!(i1.receive().is_empty());
+    assert_eq!(i2.receive().len(), 1);
+    assert_eq!(switch.mac_table().get(&(i1.mac_address, 2)), Some(&2));
+}
+
+#[test]
+fn PortSecurity_StickyLearning_ConvertsAddressesToSecure() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_port_security(0, true);
+    switch.set_port_security_maximum(0, 2).unwrap();
+
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    switch.forward();
+
+    // Act
+    switch.set_port_security_sticky(0, true).unwrap();
+    i1.sendv(
+        mac_addr!(50),
+        i2.mac_address,
+        EtherType::Debug,
+        eth2_data!(2),
+    );
+    sim.transmit();
+    switch.forward();
+
+    // Assert
+    let security = switch.port_security(0).unwrap();
+    assert_eq!(security.secure_macs, vec![i1.mac_address, mac_addr!(50)]);
+    assert_eq!(switch.security_violations(0), 0);
+}
+
+/// Two switches connected by two links, both added to port-channel 1 on each side.
+fn port_channel_network() -> (CableSimulator, Switch, Switch) {
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    s1.connect_switch(0, &mut s2, 0);
+    s1.connect_switch(1, &mut s2, 1);
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    for port in 0..2 {
+        s1.set_channel_group(port, Some(1));
+        s2.set_channel_group(port, Some(1));
+    }
+    (sim, s1, s2)
+}
+
+#[test]
+fn LinkAggregation_LacpduExchange_BundlesPorts() {
+    // Arrange
+    let (mut sim, mut s1, mut s2) = port_channel_network();
+
+    // Act
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Assert
+    assert_eq!(s1.channel_members(1), vec![0, 1]);
+    assert_eq!(s2.channel_members(1), vec![0, 1]);
+    assert_eq!(s1.port_channel(1), Some(1));
+}
+
+#[test]
+fn LinkAggregation_BundledWithRstp_DoesNotBlockMembers() {
+    // Arrange
+    let (mut sim, mut s1, mut s2) = port_channel_network();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Act
+    s1.init_stp();
+    s2.init_stp();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    s1.finish_init_stp();
+    s2.finish_init_stp();
+
+    // Assert
+    assert!(s1.is_root_bridge());
+    assert_eq!(s2.root_port(), Some(0));
+    assert!(s1.discarding_ports().is_empty());
+    assert!(s2.discarding_ports().is_empty());
+}
+
+#[test]
+fn LinkAggregation_MemberDisconnected_RemovesPortFromChannel() {
+    // Arrange
+    let (mut sim, mut s1, mut s2) = port_channel_network();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+
+    // Act
+    s1.disconnect(1);
+
+    // Assert
+    assert!(!s1.is_bundled(1));
+    assert_eq!(s1.channel_members(1), vec![0]);
+}
+
+#[test]
+fn LinkAggregation_LoadBalanceSrcDstIp_SpreadsFlowsAcrossMembers() {
+    // Arrange
+    let (mut sim, mut s1, mut s2) = port_channel_network();
+    let mut i1 = EthernetInterface::new(mac_addr!(100));
+    s1.connect(2, &mut i1);
+    sim.add(i1.port());
+    s1.set_load_balance(LoadBalance::SrcDstIp);
+
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    sim.transmit();
+
+    // Act
+    for host in 0..4 {
+        let mut ip_header = vec![0; 20];
+        ip_header[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        ip_header[16..20].copy_from_slice(&[10, 0, 0, host]);
+        i1.send(mac_addr!(200), EtherType::Ipv4, ip_header);
+    }
+    sim.transmit();
+    s1.forward();
+
+    // Assert
+    let ipv4_frames = |port: usize| {
+        s1.ports()[port]
+            .borrow()
+            .sniff()
+            .1
+            .into_iter()
+            .filter(|f| matches!(EthernetFrame::from_bytes(f.clone()), Ok(EthernetFrame::Ethernet2(f)) if f.ether_type == EtherType::Ipv4))
+            .count()
+    };
+    assert_eq!(ipv4_frames(0), 2);
+    assert_eq!(ipv4_frames(1), 2);
+}
+
+#[test]
+fn Monitor_RxSource_CopiesReceivedFramesToDestination() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut sniffer]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 3]);
+    switch
+        .set_monitor_session(&[0], MirrorDirection::Rx, 3)
+        .unwrap();
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    i2.send(i1.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    let sniffed = sniffer.receive_eth2();
+
+    // Assert
+    assert_eq!(sniffed.len(), 1);
+    assert_eq!(sniffed[0].source_address, i1.mac_address);
+    assert_eq!(sniffed[0].destination_address, i2.mac_address);
+    assert_eq!(i2.receive_eth2().len(), 1);
+}
+
+#[test]
+fn Monitor_TxSource_CopiesSentFramesToDestination() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut sniffer]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 3]);
+    switch
+        .set_monitor_session(&[1], MirrorDirection::Tx, 3)
+        .unwrap();
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    i2.send(i1.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    let sniffed = sniffer.receive_eth2();
+
+    // Assert
+    assert_eq!(sniffed.len(), 1);
+    assert_eq!(sniffed[0].source_address, i1.mac_address);
+}
+
+#[test]
+fn Monitor_DestinationPort_DropsIngressAndIsNotFlooded() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut sniffer]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 3]);
+    switch
+        .set_monitor_session(&[1], MirrorDirection::Rx, 3)
+        .unwrap();
+
+    // Act
+    sniffer.send(mac_broadcast_addr!(), EtherType::Debug, eth2_data!(1));
+    i1.send(mac_broadcast_addr!(), EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(i1.receive().len(), 0);
+    assert_eq!(i2.receive().len(), 1);
+    assert_eq!(sniffer.receive().len(), 0);
+    assert!(!switch.mac_table().values().any(|p| *p == 3));
+}
+
+#[test]
+fn Monitor_DestinationIsSource_ReturnsError() {
+    // Arrange
+    let (_, mut switch, _) = network(Switch::from_seed(4, 1), desktops(), [0, 1, 3]);
+
+    // Act
+    let result = switch.set_monitor_session(&[0, 3], MirrorDirection::Both, 3);
+
+    // Assert
+    assert!(result.is_err());
+    assert!(switch.monitor_session().is_none());
+}
+
+#[test]
+fn StormControl_BroadcastLoopWithoutStp_StopsStorm() {
+    // Arrange
+    //
+    //  i1 - s1 = s2      (two links, RSTP disabled)
+    //
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(100));
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    s1.connect(2, &mut i1);
+    s1.connect_switch(0, &mut s2, 0);
+    s1.connect_switch(1, &mut s2, 1);
+    sim.add(i1.port());
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    for port in 0..2 {
+        s1.set_storm_control(port, TrafficClass::Broadcast, Some(5));
+    }
+
+    // Act
+    i1.send(mac_broadcast_addr!(), EtherType::Debug, eth2_data!(1));
+    for _ in 0..20 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    sim.transmit();
+
+    // Assert
+    assert!(s1.storm_drops(0) + s1.storm_drops(1) > 0);
+    assert!(s1.ports().iter().all(|p| !p.borrow().has_outgoing()));
+    assert!(s2.ports().iter().all(|p| !p.borrow().has_outgoing()));
+}
+
+#[test]
+fn StormControl_ExceedsThresholdShutdown_ErrDisablesPort() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_storm_control(0, TrafficClass::Multicast, Some(2));
+    switch.set_storm_control_action(0, StormAction::Shutdown);
+
+    // Act
+    for i in 0..3 {
+        i1.send(
+            [0x01, 0x00, 0x5E, 0x00, 0x00, 0x01],
+            EtherType::Debug,
+            eth2_data!(i),
+        );
+    }
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert!(switch.is_err_disabled(0));
+    assert_eq!(switch.storm_count(0, TrafficClass::Multicast), 3);
+    assert_eq!(switch.storm_drops(0), 1);
+    assert_eq!(i2.receive().len(), 2);
+}
+
+#[test]
+fn StormControl_NextSecond_ResetsCounts() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    switch.set_clock(&clock);
+    switch.set_storm_control(0, TrafficClass::UnknownUnicast, Some(1));
+
+    for i in 0..2 {
+        i1.send(mac_addr!(50), EtherType::Debug, eth2_data!(i));
+    }
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+    assert_eq!(i2.receive().len(), 1);
+
+    // Act
+    clock.advance(Duration::from_secs(1));
+    switch.tick();
+    i1.send(mac_addr!(50), EtherType::Debug, eth2_data!(3));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(switch.storm_count(0, TrafficClass::UnknownUnicast), 1);
+    assert_eq!(i2.receive().len(), 1);
+}
+
+#[test]
+fn StormControl_NoThreshold_DoesNotCount() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    switch.set_clock(&clock);
+
+    // Act
+    for i in 0..3 {
+        i1.send(mac_addr!(50), EtherType::Debug, eth2_data!(i));
+    }
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+    clock.advance(Duration::from_secs(2));
+    switch.tick();
+
+    // Assert
+    assert_eq!(switch.storm_count(0, TrafficClass::UnknownUnicast), 0);
+    assert_eq!(switch.storm_drops(0), 0);
+    assert_eq!(i2.receive().len(), 3);
+}
+
+#[test]
+fn IgmpSnooping_GroupWithMember_ForwardsOnlyToMember() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut i3]) =
+        network(Switch::from_seed(4, 1), ipv4_desktops(), [0, 1, 2]);
+    i2.join_group([239, 1, 1, 1]).unwrap();
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Act
+    i1.multicast_to([239, 1, 1, 1], vec![1], Ipv4Protocol::Test);
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(
+        switch.igmp_groups().get(&([239, 1, 1, 1], 1)),
+        Some(&[1].into())
+    );
+    assert_eq!(i2.receive().len(), 1);
+    assert!(i3.ethernet.receive().is_empty());
+}
+
+#[test]
+fn IgmpSnooping_LinkLocalMulticast_FloodsFrame() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut i3]) =
+        network(Switch::from_seed(4, 1), ipv4_desktops(), [0, 1, 2]);
+
+    // Act
+    i1.multicast_to([224, 0, 0, 5], vec![1], Ipv4Protocol::Test);
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(i2.ethernet.receive().len(), 1);
+    assert_eq!(i3.ethernet.receive().len(), 1);
+}
+
+#[test]
+fn IgmpSnooping_MemberLeaves_StopsForwardingGroup() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut i3]) =
+        network(Switch::from_seed(4, 1), ipv4_desktops(), [0, 1, 2]);
+    i2.join_group([239, 1, 1, 1]).unwrap();
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Act
+    i2.leave_group([239, 1, 1, 1]).unwrap();
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    i1.multicast_to([239, 1, 1, 1], vec![1], Ipv4Protocol::Test);
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert!(switch.igmp_groups().is_empty());
+    assert!(i2.ethernet.receive().is_empty());
+    assert!(i3.ethernet.receive().is_empty());
+}
+
+#[test]
+fn IgmpSnooping_Disabled_FloodsMulticast() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, mut i2, mut i3]) =
+        network(Switch::from_seed(4, 1), ipv4_desktops(), [0, 1, 2]);
+    switch.set_igmp_snooping(false);
+
+    // Act
+    i1.multicast_to([239, 1, 1, 1], vec![1], Ipv4Protocol::Test);
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+
+    // Assert
+    assert_eq!(i2.ethernet.receive().len(), 1);
+    assert_eq!(i3.ethernet.receive().len(), 1);
+}
+
+#[test]
+fn Log_ReceiveNotInTable_LogsLearnedAndFlooded() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut i1 = EthernetInterface::new(mac_addr!(1));
+    let mut i2 = EthernetInterface::new(mac_addr!(2));
+    let mut switch = Switch::from_seed(4, 1);
+
+    switch.connect(0, &mut i1);
+    switch.connect(1, &mut i2);
+
+    sim.adds(vec![i1.port(), i2.port()]);
+    sim.adds(switch.ports());
+
+    // Act
+    i1.send(i2.mac_address, EtherType::Debug, eth2_data!(1));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+    let flooded = switch.take_events();
+
+    i2.send(i1.mac_address, EtherType::Debug, eth2_data!(2));
+    sim.transmit();
+    switch.forward();
+    sim.transmit();
+    let forwarded = switch.take_events();
+
+    // Assert
+    assert_eq!(
+        flooded,
+        vec![
+            (
+                Some(0),
+                LogEvent::MacLearned {
+                    mac: i1.mac_address,
+                    vlan: 1
+                }
+            ),
+            (
+                Some(0),
+                LogEvent::Flooded {
+                    destination: i2.mac_address,
+                    vlan: 1,
+                    class: TrafficClass::UnknownUnicast
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        forwarded,
+        vec![(
+            Some(1),
+            LogEvent::MacLearned {
+                mac: i2.mac_address,
+                vlan: 1
+            }
+        )]
+    );
+}
+
+#[test]
+fn Log_PortSecurityViolation_LogsDrop() {
+    // Arrange
+    let (mut sim, mut switch, [mut i1, i2, _]) =
+        network(Switch::from_seed(4, 1), desktops(), [0, 1, 2]);
+    switch.set_port_security(0, true);
+    switch
+        .set_port_security_violation(0, ViolationMode::Protect)
+        .unwrap();
+    switch.add_secure_mac(0, i1.mac_address).unwrap();
+
+    // Act
+    i1.sendv(
+        mac_addr!(50),
+        i2.mac_address,
+        EtherType::Debug,
+        eth2_data!(1),
+    );
+    sim.transmit();
+    switch.forward();
+
+    // Assert
+    assert_eq!(
+        switch.take_events(),
+        vec![(
+            Some(0),
+            LogEvent::Dropped {
+                reason: DropReason::PortSecurity,
+                destination: None
+            }
+        )]
+    );
+}
+
+#[test]
+fn Log_LoopBlocked_LogsStpChange() {
+    // Arrange
+    let mut sim = CableSimulator::default();
+    let mut s1 = Switch::from_seed(1, 1);
+    let mut s2 = Switch::from_seed(33, 2);
+
+    s1.connect_switch(0, &mut s2, 0);
+    s1.connect_switch(1, &mut s2, 1);
+
+    sim.adds(s1.ports());
+    sim.adds(s2.ports());
+
+    // Act
+    s1.init_stp();
+    for _ in 0..3 {
+        sim.transmit();
+        s1.forward();
+        s2.forward();
+    }
+    s1.finish_init_stp();
+    s1.tick();
+
+    // Assert
+    let changes: Vec<_> = s1
+        .take_events()
+        .into_iter()
+        .filter(|(_, event)| matches!(event, LogEvent::StpChanged { .. }))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![
+            (
+                Some(0),
+                LogEvent::StpChanged {
+                    instance: CIST,
+                    role: Some(StpRole::Designated),
+                    state: StpState::Forwarding
+                }
+            ),
+            (
+                Some(1),
+                LogEvent::StpChanged {
+                    instance: CIST,
+                    role: Some(StpRole::Backup),
+                    state: StpState::Discarding
+                }
+            ),
+        ]
+    );
+}
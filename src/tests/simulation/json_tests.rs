@@ -0,0 +1,90 @@
+#![allow(non_snake_case)]
+
+use crate::{json::Json, json_object};
+
+#[test]
+fn Json_ToString_ParsesBackToSameValue() {
+    // Arrange
+    let value = json_object! {
+        "version" => 1u64,
+        "label" => "Router \"1\"\n",
+        "pos" => vec![1.5, -2.0],
+        "gateway" => None::<&str>,
+        "devices" => Json::Array(vec![json_object! { "rip" => true }, json_object! {}]),
+    };
+
+    // Act
+    let text = value.to_string();
+    let parsed = Json::parse(&text).unwrap();
+
+    // Assert
+    assert_eq!(parsed, value);
+    assert!(text.contains("\"pos\": [1.5, -2]"));
+    assert!(text.contains("\"label\": \"Router \\\"1\\\"\\n\""));
+}
+
+#[test]
+fn Json_Parse_ReadsMembersAndEscapes() {
+    // Arrange
+    let text =
+        r#" { "a" : [ 1, 2e3, -0.5 ], "b": "\u00e9\ud83d\ude00\/", "c": false, "d": null } "#;
+
+    // Act
+    let value = Json::parse(text).unwrap();
+
+    // Assert
+    let a = value.get("a").unwrap().as_array().unwrap();
+    assert_eq!(a[0].as_u64(), Some(1));
+    assert_eq!(a[1].as_u64(), Some(2000));
+    assert_eq!(a[2].as_u64(), None);
+    assert_eq!(a[2].as_f64(), Some(-0.5));
+    assert_eq!(value.get("b").unwrap().as_str(), Some("é😀/"));
+    assert_eq!(value.get("c").unwrap().as_bool(), Some(false));
+    assert!(value.get("d").unwrap().is_null());
+    assert!(value.get("e").is_none());
+}
+
+#[test]
+fn Json_Parse_InvalidDocument_ReturnsError() {
+    // Arrange
+    let documents = [
+        "",
+        "{",
+        "[1, 2",
+        "{\"a\" 1}",
+        "{\"a\": 1,}",
+        "\"unterminated",
+        "tru",
+        "1 2",
+        "\"\\x\"",
+    ];
+
+    // Act
+    let results: Vec<_> = documents.iter().map(|d| Json::parse(d)).collect();
+
+    // Assert
+    for (document, result) in documents.iter().zip(results) {
+        assert!(result.is_err(), "{:?} should not parse", document);
+    }
+}
+
+#[test]
+fn Json_ToLine_WritesOneLineThatParsesBack() {
+    // Arrange
+    let value = json_object! {
+        "time" => 1.5,
+        "reason" => "Dropped\n",
+        "fields" => json_object! { "vlan" => 10u64, "via" => None::<&str> },
+        "ports" => vec![1u64, 2],
+    };
+
+    // Act
+    let line = value.to_line();
+
+    // Assert
+    assert_eq!(
+        line,
+        r#"{"time":1.5,"reason":"Dropped\n","fields":{"vlan":10,"via":null},"ports":[1,2]}"#
+    );
+    assert_eq!(Json::parse(&line).unwrap(), value);
+}
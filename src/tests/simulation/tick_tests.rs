@@ -9,10 +9,9 @@ use crate::{
         ethernet::{interface::EthernetInterface, EtherType},
         ipv4::interface::Ipv4Interface,
     },
-    tick::{Tickable, TimeProvider},
+    tick::{Clock, ClockMode, TickTimer, Tickable},
 };
 
-#[ignore = "Messes with the global TimeProvider instance"]
 #[test]
 fn Tick_SwitchRstpInit_FinishesAfter15Seconds() {
     // Arrange
@@ -29,10 +28,9 @@ fn Tick_SwitchRstpInit_FinishesAfter15Seconds() {
 
     s.init_stp();
 
-    {
-        let mut tp = TimeProvider::instance().lock().unwrap();
-        tp.freeze();
-    }
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    s.set_clock(&clock);
 
     // Act
     sim.tick();
@@ -40,11 +38,7 @@ fn Tick_SwitchRstpInit_FinishesAfter15Seconds() {
     sim.tick();
     s.tick();
 
-    {
-        let mut tp = TimeProvider::instance().lock().unwrap();
-        tp.advance(Duration::from_secs(15));
-        tp.unfreeze();
-    }
+    clock.advance(Duration::from_secs(15));
 
     s.tick();
 
@@ -59,7 +53,6 @@ fn Tick_SwitchRstpInit_FinishesAfter15Seconds() {
     assert_eq!(i2_data.len(), 1);
 }
 
-#[ignore = "Messes with the global TimeProvider instance"]
 #[test]
 fn Tick_RouterRipMulticast_SendsEveryFiveSeconds() {
     // Arrange
@@ -79,19 +72,15 @@ fn Tick_RouterRipMulticast_SendsEveryFiveSeconds() {
     r.enable_interface(0, [192, 168, 1, 1], [255, 255, 255, 0]);
     r.enable_rip(0).expect("Failed to enable RIP");
 
-    {
-        let mut tp = TimeProvider::instance().lock().unwrap();
-        tp.freeze();
-    }
+    let clock = Clock::new(ClockMode::Virtual);
+    sim.set_clock(&clock);
+    r.set_clock(&clock);
 
     // Act
     for _ in 0..2 {
         sim.tick();
         r.tick();
-        {
-            let mut tp = TimeProvider::instance().lock().unwrap();
-            tp.advance(Duration::from_secs(5));
-        }
+        clock.advance(Duration::from_secs(5));
         sim.tick();
         r.tick();
     }
@@ -99,3 +88,123 @@ fn Tick_RouterRipMulticast_SendsEveryFiveSeconds() {
     // Assert
     assert_eq!(i1.receive().len(), 2);
 }
+
+#[test]
+fn Clock_Clones_ShareTime() {
+    // Arrange
+    let clock = Clock::new(ClockMode::Virtual);
+    let other = Clock::new(ClockMode::Virtual);
+    let shared = clock.clone();
+
+    // Act
+    clock.advance(Duration::from_secs(3));
+
+    // Assert
+    assert_eq!(shared.now(), clock.now());
+    assert_eq!(
+        clock.now().duration_since(other.now()).unwrap(),
+        Duration::from_secs(3)
+    );
+}
+
+#[test]
+fn Clock_FreezeAndUnfreeze_SwitchesMode() {
+    // Arrange
+    let clock = Clock::new(ClockMode::Wall);
+
+    // Act
+    clock.freeze();
+    let frozen = clock.mode();
+    let before = clock.now();
+    clock.advance(Duration::from_secs(60));
+    clock.unfreeze();
+
+    // Assert
+    assert_eq!(frozen, ClockMode::Frozen);
+    assert_eq!(clock.mode(), ClockMode::Wall);
+    assert!(clock.now() >= before + Duration::from_secs(60)); // Keeps the advanced time
+    assert_eq!(Clock::new(ClockMode::Virtual).mode(), ClockMode::Virtual);
+}
+
+#[test]
+#[should_panic(expected = "A virtual clock cannot be unfrozen")]
+fn Clock_UnfreezeVirtual_Panics() {
+    Clock::new(ClockMode::Virtual).unfreeze();
+}
+
+#[test]
+fn TickTimer_SetClock_KeepsTimeLeft() {
+    // Arrange
+    let mut timer = TickTimer::default();
+    timer.schedule("event", 5, false);
+    let clock = Clock::new(ClockMode::Virtual);
+
+    // Act
+    timer.set_clock(&clock);
+    clock.advance(Duration::from_secs(4));
+    let early = timer.ready();
+    clock.advance(Duration::from_secs(1));
+    let due = timer.ready();
+
+    // Assert
+    assert!(early.is_empty());
+    assert_eq!(due, vec!["event"]);
+}
+
+#[test]
+fn Clock_TakeDue_ReturnsEventsInOrderOnce() {
+    // Arrange
+    let clock = Clock::new(ClockMode::Virtual);
+    let start = clock.now();
+    clock.schedule(start + Duration::from_secs(2), "late");
+    clock.schedule(start + Duration::from_secs(1), "b");
+    clock.schedule(start + Duration::from_secs(1), "a");
+    clock.schedule(start + Duration::from_secs(1), "b");
+
+    // Act
+    let before = clock.take_due();
+    clock.advance(Duration::from_secs(1));
+    let due = clock.take_due();
+
+    // Assert
+    assert!(before.is_empty());
+    let names: Vec<&str> = due.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(names, vec!["b", "a"]);
+    assert_eq!(clock.next_event(), Some(start + Duration::from_secs(2)));
+}
+
+#[test]
+fn TickTimer_Schedule_PutsKeyOnAgenda() {
+    // Arrange
+    let clock = Clock::new(ClockMode::Virtual);
+    let mut timer = TickTimer::new(&clock);
+
+    // Act
+    timer.schedule("event", 5, true);
+    let first = clock.next_event();
+    clock.advance_to(first.unwrap());
+    clock.take_due();
+    timer.tick();
+
+    // Assert
+    assert_eq!(
+        first,
+        Some(Clock::new(ClockMode::Virtual).now() + Duration::from_secs(5))
+    );
+    assert_eq!(
+        clock.next_event(),
+        Some(clock.now() + Duration::from_secs(5))
+    ); // Rearmed
+}
+
+#[test]
+fn Clock_Elapsed_CountsSimulatedTime() {
+    // Arrange
+    let clock = Clock::new(ClockMode::Virtual);
+
+    // Act
+    clock.advance(Duration::from_millis(1500));
+
+    // Assert
+    assert_eq!(clock.elapsed(), Duration::from_millis(1500));
+}
@@ -1,81 +1,214 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt::Debug;
 use std::hash::Hash;
-use std::sync::{Mutex, OnceLock};
-use std::time::{Duration, SystemTime};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Provides an interface to freeze and manipulate time.
-pub struct TimeProvider {
+/// Least simulated time between two steps of a simulation. Work scheduled for the current time, such as a frame
+/// waiting in a buffer, runs one step later, so a simulation busy with work always moves forward.
+pub const STEP: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockMode {
+    /// Follows the system time, and can be frozen.
+    Wall,
+    /// Starts frozen at the system time, and can be advanced or unfrozen to follow it again.
+    Frozen,
+    /// Starts at the Unix epoch and only moves when advanced, independent of the system time.
+    Virtual,
+}
+
+/// The time of a simulation, with an interface to freeze and manipulate it.
+///
+/// Clones share the same time. A simulation hands its clock to every timer and port it holds, so separate simulations
+/// keep separate time.
+///
+/// The clock also holds the agenda of the simulation, the events its timers and ports schedule for when they have work
+/// to do. A frozen clock can jump from event to event, skipping the time in between.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    state: Rc<RefCell<ClockState>>,
+}
+
+#[derive(Debug, Clone)]
+struct ClockState {
+    mode: ClockMode,
     frozen: Option<SystemTime>,
     offset: Duration,
     last_unfrozen: SystemTime,
     last_frozen: Option<SystemTime>,
+    started: SystemTime, // Time the simulation started at
+    agenda: BinaryHeap<Reverse<(SystemTime, u64, String)>>, // (Time, Order Scheduled, Name)
+    scheduled: u64,
 }
 
-impl TimeProvider {
-    /// A singleton instance of TimeProvider.
-    // Really, I just don't want to pass the instance around everywhere and I'm definitely not going to make a DI container.
-    pub fn instance() -> &'static Mutex<Self> {
-        static INSTANCE: OnceLock<Mutex<TimeProvider>> = OnceLock::new();
-        INSTANCE.get_or_init(|| Mutex::new(TimeProvider::new()))
-    }
+/// A point in time a simulation has work to do, scheduled on its clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub time: SystemTime,
+    pub name: String,
+}
 
-    pub fn new() -> Self {
+impl Default for ClockState {
+    fn default() -> Self {
         Self {
+            mode: ClockMode::Wall,
             frozen: None,
             offset: Duration::ZERO,
             last_unfrozen: SystemTime::now(),
             last_frozen: None,
+            started: SystemTime::now(),
+            agenda: BinaryHeap::new(),
+            scheduled: 0,
         }
     }
+}
 
-    /// Freezes the current time.
-    /// This will cause the TimeProvider to return the same time until `unfreeze` is called.
-    pub fn freeze(&mut self) {
-        if self.frozen.is_some() {
-            panic!("TimeProvider is already frozen");
+impl Clock {
+    pub fn new(mode: ClockMode) -> Self {
+        let clock = Clock::default();
+        match mode {
+            ClockMode::Wall => {}
+            ClockMode::Frozen => clock.freeze(),
+            ClockMode::Virtual => {
+                let mut state = clock.state.borrow_mut();
+                state.mode = ClockMode::Virtual;
+                state.frozen = Some(UNIX_EPOCH);
+                state.last_frozen = state.frozen;
+                state.started = UNIX_EPOCH;
+            }
+        }
+        clock
+    }
+
+    /// Copies the clock, with its time and agenda, into a clock of its own.
+    pub fn snapshot(&self) -> Clock {
+        Clock {
+            state: Rc::new(RefCell::new(self.state.borrow().clone())),
         }
+    }
 
-        self.frozen = Some(self.now());
-        self.last_frozen = self.frozen;
+    /// Returns the mode of the clock. A frozen wall clock is `Frozen`, and an unfrozen one is `Wall`.
+    pub fn mode(&self) -> ClockMode {
+        let state = self.state.borrow();
+        match (state.mode, state.frozen) {
+            (ClockMode::Virtual, _) => ClockMode::Virtual,
+            (_, Some(_)) => ClockMode::Frozen,
+            (_, None) => ClockMode::Wall,
+        }
     }
 
-    pub fn unfreeze(&mut self) {
-        if self.frozen.is_none() {
-            panic!("TimeProvider is not frozen");
+    /// Freezes the current time.
+    /// This will cause the clock to return the same time until `unfreeze` is called.
+    pub fn freeze(&self) {
+        let now = self.now();
+        let mut state = self.state.borrow_mut();
+        if state.frozen.is_some() {
+            panic!("Clock is already frozen");
         }
 
-        if let Some(frozen_time) = self.frozen {
-            self.offset += frozen_time
-                .duration_since(self.last_unfrozen)
-                .expect("Time went backwards");
+        state.frozen = Some(now);
+        state.last_frozen = state.frozen;
+    }
+
+    pub fn unfreeze(&self) {
+        let state = &mut *self.state.borrow_mut();
+        if state.mode == ClockMode::Virtual {
+            panic!("A virtual clock cannot be unfrozen");
         }
 
-        self.frozen = None;
-        self.last_unfrozen = SystemTime::now();
+        let Some(frozen_time) = state.frozen else {
+            panic!("Clock is not frozen");
+        };
+
+        state.offset += frozen_time
+            .duration_since(state.last_unfrozen)
+            .expect("Time went backwards");
+        state.frozen = None;
+        state.last_unfrozen = SystemTime::now();
     }
 
     /// Advances the current frozen time by the given duration.
-    pub fn advance(&mut self, duration: Duration) {
-        match self.frozen {
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.borrow_mut();
+        match state.frozen {
             Some(frozen_time) => {
-                self.frozen = Some(frozen_time + duration);
+                state.frozen = Some(frozen_time + duration);
             }
             None => {
-                panic!("TimeProvider is not frozen");
+                panic!("Clock is not frozen");
             }
         }
     }
 
     /// Returns the time. Not accurate to SystemTime::now(), considers frozen time and offset.
     pub fn now(&self) -> SystemTime {
-        match self.frozen {
+        let state = self.state.borrow();
+        match state.frozen {
             Some(frozen_time) => frozen_time,
-            None => SystemTime::now() + self.offset,
+            None => SystemTime::now() + state.offset,
         }
     }
 
     pub fn last_frozen(&self) -> Option<SystemTime> {
-        self.last_frozen
+        self.state.borrow().last_frozen
+    }
+
+    /// Returns the simulated time since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        let started = self.state.borrow().started;
+        self.now().duration_since(started).unwrap_or_default()
+    }
+
+    /// Advances the current frozen time to the given time. Times already passed are ignored.
+    pub fn advance_to(&self, time: SystemTime) {
+        let now = self.now();
+        if time > now {
+            self.advance(time.duration_since(now).unwrap());
+        }
+    }
+
+    /// Schedules an event on the agenda. Events at the same time are kept in the order they were scheduled.
+    pub fn schedule(&self, time: SystemTime, name: impl Into<String>) {
+        let mut state = self.state.borrow_mut();
+        state.scheduled += 1;
+        let order = state.scheduled;
+        state.agenda.push(Reverse((time, order, name.into())));
+    }
+
+    /// Schedules an event for the current time, to run on the next step.
+    pub fn wake(&self, name: &str) {
+        self.schedule(self.now(), name);
+    }
+
+    /// Returns the time of the earliest event on the agenda.
+    pub fn next_event(&self) -> Option<SystemTime> {
+        self.state
+            .borrow()
+            .agenda
+            .peek()
+            .map(|Reverse((time, _, _))| *time)
+    }
+
+    /// Removes the events due by the current time from the agenda, in order.
+    /// Repeats of an event at the same time are returned once.
+    pub fn take_due(&self) -> Vec<Event> {
+        let now = self.now();
+        let mut state = self.state.borrow_mut();
+        let mut events: Vec<Event> = vec![];
+        while state
+            .agenda
+            .peek()
+            .is_some_and(|Reverse((time, _, _))| *time <= now)
+        {
+            let Reverse((time, _, name)) = state.agenda.pop().unwrap();
+            if !events.iter().any(|e| e.time == time && e.name == name) {
+                events.push(Event { time, name });
+            }
+        }
+        events
     }
 }
 
@@ -84,31 +217,50 @@ pub trait Tickable {
 }
 
 /// Schedules events to occur at a time interval. Call `tick` to update the timer.
-pub struct TickTimer<T: Eq + Hash + Clone> {
+///
+/// Every key scheduled is also put on the agenda of the clock, named by its debug representation.
+#[derive(Clone)]
+pub struct TickTimer<T: Eq + Hash + Clone + Debug> {
     map: HashMap<T, (SystemTime, Duration, bool)>, // (time_ready, interval_in_seconds, persist)
+    clock: Clock,
 }
 
-impl<T: Eq + Hash + Clone> Default for TickTimer<T> {
+impl<T: Eq + Hash + Clone + Debug> Default for TickTimer<T> {
+    /// A timer on its own wall clock.
     fn default() -> Self {
+        TickTimer::new(&Clock::default())
+    }
+}
+
+impl<T: Eq + Hash + Clone + Debug> TickTimer<T> {
+    pub fn new(clock: &Clock) -> Self {
         TickTimer {
             map: HashMap::new(),
+            clock: clock.clone(),
         }
     }
-}
 
-impl<T: Eq + Hash + Clone> TickTimer<T> {
+    /// Moves the timer onto another clock. Scheduled keys keep the time they had left.
+    pub fn set_clock(&mut self, clock: &Clock) {
+        let (old, new) = (self.clock.now(), clock.now());
+        for (key, (time_ready, _, _)) in self.map.iter_mut() {
+            *time_ready = new + time_ready.duration_since(old).unwrap_or_default();
+            clock.schedule(*time_ready, format!("{:?}", key));
+        }
+        self.clock = clock.clone();
+    }
+
     /// Adds a key to the timer IFF it doesn't already exist.
     /// * `key` - The key to add to the timer.
     /// * `interval` - The interval in seconds to wait before the key is ready.
     /// * `persist` - If the key should persist after it is ready.
     pub fn schedule(&mut self, key: T, interval_in_seconds: u64, persist: bool) {
-        let now = {
-            let tp = TimeProvider::instance().lock().unwrap();
-            tp.now()
-        };
+        let now = self.clock.now();
+        let clock = &self.clock;
 
-        self.map.entry(key).or_insert_with(|| {
+        self.map.entry(key).or_insert_with_key(|key| {
             let time_to_ready = now + Duration::new(interval_in_seconds, 0);
+            clock.schedule(time_to_ready, format!("{:?}", key));
             (
                 time_to_ready,
                 Duration::new(interval_in_seconds, 0),
@@ -117,12 +269,15 @@ impl<T: Eq + Hash + Clone> TickTimer<T> {
         });
     }
 
+    /// Removes a key from the timer, so it is never ready. Its event stays on the agenda of the clock, and wakes the
+    /// simulation to find nothing to do.
+    pub fn cancel(&mut self, key: &T) {
+        self.map.remove(key);
+    }
+
     /// Returns a list of keys that are ready to be processed.
     pub fn ready(&self) -> Vec<T> {
-        let now = {
-            let tp = TimeProvider::instance().lock().unwrap();
-            tp.now()
-        };
+        let now = self.clock.now();
 
         self.map
             .iter()
@@ -132,21 +287,19 @@ impl<T: Eq + Hash + Clone> TickTimer<T> {
     }
 }
 
-impl<T: Eq + Hash + Clone> Tickable for TickTimer<T> {
+impl<T: Eq + Hash + Clone + Debug> Tickable for TickTimer<T> {
     fn tick(&mut self) {
-        let now = {
-            let tp = TimeProvider::instance().lock().unwrap();
-            tp.now()
-        };
+        let now = self.clock.now();
 
         self.map
             .retain(|_, (time_ready, _, persist)| *time_ready > now || *persist);
 
-        for (_, (time_ready, interval_in_seconds, persist)) in self.map.iter_mut() {
+        for (key, (time_ready, interval_in_seconds, persist)) in self.map.iter_mut() {
             if *time_ready > now || !*persist {
                 continue;
             }
             *time_ready = now + *interval_in_seconds;
+            self.clock.schedule(*time_ready, format!("{:?}", key));
         }
     }
 }